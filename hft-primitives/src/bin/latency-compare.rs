@@ -0,0 +1,132 @@
+//! Compares a candidate run report against a baseline.
+//!
+//! Usage:
+//!   latency-compare <baseline> <candidate> [--threshold p99=10%]... [--alpha 0.01] [--plot]
+//!
+//! Reports ending in `.csv` are read as CSV, anything else as JSON, as
+//! `--report` writes them. CSV reports carry no histograms, so their
+//! entries are judged on thresholds alone.
+//!
+//! With `--plot`, the distributions of both runs are printed side by side
+//! (baseline first) for every entry present in both reports.
+//!
+//! Exits with status 1 if any threshold is exceeded or a candidate
+//! distribution is significantly slower (one-sided Mann-Whitney at
+//! `--alpha`, off unless given), 2 on usage or I/O errors.
+
+use hft_primitives::compare::{RegressionPolicy, ReportComparison};
+use hft_primitives::plot::plot_requested_from_args;
+use hft_primitives::RunReport;
+use std::process::ExitCode;
+
+const USAGE: &str = "usage: latency-compare <baseline> <candidate> \
+                     [--threshold <percentile>=<limit>]... [--alpha <p-value>] [--plot]";
+
+// `Ok(None)` means `--help` was given.
+fn parse_args() -> Result<Option<(String, String, RegressionPolicy)>, String> {
+    let mut paths = Vec::new();
+    let mut thresholds = Vec::new();
+    let mut policy = RegressionPolicy::default();
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--threshold" => {
+                let value = args.next().ok_or("--threshold requires a value")?;
+                thresholds.push(value.parse()?);
+            }
+            "--alpha" => {
+                let value = args.next().ok_or("--alpha requires a value")?;
+                policy.alpha = value
+                    .parse()
+                    .map_err(|_| format!("invalid alpha '{}'", value))?;
+            }
+            "--plot" => {}
+            "-h" | "--help" => return Ok(None),
+            _ => paths.push(arg),
+        }
+    }
+
+    if !thresholds.is_empty() {
+        policy.thresholds = thresholds;
+    }
+    match <[String; 2]>::try_from(paths) {
+        Ok([baseline, candidate]) => Ok(Some((baseline, candidate, policy))),
+        Err(_) => Err(USAGE.to_string()),
+    }
+}
+
+fn load(path: &str) -> Result<RunReport, String> {
+    RunReport::read_from_path(path).map_err(|e| format!("failed to read {}: {}", path, e))
+}
+
+fn main() -> ExitCode {
+    let (baseline_path, candidate_path, policy) = match parse_args() {
+        Ok(Some(args)) => args,
+        Ok(None) => {
+            println!("{}", USAGE);
+            return ExitCode::SUCCESS;
+        }
+        Err(message) => {
+            eprintln!("{}", message);
+            return ExitCode::from(2);
+        }
+    };
+
+    let (baseline, candidate) = match (load(&baseline_path), load(&candidate_path)) {
+        (Ok(baseline), Ok(candidate)) => (baseline, candidate),
+        (Err(e), _) | (_, Err(e)) => {
+            eprintln!("{}", e);
+            return ExitCode::from(2);
+        }
+    };
+
+    println!(
+        "Baseline:  {} ({})",
+        baseline_path,
+        baseline
+            .metadata
+            .git_describe
+            .as_deref()
+            .unwrap_or("unknown")
+    );
+    println!(
+        "Candidate: {} ({})",
+        candidate_path,
+        candidate
+            .metadata
+            .git_describe
+            .as_deref()
+            .unwrap_or("unknown")
+    );
+    println!();
+
+    let comparison = ReportComparison::new(&baseline, &candidate);
+    comparison.print_report();
+
+    if plot_requested_from_args() {
+        for entry in &candidate.entries {
+            if let Some(base) = baseline.entry(&entry.name) {
+                println!();
+                println!("[baseline]");
+                base.print_distribution();
+                println!();
+                println!("[candidate]");
+                entry.print_distribution();
+            }
+        }
+    }
+
+    let regressions = comparison.regressions(&policy);
+    println!();
+    if regressions.is_empty() {
+        println!("✅ No regressions (alpha = {})", policy.alpha);
+        ExitCode::SUCCESS
+    } else {
+        println!("❌ {} regression(s):", regressions.len());
+        for regression in &regressions {
+            println!("  {}", regression);
+        }
+        ExitCode::from(1)
+    }
+}
@@ -0,0 +1,94 @@
+//! Audits the host for settings that add latency jitter.
+//!
+//! Usage:
+//!   sysaudit [--format text|csv|json] [--strict]
+//!
+//! Prints one line per check (CPU governor, turbo, SMT, isolcpus, nohz_full,
+//! transparent hugepages, IRQ affinity, swap, C-states). `--format json`
+//! requires the `serde` feature.
+//!
+//! Exits with status 1 under `--strict` if any check warns, 2 on usage or
+//! I/O errors.
+
+use hft_primitives::sysaudit::HostAudit;
+use std::io::{self, Write};
+use std::process::ExitCode;
+
+const USAGE: &str = "usage: sysaudit [--format text|csv|json] [--strict]";
+
+#[derive(Clone, Copy)]
+enum Format {
+    Text,
+    Csv,
+    Json,
+}
+
+// `Ok(None)` means `--help` was given.
+fn parse_args() -> Result<Option<(Format, bool)>, String> {
+    let mut format = Format::Text;
+    let mut strict = false;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--format" => {
+                format = match args.next().as_deref() {
+                    Some("text") => Format::Text,
+                    Some("csv") => Format::Csv,
+                    Some("json") => Format::Json,
+                    Some(other) => return Err(format!("unknown format '{}'\n{}", other, USAGE)),
+                    None => return Err("--format requires a value".to_string()),
+                }
+            }
+            "--strict" => strict = true,
+            "-h" | "--help" => return Ok(None),
+            other => return Err(format!("unexpected argument '{}'\n{}", other, USAGE)),
+        }
+    }
+    Ok(Some((format, strict)))
+}
+
+#[cfg(feature = "serde")]
+fn write_json(audit: &HostAudit, out: &mut impl Write) -> io::Result<()> {
+    audit.write_json(out)
+}
+
+#[cfg(not(feature = "serde"))]
+fn write_json(_audit: &HostAudit, _out: &mut impl Write) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "JSON output requires the `serde` feature",
+    ))
+}
+
+fn main() -> ExitCode {
+    let (format, strict) = match parse_args() {
+        Ok(Some(args)) => args,
+        Ok(None) => {
+            println!("{}", USAGE);
+            return ExitCode::SUCCESS;
+        }
+        Err(message) => {
+            eprintln!("{}", message);
+            return ExitCode::from(2);
+        }
+    };
+
+    let audit = HostAudit::collect();
+    let mut out = io::stdout().lock();
+    let written = match format {
+        Format::Text => write!(out, "{}", audit),
+        Format::Csv => audit.write_csv(&mut out),
+        Format::Json => write_json(&audit, &mut out),
+    };
+    if let Err(e) = written {
+        eprintln!("{}", e);
+        return ExitCode::from(2);
+    }
+
+    if strict && !audit.is_clean() {
+        ExitCode::from(1)
+    } else {
+        ExitCode::SUCCESS
+    }
+}
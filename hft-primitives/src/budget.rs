@@ -0,0 +1,337 @@
+//! Configurable latency budgets (SLOs).
+//!
+//! A [`LatencyBudget`] is a list of criteria — per-percentile limits,
+//! maximum ratios between percentiles and maximum outlier counts — that a
+//! run must satisfy. [`LatencyBudget::evaluate`] checks every criterion and
+//! returns a [`BudgetReport`] listing each violation, so the same budget can
+//! gate unit tests, benches and report tooling.
+
+use crate::histogram::LatencyHistogram;
+use crate::metrics::{LatencyMetrics, Percentile};
+use std::fmt;
+use std::time::Duration;
+
+/// A single budget criterion.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum Criterion {
+    /// The percentile must be strictly below `limit`.
+    Percentile {
+        percentile: Percentile,
+        #[cfg_attr(
+            feature = "serde",
+            serde(rename = "limit_ns", with = "crate::metrics::nanos")
+        )]
+        limit: Duration,
+    },
+    /// `numerator / denominator` must be strictly below `max`.
+    Ratio {
+        numerator: Percentile,
+        denominator: Percentile,
+        max: f64,
+    },
+    /// At most `max_count` samples may exceed `above`.
+    Outliers {
+        #[cfg_attr(
+            feature = "serde",
+            serde(rename = "above_ns", with = "crate::metrics::nanos")
+        )]
+        above: Duration,
+        max_count: u64,
+    },
+}
+
+impl fmt::Display for Criterion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Criterion::Percentile { percentile, limit } => {
+                write!(f, "{} < {:?}", percentile, limit)
+            }
+            Criterion::Ratio {
+                numerator,
+                denominator,
+                max,
+            } => write!(f, "{}/{} < {:.2}", numerator, denominator, max),
+            Criterion::Outliers { above, max_count } => {
+                write!(f, "at most {} samples above {:?}", max_count, above)
+            }
+        }
+    }
+}
+
+/// Value observed for a criterion during evaluation.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Observed {
+    Latency(Duration),
+    Ratio(f64),
+    Count(u64),
+    /// The criterion needs data the input does not carry (outlier counts
+    /// cannot be derived from summarized metrics alone).
+    Unavailable,
+}
+
+impl fmt::Display for Observed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Observed::Latency(latency) => write!(f, "{:?}", latency),
+            Observed::Ratio(ratio) => write!(f, "{:.2}", ratio),
+            Observed::Count(count) => write!(f, "{}", count),
+            Observed::Unavailable => f.write_str("unavailable"),
+        }
+    }
+}
+
+/// Outcome of one criterion.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CriterionResult {
+    pub criterion: Criterion,
+    pub observed: Observed,
+    pub passed: bool,
+}
+
+impl fmt::Display for CriterionResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let status = if self.passed { "PASS" } else { "FAIL" };
+        write!(
+            f,
+            "[{}] {} (observed {})",
+            status, self.criterion, self.observed
+        )
+    }
+}
+
+/// Pass/fail result of evaluating a [`LatencyBudget`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct BudgetReport {
+    pub results: Vec<CriterionResult>,
+}
+
+impl BudgetReport {
+    /// Returns true if every criterion passed.
+    pub fn passed(&self) -> bool {
+        self.results.iter().all(|result| result.passed)
+    }
+
+    /// Iterates over the criteria that failed.
+    pub fn violations(&self) -> impl Iterator<Item = &CriterionResult> {
+        self.results.iter().filter(|result| !result.passed)
+    }
+
+    /// Prints every criterion with its outcome.
+    pub fn print_report(&self, name: &str) {
+        let verdict = if self.passed() { "PASS" } else { "FAIL" };
+        println!("=== {} budget: {} ===", name, verdict);
+        for result in &self.results {
+            println!("  {}", result);
+        }
+    }
+}
+
+impl fmt::Display for BudgetReport {
+    /// Lists the violated criteria, one per line.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.passed() {
+            return f.write_str("all latency budget criteria passed");
+        }
+        for (i, violation) in self.violations().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{}", violation)?;
+        }
+        Ok(())
+    }
+}
+
+/// Latency budget made of percentile limits, ratio constraints and outlier
+/// limits.
+///
+/// # Examples
+/// ```
+/// use hft_primitives::budget::LatencyBudget;
+/// use hft_primitives::metrics::Percentile;
+/// use std::time::Duration;
+///
+/// // Network path: microsecond-scale limits
+/// let budget = LatencyBudget::new()
+///     .percentile_limit(Percentile::P50, Duration::from_micros(20))
+///     .percentile_limit(Percentile::P99, Duration::from_micros(50))
+///     .max_ratio(Percentile::P99, Percentile::P50, 5.0)
+///     .max_outliers(Duration::from_millis(1), 0);
+///
+/// let mut samples: Vec<Duration> = (0..1000).map(|i| Duration::from_micros(10 + i % 5)).collect();
+/// let report = budget.evaluate_samples(&mut samples);
+/// assert!(report.passed(), "{}", report);
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LatencyBudget {
+    pub criteria: Vec<Criterion>,
+}
+
+impl LatencyBudget {
+    /// Creates an empty budget (always passes).
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The budget behind [`LatencyMetrics::is_hft_grade`]:
+    /// P99/P50 < 2.0 and P99 < 1µs.
+    pub fn hft_grade() -> Self {
+        Self::new()
+            .max_ratio(Percentile::P99, Percentile::P50, 2.0)
+            .percentile_limit(Percentile::P99, Duration::from_micros(1))
+    }
+
+    /// Requires `percentile` to stay strictly below `limit`.
+    pub fn percentile_limit(mut self, percentile: Percentile, limit: Duration) -> Self {
+        self.criteria
+            .push(Criterion::Percentile { percentile, limit });
+        self
+    }
+
+    /// Requires `numerator / denominator` to stay strictly below `max`.
+    pub fn max_ratio(mut self, numerator: Percentile, denominator: Percentile, max: f64) -> Self {
+        self.criteria.push(Criterion::Ratio {
+            numerator,
+            denominator,
+            max,
+        });
+        self
+    }
+
+    /// Allows at most `max_count` samples above `above`.
+    pub fn max_outliers(mut self, above: Duration, max_count: u64) -> Self {
+        self.criteria.push(Criterion::Outliers { above, max_count });
+        self
+    }
+
+    /// Evaluates the budget against summarized metrics.
+    ///
+    /// Outlier criteria can only be decided when no sample exceeds the
+    /// threshold (`max <= above`); otherwise they are reported as failed
+    /// with [`Observed::Unavailable`]. Use
+    /// [`evaluate_histogram`](Self::evaluate_histogram) or
+    /// [`evaluate_samples`](Self::evaluate_samples) for exact counts.
+    pub fn evaluate(&self, metrics: &LatencyMetrics) -> BudgetReport {
+        self.evaluate_with(metrics, |above| (metrics.max <= above).then_some(0))
+    }
+
+    /// Evaluates the budget against a histogram.
+    pub fn evaluate_histogram(&self, histogram: &LatencyHistogram) -> BudgetReport {
+        let metrics = LatencyMetrics::from_histogram(histogram);
+        self.evaluate_with(&metrics, |above| Some(histogram.count_above(above)))
+    }
+
+    /// Evaluates the budget against raw samples (sorted in-place).
+    pub fn evaluate_samples(&self, samples: &mut [Duration]) -> BudgetReport {
+        let metrics = LatencyMetrics::from_samples(samples);
+        self.evaluate_with(&metrics, |above| {
+            // Samples are sorted, so the outliers form the tail
+            Some((samples.len() - samples.partition_point(|&s| s <= above)) as u64)
+        })
+    }
+
+    fn evaluate_with<F>(&self, metrics: &LatencyMetrics, count_above: F) -> BudgetReport
+    where
+        F: Fn(Duration) -> Option<u64>,
+    {
+        let results = self
+            .criteria
+            .iter()
+            .map(|&criterion| {
+                let (observed, passed) = match criterion {
+                    Criterion::Percentile { percentile, limit } => {
+                        let value = metrics.percentile(percentile);
+                        (Observed::Latency(value), value < limit)
+                    }
+                    Criterion::Ratio {
+                        numerator,
+                        denominator,
+                        max,
+                    } => {
+                        let ratio = metrics.percentile(numerator).as_nanos() as f64
+                            / metrics.percentile(denominator).as_nanos() as f64;
+                        (Observed::Ratio(ratio), ratio < max)
+                    }
+                    Criterion::Outliers { above, max_count } => match count_above(above) {
+                        Some(count) => (Observed::Count(count), count <= max_count),
+                        None => (Observed::Unavailable, false),
+                    },
+                };
+                CriterionResult {
+                    criterion,
+                    observed,
+                    passed,
+                }
+            })
+            .collect();
+
+        BudgetReport { results }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn samples() -> Vec<Duration> {
+        let mut samples: Vec<Duration> = (0..990).map(|_| Duration::from_nanos(100)).collect();
+        samples.extend((0..10).map(|_| Duration::from_micros(5)));
+        samples
+    }
+
+    #[test]
+    fn test_hft_grade_matches_budget() {
+        let mut fast: Vec<Duration> = (0..100).map(|i| Duration::from_nanos(100 + i)).collect();
+        let metrics = LatencyMetrics::from_samples(&mut fast);
+        assert!(metrics.is_hft_grade());
+        assert!(LatencyBudget::hft_grade().evaluate(&metrics).passed());
+
+        let mut slow = samples();
+        let metrics = LatencyMetrics::from_samples(&mut slow);
+        assert!(!metrics.is_hft_grade());
+    }
+
+    #[test]
+    fn test_lists_every_violation() {
+        let budget = LatencyBudget::new()
+            .percentile_limit(Percentile::P50, Duration::from_nanos(200))
+            .percentile_limit(Percentile::P99, Duration::from_micros(1))
+            .max_ratio(Percentile::P99, Percentile::P50, 2.0)
+            .max_outliers(Duration::from_micros(1), 5);
+
+        let report = budget.evaluate_samples(&mut samples());
+        assert!(!report.passed());
+
+        let violations: Vec<_> = report.violations().collect();
+        assert_eq!(violations.len(), 3);
+        assert_eq!(
+            violations[0].observed,
+            Observed::Latency(Duration::from_micros(5))
+        );
+        assert_eq!(violations[1].observed, Observed::Ratio(50.0));
+        assert_eq!(violations[2].observed, Observed::Count(10));
+        assert_eq!(report.to_string().lines().count(), 3);
+    }
+
+    #[test]
+    fn test_outliers_from_histogram_and_metrics() {
+        let budget = LatencyBudget::new().max_outliers(Duration::from_micros(1), 10);
+        let samples = samples();
+
+        let histogram = LatencyHistogram::from_samples(&samples);
+        assert!(budget.evaluate_histogram(&histogram).passed());
+
+        // Metrics alone cannot count the 10 outliers
+        let metrics = LatencyMetrics::from_histogram(&histogram);
+        let report = budget.evaluate(&metrics);
+        assert_eq!(report.results[0].observed, Observed::Unavailable);
+        assert!(!report.passed());
+
+        // ...but can prove there are none
+        let relaxed = LatencyBudget::new().max_outliers(Duration::from_micros(10), 0);
+        assert!(relaxed.evaluate(&metrics).passed());
+    }
+}
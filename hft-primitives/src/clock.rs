@@ -0,0 +1,425 @@
+//! Timestamp sources for latency measurement.
+//!
+//! `Instant::now()` costs ~20ns (a vDSO `clock_gettime` call), which
+//! dominates the sub-100ns operations we care about. On x86_64 with an
+//! invariant TSC, [`TscClock`] reads the time-stamp counter directly
+//! (`rdtsc`/`rdtscp`, a few ns) and converts ticks to nanoseconds with a
+//! factor calibrated against `CLOCK_MONOTONIC`.
+//!
+//! [`AutoClock`] picks the TSC when it is usable and falls back to
+//! [`InstantClock`] everywhere else. Every clock reports its own
+//! measurement overhead so it can be subtracted from results with
+//! [`LatencyMetrics::subtract_overhead`](crate::LatencyMetrics::subtract_overhead).
+
+use std::fmt;
+use std::time::{Duration, Instant};
+
+/// Number of empty start/stop pairs used to estimate clock overhead.
+const OVERHEAD_ROUNDS: usize = 10_000;
+
+/// A monotonic timestamp source.
+///
+/// Timestamps are opaque ticks; only differences between a [`start`](Clock::start)
+/// and a later [`stop`](Clock::stop) are meaningful.
+///
+/// # Examples
+/// ```
+/// use hft_primitives::clock::{AutoClock, Clock};
+///
+/// let clock = AutoClock::new();
+/// let start = clock.start();
+/// std::hint::black_box((0..100).sum::<u64>());
+/// let latency = clock.elapsed(start, clock.stop());
+/// println!("{:?} (clock overhead {:?})", latency, clock.overhead());
+/// ```
+pub trait Clock {
+    /// Reads a timestamp at the beginning of a measured region.
+    ///
+    /// Implementations fence so earlier instructions cannot leak into the
+    /// measured region.
+    fn start(&self) -> u64;
+
+    /// Reads a timestamp at the end of a measured region.
+    ///
+    /// Implementations fence so the measured instructions complete before
+    /// the timestamp is taken.
+    fn stop(&self) -> u64;
+
+    /// Converts a tick count into a duration.
+    fn ticks_to_duration(&self, ticks: u64) -> Duration;
+
+    /// Cost of an empty `start()`/`stop()` pair, as measured at construction.
+    fn overhead(&self) -> Duration;
+
+    /// Returns the duration between two timestamps (zero if reversed).
+    #[inline]
+    fn elapsed(&self, start: u64, stop: u64) -> Duration {
+        self.ticks_to_duration(stop.saturating_sub(start))
+    }
+
+    /// Times a closure.
+    #[inline]
+    fn time<F: FnOnce()>(&self, operation: F) -> Duration
+    where
+        Self: Sized,
+    {
+        let start = self.start();
+        operation();
+        self.elapsed(start, self.stop())
+    }
+}
+
+/// Measures the minimum cost of an empty start/stop pair.
+fn measure_overhead<C: Clock>(clock: &C) -> Duration {
+    let mut best = u64::MAX;
+    for _ in 0..OVERHEAD_ROUNDS {
+        let start = clock.start();
+        let stop = clock.stop();
+        best = best.min(stop.saturating_sub(start));
+    }
+    clock.ticks_to_duration(best)
+}
+
+/// Portable clock backed by `std::time::Instant`.
+///
+/// Ticks are nanoseconds since the clock was created.
+#[derive(Debug, Clone, Copy)]
+pub struct InstantClock {
+    epoch: Instant,
+    overhead: Duration,
+}
+
+impl InstantClock {
+    /// Creates the clock and measures its overhead.
+    pub fn new() -> Self {
+        let mut clock = Self {
+            epoch: Instant::now(),
+            overhead: Duration::ZERO,
+        };
+        clock.overhead = measure_overhead(&clock);
+        clock
+    }
+
+    #[inline]
+    fn now(&self) -> u64 {
+        self.epoch.elapsed().as_nanos() as u64
+    }
+}
+
+impl Default for InstantClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for InstantClock {
+    #[inline]
+    fn start(&self) -> u64 {
+        self.now()
+    }
+
+    #[inline]
+    fn stop(&self) -> u64 {
+        self.now()
+    }
+
+    #[inline]
+    fn ticks_to_duration(&self, ticks: u64) -> Duration {
+        Duration::from_nanos(ticks)
+    }
+
+    fn overhead(&self) -> Duration {
+        self.overhead
+    }
+}
+
+/// Reasons the TSC cannot be used as a clock.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ClockError {
+    /// The platform has no usable TSC: not x86_64, or the CPU lacks `rdtscp`.
+    Unsupported,
+    /// The CPU does not advertise an invariant TSC, so the tick rate may
+    /// change with frequency scaling or stop in deep C-states.
+    NotInvariant,
+    /// Calibration against `CLOCK_MONOTONIC` produced an unusable rate.
+    CalibrationFailed(String),
+}
+
+impl fmt::Display for ClockError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClockError::Unsupported => f.write_str("TSC is not available on this platform"),
+            ClockError::NotInvariant => f.write_str("CPU does not report an invariant TSC"),
+            ClockError::CalibrationFailed(reason) => {
+                write!(f, "TSC calibration failed: {}", reason)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ClockError {}
+
+/// Cycle-accurate clock reading the x86_64 time-stamp counter.
+///
+/// `start()` is `lfence; rdtsc; lfence` and `stop()` is `rdtscp; lfence`,
+/// the pairing recommended by Intel for benchmarking short code paths.
+#[cfg(target_arch = "x86_64")]
+#[derive(Debug, Clone, Copy)]
+pub struct TscClock {
+    nanos_per_tick: f64,
+    overhead: Duration,
+}
+
+#[cfg(target_arch = "x86_64")]
+impl TscClock {
+    /// Default calibration window.
+    pub const DEFAULT_CALIBRATION: Duration = Duration::from_millis(20);
+
+    /// Checks for an invariant TSC and calibrates over the default window.
+    pub fn calibrate() -> Result<Self, ClockError> {
+        Self::calibrate_for(Self::DEFAULT_CALIBRATION)
+    }
+
+    /// Checks for an invariant TSC and calibrates over `window`.
+    ///
+    /// Longer windows give a more accurate tick rate; the error is roughly
+    /// the `clock_gettime` cost divided by the window length.
+    pub fn calibrate_for(window: Duration) -> Result<Self, ClockError> {
+        if !Self::is_invariant() {
+            return Err(ClockError::NotInvariant);
+        }
+        if !has_rdtscp() {
+            return Err(ClockError::Unsupported);
+        }
+
+        let (tsc_begin, mono_begin) = tsc_and_monotonic();
+        let deadline = mono_begin + window.as_nanos() as u64;
+        let (mut tsc_end, mut mono_end) = tsc_and_monotonic();
+        while mono_end < deadline {
+            std::hint::spin_loop();
+            (tsc_end, mono_end) = tsc_and_monotonic();
+        }
+
+        let ticks = tsc_end.wrapping_sub(tsc_begin);
+        let nanos = mono_end - mono_begin;
+        if ticks == 0 || tsc_end < tsc_begin {
+            return Err(ClockError::CalibrationFailed(format!(
+                "TSC did not advance ({} ticks in {}ns)",
+                ticks, nanos
+            )));
+        }
+
+        let mut clock = Self {
+            nanos_per_tick: nanos as f64 / ticks as f64,
+            overhead: Duration::ZERO,
+        };
+        clock.overhead = measure_overhead(&clock);
+        Ok(clock)
+    }
+
+    /// Returns true if CPUID reports an invariant TSC
+    /// (leaf `0x8000_0007`, EDX bit 8).
+    pub fn is_invariant() -> bool {
+        use std::arch::x86_64::__cpuid;
+
+        let max_extended_leaf = __cpuid(0x8000_0000).eax;
+        if max_extended_leaf < 0x8000_0007 {
+            return false;
+        }
+        __cpuid(0x8000_0007).edx & (1 << 8) != 0
+    }
+
+    /// Returns the calibrated TSC frequency in GHz (ticks per nanosecond).
+    pub fn frequency_ghz(&self) -> f64 {
+        1.0 / self.nanos_per_tick
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+impl Clock for TscClock {
+    #[inline]
+    fn start(&self) -> u64 {
+        use std::arch::x86_64::{_mm_lfence, _rdtsc};
+
+        // SAFETY: rdtsc/lfence are available on every x86_64 CPU
+        unsafe {
+            _mm_lfence();
+            let ticks = _rdtsc();
+            _mm_lfence();
+            ticks
+        }
+    }
+
+    #[inline]
+    fn stop(&self) -> u64 {
+        use std::arch::x86_64::{__rdtscp, _mm_lfence};
+
+        let mut aux = 0;
+        // SAFETY: `calibrate` checked for rdtscp before constructing the clock
+        unsafe {
+            let ticks = __rdtscp(&mut aux);
+            _mm_lfence();
+            ticks
+        }
+    }
+
+    #[inline]
+    fn ticks_to_duration(&self, ticks: u64) -> Duration {
+        Duration::from_nanos((ticks as f64 * self.nanos_per_tick) as u64)
+    }
+
+    fn overhead(&self) -> Duration {
+        self.overhead
+    }
+}
+
+/// Returns true if CPUID reports `rdtscp` (leaf `0x8000_0001`, EDX bit 27).
+#[cfg(target_arch = "x86_64")]
+fn has_rdtscp() -> bool {
+    use std::arch::x86_64::__cpuid;
+
+    __cpuid(0x8000_0001).edx & (1 << 27) != 0
+}
+
+/// Reads the TSC and `CLOCK_MONOTONIC` (in ns) as close together as possible.
+///
+/// The TSC is sampled on both sides of `clock_gettime` and averaged to
+/// cancel out the call's own latency.
+#[cfg(target_arch = "x86_64")]
+fn tsc_and_monotonic() -> (u64, u64) {
+    use std::arch::x86_64::_rdtsc;
+
+    let mut ts = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    // SAFETY: `ts` is a valid timespec and CLOCK_MONOTONIC always exists
+    let (before, after) = unsafe {
+        let before = _rdtsc();
+        libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut ts);
+        (before, _rdtsc())
+    };
+    let mono = ts.tv_sec as u64 * 1_000_000_000 + ts.tv_nsec as u64;
+    (before + (after - before) / 2, mono)
+}
+
+/// The best clock available on this machine.
+///
+/// Uses the TSC on x86_64 when it is invariant and calibrates cleanly,
+/// [`InstantClock`] otherwise. Dispatch is a `match` on a two-variant enum,
+/// so there is no virtual call on the measurement path.
+#[derive(Debug, Clone, Copy)]
+pub enum AutoClock {
+    #[cfg(target_arch = "x86_64")]
+    Tsc(TscClock),
+    Instant(InstantClock),
+}
+
+impl AutoClock {
+    /// Calibrates the TSC if possible, otherwise falls back to `Instant`.
+    pub fn new() -> Self {
+        #[cfg(target_arch = "x86_64")]
+        if let Ok(tsc) = TscClock::calibrate() {
+            return AutoClock::Tsc(tsc);
+        }
+        AutoClock::Instant(InstantClock::new())
+    }
+
+    /// Returns a short description of the selected source.
+    pub fn description(&self) -> String {
+        match self {
+            #[cfg(target_arch = "x86_64")]
+            AutoClock::Tsc(tsc) => format!("TSC ({:.3} GHz, invariant)", tsc.frequency_ghz()),
+            AutoClock::Instant(_) => "Instant (CLOCK_MONOTONIC)".to_string(),
+        }
+    }
+}
+
+impl Default for AutoClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for AutoClock {
+    #[inline]
+    fn start(&self) -> u64 {
+        match self {
+            #[cfg(target_arch = "x86_64")]
+            AutoClock::Tsc(clock) => clock.start(),
+            AutoClock::Instant(clock) => clock.start(),
+        }
+    }
+
+    #[inline]
+    fn stop(&self) -> u64 {
+        match self {
+            #[cfg(target_arch = "x86_64")]
+            AutoClock::Tsc(clock) => clock.stop(),
+            AutoClock::Instant(clock) => clock.stop(),
+        }
+    }
+
+    #[inline]
+    fn ticks_to_duration(&self, ticks: u64) -> Duration {
+        match self {
+            #[cfg(target_arch = "x86_64")]
+            AutoClock::Tsc(clock) => clock.ticks_to_duration(ticks),
+            AutoClock::Instant(clock) => clock.ticks_to_duration(ticks),
+        }
+    }
+
+    fn overhead(&self) -> Duration {
+        match self {
+            #[cfg(target_arch = "x86_64")]
+            AutoClock::Tsc(clock) => clock.overhead(),
+            AutoClock::Instant(clock) => clock.overhead(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_tracks_sleep<C: Clock>(clock: &C) {
+        let start = clock.start();
+        std::thread::sleep(Duration::from_millis(20));
+        let elapsed = clock.elapsed(start, clock.stop());
+        assert!(elapsed >= Duration::from_millis(19), "{:?}", elapsed);
+        assert!(elapsed < Duration::from_secs(1), "{:?}", elapsed);
+    }
+
+    #[test]
+    fn test_instant_clock() {
+        let clock = InstantClock::new();
+        assert!(clock.overhead() < Duration::from_micros(10));
+        assert_tracks_sleep(&clock);
+        assert_eq!(clock.elapsed(10, 5), Duration::ZERO);
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    #[test]
+    fn test_tsc_clock() {
+        match TscClock::calibrate() {
+            Ok(clock) => {
+                assert!(clock.frequency_ghz() > 0.1 && clock.frequency_ghz() < 10.0);
+                assert!(clock.overhead() < Duration::from_micros(10));
+                assert_tracks_sleep(&clock);
+            }
+            // Some VMs hide the invariant TSC flag or rdtscp
+            Err(ClockError::NotInvariant) => assert!(!TscClock::is_invariant()),
+            Err(ClockError::Unsupported) => assert!(!has_rdtscp()),
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    #[test]
+    fn test_auto_clock() {
+        let clock = AutoClock::new();
+        assert!(!clock.description().is_empty());
+        assert_tracks_sleep(&clock);
+        let busy = clock.time(|| std::thread::sleep(Duration::from_millis(1)));
+        assert!(busy >= Duration::from_micros(900));
+    }
+}
@@ -0,0 +1,559 @@
+//! Baseline comparison and regression detection for latency runs.
+//!
+//! Compares two [`RunReport`]s entry by entry: per-percentile deltas come
+//! from the summarized [`LatencyMetrics`] and are checked against their
+//! thresholds, and when both entries carry a histogram a one-sided
+//! Mann-Whitney U test additionally flags a candidate distribution that is
+//! significantly slower as a whole.
+//!
+//! The `latency-compare` binary wraps this module for CI use.
+
+use crate::histogram::LatencyHistogram;
+use crate::metrics::Percentile;
+use crate::report::{ReportEntry, RunReport};
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// Change of one percentile between a baseline and a candidate run.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PercentileDelta {
+    pub percentile: Percentile,
+    pub baseline: Duration,
+    pub candidate: Duration,
+}
+
+impl PercentileDelta {
+    /// Absolute change in nanoseconds (positive = slower).
+    pub fn change_nanos(&self) -> i128 {
+        self.candidate.as_nanos() as i128 - self.baseline.as_nanos() as i128
+    }
+
+    /// Relative change (`0.10` = 10% slower). Infinite if the baseline is zero
+    /// and the candidate is not.
+    pub fn relative_change(&self) -> f64 {
+        let baseline = self.baseline.as_nanos() as f64;
+        let candidate = self.candidate.as_nanos() as f64;
+        if baseline == 0.0 {
+            return if candidate == 0.0 { 0.0 } else { f64::INFINITY };
+        }
+        candidate / baseline - 1.0
+    }
+}
+
+/// Result of a Mann-Whitney U test between two distributions.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SignificanceTest {
+    /// U statistic of the candidate sample.
+    pub u_statistic: f64,
+    /// Normal-approximation z score (positive = candidate slower).
+    pub z_score: f64,
+    /// Two-sided p-value.
+    pub p_value: f64,
+    /// Probability that a random candidate sample is slower than a random
+    /// baseline sample (0.5 = no shift).
+    pub prob_slower: f64,
+}
+
+impl SignificanceTest {
+    /// Returns true if the distributions differ at significance level `alpha`.
+    pub fn is_significant(&self, alpha: f64) -> bool {
+        self.p_value < alpha
+    }
+
+    /// One-sided p-value for the hypothesis that the candidate is slower.
+    /// Close to 1 when the candidate is faster.
+    pub fn p_value_slower(&self) -> f64 {
+        (erfc(self.z_score / std::f64::consts::SQRT_2) / 2.0).min(1.0)
+    }
+
+    /// Returns true if the candidate is significantly slower at level `alpha`.
+    pub fn is_significantly_slower(&self, alpha: f64) -> bool {
+        self.p_value_slower() < alpha
+    }
+}
+
+/// Runs a Mann-Whitney U test on two histograms.
+///
+/// Values sharing a histogram bucket are treated as ties, with the usual
+/// tie correction applied to the variance. Returns `None` if either
+/// histogram is empty or all values tie.
+///
+/// # Examples
+/// ```
+/// use hft_primitives::compare::mann_whitney;
+/// use hft_primitives::LatencyHistogram;
+///
+/// let mut baseline = LatencyHistogram::new();
+/// let mut candidate = LatencyHistogram::new();
+/// for i in 0..1000 {
+///     baseline.record_nanos(100 + i % 20);
+///     candidate.record_nanos(110 + i % 20);
+/// }
+///
+/// let test = mann_whitney(&baseline, &candidate).unwrap();
+/// assert!(test.is_significant(0.01));
+/// assert!(test.prob_slower > 0.5);
+/// ```
+pub fn mann_whitney(
+    baseline: &LatencyHistogram,
+    candidate: &LatencyHistogram,
+) -> Option<SignificanceTest> {
+    let n_base = baseline.len() as f64;
+    let n_cand = candidate.len() as f64;
+    if n_base == 0.0 || n_cand == 0.0 {
+        return None;
+    }
+    let n = n_base + n_cand;
+
+    // Walk buckets in ascending order, assigning each tie group its mid-rank
+    let mut rank_sum = 0.0;
+    let mut tie_term = 0.0;
+    let mut ranked = 0.0;
+    for (base_count, cand_count) in baseline.paired_counts(candidate) {
+        let group = (base_count + cand_count) as f64;
+        let mid_rank = ranked + (group + 1.0) / 2.0;
+        rank_sum += cand_count as f64 * mid_rank;
+        tie_term += group * group * group - group;
+        ranked += group;
+    }
+
+    let u = rank_sum - n_cand * (n_cand + 1.0) / 2.0;
+    let mean = n_base * n_cand / 2.0;
+    let variance = n_base * n_cand / 12.0 * ((n + 1.0) - tie_term / (n * (n - 1.0)));
+    if variance <= 0.0 {
+        return None;
+    }
+
+    let z = (u - mean) / variance.sqrt();
+    Some(SignificanceTest {
+        u_statistic: u,
+        z_score: z,
+        p_value: erfc(z.abs() / std::f64::consts::SQRT_2).min(1.0),
+        prob_slower: u / (n_base * n_cand),
+    })
+}
+
+/// Complementary error function (Numerical Recipes `erfcc`, |error| < 1.2e-7).
+fn erfc(x: f64) -> f64 {
+    let z = x.abs();
+    let t = 1.0 / (1.0 + 0.5 * z);
+    let poly = -z * z - 1.265_512_23
+        + t * (1.000_023_68
+            + t * (0.374_091_96
+                + t * (0.096_784_18
+                    + t * (-0.186_288_06
+                        + t * (0.278_868_07
+                            + t * (-1.135_203_98
+                                + t * (1.488_515_87 + t * (-0.822_152_23 + t * 0.170_872_77))))))));
+    let result = t * poly.exp();
+    if x >= 0.0 {
+        result
+    } else {
+        2.0 - result
+    }
+}
+
+/// Maximum allowed relative increase of one percentile, e.g. `p99=10%`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RegressionThreshold {
+    pub percentile: Percentile,
+    /// Allowed increase as a fraction (`0.10` = +10%).
+    pub max_increase: f64,
+}
+
+impl FromStr for RegressionThreshold {
+    type Err = String;
+
+    /// Parses `p99=10%`, `p99=+10%` or `p99=0.1`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (percentile, limit) = s
+            .split_once('=')
+            .ok_or_else(|| format!("expected <percentile>=<limit>, got '{}'", s))?;
+        let percentile = percentile.parse()?;
+
+        let limit = limit.trim().trim_start_matches('+');
+        let max_increase = match limit.strip_suffix('%') {
+            Some(pct) => pct.trim().parse::<f64>().map(|v| v / 100.0),
+            None => limit.parse::<f64>(),
+        }
+        .map_err(|_| format!("invalid threshold '{}'", limit))?;
+
+        Ok(Self {
+            percentile,
+            max_increase,
+        })
+    }
+}
+
+impl fmt::Display for RegressionThreshold {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}=+{:.1}%", self.percentile, self.max_increase * 100.0)
+    }
+}
+
+/// Rules deciding when a comparison counts as a regression.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RegressionPolicy {
+    /// Each threshold is checked on its own, whatever the significance test
+    /// says.
+    pub thresholds: Vec<RegressionThreshold>,
+    /// Significance level for the one-sided Mann-Whitney test: an entry
+    /// whose candidate distribution is significantly slower is a regression
+    /// even if no threshold is breached. Entries without histograms are
+    /// judged on thresholds alone; `0.0` disables the test.
+    ///
+    /// With the 10^5-10^6 samples of a typical entry, run-to-run noise
+    /// alone is often significant, so enable it only for quiet, pinned
+    /// hosts.
+    pub alpha: f64,
+}
+
+impl Default for RegressionPolicy {
+    /// P99 may grow by at most 10%; the significance test is off.
+    fn default() -> Self {
+        Self {
+            thresholds: vec![RegressionThreshold {
+                percentile: Percentile::P99,
+                max_increase: 0.10,
+            }],
+            alpha: 0.0,
+        }
+    }
+}
+
+/// A regression found in a candidate entry.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Regression {
+    /// A percentile grew by more than its threshold allows.
+    Threshold {
+        entry: String,
+        threshold: RegressionThreshold,
+        delta: PercentileDelta,
+    },
+    /// The candidate distribution is significantly slower than the baseline.
+    Shift {
+        entry: String,
+        test: SignificanceTest,
+        alpha: f64,
+    },
+}
+
+impl Regression {
+    /// Returns the name of the regressed entry.
+    pub fn entry(&self) -> &str {
+        match self {
+            Regression::Threshold { entry, .. } | Regression::Shift { entry, .. } => entry,
+        }
+    }
+}
+
+impl fmt::Display for Regression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Regression::Threshold {
+                entry,
+                threshold,
+                delta,
+            } => write!(
+                f,
+                "{}: {} {:?} -> {:?} ({:+.1}%, limit {})",
+                entry,
+                delta.percentile,
+                delta.baseline,
+                delta.candidate,
+                delta.relative_change() * 100.0,
+                threshold,
+            ),
+            Regression::Shift { entry, test, alpha } => write!(
+                f,
+                "{}: distribution shifted slower (P(slower) = {:.3}, p = {:.4}, alpha {})",
+                entry,
+                test.prob_slower,
+                test.p_value_slower(),
+                alpha,
+            ),
+        }
+    }
+}
+
+/// Comparison of one named entry present in both reports.
+#[derive(Debug, Clone)]
+pub struct EntryComparison {
+    pub name: String,
+    pub deltas: Vec<PercentileDelta>,
+    /// `None` unless both entries carry a histogram.
+    pub significance: Option<SignificanceTest>,
+}
+
+impl EntryComparison {
+    /// Compares two entries of the same experiment.
+    pub fn between(baseline: &ReportEntry, candidate: &ReportEntry) -> Self {
+        let deltas = Percentile::ALL
+            .iter()
+            .map(|&percentile| PercentileDelta {
+                percentile,
+                baseline: baseline.metrics.percentile(percentile),
+                candidate: candidate.metrics.percentile(percentile),
+            })
+            .collect();
+
+        let significance = match (&baseline.histogram, &candidate.histogram) {
+            (Some(base), Some(cand)) => mann_whitney(base, cand),
+            _ => None,
+        };
+
+        Self {
+            name: candidate.name.clone(),
+            deltas,
+            significance,
+        }
+    }
+
+    /// Returns the delta for a percentile.
+    pub fn delta(&self, percentile: Percentile) -> Option<&PercentileDelta> {
+        self.deltas.iter().find(|d| d.percentile == percentile)
+    }
+
+    /// Returns the regressions of this entry under `policy`: every breached
+    /// threshold, then a significant slower shift if there is one.
+    pub fn regressions(&self, policy: &RegressionPolicy) -> Vec<Regression> {
+        let mut regressions: Vec<Regression> = policy
+            .thresholds
+            .iter()
+            .filter_map(|threshold| {
+                let delta = self.delta(threshold.percentile)?;
+                (delta.relative_change() > threshold.max_increase).then(|| Regression::Threshold {
+                    entry: self.name.clone(),
+                    threshold: *threshold,
+                    delta: *delta,
+                })
+            })
+            .collect();
+
+        if let Some(test) = self.significance {
+            if test.is_significantly_slower(policy.alpha) {
+                regressions.push(Regression::Shift {
+                    entry: self.name.clone(),
+                    test,
+                    alpha: policy.alpha,
+                });
+            }
+        }
+        regressions
+    }
+}
+
+/// Entry-by-entry comparison of two reports.
+///
+/// # Examples
+/// ```
+/// use hft_primitives::compare::{ReportComparison, RegressionPolicy};
+/// use hft_primitives::report::{RunMetadata, RunReport};
+/// use std::time::Duration;
+///
+/// let mut baseline = RunReport::new(RunMetadata::collect("bench", true, 1000));
+/// let mut candidate = baseline.clone();
+///
+/// let mut fast: Vec<Duration> = (0..1000).map(|i| Duration::from_nanos(100 + i % 50)).collect();
+/// let mut slow: Vec<Duration> = (0..1000).map(|i| Duration::from_nanos(150 + i % 50)).collect();
+/// baseline.add_samples("op", &mut fast);
+/// candidate.add_samples("op", &mut slow);
+///
+/// // P99 is over its threshold, and the whole distribution shifted
+/// let comparison = ReportComparison::new(&baseline, &candidate);
+/// assert_eq!(comparison.regressions(&RegressionPolicy::default()).len(), 1);
+/// let policy = RegressionPolicy {
+///     alpha: 0.01,
+///     ..RegressionPolicy::default()
+/// };
+/// assert_eq!(comparison.regressions(&policy).len(), 2);
+/// ```
+#[derive(Debug, Clone)]
+pub struct ReportComparison {
+    pub entries: Vec<EntryComparison>,
+    /// Entries present in the baseline but missing from the candidate.
+    pub missing: Vec<String>,
+    /// Entries only present in the candidate.
+    pub added: Vec<String>,
+}
+
+impl ReportComparison {
+    /// Matches entries by name and compares each pair.
+    pub fn new(baseline: &RunReport, candidate: &RunReport) -> Self {
+        let mut entries = Vec::new();
+        let mut missing = Vec::new();
+        for base in &baseline.entries {
+            match candidate.entry(&base.name) {
+                Some(cand) => entries.push(EntryComparison::between(base, cand)),
+                None => missing.push(base.name.clone()),
+            }
+        }
+        let added = candidate
+            .entries
+            .iter()
+            .filter(|cand| baseline.entry(&cand.name).is_none())
+            .map(|cand| cand.name.clone())
+            .collect();
+
+        Self {
+            entries,
+            missing,
+            added,
+        }
+    }
+
+    /// Returns every regression across all entries.
+    pub fn regressions(&self, policy: &RegressionPolicy) -> Vec<Regression> {
+        self.entries
+            .iter()
+            .flat_map(|entry| entry.regressions(policy))
+            .collect()
+    }
+
+    /// Prints a per-entry table of percentile deltas.
+    pub fn print_report(&self) {
+        for entry in &self.entries {
+            println!("=== {} ===", entry.name);
+            for delta in &entry.deltas {
+                println!(
+                    "  {:<5} {:>12?} -> {:>12?}  ({:+.1}%)",
+                    delta.percentile.name(),
+                    delta.baseline,
+                    delta.candidate,
+                    delta.relative_change() * 100.0
+                );
+            }
+            match &entry.significance {
+                Some(test) => println!(
+                    "  Mann-Whitney: z = {:.2}, p = {:.4}, P(slower) = {:.3}",
+                    test.z_score, test.p_value, test.prob_slower
+                ),
+                None => println!("  Mann-Whitney: n/a (no histograms)"),
+            }
+        }
+        for name in &self.missing {
+            println!("Missing from candidate: {}", name);
+        }
+        for name in &self.added {
+            println!("New in candidate: {}", name);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::report::RunMetadata;
+    use crate::LatencyMetrics;
+
+    fn metadata() -> RunMetadata {
+        RunMetadata {
+            binary: "test".to_string(),
+            host_cpus: 4,
+            pinned: true,
+            iterations: 0,
+            git_describe: None,
+            timestamp_unix: 0,
+        }
+    }
+
+    fn samples(base: u64, spread: u64, count: u64) -> Vec<Duration> {
+        (0..count)
+            .map(|i| Duration::from_nanos(base + (i * 7919) % spread))
+            .collect()
+    }
+
+    #[test]
+    fn test_erfc() {
+        assert!((erfc(0.0) - 1.0).abs() < 1e-6);
+        assert!((erfc(1.0) - 0.157_299_2).abs() < 1e-6);
+        assert!((erfc(-1.0) - 1.842_700_8).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_mann_whitney_identical_distributions() {
+        let a = LatencyHistogram::from_samples(&samples(100, 50, 2000));
+        let b = LatencyHistogram::from_samples(&samples(100, 50, 2000));
+
+        let test = mann_whitney(&a, &b).unwrap();
+        assert!(test.z_score.abs() < 1e-9);
+        assert!(!test.is_significant(0.05));
+        assert!((test.prob_slower - 0.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_threshold_parse() {
+        let threshold: RegressionThreshold = "p99=+10%".parse().unwrap();
+        assert_eq!(threshold.percentile, Percentile::P99);
+        assert!((threshold.max_increase - 0.10).abs() < 1e-12);
+
+        let threshold: RegressionThreshold = "p999=0.25".parse().unwrap();
+        assert_eq!(threshold.percentile, Percentile::P999);
+        assert!((threshold.max_increase - 0.25).abs() < 1e-12);
+
+        assert!("p99".parse::<RegressionThreshold>().is_err());
+        assert!("p99=fast".parse::<RegressionThreshold>().is_err());
+    }
+
+    #[test]
+    fn test_thresholds_apply_without_a_shift() {
+        // Same distribution, but a P99 blown out by a tail the bulk hides
+        let mut baseline = RunReport::new(metadata());
+        let mut candidate = RunReport::new(metadata());
+        baseline.add_samples("op", &mut samples(100, 50, 2000));
+        candidate.add_samples("op", &mut samples(100, 50, 2000));
+        candidate.entries[0].metrics.p99 = Duration::from_nanos(200);
+
+        let comparison = ReportComparison::new(&baseline, &candidate);
+        let regressions = comparison.regressions(&RegressionPolicy::default());
+        assert_eq!(regressions.len(), 1);
+        assert!(matches!(regressions[0], Regression::Threshold { .. }));
+        assert_eq!(regressions[0].entry(), "op");
+    }
+
+    #[test]
+    fn test_shift_is_one_sided() {
+        let policy = RegressionPolicy {
+            thresholds: Vec::new(),
+            alpha: 0.01,
+        };
+        let mut baseline = RunReport::new(metadata());
+        baseline.add_samples("op", &mut samples(100, 50, 2000));
+
+        // Slower: flagged even though no threshold is configured
+        let mut slower = RunReport::new(metadata());
+        slower.add_samples("op", &mut samples(110, 50, 2000));
+        let regressions = ReportComparison::new(&baseline, &slower).regressions(&policy);
+        assert_eq!(regressions.len(), 1);
+        assert!(matches!(regressions[0], Regression::Shift { .. }));
+
+        // Equally significant, but faster
+        let mut faster = RunReport::new(metadata());
+        faster.add_samples("op", &mut samples(90, 50, 2000));
+        let comparison = ReportComparison::new(&baseline, &faster);
+        assert!(comparison.entries[0]
+            .significance
+            .unwrap()
+            .is_significant(0.01));
+        assert!(comparison.regressions(&policy).is_empty());
+    }
+
+    #[test]
+    fn test_missing_and_added_entries() {
+        let mut baseline = RunReport::new(metadata());
+        let mut candidate = RunReport::new(metadata());
+        baseline.add_metrics("old", LatencyMetrics::default());
+        baseline.add_metrics("shared", LatencyMetrics::default());
+        candidate.add_metrics("shared", LatencyMetrics::default());
+        candidate.add_metrics("new", LatencyMetrics::default());
+
+        let comparison = ReportComparison::new(&baseline, &candidate);
+        assert_eq!(comparison.entries.len(), 1);
+        assert_eq!(comparison.missing, vec!["old".to_string()]);
+        assert_eq!(comparison.added, vec!["new".to_string()]);
+        assert!(comparison
+            .regressions(&RegressionPolicy::default())
+            .is_empty());
+    }
+}
@@ -2,9 +2,221 @@
 //!
 //! Enables pinning threads to specific CPU cores to improve cache locality
 //! and reduce latency variance from OS scheduling.
+//!
+//! The `try_*` functions and [`current_affinity`] report failures as
+//! [`PinError`]; [`pin_thread_to_core`] is a convenience wrapper that only
+//! prints a warning.
+
+use std::fmt;
+use std::str::FromStr;
+
+mod placement;
+mod topology;
+
+pub use placement::{Assignment, Constraint, Placement, PlacementError, PlacementPlanner};
+pub use topology::{nic_numa_node, CpuInfo, NumaNode, Topology, TopologyError};
+
+/// Number of CPUs representable in a [`CpuSet`] (glibc `CPU_SETSIZE`).
+pub const MAX_CPUS: usize = 1024;
+
+/// Reasons a thread could not be pinned or its affinity queried.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PinError {
+    /// The core ID does not fit in a `cpu_set_t`.
+    CoreOutOfRange(usize),
+    /// An empty set of cores was requested.
+    EmptySet,
+    /// The kernel rejected `syscall` with `errno` (e.g. `EINVAL` when none
+    /// of the cores is online or allowed, `ESRCH` for an unknown TID).
+    Os { syscall: &'static str, errno: i32 },
+    /// Thread affinity is not supported on this platform.
+    Unsupported,
+}
+
+impl fmt::Display for PinError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PinError::CoreOutOfRange(core) => {
+                write!(f, "core {} is out of range (max {})", core, MAX_CPUS - 1)
+            }
+            PinError::EmptySet => f.write_str("no cores requested"),
+            PinError::Os { syscall, errno } => write!(
+                f,
+                "{} failed: {}",
+                syscall,
+                std::io::Error::from_raw_os_error(*errno)
+            ),
+            PinError::Unsupported => f.write_str("CPU pinning is not supported on this platform"),
+        }
+    }
+}
+
+impl std::error::Error for PinError {}
+
+/// A set of CPU core IDs, mirroring the kernel's `cpu_set_t`.
+///
+/// # Examples
+/// ```
+/// use hft_primitives::cpu_pinning::CpuSet;
+///
+/// let set = CpuSet::from_cores([0, 1, 2, 3, 8]).unwrap();
+/// assert!(set.contains(8));
+/// assert_eq!(set.len(), 5);
+/// assert_eq!(set.to_string(), "0-3,8");
+/// ```
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CpuSet {
+    bits: [u64; MAX_CPUS / 64],
+}
+
+impl CpuSet {
+    /// Creates an empty set.
+    pub const fn new() -> Self {
+        Self {
+            bits: [0; MAX_CPUS / 64],
+        }
+    }
+
+    /// Creates a set containing `cores`.
+    pub fn from_cores<I: IntoIterator<Item = usize>>(cores: I) -> Result<Self, PinError> {
+        let mut set = Self::new();
+        for core in cores {
+            set.insert(core)?;
+        }
+        Ok(set)
+    }
+
+    /// Adds a core to the set.
+    pub fn insert(&mut self, core: usize) -> Result<(), PinError> {
+        if core >= MAX_CPUS {
+            return Err(PinError::CoreOutOfRange(core));
+        }
+        self.bits[core / 64] |= 1 << (core % 64);
+        Ok(())
+    }
+
+    /// Removes a core from the set.
+    pub fn remove(&mut self, core: usize) {
+        if core < MAX_CPUS {
+            self.bits[core / 64] &= !(1 << (core % 64));
+        }
+    }
+
+    /// Returns true if `core` is in the set.
+    pub fn contains(&self, core: usize) -> bool {
+        core < MAX_CPUS && self.bits[core / 64] & (1 << (core % 64)) != 0
+    }
+
+    /// Returns the number of cores in the set.
+    pub fn len(&self) -> usize {
+        self.bits
+            .iter()
+            .map(|word| word.count_ones() as usize)
+            .sum()
+    }
+
+    /// Returns true if the set contains no cores.
+    pub fn is_empty(&self) -> bool {
+        self.bits.iter().all(|&word| word == 0)
+    }
+
+    /// Iterates over the cores in ascending order.
+    pub fn iter(&self) -> impl Iterator<Item = usize> + '_ {
+        (0..MAX_CPUS).filter(move |&core| self.contains(core))
+    }
+
+    #[cfg(target_os = "linux")]
+    fn to_libc(self) -> libc::cpu_set_t {
+        let mut cpu_set: libc::cpu_set_t = unsafe { std::mem::zeroed() };
+        for core in self.iter() {
+            unsafe { libc::CPU_SET(core, &mut cpu_set) };
+        }
+        cpu_set
+    }
+
+    #[cfg(target_os = "linux")]
+    fn from_libc(cpu_set: &libc::cpu_set_t) -> Self {
+        let mut set = Self::new();
+        for core in 0..MAX_CPUS {
+            if unsafe { libc::CPU_ISSET(core, cpu_set) } {
+                set.bits[core / 64] |= 1 << (core % 64);
+            }
+        }
+        set
+    }
+}
+
+impl Default for CpuSet {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for CpuSet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "CpuSet({})", self)
+    }
+}
+
+/// Formats the set in the kernel's cpulist format, e.g. `0-3,8,10-11`.
+impl fmt::Display for CpuSet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut cores = self.iter().peekable();
+        let mut first = true;
+        while let Some(start) = cores.next() {
+            let mut end = start;
+            while cores.peek() == Some(&(end + 1)) {
+                end = cores.next().unwrap();
+            }
+            if !first {
+                f.write_str(",")?;
+            }
+            first = false;
+            if start == end {
+                write!(f, "{}", start)?;
+            } else {
+                write!(f, "{}-{}", start, end)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for CpuSet {
+    type Err = String;
+
+    /// Parses the kernel's cpulist format (`0-3,8,10-11`); an empty string
+    /// is the empty set.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut set = CpuSet::new();
+        for part in s.trim().split(',').filter(|part| !part.is_empty()) {
+            let parse = |value: &str| {
+                value
+                    .trim()
+                    .parse::<usize>()
+                    .map_err(|_| format!("invalid CPU list '{}'", s.trim()))
+            };
+            let (start, end) = match part.split_once('-') {
+                Some((start, end)) => (parse(start)?, parse(end)?),
+                None => (parse(part)?, parse(part)?),
+            };
+            if start > end {
+                return Err(format!("invalid CPU range '{}'", part));
+            }
+            for core in start..=end {
+                set.insert(core).map_err(|e| e.to_string())?;
+            }
+        }
+        Ok(set)
+    }
+}
 
 /// Pins the current thread to a specific CPU core.
 ///
+/// Convenience wrapper around [`try_pin_thread_to_core`] that prints a
+/// warning instead of returning an error, for benchmarks that should still
+/// run (unpinned) when pinning fails.
+///
 /// This function uses platform-specific APIs to set thread affinity:
 /// - Linux: `sched_setaffinity()`
 /// - Other platforms: No-op (prints warning)
@@ -37,27 +249,107 @@
 /// # Platform Support
 /// - ✅ Linux (via libc)
 /// - ⚠️ macOS/Windows: No-op with warning
-#[cfg(target_os = "linux")]
 pub fn pin_thread_to_core(core_id: usize) {
-    use libc::{cpu_set_t, sched_setaffinity, CPU_SET};
-    use std::mem;
-
-    let mut cpu_set: cpu_set_t = unsafe { mem::zeroed() };
-    unsafe {
-        CPU_SET(core_id, &mut cpu_set);
-        let result = sched_setaffinity(0, mem::size_of::<cpu_set_t>(), &cpu_set);
-        if result != 0 {
-            eprintln!("Warning: Failed to pin thread to core {}", core_id);
-        }
+    if let Err(e) = try_pin_thread_to_core(core_id) {
+        eprintln!("Warning: Failed to pin thread to core {}: {}", core_id, e);
     }
 }
 
+/// Pins the current thread to a specific CPU core.
+///
+/// # Errors
+/// - [`PinError::CoreOutOfRange`] if `core_id >= MAX_CPUS`
+/// - [`PinError::Os`] if the kernel rejects the core (e.g. offline, or
+///   outside the process's cgroup cpuset)
+/// - [`PinError::Unsupported`] on non-Linux platforms
+///
+/// # Examples
+/// ```no_run
+/// use hft_primitives::cpu_pinning::try_pin_thread_to_core;
+///
+/// if let Err(e) = try_pin_thread_to_core(3) {
+///     eprintln!("running unpinned: {}", e);
+/// }
+/// ```
+pub fn try_pin_thread_to_core(core_id: usize) -> Result<(), PinError> {
+    pin_thread_to_cores(&CpuSet::from_cores([core_id])?)
+}
+
+/// Restricts the current thread to the given set of cores.
+pub fn pin_thread_to_cores(cores: &CpuSet) -> Result<(), PinError> {
+    set_affinity(0, cores)
+}
+
+/// Restricts another thread, identified by its kernel TID, to `cores`.
+///
+/// Useful for pinning threads the caller does not control (e.g. a
+/// runtime's I/O thread that reported its TID via [`current_thread_id`]).
+pub fn pin_thread_by_tid(tid: i32, cores: &CpuSet) -> Result<(), PinError> {
+    set_affinity(tid, cores)
+}
+
+/// Returns the set of cores the current thread may run on.
+pub fn current_affinity() -> Result<CpuSet, PinError> {
+    affinity_of_tid(0)
+}
+
+/// Returns the set of cores the thread with kernel TID `tid` may run on.
+#[cfg(target_os = "linux")]
+pub fn affinity_of_tid(tid: i32) -> Result<CpuSet, PinError> {
+    let mut cpu_set: libc::cpu_set_t = unsafe { std::mem::zeroed() };
+    let result = unsafe {
+        libc::sched_getaffinity(tid, std::mem::size_of::<libc::cpu_set_t>(), &mut cpu_set)
+    };
+    if result != 0 {
+        return Err(last_os_error("sched_getaffinity"));
+    }
+    Ok(CpuSet::from_libc(&cpu_set))
+}
+
 #[cfg(not(target_os = "linux"))]
-pub fn pin_thread_to_core(core_id: usize) {
-    eprintln!(
-        "Warning: CPU pinning not supported on this platform (requested core {})",
-        core_id
-    );
+pub fn affinity_of_tid(_tid: i32) -> Result<CpuSet, PinError> {
+    Err(PinError::Unsupported)
+}
+
+/// Returns the kernel thread ID of the calling thread.
+#[cfg(target_os = "linux")]
+pub fn current_thread_id() -> i32 {
+    unsafe { libc::gettid() }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn current_thread_id() -> i32 {
+    0
+}
+
+#[cfg(target_os = "linux")]
+fn set_affinity(tid: i32, cores: &CpuSet) -> Result<(), PinError> {
+    if cores.is_empty() {
+        return Err(PinError::EmptySet);
+    }
+    let cpu_set = cores.to_libc();
+    let result =
+        unsafe { libc::sched_setaffinity(tid, std::mem::size_of::<libc::cpu_set_t>(), &cpu_set) };
+    if result != 0 {
+        return Err(last_os_error("sched_setaffinity"));
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn set_affinity(_tid: i32, cores: &CpuSet) -> Result<(), PinError> {
+    if cores.is_empty() {
+        return Err(PinError::EmptySet);
+    }
+    Err(PinError::Unsupported)
+}
+
+#[cfg(target_os = "linux")]
+fn last_os_error(syscall: &'static str) -> PinError {
+    PinError::Os {
+        syscall,
+        errno: std::io::Error::last_os_error().raw_os_error().unwrap_or(0),
+    }
 }
 
 /// Returns the number of available CPU cores.
@@ -91,4 +383,79 @@ mod tests {
         // Just ensure it doesn't panic
         pin_thread_to_core(0);
     }
+
+    #[test]
+    fn test_cpu_set() {
+        let mut set = CpuSet::from_cores([5, 0, 1, 2, 7, 8]).unwrap();
+        assert_eq!(set.len(), 6);
+        assert_eq!(set.iter().collect::<Vec<_>>(), vec![0, 1, 2, 5, 7, 8]);
+        assert_eq!(set.to_string(), "0-2,5,7-8");
+
+        set.remove(1);
+        assert!(!set.contains(1));
+        assert_eq!(set.to_string(), "0,2,5,7-8");
+
+        assert_eq!(
+            set.insert(MAX_CPUS),
+            Err(PinError::CoreOutOfRange(MAX_CPUS))
+        );
+        assert!(CpuSet::new().is_empty());
+        assert_eq!(CpuSet::new().to_string(), "");
+    }
+
+    #[test]
+    fn test_parse_cpu_list() {
+        let set: CpuSet = "0-2,5,7-8\n".parse().unwrap();
+        assert_eq!(set.iter().collect::<Vec<_>>(), vec![0, 1, 2, 5, 7, 8]);
+        assert_eq!(set.to_string().parse::<CpuSet>().unwrap(), set);
+        assert!("".parse::<CpuSet>().unwrap().is_empty());
+
+        assert!("3-1".parse::<CpuSet>().is_err());
+        assert!("a,b".parse::<CpuSet>().is_err());
+        assert!("0-4096".parse::<CpuSet>().is_err());
+    }
+
+    #[test]
+    fn test_invalid_requests() {
+        assert_eq!(
+            try_pin_thread_to_core(MAX_CPUS + 1),
+            Err(PinError::CoreOutOfRange(MAX_CPUS + 1))
+        );
+        assert_eq!(pin_thread_to_cores(&CpuSet::new()), Err(PinError::EmptySet));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_pin_and_query_affinity() {
+        // Run on a fresh thread so the test harness thread keeps its affinity
+        std::thread::spawn(|| {
+            let allowed = current_affinity().unwrap();
+            assert!(!allowed.is_empty());
+
+            let core = allowed.iter().next().unwrap();
+            try_pin_thread_to_core(core).unwrap();
+            assert_eq!(
+                current_affinity().unwrap(),
+                CpuSet::from_cores([core]).unwrap()
+            );
+
+            // Same thread addressed by TID
+            let tid = current_thread_id();
+            pin_thread_by_tid(tid, &allowed).unwrap();
+            assert_eq!(affinity_of_tid(tid).unwrap(), allowed);
+
+            // Errors name the failing syscall
+            let err = affinity_of_tid(i32::MAX).unwrap_err();
+            assert!(matches!(
+                err,
+                PinError::Os {
+                    syscall: "sched_getaffinity",
+                    ..
+                }
+            ));
+            assert!(err.to_string().starts_with("sched_getaffinity failed"));
+        })
+        .join()
+        .unwrap();
+    }
 }
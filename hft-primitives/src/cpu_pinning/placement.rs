@@ -0,0 +1,461 @@
+//! Topology-aware core assignment for thread-per-core pipelines.
+//!
+//! Pinning worker `i` to core `i` puts the first hot thread on CPU 0 (which
+//! usually services most interrupts and timers) and can land two hot threads
+//! on SMT siblings of one physical core. [`PlacementPlanner`] assigns named
+//! roles to cores from a [`Topology`] under a set of [`Constraint`]s, and
+//! explains which constraint ran out of cores when no assignment exists.
+
+use super::topology::Topology;
+use super::{try_pin_thread_to_core, CpuSet, PinError};
+use std::fmt;
+
+/// A rule the planner applies to every role.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Constraint {
+    /// No two roles share a physical core (hard).
+    NoSmtSharing,
+    /// Every role runs on this NUMA node, e.g. the NIC's (hard).
+    OnNode(usize),
+    /// Never use these CPUs, e.g. housekeeping cores (hard).
+    Avoid(CpuSet),
+    /// Use `isolcpus` cores before others (soft).
+    PreferIsolated,
+}
+
+impl fmt::Display for Constraint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Constraint::NoSmtSharing => f.write_str("no SMT sharing"),
+            Constraint::OnNode(node) => write!(f, "on NUMA node {}", node),
+            Constraint::Avoid(cpus) => write!(f, "avoid CPUs {}", cpus),
+            Constraint::PreferIsolated => f.write_str("prefer isolated cores"),
+        }
+    }
+}
+
+/// Reasons no assignment could be produced.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PlacementError {
+    /// No roles were requested.
+    NoRoles,
+    /// Two roles share a name.
+    DuplicateRole(String),
+    /// `OnNode` names a node the topology does not have.
+    UnknownNode(usize),
+    /// The constraints leave fewer usable cores than roles.
+    ///
+    /// `steps` lists how many CPUs remained after each hard constraint.
+    InsufficientCores {
+        requested: usize,
+        available: usize,
+        steps: Vec<String>,
+    },
+}
+
+impl fmt::Display for PlacementError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PlacementError::NoRoles => f.write_str("no roles to place"),
+            PlacementError::DuplicateRole(name) => write!(f, "role '{}' requested twice", name),
+            PlacementError::UnknownNode(node) => write!(f, "NUMA node {} does not exist", node),
+            PlacementError::InsufficientCores {
+                requested,
+                available,
+                steps,
+            } => write!(
+                f,
+                "cannot place {} role(s) on {} usable core(s): {}",
+                requested,
+                available,
+                steps.join(" -> ")
+            ),
+        }
+    }
+}
+
+impl std::error::Error for PlacementError {}
+
+/// One role's assigned CPU.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Assignment {
+    pub role: String,
+    pub cpu: usize,
+    pub node: usize,
+    pub isolated: bool,
+}
+
+/// Result of [`PlacementPlanner::plan`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Placement {
+    assignments: Vec<Assignment>,
+    warnings: Vec<String>,
+}
+
+impl Placement {
+    /// Returns the CPU assigned to `role`.
+    pub fn cpu(&self, role: &str) -> Option<usize> {
+        self.assignments
+            .iter()
+            .find(|assignment| assignment.role == role)
+            .map(|assignment| assignment.cpu)
+    }
+
+    /// Returns the assignments in the order roles were added.
+    pub fn assignments(&self) -> &[Assignment] {
+        &self.assignments
+    }
+
+    /// Returns soft preferences that could not be met.
+    pub fn warnings(&self) -> &[String] {
+        &self.warnings
+    }
+
+    /// Pins the calling thread to the CPU assigned to `role`.
+    ///
+    /// # Panics
+    /// Panics if `role` was not part of the plan.
+    pub fn pin_current_thread(&self, role: &str) -> Result<(), PinError> {
+        let cpu = self
+            .cpu(role)
+            .unwrap_or_else(|| panic!("role '{}' is not in the placement", role));
+        try_pin_thread_to_core(cpu)
+    }
+}
+
+impl fmt::Display for Placement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for assignment in &self.assignments {
+            writeln!(
+                f,
+                "  {:<16} CPU {:>3} (node {}){}",
+                assignment.role,
+                assignment.cpu,
+                assignment.node,
+                if assignment.isolated {
+                    ", isolated"
+                } else {
+                    ""
+                }
+            )?;
+        }
+        for warning in &self.warnings {
+            writeln!(f, "  warning: {}", warning)?;
+        }
+        Ok(())
+    }
+}
+
+/// Assigns named roles to CPUs.
+///
+/// Roles are placed in the order they are added, so the most
+/// latency-critical role should come first. Among the CPUs left by the hard
+/// constraints, the planner prefers isolated cores (with
+/// [`Constraint::PreferIsolated`]), then `nohz_full` cores, and uses CPU 0
+/// last.
+///
+/// # Examples
+/// ```no_run
+/// use hft_primitives::cpu_pinning::{PlacementPlanner, Topology};
+///
+/// let topology = Topology::detect().unwrap();
+/// let placement = PlacementPlanner::new(&topology)
+///     .role("receiver")
+///     .role("consumer")
+///     .no_smt_sharing()
+///     .prefer_isolated()
+///     .plan();
+///
+/// match placement {
+///     Ok(placement) => print!("{}", placement),
+///     Err(e) => eprintln!("{}", e), // says which constraint ran out of cores
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct PlacementPlanner<'a> {
+    topology: &'a Topology,
+    roles: Vec<String>,
+    constraints: Vec<Constraint>,
+    allowed: Option<CpuSet>,
+}
+
+impl<'a> PlacementPlanner<'a> {
+    /// Creates a planner over every online CPU of `topology`.
+    pub fn new(topology: &'a Topology) -> Self {
+        Self {
+            topology,
+            roles: Vec::new(),
+            constraints: Vec::new(),
+            allowed: None,
+        }
+    }
+
+    /// Adds a role needing one dedicated CPU.
+    pub fn role<S: Into<String>>(mut self, name: S) -> Self {
+        self.roles.push(name.into());
+        self
+    }
+
+    /// Adds a constraint.
+    pub fn constraint(mut self, constraint: Constraint) -> Self {
+        self.constraints.push(constraint);
+        self
+    }
+
+    /// Shorthand for [`Constraint::NoSmtSharing`].
+    pub fn no_smt_sharing(self) -> Self {
+        self.constraint(Constraint::NoSmtSharing)
+    }
+
+    /// Shorthand for [`Constraint::OnNode`].
+    pub fn on_node(self, node: usize) -> Self {
+        self.constraint(Constraint::OnNode(node))
+    }
+
+    /// Shorthand for [`Constraint::Avoid`].
+    pub fn avoid(self, cpus: CpuSet) -> Self {
+        self.constraint(Constraint::Avoid(cpus))
+    }
+
+    /// Shorthand for [`Constraint::PreferIsolated`].
+    pub fn prefer_isolated(self) -> Self {
+        self.constraint(Constraint::PreferIsolated)
+    }
+
+    /// Restricts placement to `cpus`, e.g. the process's
+    /// [`current_affinity`](super::current_affinity) under a cgroup cpuset.
+    pub fn within(mut self, cpus: CpuSet) -> Self {
+        self.allowed = Some(cpus);
+        self
+    }
+
+    /// Computes the assignment.
+    pub fn plan(&self) -> Result<Placement, PlacementError> {
+        if self.roles.is_empty() {
+            return Err(PlacementError::NoRoles);
+        }
+        for (i, role) in self.roles.iter().enumerate() {
+            if self.roles[..i].contains(role) {
+                return Err(PlacementError::DuplicateRole(role.clone()));
+            }
+        }
+
+        let topology = self.topology;
+        let mut candidates = *topology.online();
+        let mut steps = vec![format!("{} online", candidates.len())];
+
+        if let Some(allowed) = &self.allowed {
+            candidates = intersect(&candidates, allowed);
+            steps.push(format!("{} within allowed {}", candidates.len(), allowed));
+        }
+        for constraint in &self.constraints {
+            match constraint {
+                Constraint::Avoid(cpus) => {
+                    for cpu in cpus.iter() {
+                        candidates.remove(cpu);
+                    }
+                    steps.push(format!("{} after avoiding {}", candidates.len(), cpus));
+                }
+                Constraint::OnNode(node) => {
+                    let node_cpus = topology
+                        .nodes()
+                        .iter()
+                        .find(|n| n.id == *node)
+                        .map(|n| n.cpus)
+                        .ok_or(PlacementError::UnknownNode(*node))?;
+                    candidates = intersect(&candidates, &node_cpus);
+                    steps.push(format!("{} on node {}", candidates.len(), node));
+                }
+                Constraint::NoSmtSharing | Constraint::PreferIsolated => {}
+            }
+        }
+
+        let no_smt_sharing = self.constraints.contains(&Constraint::NoSmtSharing);
+        let prefer_isolated = self.constraints.contains(&Constraint::PreferIsolated);
+
+        let mut ranked: Vec<usize> = candidates.iter().collect();
+        ranked.sort_by_key(|&cpu| {
+            (
+                !(prefer_isolated && topology.isolated().contains(cpu)),
+                !topology.nohz_full().contains(cpu),
+                cpu == 0,
+                cpu,
+            )
+        });
+
+        let mut used = CpuSet::new();
+        let mut chosen = Vec::with_capacity(self.roles.len());
+        for cpu in ranked {
+            if chosen.len() == self.roles.len() {
+                break;
+            }
+            let siblings = topology
+                .siblings(cpu)
+                .copied()
+                .unwrap_or_else(|| CpuSet::from_cores([cpu]).expect("online CPU ids fit"));
+            if no_smt_sharing && siblings.iter().any(|sibling| used.contains(sibling)) {
+                continue;
+            }
+            used.insert(cpu).expect("online CPU ids fit");
+            chosen.push(cpu);
+        }
+
+        if chosen.len() < self.roles.len() {
+            if no_smt_sharing {
+                steps.push(format!(
+                    "{} physical cores without SMT sharing",
+                    chosen.len()
+                ));
+            }
+            return Err(PlacementError::InsufficientCores {
+                requested: self.roles.len(),
+                available: chosen.len(),
+                steps,
+            });
+        }
+
+        let assignments: Vec<Assignment> = self
+            .roles
+            .iter()
+            .zip(chosen)
+            .map(|(role, cpu)| Assignment {
+                role: role.clone(),
+                cpu,
+                node: topology.node_of(cpu).unwrap_or(0),
+                isolated: topology.isolated().contains(cpu),
+            })
+            .collect();
+
+        let mut warnings = Vec::new();
+        if prefer_isolated {
+            for assignment in assignments.iter().filter(|a| !a.isolated) {
+                warnings.push(format!(
+                    "role '{}' is on non-isolated CPU {}",
+                    assignment.role, assignment.cpu
+                ));
+            }
+        }
+        if let Some(assignment) = assignments.iter().find(|a| a.cpu == 0) {
+            warnings.push(format!(
+                "role '{}' shares CPU 0 with most interrupts and timers",
+                assignment.role
+            ));
+        }
+
+        Ok(Placement {
+            assignments,
+            warnings,
+        })
+    }
+}
+
+fn intersect(a: &CpuSet, b: &CpuSet) -> CpuSet {
+    CpuSet::from_cores(a.iter().filter(|&cpu| b.contains(cpu))).expect("subset of a valid set")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    fn fixture(name: &str) -> Topology {
+        Topology::from_sysfs(
+            Path::new(env!("CARGO_MANIFEST_DIR"))
+                .join("tests/fixtures/sysfs")
+                .join(name),
+        )
+        .unwrap()
+    }
+
+    fn set(list: &str) -> CpuSet {
+        list.parse().unwrap()
+    }
+
+    #[test]
+    fn test_prefers_isolated_and_avoids_cpu0() {
+        // Two sockets x two cores x SMT2; CPU 3 isolated, CPU 7 offline
+        let topology = fixture("dual-socket-smt");
+        let placement = PlacementPlanner::new(&topology)
+            .role("receiver")
+            .role("consumer")
+            .role("logger")
+            .no_smt_sharing()
+            .prefer_isolated()
+            .plan()
+            .unwrap();
+
+        assert_eq!(placement.cpu("receiver"), Some(3));
+        assert!(placement.assignments()[0].isolated);
+        // Remaining cores: CPU 0 last, and never a sibling of a used CPU
+        assert_eq!(placement.cpu("consumer"), Some(1));
+        assert_eq!(placement.cpu("logger"), Some(2));
+        assert_eq!(placement.warnings().len(), 2);
+        assert!(placement.to_string().contains("receiver"));
+    }
+
+    #[test]
+    fn test_node_constraint_explains_shortage() {
+        let topology = fixture("dual-socket-smt");
+        let err = PlacementPlanner::new(&topology)
+            .role("a")
+            .role("b")
+            .role("c")
+            .on_node(0)
+            .no_smt_sharing()
+            .plan()
+            .unwrap_err();
+
+        match &err {
+            PlacementError::InsufficientCores {
+                requested,
+                available,
+                steps,
+            } => {
+                assert_eq!((*requested, *available), (3, 2));
+                assert_eq!(steps[1], "4 on node 0");
+            }
+            other => panic!("unexpected error {:?}", other),
+        }
+        assert!(err
+            .to_string()
+            .contains("2 physical cores without SMT sharing"));
+
+        // Without the SMT constraint the node's four threads suffice
+        let placement = PlacementPlanner::new(&topology)
+            .role("a")
+            .role("b")
+            .role("c")
+            .on_node(0)
+            .plan()
+            .unwrap();
+        assert!(placement
+            .assignments()
+            .iter()
+            .all(|a| set("0-1,4-5").contains(a.cpu)));
+        assert_eq!(placement.cpu("c"), Some(5));
+    }
+
+    #[test]
+    fn test_invalid_requests() {
+        let topology = fixture("single-socket");
+        let planner = PlacementPlanner::new(&topology);
+        assert_eq!(planner.plan(), Err(PlacementError::NoRoles));
+        assert_eq!(
+            planner.clone().role("a").role("a").plan(),
+            Err(PlacementError::DuplicateRole("a".to_string()))
+        );
+        assert_eq!(
+            planner.clone().role("a").on_node(3).plan(),
+            Err(PlacementError::UnknownNode(3))
+        );
+
+        let placement = planner
+            .role("a")
+            .avoid(set("0"))
+            .within(set("0-2"))
+            .plan()
+            .unwrap();
+        assert_eq!(placement.cpu("a"), Some(1));
+        assert!(placement.warnings().is_empty());
+    }
+}
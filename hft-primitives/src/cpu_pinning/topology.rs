@@ -0,0 +1,459 @@
+//! CPU topology discovery from sysfs.
+//!
+//! `available_parallelism()` counts logical CPUs but cannot tell SMT
+//! siblings, physical cores, sockets or NUMA nodes apart. [`Topology`]
+//! reads `/sys/devices/system/cpu` and `/sys/devices/system/node` so
+//! placement code can keep hot threads off shared cores and on the right
+//! node.
+
+use super::CpuSet;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Location of one online logical CPU.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CpuInfo {
+    /// Logical CPU number (as used by `sched_setaffinity`).
+    pub id: usize,
+    /// Socket (`physical_package_id`).
+    pub package: usize,
+    /// Physical core within the socket (`core_id`).
+    pub core: usize,
+    /// NUMA node the CPU belongs to.
+    pub node: usize,
+    /// Online logical CPUs sharing this physical core, including `id`.
+    pub siblings: CpuSet,
+}
+
+/// A NUMA node and the CPUs attached to it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NumaNode {
+    pub id: usize,
+    pub cpus: CpuSet,
+    /// ACPI SLIT distances to every node, indexed by node ID
+    /// (10 = local). Empty if the kernel does not report them.
+    pub distances: Vec<u32>,
+}
+
+/// Errors reading or parsing the sysfs topology tree.
+#[derive(Debug)]
+pub enum TopologyError {
+    /// A required file could not be read.
+    Io { path: PathBuf, source: io::Error },
+    /// A file did not contain the expected format.
+    Parse { path: PathBuf, message: String },
+}
+
+impl fmt::Display for TopologyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TopologyError::Io { path, source } => {
+                write!(f, "failed to read {}: {}", path.display(), source)
+            }
+            TopologyError::Parse { path, message } => {
+                write!(f, "failed to parse {}: {}", path.display(), message)
+            }
+        }
+    }
+}
+
+impl std::error::Error for TopologyError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            TopologyError::Io { source, .. } => Some(source),
+            TopologyError::Parse { .. } => None,
+        }
+    }
+}
+
+/// CPU, core, socket and NUMA layout of the machine.
+///
+/// Kernels built without NUMA support have no `/sys/devices/system/node`;
+/// the topology then reports a single node 0 holding every online CPU.
+///
+/// # Examples
+/// ```no_run
+/// use hft_primitives::cpu_pinning::Topology;
+///
+/// let topology = Topology::detect().unwrap();
+/// println!("{} CPUs on {} physical cores", topology.online().len(), topology.physical_cores().len());
+/// for node in topology.nodes() {
+///     println!("node {}: {}", node.id, node.cpus);
+/// }
+/// println!("isolated: {}", topology.isolated());
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Topology {
+    cpus: Vec<CpuInfo>,
+    nodes: Vec<NumaNode>,
+    online: CpuSet,
+    isolated: CpuSet,
+    nohz_full: CpuSet,
+}
+
+impl Topology {
+    /// Reads the topology of the running system from `/sys`.
+    pub fn detect() -> Result<Self, TopologyError> {
+        Self::from_sysfs("/sys")
+    }
+
+    /// Reads the topology from a sysfs tree mounted at `root`.
+    ///
+    /// `root` is the directory containing `devices/`, so tests can point it
+    /// at a fixture tree.
+    pub fn from_sysfs<P: AsRef<Path>>(root: P) -> Result<Self, TopologyError> {
+        let system = root.as_ref().join("devices/system");
+        let cpu_dir = system.join("cpu");
+
+        let online = read_cpu_list(&cpu_dir.join("online"))?;
+        // Both files are absent on kernels without the feature; nohz_full
+        // reads "(null)" when the feature is built but not configured
+        let isolated = read_optional_cpu_list(&cpu_dir.join("isolated"))?;
+        let nohz_full = read_optional_cpu_list(&cpu_dir.join("nohz_full"))?;
+
+        let nodes = read_nodes(&system.join("node"), &online)?;
+
+        let mut cpus = Vec::with_capacity(online.len());
+        for id in online.iter() {
+            let topology = cpu_dir.join(format!("cpu{}/topology", id));
+            let node = nodes
+                .iter()
+                .find(|node| node.cpus.contains(id))
+                .map_or(0, |node| node.id);
+            let mut siblings = read_cpu_list(&topology.join("thread_siblings_list"))?;
+            siblings.insert(id).expect("online CPU ids fit in a CpuSet");
+            cpus.push(CpuInfo {
+                id,
+                package: read_number(&topology.join("physical_package_id"))?,
+                core: read_number(&topology.join("core_id"))?,
+                node,
+                siblings,
+            });
+        }
+
+        Ok(Self {
+            cpus,
+            nodes,
+            online,
+            isolated,
+            nohz_full,
+        })
+    }
+
+    /// Returns every online CPU in ascending order.
+    pub fn cpus(&self) -> &[CpuInfo] {
+        &self.cpus
+    }
+
+    /// Returns the details of one online CPU.
+    pub fn cpu(&self, id: usize) -> Option<&CpuInfo> {
+        self.cpus.iter().find(|cpu| cpu.id == id)
+    }
+
+    /// Returns the NUMA nodes in ascending order.
+    pub fn nodes(&self) -> &[NumaNode] {
+        &self.nodes
+    }
+
+    /// Returns the NUMA node of an online CPU.
+    pub fn node_of(&self, cpu: usize) -> Option<usize> {
+        self.cpu(cpu).map(|cpu| cpu.node)
+    }
+
+    /// Returns the set of online CPUs.
+    pub fn online(&self) -> &CpuSet {
+        &self.online
+    }
+
+    /// Returns CPUs removed from the scheduler with `isolcpus=`.
+    pub fn isolated(&self) -> &CpuSet {
+        &self.isolated
+    }
+
+    /// Returns CPUs running tickless with `nohz_full=`.
+    pub fn nohz_full(&self) -> &CpuSet {
+        &self.nohz_full
+    }
+
+    /// Returns the online SMT siblings of `cpu`, including `cpu` itself.
+    pub fn siblings(&self, cpu: usize) -> Option<&CpuSet> {
+        self.cpu(cpu).map(|cpu| &cpu.siblings)
+    }
+
+    /// Returns one set of logical CPUs per physical core, ordered by the
+    /// lowest CPU number in each.
+    pub fn physical_cores(&self) -> Vec<CpuSet> {
+        let mut cores: Vec<CpuSet> = Vec::new();
+        for cpu in &self.cpus {
+            if !cores.iter().any(|core| core.contains(cpu.id)) {
+                cores.push(cpu.siblings);
+            }
+        }
+        cores
+    }
+
+    /// Returns the socket IDs in ascending order.
+    pub fn packages(&self) -> Vec<usize> {
+        let mut packages: Vec<usize> = self.cpus.iter().map(|cpu| cpu.package).collect();
+        packages.sort_unstable();
+        packages.dedup();
+        packages
+    }
+
+    /// Returns true if any physical core runs more than one online thread.
+    pub fn has_smt(&self) -> bool {
+        self.cpus.iter().any(|cpu| cpu.siblings.len() > 1)
+    }
+}
+
+impl fmt::Display for Topology {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{} CPUs, {} physical cores, {} socket(s), {} NUMA node(s)",
+            self.online.len(),
+            self.physical_cores().len(),
+            self.packages().len(),
+            self.nodes.len()
+        )?;
+        for node in &self.nodes {
+            writeln!(f, "  node {}: {}", node.id, node.cpus)?;
+        }
+        writeln!(f, "  isolated: {}", display_or_none(&self.isolated))?;
+        write!(f, "  nohz_full: {}", display_or_none(&self.nohz_full))
+    }
+}
+
+/// Returns the NUMA node of a network interface's PCI device, read from
+/// `<root>/class/net/<interface>/device/numa_node`.
+///
+/// Returns `None` for virtual interfaces (no `device`) and devices without
+/// NUMA affinity (the kernel reports `-1`).
+///
+/// # Examples
+/// ```no_run
+/// use hft_primitives::cpu_pinning::{nic_numa_node, PlacementPlanner, Topology};
+///
+/// let topology = Topology::detect().unwrap();
+/// let mut planner = PlacementPlanner::new(&topology).role("receiver");
+/// if let Some(node) = nic_numa_node("/sys", "eth0").unwrap() {
+///     planner = planner.on_node(node);
+/// }
+/// ```
+pub fn nic_numa_node<P: AsRef<Path>>(
+    root: P,
+    interface: &str,
+) -> Result<Option<usize>, TopologyError> {
+    let path = root
+        .as_ref()
+        .join("class/net")
+        .join(interface)
+        .join("device/numa_node");
+    let Some(contents) = read_optional(&path)? else {
+        return Ok(None);
+    };
+    match contents.trim().parse::<i64>() {
+        Ok(node) => Ok(usize::try_from(node).ok()),
+        Err(_) => Err(TopologyError::Parse {
+            path,
+            message: format!("expected a node number, got '{}'", contents.trim()),
+        }),
+    }
+}
+
+fn display_or_none(set: &CpuSet) -> String {
+    if set.is_empty() {
+        "none".to_string()
+    } else {
+        set.to_string()
+    }
+}
+
+/// Reads `node/nodeN/{cpulist,distance}`, or synthesizes node 0 when the
+/// kernel has no NUMA directory.
+fn read_nodes(node_dir: &Path, online: &CpuSet) -> Result<Vec<NumaNode>, TopologyError> {
+    let entries = match fs::read_dir(node_dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            return Ok(vec![NumaNode {
+                id: 0,
+                cpus: *online,
+                distances: Vec::new(),
+            }]);
+        }
+        Err(source) => {
+            return Err(TopologyError::Io {
+                path: node_dir.to_path_buf(),
+                source,
+            })
+        }
+    };
+
+    let mut nodes = Vec::new();
+    for entry in entries {
+        let entry = entry.map_err(|source| TopologyError::Io {
+            path: node_dir.to_path_buf(),
+            source,
+        })?;
+        let name = entry.file_name();
+        let Some(id) = name
+            .to_str()
+            .and_then(|name| name.strip_prefix("node"))
+            .and_then(|id| id.parse::<usize>().ok())
+        else {
+            continue;
+        };
+
+        let path = entry.path();
+        let distance_path = path.join("distance");
+        let distances = match read_optional(&distance_path)? {
+            Some(contents) => contents
+                .split_whitespace()
+                .map(|d| d.parse::<u32>())
+                .collect::<Result<_, _>>()
+                .map_err(|_| TopologyError::Parse {
+                    path: distance_path,
+                    message: format!("invalid distances '{}'", contents.trim()),
+                })?,
+            None => Vec::new(),
+        };
+
+        nodes.push(NumaNode {
+            id,
+            cpus: read_cpu_list(&path.join("cpulist"))?,
+            distances,
+        });
+    }
+    nodes.sort_by_key(|node| node.id);
+    Ok(nodes)
+}
+
+fn read(path: &Path) -> Result<String, TopologyError> {
+    fs::read_to_string(path).map_err(|source| TopologyError::Io {
+        path: path.to_path_buf(),
+        source,
+    })
+}
+
+fn read_optional(path: &Path) -> Result<Option<String>, TopologyError> {
+    match fs::read_to_string(path) {
+        Ok(contents) => Ok(Some(contents)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(source) => Err(TopologyError::Io {
+            path: path.to_path_buf(),
+            source,
+        }),
+    }
+}
+
+fn read_number(path: &Path) -> Result<usize, TopologyError> {
+    let contents = read(path)?;
+    contents.trim().parse().map_err(|_| TopologyError::Parse {
+        path: path.to_path_buf(),
+        message: format!("expected a number, got '{}'", contents.trim()),
+    })
+}
+
+fn read_cpu_list(path: &Path) -> Result<CpuSet, TopologyError> {
+    parse_cpu_list(path, &read(path)?)
+}
+
+fn read_optional_cpu_list(path: &Path) -> Result<CpuSet, TopologyError> {
+    match read_optional(path)? {
+        Some(contents) if contents.trim() != "(null)" => parse_cpu_list(path, &contents),
+        _ => Ok(CpuSet::new()),
+    }
+}
+
+fn parse_cpu_list(path: &Path, contents: &str) -> Result<CpuSet, TopologyError> {
+    contents.parse().map_err(|message| TopologyError::Parse {
+        path: path.to_path_buf(),
+        message,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture(name: &str) -> Topology {
+        let root = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures/sysfs")
+            .join(name);
+        Topology::from_sysfs(root).unwrap()
+    }
+
+    fn set(list: &str) -> CpuSet {
+        list.parse().unwrap()
+    }
+
+    #[test]
+    fn test_dual_socket_smt() {
+        let topology = fixture("dual-socket-smt");
+
+        // cpu7 is offline and therefore absent
+        assert_eq!(topology.online(), &set("0-6"));
+        assert_eq!(topology.cpus().len(), 7);
+        assert!(topology.cpu(7).is_none());
+
+        assert_eq!(topology.packages(), vec![0, 1]);
+        assert!(topology.has_smt());
+        assert_eq!(topology.siblings(1), Some(&set("1,5")));
+        assert_eq!(topology.siblings(3), Some(&set("3")));
+        assert_eq!(
+            topology.physical_cores(),
+            vec![set("0,4"), set("1,5"), set("2,6"), set("3")]
+        );
+
+        let cpu6 = topology.cpu(6).unwrap();
+        assert_eq!((cpu6.package, cpu6.core, cpu6.node), (1, 0, 1));
+
+        assert_eq!(topology.nodes().len(), 2);
+        assert_eq!(topology.nodes()[0].cpus, set("0-1,4-5"));
+        assert_eq!(topology.nodes()[1].distances, vec![21, 10]);
+        assert_eq!(topology.node_of(4), Some(0));
+
+        assert_eq!(topology.isolated(), &set("3,7"));
+        assert_eq!(topology.nohz_full(), &set("3,7"));
+    }
+
+    #[test]
+    fn test_single_socket_without_numa() {
+        let topology = fixture("single-socket");
+
+        assert_eq!(topology.online(), &set("0-3"));
+        assert!(!topology.has_smt());
+        assert_eq!(topology.physical_cores().len(), 4);
+
+        // No node directory: one synthetic node with every CPU
+        assert_eq!(topology.nodes().len(), 1);
+        assert_eq!(topology.nodes()[0].cpus, set("0-3"));
+        assert_eq!(topology.node_of(2), Some(0));
+
+        assert!(topology.isolated().is_empty());
+        assert!(topology.nohz_full().is_empty());
+
+        let summary = topology.to_string();
+        assert!(summary.starts_with("4 CPUs, 4 physical cores, 1 socket(s), 1 NUMA node(s)"));
+        assert!(summary.contains("isolated: none"));
+    }
+
+    #[test]
+    fn test_nic_numa_node() {
+        let root =
+            Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/sysfs/dual-socket-smt");
+        assert_eq!(nic_numa_node(&root, "eth0").unwrap(), Some(1));
+        assert_eq!(nic_numa_node(&root, "lo").unwrap(), None);
+    }
+
+    #[test]
+    fn test_missing_tree_reports_path() {
+        let err = Topology::from_sysfs("/nonexistent-sysfs").unwrap_err();
+        assert!(matches!(err, TopologyError::Io { .. }));
+        assert!(err
+            .to_string()
+            .contains("/nonexistent-sysfs/devices/system/cpu/online"));
+    }
+}
@@ -0,0 +1,500 @@
+//! Fixed-precision latency histogram.
+//!
+//! Values are bucketed on a log-linear scale (HdrHistogram style): every
+//! power-of-two range is split into 64 linear sub-buckets, so any recorded
+//! value is reported with at most ~1.6% relative error while the whole
+//! 1ns..4h range fits in a fixed array allocated once up front.
+
+use crate::numa::{MemoryPlacement, NumaBuffer, NumaError};
+use std::time::Duration;
+
+/// Number of bits of linear resolution within each power-of-two range.
+const SUB_BUCKET_BITS: u32 = 7;
+/// Values below this are recorded exactly (one bucket per nanosecond).
+const SUB_BUCKET_COUNT: usize = 1 << SUB_BUCKET_BITS;
+const SUB_BUCKET_HALF: usize = SUB_BUCKET_COUNT / 2;
+/// Largest trackable value is `2^MAX_VALUE_BITS - 1` ns (~4.9 hours).
+/// Larger values are clamped into the last bucket.
+const MAX_VALUE_BITS: u32 = 44;
+const MAX_TRACKABLE: u64 = (1 << MAX_VALUE_BITS) - 1;
+const BUCKET_COUNT: usize =
+    SUB_BUCKET_COUNT + (MAX_VALUE_BITS - SUB_BUCKET_BITS) as usize * SUB_BUCKET_HALF;
+
+/// Latency histogram with bounded memory and fixed relative precision.
+///
+/// Unlike [`LatencyMetrics::from_samples`](crate::LatencyMetrics::from_samples),
+/// recording never allocates, so a histogram can sit on a hot path and
+/// collect millions of samples.
+///
+/// # Examples
+/// ```
+/// use hft_primitives::LatencyHistogram;
+/// use std::time::Duration;
+///
+/// let mut histogram = LatencyHistogram::new();
+/// for ns in 1..=100 {
+///     histogram.record(Duration::from_nanos(ns));
+/// }
+///
+/// assert_eq!(histogram.len(), 100);
+/// assert_eq!(histogram.value_at_quantile(0.5), Duration::from_nanos(51));
+/// ```
+///
+/// # Performance Characteristics
+/// - Record: O(1) - leading-zero count plus one array increment
+/// - Quantile lookup: O(buckets) scan (~2.5k buckets)
+/// - ~20KB per histogram, allocated in `new()`
+#[derive(Clone, PartialEq, Eq)]
+pub struct LatencyHistogram {
+    counts: NumaBuffer<u64>,
+    total: u64,
+    min: u64,
+    max: u64,
+    sum: u128,
+}
+
+impl LatencyHistogram {
+    /// Creates an empty histogram.
+    pub fn new() -> Self {
+        Self::from_counts(NumaBuffer::new(BUCKET_COUNT, |_| 0))
+    }
+
+    /// Creates an empty histogram whose bucket array is allocated with a
+    /// NUMA placement hint, e.g. on the node of the thread recording into it.
+    pub fn with_placement(placement: &MemoryPlacement) -> Result<Self, NumaError> {
+        NumaBuffer::with_placement(BUCKET_COUNT, placement, |_| 0).map(Self::from_counts)
+    }
+
+    fn from_counts(counts: NumaBuffer<u64>) -> Self {
+        Self {
+            counts,
+            total: 0,
+            min: u64::MAX,
+            max: 0,
+            sum: 0,
+        }
+    }
+
+    /// Builds a histogram from raw samples.
+    pub fn from_samples(samples: &[Duration]) -> Self {
+        let mut histogram = Self::new();
+        for &sample in samples {
+            histogram.record(sample);
+        }
+        histogram
+    }
+
+    /// Records a single latency sample.
+    #[inline]
+    pub fn record(&mut self, latency: Duration) {
+        self.record_nanos(duration_to_nanos(latency));
+    }
+
+    /// Records a single latency sample given in nanoseconds.
+    #[inline]
+    pub fn record_nanos(&mut self, nanos: u64) {
+        self.record_nanos_n(nanos, 1);
+    }
+
+    /// Records `count` occurrences of the same value.
+    #[inline]
+    pub fn record_nanos_n(&mut self, nanos: u64, count: u64) {
+        if count == 0 {
+            return;
+        }
+        let nanos = nanos.min(MAX_TRACKABLE);
+        self.counts[bucket_index(nanos)] += count;
+        self.total += count;
+        self.min = self.min.min(nanos);
+        self.max = self.max.max(nanos);
+        self.sum += nanos as u128 * count as u128;
+    }
+
+    /// Records a sample, back-filling the samples coordinated omission hid.
+    ///
+    /// When a measured operation was expected to start every
+    /// `expected_interval`, a stall of `latency` also delayed the operations
+    /// that should have been issued during it. Like HdrHistogram's
+    /// `record_corrected`, this records `latency`, then `latency - interval`,
+    /// `latency - 2 * interval`, ... down to `interval`.
+    ///
+    /// Returns the number of back-filled samples.
+    ///
+    /// # Examples
+    /// ```
+    /// use hft_primitives::LatencyHistogram;
+    /// use std::time::Duration;
+    ///
+    /// let mut histogram = LatencyHistogram::new();
+    /// // 1ms stall while issuing one request every 100µs
+    /// let added = histogram.record_corrected(Duration::from_millis(1), Duration::from_micros(100));
+    /// assert_eq!(added, 9);
+    /// assert_eq!(histogram.len(), 10);
+    /// ```
+    pub fn record_corrected(&mut self, latency: Duration, expected_interval: Duration) -> u64 {
+        let value = duration_to_nanos(latency);
+        let interval = duration_to_nanos(expected_interval);
+        self.record_nanos(value);
+        if interval == 0 || value <= interval {
+            return 0;
+        }
+
+        let mut backfilled = 0;
+        let mut missing = value - interval;
+        while missing >= interval {
+            self.record_nanos(missing);
+            backfilled += 1;
+            missing -= interval;
+        }
+        backfilled
+    }
+
+    /// Returns the number of recorded samples.
+    pub fn len(&self) -> u64 {
+        self.total
+    }
+
+    /// Returns true if nothing has been recorded.
+    pub fn is_empty(&self) -> bool {
+        self.total == 0
+    }
+
+    /// Returns the smallest recorded value (exact).
+    pub fn min(&self) -> Duration {
+        if self.is_empty() {
+            Duration::ZERO
+        } else {
+            Duration::from_nanos(self.min)
+        }
+    }
+
+    /// Returns the largest recorded value (exact).
+    pub fn max(&self) -> Duration {
+        Duration::from_nanos(self.max)
+    }
+
+    /// Returns the mean of all recorded values (exact).
+    pub fn mean(&self) -> Duration {
+        if self.is_empty() {
+            return Duration::ZERO;
+        }
+        Duration::from_nanos((self.sum / self.total as u128) as u64)
+    }
+
+    /// Returns the value at the given quantile (`0.0..=1.0`).
+    ///
+    /// Uses the same rank convention as
+    /// [`LatencyMetrics::from_samples`](crate::LatencyMetrics::from_samples)
+    /// (`sorted[(len * q) as usize]`) and reports the highest value
+    /// equivalent to the matching bucket, clamped to the recorded min/max.
+    pub fn value_at_quantile(&self, quantile: f64) -> Duration {
+        if self.is_empty() {
+            return Duration::ZERO;
+        }
+        let rank = ((self.total as f64 * quantile.clamp(0.0, 1.0)) as u64).min(self.total - 1);
+
+        let mut seen = 0;
+        for (index, &count) in self.counts.iter().enumerate() {
+            seen += count;
+            if seen > rank {
+                let (_, upper) = bucket_bounds(index);
+                let value = (upper - 1).clamp(self.min, self.max);
+                return Duration::from_nanos(value);
+            }
+        }
+        self.max()
+    }
+
+    /// Returns the number of samples strictly greater than `threshold`.
+    ///
+    /// Samples sharing a bucket with `threshold` are counted as not exceeding
+    /// it, so the result is accurate to the histogram's precision.
+    pub fn count_above(&self, threshold: Duration) -> u64 {
+        let threshold = duration_to_nanos(threshold);
+        if threshold >= self.max {
+            return 0;
+        }
+        let first = bucket_index(threshold.min(MAX_TRACKABLE)) + 1;
+        self.counts[first.min(BUCKET_COUNT)..].iter().sum()
+    }
+
+    /// Iterates over non-empty buckets as `(lower_ns, upper_ns, count)`,
+    /// where the bucket covers `lower_ns..upper_ns`.
+    pub fn buckets(&self) -> impl Iterator<Item = (u64, u64, u64)> + '_ {
+        self.counts
+            .iter()
+            .enumerate()
+            .filter(|(_, &count)| count > 0)
+            .map(|(index, &count)| {
+                let (lower, upper) = bucket_bounds(index);
+                (lower, upper, count)
+            })
+    }
+
+    /// Adds all samples from `other` into this histogram.
+    pub fn merge(&mut self, other: &LatencyHistogram) {
+        for (mine, theirs) in self.counts.iter_mut().zip(other.counts.iter()) {
+            *mine += theirs;
+        }
+        self.total += other.total;
+        self.min = self.min.min(other.min);
+        self.max = self.max.max(other.max);
+        self.sum += other.sum;
+    }
+
+    /// Removes all recorded samples without releasing memory.
+    pub fn clear(&mut self) {
+        self.counts.fill(0);
+        self.total = 0;
+        self.min = u64::MAX;
+        self.max = 0;
+        self.sum = 0;
+    }
+
+    /// Iterates over `(count_a, count_b)` pairs for buckets where either
+    /// histogram is non-empty, in ascending value order.
+    pub(crate) fn paired_counts<'a>(
+        &'a self,
+        other: &'a LatencyHistogram,
+    ) -> impl Iterator<Item = (u64, u64)> + 'a {
+        self.counts
+            .iter()
+            .zip(other.counts.iter())
+            .filter(|(&a, &b)| a > 0 || b > 0)
+            .map(|(&a, &b)| (a, b))
+    }
+}
+
+impl Default for LatencyHistogram {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl std::fmt::Debug for LatencyHistogram {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LatencyHistogram")
+            .field("len", &self.total)
+            .field("min", &self.min())
+            .field("max", &self.max())
+            .field("mean", &self.mean())
+            .finish()
+    }
+}
+
+#[inline]
+fn duration_to_nanos(duration: Duration) -> u64 {
+    duration.as_nanos().min(u64::MAX as u128) as u64
+}
+
+/// Maps a value (already clamped to `MAX_TRACKABLE`) to its bucket index.
+#[inline]
+fn bucket_index(nanos: u64) -> usize {
+    if nanos < SUB_BUCKET_COUNT as u64 {
+        return nanos as usize;
+    }
+    let msb = 63 - nanos.leading_zeros();
+    let shift = msb - (SUB_BUCKET_BITS - 1);
+    let sub_bucket = (nanos >> shift) as usize; // in SUB_BUCKET_HALF..SUB_BUCKET_COUNT
+    SUB_BUCKET_COUNT + (shift as usize - 1) * SUB_BUCKET_HALF + (sub_bucket - SUB_BUCKET_HALF)
+}
+
+/// Returns the `lower..upper` value range covered by a bucket.
+fn bucket_bounds(index: usize) -> (u64, u64) {
+    if index < SUB_BUCKET_COUNT {
+        return (index as u64, index as u64 + 1);
+    }
+    let offset = index - SUB_BUCKET_COUNT;
+    let shift = (offset / SUB_BUCKET_HALF + 1) as u32;
+    let sub_bucket = (offset % SUB_BUCKET_HALF + SUB_BUCKET_HALF) as u64;
+    let lower = sub_bucket << shift;
+    (lower, lower + (1 << shift))
+}
+
+/// Sparse serialized form: only non-empty buckets are written, keyed by
+/// their lower bound so the file stays readable without this crate.
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct HistogramRepr {
+    count: u64,
+    min_ns: u64,
+    max_ns: u64,
+    sum_ns: u128,
+    /// `[lower_ns, count]` pairs for each non-empty bucket.
+    buckets: Vec<(u64, u64)>,
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for LatencyHistogram {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        HistogramRepr {
+            count: self.total,
+            min_ns: self.min().as_nanos() as u64,
+            max_ns: self.max,
+            sum_ns: self.sum,
+            buckets: self
+                .buckets()
+                .map(|(lower, _, count)| (lower, count))
+                .collect(),
+        }
+        .serialize(serializer)
+    }
+}
+
+/// Rejects a serialized histogram whose count, min or max disagree with its
+/// buckets, so a hand-edited or truncated file cannot skew the percentiles.
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for LatencyHistogram {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        use serde::de::Error;
+
+        let repr = HistogramRepr::deserialize(deserializer)?;
+        let mut histogram = LatencyHistogram::new();
+        for (lower, count) in repr.buckets {
+            histogram.total = histogram
+                .total
+                .checked_add(count)
+                .ok_or_else(|| D::Error::custom("histogram bucket counts overflow"))?;
+            histogram.counts[bucket_index(lower.min(MAX_TRACKABLE))] += count;
+        }
+        if histogram.total != repr.count {
+            return Err(D::Error::custom(format_args!(
+                "histogram count {} does not match its buckets ({})",
+                repr.count, histogram.total
+            )));
+        }
+
+        if let (Some(first), Some(last)) = (
+            histogram.counts.iter().position(|&c| c > 0),
+            histogram.counts.iter().rposition(|&c| c > 0),
+        ) {
+            let in_bucket = |nanos: u64, index| bucket_index(nanos.min(MAX_TRACKABLE)) == index;
+            if !in_bucket(repr.min_ns, first) || !in_bucket(repr.max_ns, last) {
+                return Err(D::Error::custom(format_args!(
+                    "histogram min {}ns / max {}ns lie outside its lowest and highest buckets",
+                    repr.min_ns, repr.max_ns
+                )));
+            }
+            histogram.min = repr.min_ns;
+            histogram.max = repr.max_ns;
+        }
+        histogram.sum = repr.sum_ns;
+        Ok(histogram)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bucket_index_round_trip() {
+        let mut previous_upper = 0;
+        for index in 0..BUCKET_COUNT {
+            let (lower, upper) = bucket_bounds(index);
+            assert_eq!(lower, previous_upper, "buckets must be contiguous");
+            assert_eq!(bucket_index(lower), index);
+            assert_eq!(bucket_index(upper - 1), index);
+            previous_upper = upper;
+        }
+        assert_eq!(previous_upper, MAX_TRACKABLE + 1);
+    }
+
+    #[test]
+    fn test_quantiles_match_sorted_samples() {
+        let mut samples: Vec<Duration> = (1..=1000).map(Duration::from_nanos).collect();
+        let histogram = LatencyHistogram::from_samples(&samples);
+        let metrics = crate::LatencyMetrics::from_samples(&mut samples);
+
+        assert_eq!(histogram.len(), 1000);
+        assert_eq!(histogram.min(), metrics.min);
+        assert_eq!(histogram.max(), metrics.max);
+        assert_eq!(histogram.mean(), metrics.avg);
+
+        // Within bucket precision (1/64) of the exact percentiles
+        for (quantile, exact) in [(0.5, metrics.p50), (0.99, metrics.p99)] {
+            let approx = histogram.value_at_quantile(quantile).as_nanos() as f64;
+            let exact = exact.as_nanos() as f64;
+            assert!((approx - exact).abs() / exact <= 1.0 / 64.0);
+        }
+    }
+
+    #[test]
+    fn test_count_above_and_merge() {
+        let mut a = LatencyHistogram::new();
+        let mut b = LatencyHistogram::new();
+        a.record_nanos_n(50, 10);
+        b.record_nanos_n(5_000, 2);
+        b.record(Duration::from_secs(1));
+
+        a.merge(&b);
+        assert_eq!(a.len(), 13);
+        assert_eq!(a.count_above(Duration::from_nanos(100)), 3);
+        assert_eq!(a.count_above(Duration::from_millis(1)), 1);
+        assert_eq!(a.count_above(Duration::from_secs(2)), 0);
+        assert_eq!(a.min(), Duration::from_nanos(50));
+
+        a.clear();
+        assert!(a.is_empty());
+        assert_eq!(a.value_at_quantile(0.99), Duration::ZERO);
+    }
+
+    #[test]
+    fn test_record_corrected() {
+        let interval = Duration::from_nanos(100);
+        let mut histogram = LatencyHistogram::new();
+
+        // Fast operations are recorded as-is
+        assert_eq!(
+            histogram.record_corrected(Duration::from_nanos(80), interval),
+            0
+        );
+        assert_eq!(
+            histogram.record_corrected(Duration::from_nanos(100), interval),
+            0
+        );
+
+        // A 450ns stall hides requests that would have waited 350, 250 and 150ns
+        assert_eq!(
+            histogram.record_corrected(Duration::from_nanos(450), interval),
+            3
+        );
+        let values: Vec<u64> = histogram.buckets().map(|(lower, _, _)| lower).collect();
+        assert_eq!(values, vec![80, 100, 150, 250, 348, 448]);
+        assert_eq!(histogram.len(), 6);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_round_trip() {
+        let samples: Vec<Duration> = (0..500).map(|i| Duration::from_nanos(i * 37)).collect();
+        let histogram = LatencyHistogram::from_samples(&samples);
+
+        let json = serde_json::to_string(&histogram).unwrap();
+        let parsed: LatencyHistogram = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed, histogram);
+
+        let empty: LatencyHistogram =
+            serde_json::from_str(r#"{"count":0,"min_ns":0,"max_ns":0,"sum_ns":0,"buckets":[]}"#)
+                .unwrap();
+        assert!(empty.is_empty());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_rejects_inconsistent_summary() {
+        // Two samples, in the 10ns and 20ns buckets
+        let parse = |count: u64, min: u64, max: u64| {
+            serde_json::from_str::<LatencyHistogram>(&format!(
+                r#"{{"count":{},"min_ns":{},"max_ns":{},"sum_ns":30,"buckets":[[10,1],[20,1]]}}"#,
+                count, min, max
+            ))
+        };
+        assert!(parse(2, 10, 20).is_ok());
+
+        let err = parse(5, 10, 20).unwrap_err();
+        assert!(err.to_string().contains("does not match its buckets"));
+        assert!(parse(2, 1, 20).is_err());
+        assert!(parse(2, 10, 9000).is_err());
+    }
+}
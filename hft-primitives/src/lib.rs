@@ -6,15 +6,46 @@
 //! # Features
 //! - Lock-free SPSC ring buffer
 //! - Atomic counters with relaxed ordering
-//! - CPU pinning utilities (Linux)
+//! - CPU pinning and affinity queries with typed errors (Linux)
+//! - CPU topology discovery from sysfs (SMT siblings, sockets, NUMA nodes)
+//! - Topology-aware thread placement planning
+//! - Thread-per-core runtime with start barrier and coordinated shutdown
+//! - Real-time scheduling, memory locking and page prefaulting
+//! - NUMA-aware buffer allocation (bind, interleave, first touch)
 //! - Performance metrics collection
+//! - TSC-based cycle-accurate clock with calibration (x86_64)
+//! - Configurable latency budgets (SLOs)
+//! - Coordinated-omission-aware recording and fixed-rate load driving
+//! - Fixed-precision latency histograms
+//! - Rolling per-interval latency windows
+//! - Per-hop pipeline latency tracing
+//! - ASCII histogram and percentile-distribution plots
+//! - Machine-readable run reports (JSON with the `serde` feature, CSV)
+//! - Baseline comparison and regression detection
+//! - Host latency-readiness audit (governor, SMT, isolation, IRQs, swap, C-states)
 
 pub mod atomic_counter;
+pub mod budget;
+pub mod clock;
+pub mod compare;
 pub mod cpu_pinning;
+pub mod histogram;
+pub mod load_driver;
 pub mod metrics;
+pub mod numa;
+pub mod plot;
+pub mod realtime;
+pub mod report;
 pub mod ring_buffer;
+pub mod rolling;
+pub mod runtime;
+pub mod sysaudit;
+pub mod trace;
 
 pub use atomic_counter::AtomicCounter;
+pub use budget::LatencyBudget;
 pub use cpu_pinning::pin_thread_to_core;
+pub use histogram::LatencyHistogram;
 pub use metrics::LatencyMetrics;
+pub use report::RunReport;
 pub use ring_buffer::LockFreeRingBuffer;
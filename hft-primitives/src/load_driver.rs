@@ -0,0 +1,201 @@
+//! Fixed-rate load driver.
+//!
+//! Issues operations on a fixed schedule and measures each one from its
+//! *intended* start time rather than the moment it actually started. When an
+//! operation stalls, the operations queued behind it start late and that
+//! delay shows up in their latency — exactly what a client sending at a
+//! fixed rate would observe. Back-to-back timing loops hide it
+//! (coordinated omission).
+
+use crate::histogram::LatencyHistogram;
+use crate::metrics::LatencyMetrics;
+use std::time::{Duration, Instant};
+
+/// Result of a fixed-rate run.
+#[derive(Debug, Clone)]
+pub struct LoadRunResult {
+    /// Latency measured from the intended start of each operation.
+    pub response_time: LatencyHistogram,
+    /// Latency measured from the actual start of each operation
+    /// (what a back-to-back loop would report).
+    pub service_time: LatencyHistogram,
+    /// Operations that started after their intended start time.
+    pub late_starts: u64,
+    /// Wall-clock duration of the run.
+    pub elapsed: Duration,
+}
+
+impl LoadRunResult {
+    /// Summarizes response times (intended start to completion).
+    pub fn response_metrics(&self) -> LatencyMetrics {
+        LatencyMetrics::from_histogram(&self.response_time)
+    }
+
+    /// Summarizes service times (actual start to completion).
+    pub fn service_metrics(&self) -> LatencyMetrics {
+        LatencyMetrics::from_histogram(&self.service_time)
+    }
+
+    /// Returns the achieved operation rate in operations per second, or 0
+    /// if the run took no measurable time.
+    pub fn achieved_rate(&self) -> f64 {
+        let seconds = self.elapsed.as_secs_f64();
+        if seconds > 0.0 {
+            self.response_time.len() as f64 / seconds
+        } else {
+            0.0
+        }
+    }
+}
+
+/// Issues operations at a fixed rate and records coordinated-omission-free
+/// latencies.
+///
+/// The driver spin-waits until each operation's intended start time, so it
+/// should run on a dedicated (ideally pinned) core.
+///
+/// # Examples
+/// ```
+/// use hft_primitives::load_driver::FixedRateDriver;
+/// use std::time::Duration;
+///
+/// let driver = FixedRateDriver::new(Duration::from_micros(10));
+/// let result = driver.run(1000, |i| {
+///     std::hint::black_box(i * 2);
+/// });
+///
+/// assert_eq!(result.response_time.len(), 1000);
+/// // Response time includes queueing delay, so it never beats service time
+/// assert!(result.response_metrics().max >= result.service_metrics().max);
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct FixedRateDriver {
+    interval: Duration,
+}
+
+impl FixedRateDriver {
+    /// Creates a driver starting one operation every `interval`.
+    pub fn new(interval: Duration) -> Self {
+        Self { interval }
+    }
+
+    /// Creates a driver issuing `rate` operations per second.
+    ///
+    /// # Panics
+    /// Panics if `rate` is not a positive, finite number, or so small that
+    /// the interval does not fit in a `Duration`.
+    pub fn with_rate(rate: f64) -> Self {
+        assert!(
+            rate.is_finite() && rate > 0.0,
+            "rate must be positive, got {}",
+            rate
+        );
+        let interval = Duration::try_from_secs_f64(1.0 / rate)
+            .unwrap_or_else(|_| panic!("rate {} is too low for a Duration interval", rate));
+        Self::new(interval)
+    }
+
+    /// Returns the interval between intended start times.
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    /// Runs `count` operations, passing each its sequence number.
+    pub fn run<F>(&self, count: u64, mut operation: F) -> LoadRunResult
+    where
+        F: FnMut(u64),
+    {
+        let mut response_time = LatencyHistogram::new();
+        let mut service_time = LatencyHistogram::new();
+        let mut late_starts = 0;
+
+        let start = Instant::now();
+        for i in 0..count {
+            let intended = start + self.interval.mul_f64(i as f64);
+
+            let mut now = Instant::now();
+            // The first intended start is `start` itself, which has always
+            // passed
+            if i > 0 && now > intended {
+                late_starts += 1;
+            }
+            while now < intended {
+                std::hint::spin_loop();
+                now = Instant::now();
+            }
+
+            operation(i);
+            let done = Instant::now();
+
+            response_time.record(done - intended);
+            service_time.record(done - now);
+        }
+
+        LoadRunResult {
+            response_time,
+            service_time,
+            late_starts,
+            elapsed: start.elapsed(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stall_delays_following_operations() {
+        let driver = FixedRateDriver::new(Duration::from_micros(100));
+        let result = driver.run(20, |i| {
+            if i == 5 {
+                std::thread::sleep(Duration::from_millis(1));
+            }
+        });
+
+        assert_eq!(result.response_time.len(), 20);
+        assert!(result.late_starts >= 5);
+
+        // Only one operation was slow, but the ones queued behind it inherit
+        // its delay when measured from their intended start
+        let slow_service = result.service_time.count_above(Duration::from_micros(500));
+        let slow_response = result.response_time.count_above(Duration::from_micros(500));
+        assert!(slow_service >= 1);
+        assert!(slow_response >= slow_service + 3);
+    }
+
+    #[test]
+    fn test_with_rate() {
+        let driver = FixedRateDriver::with_rate(1_000_000.0);
+        assert_eq!(driver.interval(), Duration::from_micros(1));
+
+        let result = FixedRateDriver::with_rate(100_000.0).run(100, |_| {});
+        assert!(result.elapsed >= Duration::from_micros(990));
+        assert!(result.achieved_rate() <= 101_100.0);
+    }
+
+    #[test]
+    fn test_empty_run() {
+        let result = FixedRateDriver::new(Duration::from_micros(10)).run(0, |_| {});
+        assert_eq!(result.late_starts, 0);
+        assert_eq!(result.achieved_rate(), 0.0);
+
+        let instant = LoadRunResult {
+            elapsed: Duration::ZERO,
+            ..result
+        };
+        assert_eq!(instant.achieved_rate(), 0.0);
+    }
+
+    #[test]
+    fn test_first_start_is_not_late() {
+        let result = FixedRateDriver::new(Duration::from_millis(1)).run(1, |_| {});
+        assert_eq!(result.late_starts, 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "rate must be positive")]
+    fn test_with_rate_rejects_zero() {
+        FixedRateDriver::with_rate(0.0);
+    }
+}
@@ -1,7 +1,16 @@
 //! Performance metrics collection and analysis.
 //!
 //! Utilities for collecting and analyzing latency measurements.
+//!
+//! With the `serde` feature enabled, [`LatencyMetrics`] serializes with all
+//! durations expressed as integer nanoseconds (`p99_ns`, `max_ns`, ...) so
+//! reports from different runs can be diffed by CI tooling.
 
+use crate::budget::{BudgetReport, LatencyBudget};
+use crate::histogram::LatencyHistogram;
+use std::fmt;
+use std::io::{self, Write};
+use std::str::FromStr;
 use std::time::Duration;
 
 /// Latency metrics analyzer for HFT systems.
@@ -24,14 +33,22 @@ use std::time::Duration;
 /// println!("P99: {:?}", metrics.p99);
 /// ```
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct LatencyMetrics {
     pub samples: usize,
+    #[cfg_attr(feature = "serde", serde(rename = "min_ns", with = "nanos"))]
     pub min: Duration,
+    #[cfg_attr(feature = "serde", serde(rename = "max_ns", with = "nanos"))]
     pub max: Duration,
+    #[cfg_attr(feature = "serde", serde(rename = "avg_ns", with = "nanos"))]
     pub avg: Duration,
+    #[cfg_attr(feature = "serde", serde(rename = "p50_ns", with = "nanos"))]
     pub p50: Duration,
+    #[cfg_attr(feature = "serde", serde(rename = "p95_ns", with = "nanos"))]
     pub p95: Duration,
+    #[cfg_attr(feature = "serde", serde(rename = "p99_ns", with = "nanos"))]
     pub p99: Duration,
+    #[cfg_attr(feature = "serde", serde(rename = "p999_ns", with = "nanos"))]
     pub p999: Duration,
 }
 
@@ -69,6 +86,71 @@ impl LatencyMetrics {
         }
     }
 
+    /// Summarizes a histogram into metrics.
+    ///
+    /// Min, max and average are exact; percentiles carry the histogram's
+    /// bucket precision.
+    pub fn from_histogram(histogram: &LatencyHistogram) -> Self {
+        if histogram.is_empty() {
+            return Self::default();
+        }
+
+        Self {
+            samples: histogram.len() as usize,
+            min: histogram.min(),
+            max: histogram.max(),
+            avg: histogram.mean(),
+            p50: histogram.value_at_quantile(0.5),
+            p95: histogram.value_at_quantile(0.95),
+            p99: histogram.value_at_quantile(0.99),
+            p999: histogram.value_at_quantile(0.999),
+        }
+    }
+
+    /// Returns the value of the given percentile.
+    pub fn percentile(&self, percentile: Percentile) -> Duration {
+        match percentile {
+            Percentile::Min => self.min,
+            Percentile::P50 => self.p50,
+            Percentile::P95 => self.p95,
+            Percentile::P99 => self.p99,
+            Percentile::P999 => self.p999,
+            Percentile::Max => self.max,
+        }
+    }
+
+    /// Returns a copy with a fixed measurement overhead removed from every
+    /// latency (saturating at zero).
+    ///
+    /// Use with [`Clock::overhead`](crate::clock::Clock::overhead) so timer
+    /// cost doesn't dominate measurements of very short operations.
+    ///
+    /// # Examples
+    /// ```
+    /// use hft_primitives::LatencyMetrics;
+    /// use std::time::Duration;
+    ///
+    /// let mut samples = vec![Duration::from_nanos(30), Duration::from_nanos(50)];
+    /// let metrics = LatencyMetrics::from_samples(&mut samples);
+    ///
+    /// let corrected = metrics.subtract_overhead(Duration::from_nanos(20));
+    /// assert_eq!(corrected.min, Duration::from_nanos(10));
+    /// assert_eq!(corrected.max, Duration::from_nanos(30));
+    /// ```
+    pub fn subtract_overhead(&self, overhead: Duration) -> Self {
+        let sub = |value: Duration| value.saturating_sub(overhead);
+        Self {
+            samples: self.samples,
+            min: sub(self.min),
+            max: sub(self.max),
+            avg: sub(self.avg),
+            p50: sub(self.p50),
+            p95: sub(self.p95),
+            p99: sub(self.p99),
+            p999: sub(self.p999),
+        }
+    }
+
     /// Calculates the P99/P50 ratio as a measure of consistency.
     ///
     /// Values < 2.0 indicate good consistency.
@@ -96,8 +178,242 @@ impl LatencyMetrics {
     /// Criteria:
     /// - P99/P50 ratio < 2.0 (good consistency)
     /// - P99 < 1 microsecond (low latency)
+    ///
+    /// These limits only make sense for in-process operations; use a
+    /// [`LatencyBudget`] to check other paths (e.g. network) against their
+    /// own limits.
     pub fn is_hft_grade(&self) -> bool {
-        self.consistency_ratio() < 2.0 && self.p99 < Duration::from_micros(1)
+        LatencyBudget::hft_grade().evaluate(self).passed()
+    }
+
+    /// Checks the metrics against a latency budget.
+    pub fn evaluate(&self, budget: &LatencyBudget) -> BudgetReport {
+        budget.evaluate(self)
+    }
+
+    /// Column names matching the rows produced by [`write_csv_row`](Self::write_csv_row).
+    pub const CSV_HEADER: &'static str =
+        "name,samples,min_ns,avg_ns,p50_ns,p95_ns,p99_ns,p999_ns,max_ns,p99_p50_ratio";
+
+    /// Writes the metrics as a single CSV row (without trailing newline).
+    ///
+    /// All durations are written as integer nanoseconds.
+    ///
+    /// # Examples
+    /// ```
+    /// use hft_primitives::LatencyMetrics;
+    /// use std::time::Duration;
+    ///
+    /// let mut samples = vec![Duration::from_nanos(100), Duration::from_nanos(200)];
+    /// let metrics = LatencyMetrics::from_samples(&mut samples);
+    ///
+    /// let mut row = Vec::new();
+    /// metrics.write_csv_row(&mut row, "baseline").unwrap();
+    /// assert!(String::from_utf8(row).unwrap().starts_with("baseline,2,100,"));
+    /// ```
+    pub fn write_csv_row<W: Write>(&self, writer: &mut W, name: &str) -> io::Result<()> {
+        write!(
+            writer,
+            "{},{},{},{},{},{},{},{},{},{:.4}",
+            csv_field(name),
+            self.samples,
+            self.min.as_nanos(),
+            self.avg.as_nanos(),
+            self.p50.as_nanos(),
+            self.p95.as_nanos(),
+            self.p99.as_nanos(),
+            self.p999.as_nanos(),
+            self.max.as_nanos(),
+            self.consistency_ratio(),
+        )
+    }
+
+    /// Serializes the metrics as a JSON object.
+    #[cfg(feature = "serde")]
+    pub fn to_json(&self) -> serde_json::Result<String> {
+        serde_json::to_string_pretty(self)
+    }
+}
+
+/// Latency recorder that corrects for coordinated omission.
+///
+/// Benchmarks that time back-to-back operations stop issuing work while an
+/// operation stalls, so the requests that *should* have been sent during the
+/// stall are never measured and the tail looks far better than what a
+/// fixed-rate client would see. Given the interval at which operations are
+/// expected to start, this recorder back-fills those missing samples (see
+/// [`LatencyHistogram::record_corrected`]).
+///
+/// # Examples
+/// ```
+/// use hft_primitives::metrics::CorrectedLatencyRecorder;
+/// use std::time::Duration;
+///
+/// let mut recorder = CorrectedLatencyRecorder::new(Duration::from_micros(10));
+/// for _ in 0..99 {
+///     recorder.record(Duration::from_micros(1));
+/// }
+/// recorder.record(Duration::from_millis(1)); // one stall
+///
+/// // The stall also delayed the ~99 requests due while it lasted
+/// assert_eq!(recorder.recorded(), 100);
+/// assert_eq!(recorder.backfilled(), 99);
+/// assert!(recorder.metrics().p50 > Duration::from_micros(1));
+/// ```
+#[derive(Debug, Clone)]
+pub struct CorrectedLatencyRecorder {
+    expected_interval: Duration,
+    histogram: LatencyHistogram,
+    recorded: u64,
+    backfilled: u64,
+}
+
+impl CorrectedLatencyRecorder {
+    /// Creates a recorder for operations expected every `expected_interval`.
+    pub fn new(expected_interval: Duration) -> Self {
+        Self {
+            expected_interval,
+            histogram: LatencyHistogram::new(),
+            recorded: 0,
+            backfilled: 0,
+        }
+    }
+
+    /// Records one measured latency plus any back-filled samples.
+    #[inline]
+    pub fn record(&mut self, latency: Duration) {
+        self.recorded += 1;
+        self.backfilled += self
+            .histogram
+            .record_corrected(latency, self.expected_interval);
+    }
+
+    /// Returns the expected interval between operations.
+    pub fn expected_interval(&self) -> Duration {
+        self.expected_interval
+    }
+
+    /// Returns the number of samples actually measured.
+    pub fn recorded(&self) -> u64 {
+        self.recorded
+    }
+
+    /// Returns the number of samples added by the correction.
+    pub fn backfilled(&self) -> u64 {
+        self.backfilled
+    }
+
+    /// Returns the corrected histogram.
+    pub fn histogram(&self) -> &LatencyHistogram {
+        &self.histogram
+    }
+
+    /// Consumes the recorder, returning the corrected histogram.
+    pub fn into_histogram(self) -> LatencyHistogram {
+        self.histogram
+    }
+
+    /// Summarizes the corrected distribution.
+    pub fn metrics(&self) -> LatencyMetrics {
+        LatencyMetrics::from_histogram(&self.histogram)
+    }
+}
+
+/// A percentile tracked by [`LatencyMetrics`].
+///
+/// Parses from the names used in reports and on the command line:
+/// `min`, `p50`, `p95`, `p99`, `p999` (or `p99.9`) and `max`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum Percentile {
+    Min,
+    P50,
+    P95,
+    P99,
+    P999,
+    Max,
+}
+
+impl Percentile {
+    /// All percentiles in ascending order.
+    pub const ALL: [Percentile; 6] = [
+        Percentile::Min,
+        Percentile::P50,
+        Percentile::P95,
+        Percentile::P99,
+        Percentile::P999,
+        Percentile::Max,
+    ];
+
+    /// Returns the quantile (`0.0..=1.0`) this percentile corresponds to.
+    pub fn quantile(self) -> f64 {
+        match self {
+            Percentile::Min => 0.0,
+            Percentile::P50 => 0.5,
+            Percentile::P95 => 0.95,
+            Percentile::P99 => 0.99,
+            Percentile::P999 => 0.999,
+            Percentile::Max => 1.0,
+        }
+    }
+
+    /// Returns the short name used in reports (`"p99"`).
+    pub fn name(self) -> &'static str {
+        match self {
+            Percentile::Min => "min",
+            Percentile::P50 => "p50",
+            Percentile::P95 => "p95",
+            Percentile::P99 => "p99",
+            Percentile::P999 => "p999",
+            Percentile::Max => "max",
+        }
+    }
+}
+
+impl fmt::Display for Percentile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Percentile {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "min" | "p0" => Ok(Percentile::Min),
+            "p50" | "median" => Ok(Percentile::P50),
+            "p95" => Ok(Percentile::P95),
+            "p99" => Ok(Percentile::P99),
+            "p999" | "p99.9" => Ok(Percentile::P999),
+            "max" | "p100" => Ok(Percentile::Max),
+            other => Err(format!("unknown percentile '{}'", other)),
+        }
+    }
+}
+
+/// Quotes a CSV field if it contains a delimiter, quote or newline.
+pub(crate) fn csv_field(value: &str) -> std::borrow::Cow<'_, str> {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\"")).into()
+    } else {
+        value.into()
+    }
+}
+
+/// Serde adapter storing a `Duration` as integer nanoseconds.
+#[cfg(feature = "serde")]
+pub(crate) mod nanos {
+    use serde::{Deserialize, Deserializer, Serializer};
+    use std::time::Duration;
+
+    pub fn serialize<S: Serializer>(value: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u64(value.as_nanos().min(u64::MAX as u128) as u64)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+        u64::deserialize(deserializer).map(Duration::from_nanos)
     }
 }
 
@@ -157,4 +473,73 @@ mod tests {
         assert_eq!(metrics.samples, 0);
         assert_eq!(metrics.p50, Duration::ZERO);
     }
+
+    #[test]
+    fn test_from_histogram() {
+        let mut samples: Vec<Duration> = (1..=100).map(Duration::from_nanos).collect();
+        let histogram = LatencyHistogram::from_samples(&samples);
+
+        let exact = LatencyMetrics::from_samples(&mut samples);
+        let approx = LatencyMetrics::from_histogram(&histogram);
+        for percentile in Percentile::ALL {
+            // Values below 128ns are recorded exactly
+            assert_eq!(approx.percentile(percentile), exact.percentile(percentile));
+        }
+        assert_eq!(approx.avg, exact.avg);
+    }
+
+    #[test]
+    fn test_corrected_recorder() {
+        let mut recorder = CorrectedLatencyRecorder::new(Duration::from_micros(1));
+        for _ in 0..1000 {
+            recorder.record(Duration::from_nanos(200));
+        }
+        assert_eq!(recorder.backfilled(), 0);
+        assert_eq!(recorder.metrics().p99, Duration::from_nanos(200));
+
+        // A single 100µs stall hides ~99 requests behind it, moving P99 from
+        // 200ns into the microsecond range
+        recorder.record(Duration::from_micros(100));
+        assert_eq!(recorder.recorded(), 1001);
+        assert_eq!(recorder.backfilled(), 99);
+        assert!(recorder.metrics().p99 > Duration::from_micros(1));
+    }
+
+    #[test]
+    fn test_percentile_parse() {
+        assert_eq!("p99".parse::<Percentile>(), Ok(Percentile::P99));
+        assert_eq!("P99.9".parse::<Percentile>(), Ok(Percentile::P999));
+        assert_eq!("max".parse::<Percentile>(), Ok(Percentile::Max));
+        assert!("p42".parse::<Percentile>().is_err());
+    }
+
+    #[test]
+    fn test_csv_row() {
+        let mut samples = vec![Duration::from_nanos(100), Duration::from_nanos(300)];
+        let metrics = LatencyMetrics::from_samples(&mut samples);
+
+        let mut row = Vec::new();
+        metrics.write_csv_row(&mut row, "a,b").unwrap();
+        let row = String::from_utf8(row).unwrap();
+        assert_eq!(row, "\"a,b\",2,100,200,300,300,300,300,300,1.0000");
+        assert_eq!(
+            row.matches(',').count() - 1,
+            LatencyMetrics::CSV_HEADER.matches(',').count()
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_json_round_trip() {
+        let mut samples = vec![Duration::from_nanos(120), Duration::from_nanos(450)];
+        let metrics = LatencyMetrics::from_samples(&mut samples);
+
+        let json = metrics.to_json().unwrap();
+        assert!(json.contains("\"p99_ns\": 450"));
+
+        let parsed: LatencyMetrics = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed.samples, 2);
+        assert_eq!(parsed.min, Duration::from_nanos(120));
+        assert_eq!(parsed.p99, Duration::from_nanos(450));
+    }
 }
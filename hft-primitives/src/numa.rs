@@ -0,0 +1,608 @@
+//! NUMA-aware allocation for queues and buffers.
+//!
+//! Linux places a page on the node of the thread that first touches it. A
+//! ring buffer built on the main thread therefore lives on the main
+//! thread's node, even when the producer and consumer are pinned to another
+//! socket. Every access then crosses the interconnect. [`NumaBuffer`] allocates
+//! its backing pages with an explicit [`MemoryPlacement`]: bound to a node
+//! (`mbind`), interleaved across nodes, or first-touched from a thread
+//! pinned to a given CPU.
+//!
+//! [`LockFreeRingBuffer::with_placement`](crate::LockFreeRingBuffer::with_placement)
+//! and [`LatencyHistogram::with_placement`](crate::LatencyHistogram::with_placement)
+//! accept the same hint.
+
+use std::fmt;
+use std::ops::{Deref, DerefMut};
+use std::ptr::NonNull;
+
+/// Highest node number (exclusive) accepted in a placement.
+pub const MAX_NODES: usize = 64;
+
+/// Where a buffer's pages should live.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum MemoryPlacement {
+    /// The global allocator; pages land on the node of the first thread
+    /// that touches them (normally the constructing thread).
+    #[default]
+    Local,
+    /// Pages bound to one node (`MPOL_BIND`).
+    Node(usize),
+    /// Pages spread round-robin across the given nodes (`MPOL_INTERLEAVE`).
+    Interleave(Vec<usize>),
+    /// Pages first-touched by a temporary thread pinned to `cpu`, so they
+    /// land on that CPU's node under the default policy.
+    FirstTouch { cpu: usize },
+}
+
+impl fmt::Display for MemoryPlacement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MemoryPlacement::Local => f.write_str("local"),
+            MemoryPlacement::Node(node) => write!(f, "node {}", node),
+            MemoryPlacement::Interleave(nodes) => {
+                let nodes: Vec<String> = nodes.iter().map(|n| n.to_string()).collect();
+                write!(f, "interleave {}", nodes.join(","))
+            }
+            MemoryPlacement::FirstTouch { cpu } => write!(f, "first touch from CPU {}", cpu),
+        }
+    }
+}
+
+/// Errors from NUMA placement.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NumaError {
+    /// The node number is not below [`MAX_NODES`].
+    InvalidNode(usize),
+    /// An interleave placement listed no nodes.
+    EmptyNodeSet,
+    /// The placement only applies to allocations, not to a thread policy.
+    NotAPolicy,
+    /// The buffer size in bytes does not fit in `usize`.
+    TooLarge { len: usize },
+    /// Pinning the first-touch thread failed.
+    Pin(crate::cpu_pinning::PinError),
+    /// The syscall failed; holds `errno` (`EINVAL` for a node that does not
+    /// exist, `ENOSYS` for a kernel without NUMA support).
+    Os { operation: &'static str, errno: i32 },
+    /// The platform has no NUMA API.
+    Unsupported,
+}
+
+impl fmt::Display for NumaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NumaError::InvalidNode(node) => {
+                write!(
+                    f,
+                    "NUMA node {} is out of range (max {})",
+                    node,
+                    MAX_NODES - 1
+                )
+            }
+            NumaError::EmptyNodeSet => f.write_str("interleave placement has no nodes"),
+            NumaError::NotAPolicy => {
+                f.write_str("first-touch placement cannot be used as a thread memory policy")
+            }
+            NumaError::TooLarge { len } => {
+                write!(f, "a buffer of {} elements does not fit in memory", len)
+            }
+            NumaError::Pin(e) => write!(f, "first-touch thread could not be pinned: {}", e),
+            NumaError::Os { operation, errno } => write!(
+                f,
+                "{} failed: {}",
+                operation,
+                std::io::Error::from_raw_os_error(*errno)
+            ),
+            NumaError::Unsupported => {
+                f.write_str("NUMA placement is not supported on this platform")
+            }
+        }
+    }
+}
+
+impl std::error::Error for NumaError {}
+
+enum Storage<T> {
+    Heap(Box<[T]>),
+    Mapped {
+        ptr: NonNull<T>,
+        len: usize,
+        map_len: usize,
+    },
+}
+
+/// Fixed-length buffer whose pages are placed according to a
+/// [`MemoryPlacement`].
+///
+/// Dereferences to `[T]`. Non-local placements are backed by an anonymous
+/// `mmap` so the policy applies to whole pages owned by the buffer alone.
+///
+/// # Examples
+/// ```
+/// use hft_primitives::numa::{MemoryPlacement, NumaBuffer};
+///
+/// let buffer = NumaBuffer::with_placement(1024, &MemoryPlacement::Node(0), |i| i as u64)
+///     .unwrap_or_else(|e| {
+///         eprintln!("NUMA placement unavailable ({}); using local memory", e);
+///         NumaBuffer::new(1024, |i| i as u64)
+///     });
+/// assert_eq!(buffer[10], 10);
+/// ```
+pub struct NumaBuffer<T> {
+    storage: Storage<T>,
+    placement: MemoryPlacement,
+}
+
+// SAFETY: NumaBuffer owns its elements like a Box<[T]>
+unsafe impl<T: Send> Send for NumaBuffer<T> {}
+unsafe impl<T: Sync> Sync for NumaBuffer<T> {}
+
+impl<T> NumaBuffer<T> {
+    /// Creates a buffer from the global allocator with `len` elements
+    /// produced by `init`.
+    pub fn new<F: FnMut(usize) -> T>(len: usize, init: F) -> Self {
+        Self {
+            storage: Storage::Heap((0..len).map(init).collect()),
+            placement: MemoryPlacement::Local,
+        }
+    }
+
+    /// Creates a buffer of `len` elements produced by `init`, with its pages
+    /// placed as requested.
+    ///
+    /// Elements are written by the calling thread after placement, so the
+    /// policy (not the caller's node) decides where they live.
+    pub fn with_placement<F: FnMut(usize) -> T>(
+        len: usize,
+        placement: &MemoryPlacement,
+        init: F,
+    ) -> Result<Self, NumaError> {
+        validate(placement)?;
+        let bytes = len
+            .checked_mul(std::mem::size_of::<T>())
+            .ok_or(NumaError::TooLarge { len })?;
+        if *placement == MemoryPlacement::Local || bytes == 0 {
+            let mut buffer = Self::new(len, init);
+            buffer.placement = placement.clone();
+            return Ok(buffer);
+        }
+
+        let map_len = bytes
+            .div_ceil(page_size())
+            .checked_mul(page_size())
+            .ok_or(NumaError::TooLarge { len })?;
+        let ptr = sys::map(map_len)?;
+        // Unmaps on early return; forgotten once the buffer owns the mapping
+        let mapping = Mapping { ptr, len: map_len };
+        match placement {
+            MemoryPlacement::Node(node) => sys::bind(ptr, map_len, sys::MPOL_BIND, &[*node])?,
+            MemoryPlacement::Interleave(nodes) => {
+                sys::bind(ptr, map_len, sys::MPOL_INTERLEAVE, nodes)?
+            }
+            MemoryPlacement::FirstTouch { cpu } => first_touch(ptr, map_len, *cpu)?,
+            MemoryPlacement::Local => unreachable!(),
+        }
+        std::mem::forget(mapping);
+
+        let elements = ptr.cast::<T>();
+        let mut init = init;
+        for i in 0..len {
+            // SAFETY: the mapping is page-aligned and holds `len` elements.
+            // If `init` panics the mapping leaks; nothing is dropped twice.
+            unsafe { elements.as_ptr().add(i).write(init(i)) };
+        }
+        Ok(Self {
+            storage: Storage::Mapped {
+                ptr: elements,
+                len,
+                map_len,
+            },
+            placement: placement.clone(),
+        })
+    }
+
+    /// Returns the placement the buffer was created with.
+    pub fn placement(&self) -> &MemoryPlacement {
+        &self.placement
+    }
+
+    /// Returns the node currently backing the first element, as reported
+    /// by the kernel.
+    pub fn resident_node(&self) -> Result<usize, NumaError> {
+        if self.is_empty() || std::mem::size_of::<T>() == 0 {
+            return Err(NumaError::Os {
+                operation: "get_mempolicy",
+                errno: EINVAL,
+            });
+        }
+        node_of_address(self.as_ptr().cast())
+    }
+}
+
+impl<T> Deref for NumaBuffer<T> {
+    type Target = [T];
+
+    fn deref(&self) -> &[T] {
+        match &self.storage {
+            Storage::Heap(elements) => elements,
+            // SAFETY: `len` elements were initialized in `with_placement`
+            Storage::Mapped { ptr, len, .. } => unsafe {
+                std::slice::from_raw_parts(ptr.as_ptr(), *len)
+            },
+        }
+    }
+}
+
+impl<T> DerefMut for NumaBuffer<T> {
+    fn deref_mut(&mut self) -> &mut [T] {
+        match &mut self.storage {
+            Storage::Heap(elements) => elements,
+            // SAFETY: as in `deref`; `&mut self` guarantees exclusivity
+            Storage::Mapped { ptr, len, .. } => unsafe {
+                std::slice::from_raw_parts_mut(ptr.as_ptr(), *len)
+            },
+        }
+    }
+}
+
+impl<T> Drop for NumaBuffer<T> {
+    fn drop(&mut self) {
+        if let Storage::Mapped { ptr, len, map_len } = self.storage {
+            unsafe {
+                std::ptr::drop_in_place(std::ptr::slice_from_raw_parts_mut(ptr.as_ptr(), len));
+            }
+            drop(Mapping {
+                ptr: ptr.cast(),
+                len: map_len,
+            });
+        }
+    }
+}
+
+impl<T: Clone> Clone for NumaBuffer<T> {
+    /// Clones onto the same node(s), falling back to local memory if the
+    /// placement can no longer be applied.
+    ///
+    /// Never spawns a thread: a first-touch buffer is cloned bound to the
+    /// node that currently backs it, and the clone reports that
+    /// [`MemoryPlacement::Node`] placement.
+    fn clone(&self) -> Self {
+        let placement = match &self.placement {
+            MemoryPlacement::FirstTouch { .. } => match self.resident_node() {
+                Ok(node) => MemoryPlacement::Node(node),
+                Err(_) => MemoryPlacement::Local,
+            },
+            placement => placement.clone(),
+        };
+        Self::with_placement(self.len(), &placement, |i| self[i].clone())
+            .unwrap_or_else(|_| Self::new(self.len(), |i| self[i].clone()))
+    }
+}
+
+impl<T: PartialEq> PartialEq for NumaBuffer<T> {
+    fn eq(&self, other: &Self) -> bool {
+        **self == **other
+    }
+}
+
+impl<T: Eq> Eq for NumaBuffer<T> {}
+
+impl<T: fmt::Debug> fmt::Debug for NumaBuffer<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("NumaBuffer")
+            .field("len", &self.len())
+            .field("placement", &self.placement)
+            .finish()
+    }
+}
+
+/// Sets the memory policy for every later allocation of the calling thread
+/// (`set_mempolicy`). [`MemoryPlacement::Local`] restores the default.
+pub fn set_thread_memory_policy(placement: &MemoryPlacement) -> Result<(), NumaError> {
+    validate(placement)?;
+    match placement {
+        MemoryPlacement::Local => sys::set_policy(sys::MPOL_DEFAULT, &[]),
+        MemoryPlacement::Node(node) => sys::set_policy(sys::MPOL_BIND, &[*node]),
+        MemoryPlacement::Interleave(nodes) => sys::set_policy(sys::MPOL_INTERLEAVE, nodes),
+        MemoryPlacement::FirstTouch { .. } => Err(NumaError::NotAPolicy),
+    }
+}
+
+/// Returns the node backing the page at `address`, faulting it in if needed.
+pub fn node_of_address(address: *const u8) -> Result<usize, NumaError> {
+    sys::node_of(address)
+}
+
+fn validate(placement: &MemoryPlacement) -> Result<(), NumaError> {
+    let nodes: &[usize] = match placement {
+        MemoryPlacement::Node(node) => std::slice::from_ref(node),
+        MemoryPlacement::Interleave(nodes) if nodes.is_empty() => {
+            return Err(NumaError::EmptyNodeSet)
+        }
+        MemoryPlacement::Interleave(nodes) => nodes,
+        MemoryPlacement::Local | MemoryPlacement::FirstTouch { .. } => &[],
+    };
+    match nodes.iter().find(|&&node| node >= MAX_NODES) {
+        Some(&node) => Err(NumaError::InvalidNode(node)),
+        None => Ok(()),
+    }
+}
+
+/// Touches every page of the mapping from a thread pinned to `cpu`.
+fn first_touch(ptr: NonNull<u8>, len: usize, cpu: usize) -> Result<(), NumaError> {
+    let base = ptr.as_ptr() as usize;
+    std::thread::scope(|scope| {
+        scope
+            .spawn(move || {
+                crate::cpu_pinning::try_pin_thread_to_core(cpu).map_err(NumaError::Pin)?;
+                for offset in (0..len).step_by(page_size()) {
+                    // SAFETY: inside the mapping, which outlives the scope
+                    unsafe { std::ptr::write_volatile((base + offset) as *mut u8, 0) };
+                }
+                Ok(())
+            })
+            .join()
+            .unwrap_or(Err(NumaError::Unsupported))
+    })
+}
+
+/// Owned anonymous mapping, unmapped on drop.
+struct Mapping {
+    ptr: NonNull<u8>,
+    len: usize,
+}
+
+impl Drop for Mapping {
+    fn drop(&mut self) {
+        sys::unmap(self.ptr, self.len);
+    }
+}
+
+fn page_size() -> usize {
+    sys::page_size()
+}
+
+#[cfg(target_os = "linux")]
+const EINVAL: i32 = libc::EINVAL;
+#[cfg(not(target_os = "linux"))]
+const EINVAL: i32 = 22;
+
+#[cfg(target_os = "linux")]
+mod sys {
+    use super::{NumaError, MAX_NODES};
+    use std::ptr::NonNull;
+
+    pub const MPOL_DEFAULT: libc::c_int = libc::MPOL_DEFAULT;
+    pub const MPOL_BIND: libc::c_int = libc::MPOL_BIND;
+    pub const MPOL_INTERLEAVE: libc::c_int = libc::MPOL_INTERLEAVE;
+    // get_mempolicy flags (not exported by libc)
+    const MPOL_F_NODE: libc::c_ulong = 1;
+    const MPOL_F_ADDR: libc::c_ulong = 2;
+
+    fn os_error(operation: &'static str) -> NumaError {
+        NumaError::Os {
+            operation,
+            errno: std::io::Error::last_os_error().raw_os_error().unwrap_or(0),
+        }
+    }
+
+    // The kernel reads `maxnode - 1` bits of the mask, so pass one more
+    // than the number of nodes it must see.
+    const MAX_NODE_BITS: libc::c_ulong = MAX_NODES as libc::c_ulong + 1;
+
+    fn node_mask(nodes: &[usize]) -> libc::c_ulong {
+        nodes.iter().fold(0, |mask, &node| mask | (1 << node))
+    }
+
+    pub fn page_size() -> usize {
+        match unsafe { libc::sysconf(libc::_SC_PAGESIZE) } {
+            size if size > 0 => size as usize,
+            _ => 4096,
+        }
+    }
+
+    pub fn map(len: usize) -> Result<NonNull<u8>, NumaError> {
+        let ptr = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                len,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+                -1,
+                0,
+            )
+        };
+        if ptr == libc::MAP_FAILED {
+            return Err(os_error("mmap"));
+        }
+        NonNull::new(ptr.cast()).ok_or_else(|| os_error("mmap"))
+    }
+
+    pub fn unmap(ptr: NonNull<u8>, len: usize) {
+        unsafe { libc::munmap(ptr.as_ptr().cast(), len) };
+    }
+
+    pub fn bind(
+        ptr: NonNull<u8>,
+        len: usize,
+        mode: libc::c_int,
+        nodes: &[usize],
+    ) -> Result<(), NumaError> {
+        let mask = node_mask(nodes);
+        let result = unsafe {
+            libc::syscall(
+                libc::SYS_mbind,
+                ptr.as_ptr(),
+                len,
+                mode,
+                &mask as *const libc::c_ulong,
+                MAX_NODE_BITS,
+                0 as libc::c_uint,
+            )
+        };
+        if result != 0 {
+            return Err(os_error("mbind"));
+        }
+        Ok(())
+    }
+
+    pub fn set_policy(mode: libc::c_int, nodes: &[usize]) -> Result<(), NumaError> {
+        let mask = node_mask(nodes);
+        let (mask_ptr, max_node) = if nodes.is_empty() {
+            (std::ptr::null(), 0)
+        } else {
+            (&mask as *const libc::c_ulong, MAX_NODE_BITS)
+        };
+        let result = unsafe { libc::syscall(libc::SYS_set_mempolicy, mode, mask_ptr, max_node) };
+        if result != 0 {
+            return Err(os_error("set_mempolicy"));
+        }
+        Ok(())
+    }
+
+    pub fn node_of(address: *const u8) -> Result<usize, NumaError> {
+        let mut node: libc::c_int = 0;
+        let result = unsafe {
+            libc::syscall(
+                libc::SYS_get_mempolicy,
+                &mut node as *mut libc::c_int,
+                std::ptr::null_mut::<libc::c_ulong>(),
+                0 as libc::c_ulong,
+                address,
+                MPOL_F_NODE | MPOL_F_ADDR,
+            )
+        };
+        if result != 0 {
+            return Err(os_error("get_mempolicy"));
+        }
+        Ok(node as usize)
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod sys {
+    use super::NumaError;
+    use std::ptr::NonNull;
+
+    pub const MPOL_DEFAULT: i32 = 0;
+    pub const MPOL_BIND: i32 = 2;
+    pub const MPOL_INTERLEAVE: i32 = 3;
+
+    pub fn page_size() -> usize {
+        4096
+    }
+
+    pub fn map(_len: usize) -> Result<NonNull<u8>, NumaError> {
+        Err(NumaError::Unsupported)
+    }
+
+    pub fn unmap(_ptr: NonNull<u8>, _len: usize) {}
+
+    pub fn bind(
+        _ptr: NonNull<u8>,
+        _len: usize,
+        _mode: i32,
+        _nodes: &[usize],
+    ) -> Result<(), NumaError> {
+        Err(NumaError::Unsupported)
+    }
+
+    pub fn set_policy(_mode: i32, _nodes: &[usize]) -> Result<(), NumaError> {
+        Err(NumaError::Unsupported)
+    }
+
+    pub fn node_of(_address: *const u8) -> Result<usize, NumaError> {
+        Err(NumaError::Unsupported)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu_pinning::current_affinity;
+    use std::rc::Rc;
+
+    /// Accepts success, or the errors a kernel without NUMA support returns.
+    #[cfg(target_os = "linux")]
+    fn placed_or_unavailable<T>(result: Result<NumaBuffer<T>, NumaError>) -> Option<NumaBuffer<T>> {
+        match result {
+            Ok(buffer) => Some(buffer),
+            Err(NumaError::Os { errno, .. }) if errno == libc::ENOSYS => None,
+            Err(e) => panic!("unexpected NUMA error: {}", e),
+        }
+    }
+
+    #[test]
+    fn test_placement_validation() {
+        let invalid = NumaBuffer::with_placement(8, &MemoryPlacement::Node(MAX_NODES), |_| 0u8);
+        assert_eq!(invalid.unwrap_err(), NumaError::InvalidNode(MAX_NODES));
+
+        let empty = NumaBuffer::with_placement(8, &MemoryPlacement::Interleave(vec![]), |_| 0u8);
+        assert_eq!(empty.unwrap_err(), NumaError::EmptyNodeSet);
+
+        let policy = set_thread_memory_policy(&MemoryPlacement::FirstTouch { cpu: 0 });
+        assert_eq!(policy, Err(NumaError::NotAPolicy));
+
+        let huge = NumaBuffer::with_placement(usize::MAX, &MemoryPlacement::Node(0), |_| 0u64);
+        assert_eq!(huge.unwrap_err(), NumaError::TooLarge { len: usize::MAX });
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_node_and_interleave_placement() {
+        let placement = MemoryPlacement::Node(0);
+        if let Some(buffer) =
+            placed_or_unavailable(NumaBuffer::with_placement(10_000, &placement, |i| i as u64))
+        {
+            assert_eq!(buffer.len(), 10_000);
+            assert_eq!(buffer[9_999], 9_999);
+            assert_eq!(buffer.placement(), &placement);
+            assert_eq!(buffer.resident_node(), Ok(0));
+
+            let copy = buffer.clone();
+            assert_eq!(copy, buffer);
+            assert_eq!(copy.placement(), &placement);
+        }
+
+        let interleave = MemoryPlacement::Interleave(vec![0]);
+        placed_or_unavailable(NumaBuffer::with_placement(4096, &interleave, |_| 1u8));
+
+        // A node that does not exist is rejected by the kernel
+        let missing =
+            NumaBuffer::with_placement(16, &MemoryPlacement::Node(MAX_NODES - 1), |_| 0u8);
+        assert!(matches!(missing, Err(NumaError::Os { .. })));
+    }
+
+    #[test]
+    fn test_first_touch_and_drop() {
+        let cpu = current_affinity()
+            .ok()
+            .and_then(|allowed| allowed.iter().next())
+            .unwrap_or(0);
+        let marker = Rc::new(());
+        let buffer = NumaBuffer::with_placement(100, &MemoryPlacement::FirstTouch { cpu }, |_| {
+            Rc::clone(&marker)
+        });
+        if cfg!(target_os = "linux") {
+            let buffer = buffer.unwrap();
+            assert_eq!(Rc::strong_count(&marker), 101);
+
+            // Cloning binds to the resident node instead of first-touching
+            let copy = buffer.clone();
+            assert_eq!(Rc::strong_count(&marker), 201);
+            assert!(matches!(
+                copy.placement(),
+                MemoryPlacement::Node(_) | MemoryPlacement::Local
+            ));
+            drop(copy);
+            drop(buffer);
+        }
+        assert_eq!(Rc::strong_count(&marker), 1);
+
+        let bad_cpu = MemoryPlacement::FirstTouch {
+            cpu: crate::cpu_pinning::MAX_CPUS,
+        };
+        assert!(NumaBuffer::with_placement(4096, &bad_cpu, |_| 0u8).is_err());
+    }
+}
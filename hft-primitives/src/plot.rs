@@ -0,0 +1,387 @@
+//! Terminal rendering of latency distributions.
+//!
+//! Seven summary numbers hide bimodal distributions (e.g. an occasional
+//! allocation spike next to a fast common path). [`HistogramChart`] draws a
+//! log-scaled ASCII histogram and [`PercentileDistribution`] prints an
+//! HdrHistogram-style table of the value at 50/75/90/99/99.9/... percent.
+//!
+//! `latency-lab`, `latency-compare` and `hft-system` print these when run
+//! with `--plot` (see [`plot_requested_from_args`]).
+
+use crate::histogram::LatencyHistogram;
+use crate::metrics::LatencyMetrics;
+use std::fmt;
+use std::time::Duration;
+
+/// Chart rows per power-of-two latency range.
+const BINS_PER_OCTAVE: usize = 2;
+/// Default width of the longest bar, in characters.
+const DEFAULT_WIDTH: usize = 50;
+
+/// One row of a [`HistogramChart`], covering `lower..upper`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChartBin {
+    pub lower: Duration,
+    pub upper: Duration,
+    pub count: u64,
+}
+
+/// ASCII histogram with logarithmically spaced latency bins.
+///
+/// Each power-of-two latency range is split into two rows, so a distribution
+/// spanning nanoseconds to milliseconds fits on one screen. Empty rows
+/// between the fastest and slowest sample are kept, which makes gaps between
+/// modes visible.
+///
+/// # Examples
+/// ```
+/// use hft_primitives::plot::HistogramChart;
+/// use hft_primitives::LatencyHistogram;
+/// use std::time::Duration;
+///
+/// let mut histogram = LatencyHistogram::new();
+/// histogram.record_nanos_n(80, 990);
+/// histogram.record_nanos_n(5_000, 10);
+///
+/// let chart = HistogramChart::from_histogram(&histogram);
+/// println!("{}", chart);
+/// assert!(chart.bins().len() > 2);
+/// ```
+#[derive(Debug, Clone)]
+pub struct HistogramChart {
+    bins: Vec<ChartBin>,
+    total: u64,
+    width: usize,
+}
+
+impl HistogramChart {
+    /// Bins the histogram's samples on a log scale.
+    pub fn from_histogram(histogram: &LatencyHistogram) -> Self {
+        let mut counts: Vec<u64> = Vec::new();
+        let mut first_bin = usize::MAX;
+        for (lower, _, count) in histogram.buckets() {
+            let bin = bin_index(lower);
+            first_bin = first_bin.min(bin);
+            if counts.len() <= bin {
+                counts.resize(bin + 1, 0);
+            }
+            counts[bin] += count;
+        }
+
+        let bins = counts
+            .iter()
+            .enumerate()
+            .skip(first_bin.min(counts.len()))
+            .map(|(bin, &count)| ChartBin {
+                lower: Duration::from_nanos(bin_lower(bin)),
+                upper: Duration::from_nanos(bin_lower(bin + 1)),
+                count,
+            })
+            .collect();
+
+        Self {
+            bins,
+            total: histogram.len(),
+            width: DEFAULT_WIDTH,
+        }
+    }
+
+    /// Sets the length of the longest bar (default 50 characters).
+    pub fn with_width(mut self, width: usize) -> Self {
+        self.width = width.max(1);
+        self
+    }
+
+    /// Returns the chart rows from fastest to slowest.
+    pub fn bins(&self) -> &[ChartBin] {
+        &self.bins
+    }
+}
+
+impl fmt::Display for HistogramChart {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.total == 0 {
+            return writeln!(f, "  (no samples)");
+        }
+
+        let peak = self.bins.iter().map(|bin| bin.count).max().unwrap_or(1);
+        for bin in &self.bins {
+            // Non-empty bins always get at least one character so rare
+            // outliers stay visible next to a dominant mode
+            let bar = if bin.count == 0 {
+                0
+            } else {
+                ((bin.count as f64 / peak as f64 * self.width as f64).round() as usize).max(1)
+            };
+            writeln!(
+                f,
+                "  {:>10} - {:<10} |{:<width$}| {:>10} ({:>5.1}%)",
+                format!("{:?}", bin.lower),
+                format!("{:?}", bin.upper),
+                "#".repeat(bar),
+                bin.count,
+                bin.count as f64 * 100.0 / self.total as f64,
+                width = self.width
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns the chart bin containing `nanos`.
+fn bin_index(nanos: u64) -> usize {
+    let octave = 63 - nanos.max(1).leading_zeros() as usize;
+    let bin = octave * BINS_PER_OCTAVE;
+    // Upper half of the octave starts at 2^octave * sqrt(2)
+    if nanos >= bin_lower(bin + 1) {
+        bin + 1
+    } else {
+        bin
+    }
+}
+
+/// Returns the lower bound of chart bin `bin` in nanoseconds.
+///
+/// The first octave holds only 1ns, which has no upper half, so its two rows
+/// are 0ns and 1ns instead.
+fn bin_lower(bin: usize) -> u64 {
+    let octave_start = 1u64 << (bin / BINS_PER_OCTAVE);
+    if bin < BINS_PER_OCTAVE {
+        bin as u64
+    } else if bin.is_multiple_of(BINS_PER_OCTAVE) {
+        octave_start
+    } else {
+        (octave_start as f64 * std::f64::consts::SQRT_2).ceil() as u64
+    }
+}
+
+/// One row of a [`PercentileDistribution`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PercentileRow {
+    /// Quantile in `0.0..=1.0`.
+    pub quantile: f64,
+    pub value: Duration,
+    /// Samples at or below `value`.
+    pub count: u64,
+}
+
+/// HdrHistogram-style percentile distribution table.
+///
+/// Rows are at 0 (min), 50, 75, 90, then 99, 99.9, 99.99, ... for as many
+/// nines as the sample count supports, and finally 100 (max).
+///
+/// # Examples
+/// ```
+/// use hft_primitives::plot::PercentileDistribution;
+/// use hft_primitives::LatencyHistogram;
+/// use std::time::Duration;
+///
+/// let samples: Vec<Duration> = (1..=10_000).map(Duration::from_nanos).collect();
+/// let table = PercentileDistribution::from_histogram(&LatencyHistogram::from_samples(&samples));
+///
+/// let quantiles: Vec<f64> = table.rows().iter().map(|row| row.quantile).collect();
+/// assert_eq!(quantiles.len(), 8); // 0, 50, 75, 90, 99, 99.9, 99.99, max
+/// println!("{}", table);
+/// ```
+#[derive(Debug, Clone)]
+pub struct PercentileDistribution {
+    rows: Vec<PercentileRow>,
+}
+
+impl PercentileDistribution {
+    /// Builds the table from a histogram.
+    pub fn from_histogram(histogram: &LatencyHistogram) -> Self {
+        let total = histogram.len();
+        if total == 0 {
+            return Self { rows: Vec::new() };
+        }
+
+        let rows = quantile_ladder(total)
+            .into_iter()
+            .map(|quantile| PercentileRow {
+                quantile,
+                value: if quantile >= 1.0 {
+                    histogram.max()
+                } else if quantile <= 0.0 {
+                    histogram.min()
+                } else {
+                    histogram.value_at_quantile(quantile)
+                },
+                count: count_at(quantile, total),
+            })
+            .collect();
+        Self { rows }
+    }
+
+    /// Builds the table from the percentiles a [`LatencyMetrics`] carries
+    /// (min, P50, P95, P99, P99.9, max).
+    pub fn from_metrics(metrics: &LatencyMetrics) -> Self {
+        if metrics.samples == 0 {
+            return Self { rows: Vec::new() };
+        }
+        let total = metrics.samples as u64;
+        let rows = [
+            (0.0, metrics.min),
+            (0.5, metrics.p50),
+            (0.95, metrics.p95),
+            (0.99, metrics.p99),
+            (0.999, metrics.p999),
+            (1.0, metrics.max),
+        ]
+        .into_iter()
+        .map(|(quantile, value)| PercentileRow {
+            quantile,
+            value,
+            count: count_at(quantile, total),
+        })
+        .collect();
+        Self { rows }
+    }
+
+    /// Returns the table rows in ascending quantile order.
+    pub fn rows(&self) -> &[PercentileRow] {
+        &self.rows
+    }
+}
+
+impl fmt::Display for PercentileDistribution {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.rows.is_empty() {
+            return writeln!(f, "  (no samples)");
+        }
+        writeln!(
+            f,
+            "  {:>12} {:>12} {:>12} {:>18}",
+            "Value", "Percentile", "TotalCount", "1/(1-Percentile)"
+        )?;
+        for row in &self.rows {
+            let inverse = if row.quantile >= 1.0 {
+                "inf".to_string()
+            } else {
+                format!("{:.2}", 1.0 / (1.0 - row.quantile))
+            };
+            writeln!(
+                f,
+                "  {:>12} {:>12.6} {:>12} {:>18}",
+                format!("{:?}", row.value),
+                row.quantile,
+                row.count,
+                inverse
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Quantiles to report for `total` samples: 0, .5, .75, .9, then nines
+/// while at least one sample lies beyond them, then 1.0.
+fn quantile_ladder(total: u64) -> Vec<f64> {
+    let mut ladder = vec![0.0, 0.5, 0.75, 0.9];
+    let mut inverse = 100u64;
+    while inverse <= total {
+        ladder.push(1.0 - 1.0 / inverse as f64);
+        inverse *= 10;
+    }
+    ladder.push(1.0);
+    ladder
+}
+
+fn count_at(quantile: f64, total: u64) -> u64 {
+    ((quantile * total as f64).ceil() as u64).clamp(1, total)
+}
+
+/// Prints the log-scaled histogram chart followed by the percentile table.
+pub fn print_distribution(name: &str, histogram: &LatencyHistogram) {
+    println!("=== {} - Distribution ===", name);
+    print!("{}", HistogramChart::from_histogram(histogram));
+    println!();
+    print!("{}", PercentileDistribution::from_histogram(histogram));
+}
+
+/// Returns true if `--plot` was passed on the command line.
+///
+/// Binaries use this to opt into printing distributions next to their
+/// summary numbers.
+pub fn plot_requested_from_args() -> bool {
+    std::env::args().any(|arg| arg == "--plot")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bimodal_chart_shows_gap() {
+        let mut histogram = LatencyHistogram::new();
+        histogram.record_nanos_n(100, 9_000);
+        histogram.record_nanos_n(20_000, 1_000);
+
+        let chart = HistogramChart::from_histogram(&histogram).with_width(20);
+        let bins = chart.bins();
+        assert_eq!(bins.first().unwrap().count, 9_000);
+        assert_eq!(bins.last().unwrap().count, 1_000);
+        assert!(bins.len() > 10);
+        assert!(bins[1..bins.len() - 1].iter().all(|bin| bin.count == 0));
+        assert!(bins[0].lower <= Duration::from_nanos(100));
+        assert!(bins[0].upper > Duration::from_nanos(100));
+
+        let rendered = chart.to_string();
+        assert_eq!(rendered.lines().count(), bins.len());
+        assert!(rendered.contains(&"#".repeat(20)));
+        assert!(rendered.contains("90.0%)"));
+    }
+
+    #[test]
+    fn test_bins_are_half_octaves() {
+        assert_eq!(bin_index(0), 0);
+        assert_eq!(bin_index(1), 1);
+        assert_eq!(bin_index(2), 2);
+        assert_eq!(bin_index(64), 12);
+        assert_eq!(bin_index(90), 12);
+        assert_eq!(bin_index(91), 13);
+        assert_eq!(bin_lower(12), 64);
+        assert_eq!(bin_lower(13), 91);
+        assert_eq!(bin_lower(14), 128);
+    }
+
+    #[test]
+    fn test_bin_lower_bounds_increase() {
+        for bin in 0..2 * 63 {
+            assert!(bin_lower(bin) < bin_lower(bin + 1), "bin {}", bin);
+            assert_eq!(bin_index(bin_lower(bin)), bin);
+        }
+    }
+
+    #[test]
+    fn test_percentile_ladder() {
+        assert_eq!(quantile_ladder(50), vec![0.0, 0.5, 0.75, 0.9, 1.0]);
+        assert_eq!(quantile_ladder(1_000).len(), 7);
+
+        let samples: Vec<Duration> = (1..=1_000).map(Duration::from_nanos).collect();
+        let table =
+            PercentileDistribution::from_histogram(&LatencyHistogram::from_samples(&samples));
+        let rows = table.rows();
+        assert_eq!(rows[0].value, Duration::from_nanos(1));
+        assert_eq!(rows[1].count, 500);
+        assert_eq!(rows.last().unwrap().value, Duration::from_nanos(1_000));
+        assert_eq!(rows.last().unwrap().count, 1_000);
+
+        let rendered = table.to_string();
+        assert!(rendered.contains("1/(1-Percentile)"));
+        assert!(rendered.contains("inf"));
+        assert!(rendered.contains("100.00"));
+    }
+
+    #[test]
+    fn test_distribution_from_metrics() {
+        let mut samples: Vec<Duration> = (1..=100).map(Duration::from_nanos).collect();
+        let metrics = LatencyMetrics::from_samples(&mut samples);
+        let table = PercentileDistribution::from_metrics(&metrics);
+        assert_eq!(table.rows().len(), 6);
+        assert_eq!(table.rows()[3].value, metrics.p99);
+
+        let empty = PercentileDistribution::from_histogram(&LatencyHistogram::new());
+        assert!(empty.rows().is_empty());
+        assert!(empty.to_string().contains("no samples"));
+    }
+}
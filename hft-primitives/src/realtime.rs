@@ -0,0 +1,435 @@
+//! Real-time scheduling and memory locking helpers.
+//!
+//! Pinning keeps a hot thread on one core, but the scheduler can still
+//! preempt it for any other runnable task, and the first touch of a stack or
+//! heap page costs a page fault (microseconds). These helpers put a thread
+//! under `SCHED_FIFO`/`SCHED_RR`, lock the process's memory with `mlockall`
+//! and prefault stack and heap pages up front.
+//!
+//! All of them need privileges (`CAP_SYS_NICE`, `CAP_IPC_LOCK` or matching
+//! `RLIMIT_RTPRIO`/`RLIMIT_MEMLOCK`) that CI containers usually lack.
+//! Failures are returned as [`RealtimeError`];
+//! [`RealtimeError::is_permission_denied`] tells "not allowed here" apart
+//! from real errors so callers can continue without them.
+
+use std::fmt;
+
+/// Page size assumed when prefaulting.
+const PAGE_SIZE: usize = 4096;
+
+/// Scheduling policy for the calling thread.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SchedPolicy {
+    /// `SCHED_FIFO`: runs until it blocks or yields; preempts every
+    /// `SCHED_OTHER` thread.
+    Fifo,
+    /// `SCHED_RR`: like `Fifo`, with a time slice among equal priorities.
+    RoundRobin,
+    /// `SCHED_OTHER`: the default time-sharing policy (priority 0).
+    Other,
+}
+
+impl SchedPolicy {
+    /// Returns the valid priority range for the policy.
+    pub fn priority_range(self) -> (i32, i32) {
+        match self {
+            SchedPolicy::Fifo | SchedPolicy::RoundRobin => (1, 99),
+            SchedPolicy::Other => (0, 0),
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    fn to_libc(self) -> libc::c_int {
+        match self {
+            SchedPolicy::Fifo => libc::SCHED_FIFO,
+            SchedPolicy::RoundRobin => libc::SCHED_RR,
+            SchedPolicy::Other => libc::SCHED_OTHER,
+        }
+    }
+}
+
+impl fmt::Display for SchedPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            SchedPolicy::Fifo => "SCHED_FIFO",
+            SchedPolicy::RoundRobin => "SCHED_RR",
+            SchedPolicy::Other => "SCHED_OTHER",
+        })
+    }
+}
+
+/// Which mappings [`lock_memory`] locks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemoryLock {
+    /// Pages mapped now (`MCL_CURRENT`).
+    Current,
+    /// Pages mapped now and in the future (`MCL_CURRENT | MCL_FUTURE`).
+    CurrentAndFuture,
+}
+
+/// Errors from the real-time helpers.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RealtimeError {
+    /// The process lacks the capability or rlimit for `operation`.
+    PermissionDenied { operation: &'static str },
+    /// The priority is outside the policy's range.
+    InvalidPriority { policy: SchedPolicy, priority: i32 },
+    /// Any other OS failure; holds `errno`.
+    Os { operation: &'static str, errno: i32 },
+    /// The platform has no equivalent API.
+    Unsupported,
+}
+
+impl RealtimeError {
+    /// Returns true if the operation failed only for lack of privileges,
+    /// e.g. in an unprivileged container.
+    pub fn is_permission_denied(&self) -> bool {
+        matches!(self, RealtimeError::PermissionDenied { .. })
+    }
+
+    #[cfg(target_os = "linux")]
+    fn from_errno(operation: &'static str, errno: i32) -> Self {
+        match errno {
+            libc::EPERM => RealtimeError::PermissionDenied { operation },
+            // mlockall reports an exceeded RLIMIT_MEMLOCK as ENOMEM
+            libc::ENOMEM if operation == "mlockall" => {
+                RealtimeError::PermissionDenied { operation }
+            }
+            errno => RealtimeError::Os { operation, errno },
+        }
+    }
+}
+
+impl fmt::Display for RealtimeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RealtimeError::PermissionDenied { operation } => write!(
+                f,
+                "{} not permitted (needs CAP_SYS_NICE/CAP_IPC_LOCK or a higher rlimit)",
+                operation
+            ),
+            RealtimeError::InvalidPriority { policy, priority } => {
+                let (min, max) = policy.priority_range();
+                write!(
+                    f,
+                    "priority {} is outside {}..={} for {}",
+                    priority, min, max, policy
+                )
+            }
+            RealtimeError::Os { operation, errno } => write!(
+                f,
+                "{} failed: {}",
+                operation,
+                std::io::Error::from_raw_os_error(*errno)
+            ),
+            RealtimeError::Unsupported => {
+                f.write_str("real-time scheduling is not supported on this platform")
+            }
+        }
+    }
+}
+
+impl std::error::Error for RealtimeError {}
+
+/// Sets the calling thread's scheduling policy and priority.
+///
+/// # Examples
+/// ```no_run
+/// use hft_primitives::realtime::{set_thread_priority, SchedPolicy};
+///
+/// match set_thread_priority(SchedPolicy::Fifo, 80) {
+///     Ok(()) => {}
+///     Err(e) if e.is_permission_denied() => eprintln!("running without SCHED_FIFO: {}", e),
+///     Err(e) => panic!("{}", e),
+/// }
+/// ```
+pub fn set_thread_priority(policy: SchedPolicy, priority: i32) -> Result<(), RealtimeError> {
+    let (min, max) = policy.priority_range();
+    if priority < min || priority > max {
+        return Err(RealtimeError::InvalidPriority { policy, priority });
+    }
+    set_thread_priority_unchecked(policy, priority)
+}
+
+#[cfg(target_os = "linux")]
+fn set_thread_priority_unchecked(policy: SchedPolicy, priority: i32) -> Result<(), RealtimeError> {
+    let param = libc::sched_param {
+        sched_priority: priority,
+    };
+    let result =
+        unsafe { libc::pthread_setschedparam(libc::pthread_self(), policy.to_libc(), &param) };
+    if result != 0 {
+        return Err(RealtimeError::from_errno("pthread_setschedparam", result));
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn set_thread_priority_unchecked(
+    _policy: SchedPolicy,
+    _priority: i32,
+) -> Result<(), RealtimeError> {
+    Err(RealtimeError::Unsupported)
+}
+
+/// Returns the calling thread's scheduling policy and priority.
+#[cfg(target_os = "linux")]
+pub fn current_thread_priority() -> Result<(SchedPolicy, i32), RealtimeError> {
+    let mut policy = 0;
+    let mut param = libc::sched_param { sched_priority: 0 };
+    let result =
+        unsafe { libc::pthread_getschedparam(libc::pthread_self(), &mut policy, &mut param) };
+    if result != 0 {
+        return Err(RealtimeError::from_errno("pthread_getschedparam", result));
+    }
+    let policy = match policy {
+        libc::SCHED_FIFO => SchedPolicy::Fifo,
+        libc::SCHED_RR => SchedPolicy::RoundRobin,
+        _ => SchedPolicy::Other,
+    };
+    Ok((policy, param.sched_priority))
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn current_thread_priority() -> Result<(SchedPolicy, i32), RealtimeError> {
+    Err(RealtimeError::Unsupported)
+}
+
+/// Locks the process's pages into RAM so they are never swapped out or
+/// reclaimed.
+///
+/// Process-wide: affects every thread. With
+/// [`MemoryLock::CurrentAndFuture`], every later allocation is locked too
+/// and fails once `RLIMIT_MEMLOCK` is exhausted, so call it after the large
+/// allocations are made.
+#[cfg(target_os = "linux")]
+pub fn lock_memory(lock: MemoryLock) -> Result<(), RealtimeError> {
+    let flags = match lock {
+        MemoryLock::Current => libc::MCL_CURRENT,
+        MemoryLock::CurrentAndFuture => libc::MCL_CURRENT | libc::MCL_FUTURE,
+    };
+    if unsafe { libc::mlockall(flags) } != 0 {
+        return Err(RealtimeError::from_errno("mlockall", last_errno()));
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn lock_memory(_lock: MemoryLock) -> Result<(), RealtimeError> {
+    Err(RealtimeError::Unsupported)
+}
+
+/// Undoes [`lock_memory`].
+#[cfg(target_os = "linux")]
+pub fn unlock_memory() -> Result<(), RealtimeError> {
+    if unsafe { libc::munlockall() } != 0 {
+        return Err(RealtimeError::from_errno("munlockall", last_errno()));
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn unlock_memory() -> Result<(), RealtimeError> {
+    Err(RealtimeError::Unsupported)
+}
+
+#[cfg(target_os = "linux")]
+fn last_errno() -> i32 {
+    std::io::Error::last_os_error().raw_os_error().unwrap_or(0)
+}
+
+/// Touches `bytes` of the calling thread's stack so later calls that deep
+/// do not page-fault.
+///
+/// Must run on the thread that will use the stack, before its hot loop.
+/// Stays well below the thread's stack size (8MB for the main thread,
+/// 2MB for spawned threads by default).
+pub fn prefault_stack(bytes: usize) {
+    touch_stack_pages(bytes.div_ceil(PAGE_SIZE));
+}
+
+#[inline(never)]
+fn touch_stack_pages(pages: usize) {
+    if pages == 0 {
+        return;
+    }
+    let mut page = [0u8; PAGE_SIZE];
+    // Keep the write (and the frame) from being optimized away
+    std::hint::black_box(&mut page);
+    touch_stack_pages(pages - 1);
+    std::hint::black_box(&page);
+}
+
+/// Writes to every page of `memory` so it is backed by physical pages
+/// before the hot path reads it.
+pub fn prefault(memory: &mut [u8]) {
+    for offset in (0..memory.len()).step_by(PAGE_SIZE) {
+        // Volatile so the store of the existing value is not elided
+        unsafe {
+            let byte = memory.as_mut_ptr().add(offset);
+            std::ptr::write_volatile(byte, std::ptr::read_volatile(byte));
+        }
+    }
+}
+
+/// Stops the allocator from returning freed heap memory to the OS, so
+/// pages faulted in once (e.g. by [`prefault_heap`]) are reused by later
+/// allocations.
+///
+/// Process-wide and permanent: on glibc this disables heap trimming and
+/// `mmap`-backed allocations (`mallopt`) for every thread, so large
+/// allocations come from the heap too and freed memory is never released.
+/// The resident size only grows; call it once at startup in processes
+/// whose peak footprint is known.
+#[cfg(all(target_os = "linux", target_env = "gnu"))]
+pub fn lock_heap_growth() -> Result<(), RealtimeError> {
+    // mallopt returns 0 on failure and does not set errno
+    unsafe {
+        if libc::mallopt(libc::M_TRIM_THRESHOLD, -1) == 0 || libc::mallopt(libc::M_MMAP_MAX, 0) == 0
+        {
+            return Err(RealtimeError::Os {
+                operation: "mallopt",
+                errno: libc::EINVAL,
+            });
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(all(target_os = "linux", target_env = "gnu")))]
+pub fn lock_heap_growth() -> Result<(), RealtimeError> {
+    Err(RealtimeError::Unsupported)
+}
+
+/// Allocates `bytes`, touches every page and frees the block again.
+///
+/// The pages stay with the process only after [`lock_heap_growth`];
+/// otherwise a large block is unmapped on free and this merely faults the
+/// pages in once. Combine with [`lock_memory`] to keep them resident.
+pub fn prefault_heap(bytes: usize) {
+    let mut block = vec![0u8; bytes];
+    prefault(&mut block);
+    std::hint::black_box(&block);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_invalid_priority_is_rejected_before_syscall() {
+        assert_eq!(
+            set_thread_priority(SchedPolicy::Fifo, 0),
+            Err(RealtimeError::InvalidPriority {
+                policy: SchedPolicy::Fifo,
+                priority: 0
+            })
+        );
+        let err = set_thread_priority(SchedPolicy::RoundRobin, 100).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "priority 100 is outside 1..=99 for SCHED_RR"
+        );
+        assert!(set_thread_priority(SchedPolicy::Other, 5).is_err());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_errno_classification() {
+        let denied = RealtimeError::from_errno("pthread_setschedparam", libc::EPERM);
+        assert!(denied.is_permission_denied());
+        assert!(RealtimeError::from_errno("mlockall", libc::ENOMEM).is_permission_denied());
+
+        let other = RealtimeError::from_errno("pthread_setschedparam", libc::EINVAL);
+        assert!(!other.is_permission_denied());
+        assert!(other
+            .to_string()
+            .starts_with("pthread_setschedparam failed"));
+    }
+
+    /// Drops `CAP_SYS_NICE` from the calling thread's effective set
+    /// (capabilities are per-thread on Linux). Returns false if the
+    /// capability syscalls are not permitted here.
+    #[cfg(target_os = "linux")]
+    fn drop_sys_nice() -> bool {
+        #[repr(C)]
+        struct Header {
+            version: u32,
+            pid: i32,
+        }
+        #[repr(C)]
+        #[derive(Default, Clone, Copy)]
+        struct Data {
+            effective: u32,
+            permitted: u32,
+            inheritable: u32,
+        }
+        const VERSION_3: u32 = 0x2008_0522;
+        const CAP_SYS_NICE: u32 = 23;
+
+        let mut header = Header {
+            version: VERSION_3,
+            pid: 0,
+        };
+        let mut data = [Data::default(); 2];
+        unsafe {
+            if libc::syscall(libc::SYS_capget, &mut header, data.as_mut_ptr()) != 0 {
+                return skip_unless_denied(last_errno());
+            }
+            data[0].effective &= !(1 << CAP_SYS_NICE);
+            if libc::syscall(libc::SYS_capset, &header, data.as_ptr()) != 0 {
+                return skip_unless_denied(last_errno());
+            }
+        }
+        true
+    }
+
+    /// Sandboxes such as gVisor or seccomp profiles may refuse the
+    /// capability syscalls; only those errnos skip the test.
+    #[cfg(target_os = "linux")]
+    fn skip_unless_denied(errno: i32) -> bool {
+        assert!(
+            errno == libc::EPERM || errno == libc::ENOSYS,
+            "capability syscall failed: {}",
+            std::io::Error::from_raw_os_error(errno)
+        );
+        false
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_unprivileged_thread_degrades() {
+        std::thread::spawn(|| {
+            if !drop_sys_nice() {
+                return;
+            }
+            let mut limit = libc::rlimit {
+                rlim_cur: 0,
+                rlim_max: 0,
+            };
+            unsafe { libc::getrlimit(libc::RLIMIT_RTPRIO, &mut limit) };
+
+            let result = set_thread_priority(SchedPolicy::Fifo, 10);
+            if limit.rlim_cur == 0 {
+                let err = result.unwrap_err();
+                assert!(err.is_permission_denied(), "unexpected error: {}", err);
+            }
+            // Either way the thread keeps running under its old policy
+            let (policy, _) = current_thread_priority().unwrap();
+            assert!(limit.rlim_cur > 0 || policy == SchedPolicy::Other);
+        })
+        .join()
+        .unwrap();
+    }
+
+    #[test]
+    fn test_prefault_helpers() {
+        prefault_stack(64 * 1024);
+
+        let mut buffer = vec![7u8; 3 * PAGE_SIZE + 1];
+        prefault(&mut buffer);
+        assert!(buffer.iter().all(|&b| b == 7));
+
+        prefault_heap(256 * 1024);
+    }
+}
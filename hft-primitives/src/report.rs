@@ -0,0 +1,607 @@
+//! Run reports for benchmark binaries.
+//!
+//! A [`RunReport`] bundles the [`LatencyMetrics`] produced by a run with the
+//! metadata needed to compare it against other runs (host CPU count, pinning,
+//! iteration count and `git describe` of the source tree).
+//!
+//! Reports can be written (and read back) as CSV unconditionally, and as
+//! JSON when the `serde` feature is enabled.
+
+use crate::histogram::LatencyHistogram;
+use crate::metrics::{csv_field, LatencyMetrics};
+use crate::plot::{print_distribution, PercentileDistribution};
+use crate::sysaudit::HostAudit;
+use std::fs::File;
+use std::io::{self, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Describes the environment a run was executed in.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RunMetadata {
+    /// Name of the binary that produced the report.
+    pub binary: String,
+    /// Number of CPU cores available to the process.
+    pub host_cpus: usize,
+    /// Whether worker threads were pinned to cores.
+    pub pinned: bool,
+    /// Iterations per worker (or messages per run).
+    pub iterations: usize,
+    /// Output of `git describe --always --dirty`, if available.
+    pub git_describe: Option<String>,
+    /// Seconds since the Unix epoch when the metadata was collected.
+    pub timestamp_unix: u64,
+}
+
+impl RunMetadata {
+    /// Collects metadata for the current host.
+    ///
+    /// # Examples
+    /// ```
+    /// use hft_primitives::report::RunMetadata;
+    ///
+    /// let metadata = RunMetadata::collect("latency-lab", true, 100_000);
+    /// assert!(metadata.host_cpus > 0);
+    /// ```
+    pub fn collect(binary: &str, pinned: bool, iterations: usize) -> Self {
+        Self {
+            binary: binary.to_string(),
+            host_cpus: crate::cpu_pinning::get_cpu_count(),
+            pinned,
+            iterations,
+            git_describe: git_describe(),
+            timestamp_unix: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0),
+        }
+    }
+}
+
+/// Leading CSV columns of every report row.
+const CSV_METADATA_HEADER: &str = "binary,host_cpus,pinned,iterations,git_describe";
+
+/// Columns following the metadata in the scalar value section of a CSV report.
+const CSV_VALUE_HEADER: &str = "name,value,unit";
+
+/// Returns `git describe --always --dirty` for the workspace, if git is available.
+fn git_describe() -> Option<String> {
+    let output = Command::new("git")
+        .args(["describe", "--always", "--dirty"])
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let describe = String::from_utf8(output.stdout).ok()?;
+    let describe = describe.trim();
+    (!describe.is_empty()).then(|| describe.to_string())
+}
+
+/// Latency metrics for one named experiment within a run.
+///
+/// The optional histogram keeps the full distribution so later tooling
+/// (e.g. [`compare`](crate::compare)) can run significance tests.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ReportEntry {
+    pub name: String,
+    pub metrics: LatencyMetrics,
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub histogram: Option<LatencyHistogram>,
+}
+
+impl ReportEntry {
+    /// Builds an entry with exact metrics plus the histogram of `samples`.
+    ///
+    /// Samples are sorted in-place.
+    pub fn from_samples(name: &str, samples: &mut [Duration]) -> Self {
+        let histogram = LatencyHistogram::from_samples(samples);
+        Self {
+            name: name.to_string(),
+            metrics: LatencyMetrics::from_samples(samples),
+            histogram: Some(histogram),
+        }
+    }
+
+    /// Prints the entry's latency distribution: a log-scaled chart and
+    /// percentile table when the histogram is present, otherwise the
+    /// percentile table of the summary metrics.
+    pub fn print_distribution(&self) {
+        match &self.histogram {
+            Some(histogram) => print_distribution(&self.name, histogram),
+            None => {
+                println!("=== {} - Distribution ===", self.name);
+                print!("{}", PercentileDistribution::from_metrics(&self.metrics));
+            }
+        }
+    }
+}
+
+/// A named scalar result (throughput, counts, elapsed time).
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ReportValue {
+    pub name: String,
+    pub value: f64,
+    pub unit: String,
+}
+
+/// Machine-readable report of a benchmark run.
+///
+/// # Examples
+/// ```
+/// use hft_primitives::report::{RunMetadata, RunReport};
+/// use hft_primitives::LatencyMetrics;
+/// use std::time::Duration;
+///
+/// let mut samples = vec![Duration::from_nanos(100), Duration::from_nanos(150)];
+/// let mut report = RunReport::new(RunMetadata::collect("example", false, 2));
+/// report.add_metrics("baseline", LatencyMetrics::from_samples(&mut samples));
+/// report.add_value("throughput", 1.5e6, "ops/sec");
+///
+/// let mut csv = Vec::new();
+/// report.write_csv(&mut csv).unwrap();
+/// ```
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RunReport {
+    pub metadata: RunMetadata,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub entries: Vec<ReportEntry>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub values: Vec<ReportValue>,
+    /// Host latency-readiness audit taken before the run, if any.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub audit: Option<HostAudit>,
+}
+
+impl RunReport {
+    /// Creates an empty report with the given metadata.
+    pub fn new(metadata: RunMetadata) -> Self {
+        Self {
+            metadata,
+            entries: Vec::new(),
+            values: Vec::new(),
+            audit: None,
+        }
+    }
+
+    /// Adds the metrics of a named experiment.
+    pub fn add_metrics(&mut self, name: &str, metrics: LatencyMetrics) {
+        self.entries.push(ReportEntry {
+            name: name.to_string(),
+            metrics,
+            histogram: None,
+        });
+    }
+
+    /// Adds raw samples: exact metrics plus the histogram of the distribution.
+    ///
+    /// Samples are sorted in-place.
+    pub fn add_samples(&mut self, name: &str, samples: &mut [Duration]) {
+        self.entries.push(ReportEntry::from_samples(name, samples));
+    }
+
+    /// Adds a histogram along with the metrics summarized from it.
+    pub fn add_histogram(&mut self, name: &str, histogram: LatencyHistogram) {
+        self.entries.push(ReportEntry {
+            name: name.to_string(),
+            metrics: LatencyMetrics::from_histogram(&histogram),
+            histogram: Some(histogram),
+        });
+    }
+
+    /// Adds a prepared entry.
+    pub fn add_entry(&mut self, entry: ReportEntry) {
+        self.entries.push(entry);
+    }
+
+    /// Adds a named scalar result.
+    pub fn add_value(&mut self, name: &str, value: f64, unit: &str) {
+        self.values.push(ReportValue {
+            name: name.to_string(),
+            value,
+            unit: unit.to_string(),
+        });
+    }
+
+    /// Attaches the host audit taken before the run.
+    ///
+    /// The audit is included in JSON reports; CSV reports carry only the
+    /// latency entries and scalar values.
+    pub fn set_audit(&mut self, audit: HostAudit) {
+        self.audit = Some(audit);
+    }
+
+    /// Returns the metrics entry with the given name.
+    pub fn entry(&self, name: &str) -> Option<&ReportEntry> {
+        self.entries.iter().find(|entry| entry.name == name)
+    }
+
+    /// Writes the report as CSV: the latency entries, one row per entry,
+    /// then (after a blank line) the scalar values as `name,value,unit`
+    /// rows under their own header.
+    ///
+    /// Each row is prefixed with the run metadata so rows from several
+    /// reports can be concatenated into a single table. Histograms and the
+    /// audit are not included.
+    pub fn write_csv<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writeln!(
+            writer,
+            "{},{}",
+            CSV_METADATA_HEADER,
+            LatencyMetrics::CSV_HEADER
+        )?;
+        for entry in &self.entries {
+            self.write_csv_metadata(writer)?;
+            entry.metrics.write_csv_row(writer, &entry.name)?;
+            writeln!(writer)?;
+        }
+
+        if !self.values.is_empty() {
+            writeln!(writer)?;
+            writeln!(writer, "{},{}", CSV_METADATA_HEADER, CSV_VALUE_HEADER)?;
+            for value in &self.values {
+                self.write_csv_metadata(writer)?;
+                writeln!(
+                    writer,
+                    "{},{},{}",
+                    csv_field(&value.name),
+                    value.value,
+                    csv_field(&value.unit)
+                )?;
+            }
+        }
+        Ok(())
+    }
+
+    fn write_csv_metadata<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        let meta = &self.metadata;
+        write!(
+            writer,
+            "{},{},{},{},{},",
+            csv_field(&meta.binary),
+            meta.host_cpus,
+            meta.pinned,
+            meta.iterations,
+            csv_field(meta.git_describe.as_deref().unwrap_or("")),
+        )
+    }
+
+    /// Reads a report previously written with [`write_csv`](Self::write_csv).
+    ///
+    /// The metadata is taken from the first row; CSV does not carry
+    /// `timestamp_unix`, which reads back as 0. The consistency ratio
+    /// column is derived and ignored.
+    pub fn read_csv<R: Read>(mut reader: R) -> io::Result<Self> {
+        let mut text = String::new();
+        reader.read_to_string(&mut text)?;
+
+        let entry_header = format!("{},{}", CSV_METADATA_HEADER, LatencyMetrics::CSV_HEADER);
+        let value_header = format!("{},{}", CSV_METADATA_HEADER, CSV_VALUE_HEADER);
+        let mut report: Option<RunReport> = None;
+        let mut in_values = false;
+        for (row, record) in csv_records(&text).into_iter().enumerate() {
+            let row = row + 1;
+            if record.len() == 1 && record[0].is_empty() {
+                continue;
+            }
+            let joined = record.join(",");
+            if joined == entry_header || joined == value_header {
+                in_values = joined == value_header;
+                continue;
+            }
+
+            let expected = if in_values {
+                value_header.split(',').count()
+            } else {
+                entry_header.split(',').count()
+            };
+            if record.len() != expected {
+                return Err(invalid_csv(
+                    row,
+                    format!("expected {} fields, found {}", expected, record.len()),
+                ));
+            }
+            let (meta, fields) = record.split_at(5);
+            let report = match report {
+                Some(ref mut report) => report,
+                None => report.insert(RunReport::new(RunMetadata {
+                    binary: meta[0].clone(),
+                    host_cpus: parse_csv_field(row, "host_cpus", &meta[1])?,
+                    pinned: parse_csv_field(row, "pinned", &meta[2])?,
+                    iterations: parse_csv_field(row, "iterations", &meta[3])?,
+                    git_describe: (!meta[4].is_empty()).then(|| meta[4].clone()),
+                    timestamp_unix: 0,
+                })),
+            };
+
+            if in_values {
+                let value = parse_csv_field(row, "value", &fields[1])?;
+                report.add_value(&fields[0], value, &fields[2]);
+            } else {
+                let nanos = |column: usize, name: &str| {
+                    parse_csv_field(row, name, &fields[column]).map(Duration::from_nanos)
+                };
+                let metrics = LatencyMetrics {
+                    samples: parse_csv_field(row, "samples", &fields[1])?,
+                    min: nanos(2, "min_ns")?,
+                    avg: nanos(3, "avg_ns")?,
+                    p50: nanos(4, "p50_ns")?,
+                    p95: nanos(5, "p95_ns")?,
+                    p99: nanos(6, "p99_ns")?,
+                    p999: nanos(7, "p999_ns")?,
+                    max: nanos(8, "max_ns")?,
+                };
+                report.add_metrics(&fields[0], metrics);
+            }
+        }
+        report.ok_or_else(|| invalid_csv(0, "report has no rows".to_string()))
+    }
+
+    /// Writes the full report as pretty-printed JSON.
+    #[cfg(feature = "serde")]
+    pub fn write_json<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        serde_json::to_writer_pretty(&mut *writer, self)?;
+        writeln!(writer)
+    }
+
+    /// Reads a report previously written with [`write_json`](Self::write_json).
+    #[cfg(feature = "serde")]
+    pub fn read_json<R: io::Read>(reader: R) -> io::Result<Self> {
+        Ok(serde_json::from_reader(reader)?)
+    }
+
+    /// Writes the report to `path`, choosing the format from the extension.
+    ///
+    /// `.csv` files are written as CSV; anything else is written as JSON,
+    /// which requires the `serde` feature.
+    pub fn write_to_path<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let path = path.as_ref();
+        let mut writer = BufWriter::new(File::create(path)?);
+        if is_csv_path(path) {
+            self.write_csv(&mut writer)?;
+        } else {
+            self.write_json_or_unsupported(&mut writer)?;
+        }
+        writer.flush()
+    }
+
+    /// Reads a report written by [`write_to_path`](Self::write_to_path),
+    /// choosing the format from the extension in the same way.
+    pub fn read_from_path<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let path = path.as_ref();
+        let file = File::open(path)?;
+        if is_csv_path(path) {
+            Self::read_csv(file)
+        } else {
+            Self::read_json_or_unsupported(file)
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    fn write_json_or_unsupported<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        self.write_json(writer)
+    }
+
+    #[cfg(not(feature = "serde"))]
+    fn write_json_or_unsupported<W: Write>(&self, _writer: &mut W) -> io::Result<()> {
+        Err(json_unsupported())
+    }
+
+    #[cfg(feature = "serde")]
+    fn read_json_or_unsupported<R: Read>(reader: R) -> io::Result<Self> {
+        Self::read_json(reader)
+    }
+
+    #[cfg(not(feature = "serde"))]
+    fn read_json_or_unsupported<R: Read>(_reader: R) -> io::Result<Self> {
+        Err(json_unsupported())
+    }
+}
+
+fn is_csv_path(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("csv"))
+}
+
+#[cfg(not(feature = "serde"))]
+fn json_unsupported() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::Unsupported,
+        "JSON reports require the `serde` feature",
+    )
+}
+
+/// Splits CSV text into records, undoing the quoting applied by
+/// [`csv_field`].
+fn csv_records(text: &str) -> Vec<Vec<String>> {
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            '"' if quoted => quoted = false,
+            '"' if field.is_empty() => quoted = true,
+            ',' if !quoted => record.push(std::mem::take(&mut field)),
+            '\n' if !quoted => {
+                record.push(std::mem::take(&mut field));
+                records.push(std::mem::take(&mut record));
+            }
+            '\r' if !quoted => {}
+            c => field.push(c),
+        }
+    }
+    if !field.is_empty() || !record.is_empty() {
+        record.push(field);
+        records.push(record);
+    }
+    records
+}
+
+fn parse_csv_field<T: std::str::FromStr>(row: usize, column: &str, value: &str) -> io::Result<T> {
+    value
+        .parse()
+        .map_err(|_| invalid_csv(row, format!("invalid {} {:?}", column, value)))
+}
+
+fn invalid_csv(row: usize, message: String) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("CSV report row {}: {}", row, message),
+    )
+}
+
+/// Returns the path following a `--report` argument on the command line.
+///
+/// Binaries use this to opt into writing a [`RunReport`]:
+/// `cargo run --release -- --report run.json`.
+pub fn report_path_from_args() -> Option<PathBuf> {
+    report_path_from(std::env::args())
+}
+
+fn report_path_from<I: IntoIterator<Item = String>>(args: I) -> Option<PathBuf> {
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        if arg == "--report" {
+            return args.next().map(PathBuf::from);
+        }
+        if let Some(path) = arg.strip_prefix("--report=") {
+            return Some(PathBuf::from(path));
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn sample_report() -> RunReport {
+        let metadata = RunMetadata {
+            binary: "test".to_string(),
+            host_cpus: 8,
+            pinned: true,
+            iterations: 3,
+            git_describe: Some("abc1234".to_string()),
+            timestamp_unix: 0,
+        };
+        let mut samples = vec![
+            Duration::from_nanos(100),
+            Duration::from_nanos(200),
+            Duration::from_nanos(300),
+        ];
+        let mut report = RunReport::new(metadata);
+        report.add_samples("Baseline", &mut samples);
+        report.add_value("throughput", 2.5e6, "ops/sec");
+        report.set_audit(HostAudit::from_roots(
+            "/nonexistent/proc",
+            "/nonexistent/sys",
+        ));
+        report
+    }
+
+    #[test]
+    fn test_write_csv() {
+        let mut out = Vec::new();
+        sample_report().write_csv(&mut out).unwrap();
+        let csv = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+
+        assert_eq!(lines.len(), 5);
+        assert!(lines[0].starts_with("binary,host_cpus,pinned,iterations,git_describe,name,"));
+        assert!(lines[1].starts_with("test,8,true,3,abc1234,Baseline,3,100,"));
+        assert_eq!(lines[2], "");
+        assert_eq!(
+            lines[3],
+            "binary,host_cpus,pinned,iterations,git_describe,name,value,unit"
+        );
+        assert_eq!(lines[4], "test,8,true,3,abc1234,throughput,2500000,ops/sec");
+    }
+
+    #[test]
+    fn test_csv_round_trip() {
+        let mut report = sample_report();
+        report.add_value("p99, \"wire\" to user", 0.125, "µs");
+        report.add_value("dropped", 0.0, "messages");
+        let mut out = Vec::new();
+        report.write_csv(&mut out).unwrap();
+
+        let parsed = RunReport::read_csv(out.as_slice()).unwrap();
+        assert_eq!(
+            parsed.metadata,
+            RunMetadata {
+                timestamp_unix: 0,
+                ..report.metadata.clone()
+            }
+        );
+        assert_eq!(parsed.entries.len(), 1);
+        let (metrics, expected) = (&parsed.entries[0].metrics, &report.entries[0].metrics);
+        assert_eq!(parsed.entries[0].name, "Baseline");
+        assert_eq!(metrics.samples, expected.samples);
+        assert_eq!((metrics.min, metrics.max), (expected.min, expected.max));
+        assert_eq!((metrics.p50, metrics.p999), (expected.p50, expected.p999));
+        assert_eq!(parsed.values, report.values);
+
+        let path = std::env::temp_dir().join(format!("report-{}.CSV", std::process::id()));
+        report.write_to_path(&path).unwrap();
+        let from_path = RunReport::read_from_path(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(from_path.unwrap().values, report.values);
+
+        let truncated = "binary,host_cpus,pinned,iterations,git_describe,name,value,unit\nx,1\n";
+        let err = RunReport::read_csv(truncated.as_bytes()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_report_path_from_args() {
+        let args = |list: &[&str]| list.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+
+        assert_eq!(
+            report_path_from(args(&["bin", "--report", "out.json"])),
+            Some(PathBuf::from("out.json"))
+        );
+        assert_eq!(
+            report_path_from(args(&["bin", "--report=out.csv"])),
+            Some(PathBuf::from("out.csv"))
+        );
+        assert_eq!(report_path_from(args(&["bin"])), None);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_json_round_trip() {
+        let report = sample_report();
+        let mut out = Vec::new();
+        report.write_json(&mut out).unwrap();
+
+        let parsed = RunReport::read_json(out.as_slice()).unwrap();
+        assert_eq!(parsed.metadata, report.metadata);
+        assert_eq!(parsed.entries.len(), 1);
+        assert_eq!(
+            parsed.entry("Baseline").unwrap().metrics.p50,
+            Duration::from_nanos(200)
+        );
+        assert_eq!(parsed.entries[0].histogram, report.entries[0].histogram);
+        assert_eq!(parsed.values, report.values);
+        assert_eq!(parsed.audit, report.audit);
+    }
+}
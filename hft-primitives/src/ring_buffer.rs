@@ -2,8 +2,9 @@
 //!
 //! Optimized for high-frequency trading workloads with predictable latency.
 
+use crate::numa::{MemoryPlacement, NumaBuffer, NumaError};
 use std::cell::UnsafeCell;
-use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 
 /// Lock-free MPSC ring buffer optimized for HFT workloads.
 ///
@@ -11,6 +12,8 @@ use std::sync::atomic::{AtomicUsize, Ordering};
 /// - **Multiple producers and single consumer**
 /// - Producers must not wrap around to consumer position
 /// - Size is rounded up to next power of 2 for fast modulo
+/// - A producer claims slots by moving `head`, writes them, then marks
+///   each slot ready; the consumer only takes ready slots
 ///
 /// # Examples
 /// ```
@@ -22,19 +25,47 @@ use std::sync::atomic::{AtomicUsize, Ordering};
 /// ```
 ///
 /// # Performance Characteristics
-/// - Send: O(1) - CAS operation with Release ordering (may retry under contention)
-/// - Receive: O(1) - Single atomic load with Acquire ordering
+/// - Send: O(1) - CAS to claim, then a Release store per slot (may retry under contention)
+/// - Receive: O(1) - Acquire load of the slot's ready flag
 /// - No allocations after initialization
 /// - Cache-line aligned for minimal false sharing
 pub struct LockFreeRingBuffer<T> {
-    buffer: Box<[UnsafeCell<Option<T>>]>,
+    buffer: NumaBuffer<Slot<T>>,
+    // Free-running positions; the slot index is `position & mask`
     head: AtomicUsize,
     tail: AtomicUsize,
     mask: usize,
 }
 
+/// One buffer slot. `ready` is set (Release) by the producer after writing
+/// `value` and cleared by the consumer after taking it.
+struct Slot<T> {
+    ready: AtomicBool,
+    value: UnsafeCell<Option<T>>,
+}
+
+impl<T> Slot<T> {
+    fn empty() -> Self {
+        Self {
+            ready: AtomicBool::new(false),
+            value: UnsafeCell::new(None),
+        }
+    }
+
+    /// Stores `item` and publishes it to the consumer.
+    ///
+    /// # Safety
+    /// The caller must own the slot: it claimed it by advancing `head`, and
+    /// the consumer has not yet taken it.
+    unsafe fn publish(&self, item: T) {
+        *self.value.get() = Some(item);
+        self.ready.store(true, Ordering::Release);
+    }
+}
+
 // SAFETY: LockFreeRingBuffer uses atomic operations for synchronization
-// The head/tail pointers ensure no two threads access the same buffer slot simultaneously
+// The head/tail positions ensure no two producers claim the same slot, and
+// the per-slot ready flag keeps the consumer off slots still being written
 unsafe impl<T: Send> Send for LockFreeRingBuffer<T> {}
 unsafe impl<T: Send> Sync for LockFreeRingBuffer<T> {}
 
@@ -52,19 +83,42 @@ impl<T> LockFreeRingBuffer<T> {
     /// ```
     pub fn new(size: usize) -> Self {
         let capacity = size.next_power_of_two();
-        let mask = capacity - 1;
+        Self::from_buffer(NumaBuffer::new(capacity, |_| Slot::empty()))
+    }
 
-        let buffer: Vec<UnsafeCell<Option<T>>> =
-            (0..capacity).map(|_| UnsafeCell::new(None)).collect();
+    /// Creates a ring buffer whose slots are allocated with a NUMA
+    /// placement hint, e.g. on the node of the pinned consumer.
+    ///
+    /// # Examples
+    /// ```
+    /// use hft_primitives::numa::MemoryPlacement;
+    /// use hft_primitives::LockFreeRingBuffer;
+    ///
+    /// let queue = LockFreeRingBuffer::<u64>::with_placement(1024, &MemoryPlacement::Node(0))
+    ///     .unwrap_or_else(|_| LockFreeRingBuffer::new(1024));
+    /// queue.send(7).unwrap();
+    /// assert_eq!(queue.receive(), Some(7));
+    /// ```
+    pub fn with_placement(size: usize, placement: &MemoryPlacement) -> Result<Self, NumaError> {
+        let capacity = size.next_power_of_two();
+        NumaBuffer::with_placement(capacity, placement, |_| Slot::empty()).map(Self::from_buffer)
+    }
 
+    fn from_buffer(buffer: NumaBuffer<Slot<T>>) -> Self {
+        let mask = buffer.len() - 1;
         Self {
-            buffer: buffer.into_boxed_slice(),
+            buffer,
             head: AtomicUsize::new(0),
             tail: AtomicUsize::new(0),
             mask,
         }
     }
 
+    /// Returns the placement the slots were allocated with.
+    pub fn placement(&self) -> &MemoryPlacement {
+        self.buffer.placement()
+    }
+
     /// Attempts to send an item into the buffer (MPSC safe).
     ///
     /// Returns `Err(item)` if the buffer is full.
@@ -82,24 +136,28 @@ impl<T> LockFreeRingBuffer<T> {
     /// ```
     pub fn send(&self, item: T) -> Result<(), T> {
         loop {
-            let current_head = self.head.load(Ordering::Relaxed);
-            let next_head = (current_head + 1) & self.mask;
+            // Tail first: a head read before it can be stale and behind a
+            // tail the consumer has since advanced
             let current_tail = self.tail.load(Ordering::Acquire);
-            if next_head == current_tail {
+            let current_head = self.head.load(Ordering::Relaxed);
+            let used = current_head.wrapping_sub(current_tail);
+            if used > self.mask {
+                continue; // Inconsistent snapshot, read both again
+            }
+            // One slot stays empty to distinguish full from empty
+            if used == self.mask {
                 return Err(item); // Buffer full
             }
             match self.head.compare_exchange_weak(
-                current_head, // Use the variable you declared
-                next_head,    // Use the variable you declared
-                Ordering::Release,
+                current_head,
+                current_head.wrapping_add(1),
+                Ordering::Relaxed,
                 Ordering::Relaxed,
             ) {
                 Ok(_) => {
-                    // CAS succeeded - now write to buffer slot
-                    let cell = &self.buffer[current_head]; // current_head is in scope
-                    unsafe {
-                        *cell.get() = Some(item);
-                    }
+                    // CAS succeeded - the slot is ours until it is published
+                    let slot = &self.buffer[current_head & self.mask];
+                    unsafe { slot.publish(item) };
                     return Ok(()); // Exit successfully
                 }
                 Err(_) => {
@@ -110,9 +168,64 @@ impl<T> LockFreeRingBuffer<T> {
         }
     }
 
+    /// Sends as many items from the front of `items` as fit, claiming all
+    /// of their slots with a single CAS (MPSC safe).
+    ///
+    /// Sent items are removed from `items`; those that did not fit stay.
+    /// Returns the number sent.
+    ///
+    /// # Examples
+    /// ```
+    /// use hft_primitives::LockFreeRingBuffer;
+    ///
+    /// let queue = LockFreeRingBuffer::new(4);
+    /// let mut items = vec![1, 2, 3, 4, 5];
+    /// assert_eq!(queue.send_batch(&mut items), 3);
+    /// assert_eq!(items, [4, 5]);
+    /// assert_eq!(queue.receive(), Some(1));
+    /// ```
+    pub fn send_batch(&self, items: &mut Vec<T>) -> usize {
+        loop {
+            // Same load order as `send`
+            let current_tail = self.tail.load(Ordering::Acquire);
+            let current_head = self.head.load(Ordering::Relaxed);
+            let used = current_head.wrapping_sub(current_tail);
+            if used > self.mask {
+                continue;
+            }
+            // One slot stays empty to distinguish full from empty
+            let free = self.mask - used;
+            let count = items.len().min(free);
+            if count == 0 {
+                return 0;
+            }
+            if self
+                .head
+                .compare_exchange_weak(
+                    current_head,
+                    current_head.wrapping_add(count),
+                    Ordering::Relaxed,
+                    Ordering::Relaxed,
+                )
+                .is_err()
+            {
+                // Another producer claimed slots first
+                continue;
+            }
+            // Publish in order; the consumer stops at the first slot that
+            // is claimed but not yet written
+            for (offset, item) in items.drain(..count).enumerate() {
+                let slot = &self.buffer[current_head.wrapping_add(offset) & self.mask];
+                unsafe { slot.publish(item) };
+            }
+            return count;
+        }
+    }
+
     /// Attempts to receive an item from the buffer.
     ///
-    /// Returns `None` if the buffer is empty.
+    /// Returns `None` if the buffer is empty, or if the next slot has been
+    /// claimed by a producer that has not finished writing it yet.
     ///
     /// # Examples
     /// ```
@@ -125,16 +238,16 @@ impl<T> LockFreeRingBuffer<T> {
     /// ```
     pub fn receive(&self) -> Option<T> {
         let current_tail = self.tail.load(Ordering::Relaxed);
-        let current_head = self.head.load(Ordering::Acquire);
-
-        if current_head == current_tail {
-            return None; // Buffer empty
+        let slot = &self.buffer[current_tail & self.mask];
+        if !slot.ready.load(Ordering::Acquire) {
+            return None; // Buffer empty, or the producer is mid-write
         }
 
-        let cell = &self.buffer[current_tail];
-        let item = unsafe { (*cell.get()).take() };
-        let next_tail = (current_tail + 1) & self.mask;
-        self.tail.store(next_tail, Ordering::Release);
+        // SAFETY: the ready flag hands the slot to the single consumer
+        let item = unsafe { (*slot.value.get()).take() };
+        slot.ready.store(false, Ordering::Relaxed);
+        self.tail
+            .store(current_tail.wrapping_add(1), Ordering::Release);
         item
     }
 
@@ -147,9 +260,13 @@ impl<T> LockFreeRingBuffer<T> {
     ///
     /// Note: This is a snapshot and may be stale immediately.
     pub fn len(&self) -> usize {
-        let head = self.head.load(Ordering::Relaxed);
-        let tail = self.tail.load(Ordering::Relaxed);
-        (head.wrapping_sub(tail)) & self.mask
+        let tail = self.tail.load(Ordering::Acquire);
+        let head = self.head.load(Ordering::Acquire);
+        match head.wrapping_sub(tail) {
+            // A stale head read behind the tail
+            len if len > self.mask => 0,
+            len => len,
+        }
     }
 
     /// Returns true if the buffer is approximately empty.
@@ -215,6 +332,76 @@ mod tests {
         assert_eq!(count, 4000);
     }
 
+    #[test]
+    fn test_mpsc_batches_while_receiving() {
+        use std::sync::Arc;
+        use std::thread;
+
+        const PRODUCERS: u64 = 4;
+        const PER_PRODUCER: u64 = 20_000;
+
+        // Small enough that producers wrap and wait on the consumer
+        let queue = Arc::new(LockFreeRingBuffer::new(64));
+        let producers: Vec<_> = (0..PRODUCERS)
+            .map(|p| {
+                let q = Arc::clone(&queue);
+                thread::spawn(move || {
+                    let mut next = 0;
+                    while next < PER_PRODUCER {
+                        let end = (next + 1 + next % 7).min(PER_PRODUCER);
+                        let mut batch: Vec<u64> = (next..end).map(|i| p << 32 | i).collect();
+                        while !batch.is_empty() {
+                            if q.send_batch(&mut batch) == 0 {
+                                thread::yield_now();
+                            }
+                        }
+                        next = end;
+                    }
+                })
+            })
+            .collect();
+
+        // Each producer's items must arrive complete and in order
+        let mut expected = [0u64; PRODUCERS as usize];
+        let mut received = 0;
+        while received < PRODUCERS * PER_PRODUCER {
+            match queue.receive() {
+                Some(item) => {
+                    let producer = (item >> 32) as usize;
+                    assert_eq!(item & 0xFFFF_FFFF, expected[producer]);
+                    expected[producer] += 1;
+                    received += 1;
+                }
+                None => thread::yield_now(),
+            }
+        }
+        for p in producers {
+            p.join().unwrap();
+        }
+        assert!(queue.is_empty());
+        assert_eq!(queue.receive(), None);
+    }
+
+    #[test]
+    fn test_send_batch_wraps() {
+        let queue = LockFreeRingBuffer::new(8);
+        let mut items: Vec<i32> = (0..5).collect();
+        assert_eq!(queue.send_batch(&mut items), 5);
+        for i in 0..5 {
+            assert_eq!(queue.receive(), Some(i));
+        }
+
+        // Head is at 5, so this batch wraps around the end of the slots
+        let mut items: Vec<i32> = (10..20).collect();
+        assert_eq!(queue.send_batch(&mut items), 7);
+        assert_eq!(items, [17, 18, 19]);
+        assert_eq!(queue.send_batch(&mut items), 0);
+        for i in 10..17 {
+            assert_eq!(queue.receive(), Some(i));
+        }
+        assert!(queue.is_empty());
+    }
+
     #[test]
     fn test_full_buffer() {
         let queue = LockFreeRingBuffer::new(4);
@@ -224,4 +411,21 @@ mod tests {
         // Buffer full (capacity - 1 to distinguish from empty)
         assert!(queue.send(4).is_err());
     }
+
+    #[test]
+    fn test_with_placement() {
+        let placement = MemoryPlacement::FirstTouch { cpu: 0 };
+        let queue = match LockFreeRingBuffer::with_placement(100, &placement) {
+            Ok(queue) => queue,
+            // Not Linux, or CPU 0 outside this process's affinity
+            Err(_) => return,
+        };
+        assert_eq!(queue.capacity(), 128);
+        assert_eq!(queue.placement(), &placement);
+        for i in 0..127 {
+            queue.send(i).unwrap();
+        }
+        assert!(queue.send(127).is_err());
+        assert_eq!(queue.receive(), Some(0));
+    }
 }
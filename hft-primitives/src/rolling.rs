@@ -0,0 +1,327 @@
+//! Time-windowed rolling latency metrics.
+//!
+//! A whole-run [`LatencyMetrics`] hides short incidents: a 30-second hiccup
+//! in a 6-hour session barely moves the P99. [`RollingLatency`] keeps one
+//! histogram per interval (e.g. one per second) in a fixed ring, answers
+//! "P99 over the last N seconds" and hands each completed interval to a
+//! callback so it can be exported as a time series.
+
+use crate::histogram::LatencyHistogram;
+use crate::metrics::LatencyMetrics;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+/// One completed interval, passed to the [`RollingLatency::on_interval`] callback.
+#[derive(Debug)]
+pub struct IntervalSnapshot<'a> {
+    /// Interval number since the recorder started (0-based).
+    pub index: u64,
+    /// Offset of the interval start from the recorder's epoch.
+    pub start: Duration,
+    /// Length of the interval.
+    pub duration: Duration,
+    /// Summary of the interval (all zero if nothing was recorded).
+    pub metrics: LatencyMetrics,
+    /// Full distribution of the interval.
+    pub histogram: &'a LatencyHistogram,
+}
+
+type IntervalCallback = Box<dyn FnMut(&IntervalSnapshot<'_>) + Send>;
+
+struct Slot {
+    /// Interval this slot currently holds data for.
+    interval: u64,
+    histogram: LatencyHistogram,
+}
+
+/// Ring of per-interval latency histograms.
+///
+/// Memory is allocated up front (one [`LatencyHistogram`], ~20KB, per
+/// interval); recording never allocates.
+///
+/// # Examples
+/// ```
+/// use hft_primitives::rolling::RollingLatency;
+/// use std::time::{Duration, Instant};
+///
+/// let epoch = Instant::now();
+/// let mut rolling = RollingLatency::starting_at(epoch, Duration::from_secs(1), 300);
+/// rolling.on_interval(|snapshot| {
+///     println!("t={}s p99={:?}", snapshot.index, snapshot.metrics.p99);
+/// });
+///
+/// rolling.record_at(epoch, Duration::from_nanos(200));
+/// rolling.record_at(epoch + Duration::from_secs(1), Duration::from_micros(50));
+///
+/// let now = epoch + Duration::from_millis(1500);
+/// let last_second = rolling.percentile_over_at(now, Duration::from_secs(1), 0.99);
+/// assert_eq!(last_second, Duration::from_micros(50));
+/// ```
+pub struct RollingLatency {
+    epoch: Instant,
+    interval: Duration,
+    slots: Box<[Slot]>,
+    current: u64,
+    callback: Option<IntervalCallback>,
+}
+
+impl RollingLatency {
+    /// Creates a ring of `intervals` histograms, each covering `interval`,
+    /// starting now.
+    pub fn new(interval: Duration, intervals: usize) -> Self {
+        Self::starting_at(Instant::now(), interval, intervals)
+    }
+
+    /// Creates a ring whose first interval starts at `epoch`.
+    ///
+    /// # Panics
+    /// Panics if `interval` is zero or `intervals` is zero.
+    pub fn starting_at(epoch: Instant, interval: Duration, intervals: usize) -> Self {
+        assert!(!interval.is_zero(), "interval must be non-zero");
+        assert!(intervals > 0, "at least one interval is required");
+
+        let slots = (0..intervals)
+            .map(|i| Slot {
+                interval: i as u64,
+                histogram: LatencyHistogram::new(),
+            })
+            .collect();
+
+        Self {
+            epoch,
+            interval,
+            slots,
+            current: 0,
+            callback: None,
+        }
+    }
+
+    /// Registers a callback invoked once for every completed interval.
+    ///
+    /// Intervals complete lazily, when a later [`record_at`](Self::record_at)
+    /// or [`advance_to`](Self::advance_to) moves past them. Idle intervals are
+    /// reported with empty metrics. After a long idle gap the interval that
+    /// was in progress is still reported, followed by at most
+    /// `intervals - 1` of the idle intervals just before the new one.
+    pub fn on_interval<F>(&mut self, callback: F)
+    where
+        F: FnMut(&IntervalSnapshot<'_>) + Send + 'static,
+    {
+        self.callback = Some(Box::new(callback));
+    }
+
+    /// Returns the length of one interval.
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    /// Returns the total time span covered by the ring.
+    pub fn span(&self) -> Duration {
+        self.interval * self.slots.len() as u32
+    }
+
+    /// Records a latency sample at the current time.
+    #[inline]
+    pub fn record(&mut self, latency: Duration) {
+        self.record_at(Instant::now(), latency);
+    }
+
+    /// Records a latency sample observed at `now`.
+    pub fn record_at(&mut self, now: Instant, latency: Duration) {
+        self.advance_to(now);
+        let index = self.slot_index(self.current);
+        self.slots[index].histogram.record(latency);
+    }
+
+    /// Moves the ring forward to `now`, completing (and reporting) every
+    /// interval that ended before it.
+    ///
+    /// Call periodically from a timer if samples may stop arriving but
+    /// snapshots are still wanted.
+    pub fn advance_to(&mut self, now: Instant) {
+        let target = self.interval_at(now);
+        if target <= self.current {
+            return;
+        }
+
+        // The interval in progress holds samples; always report it
+        self.emit(self.current);
+
+        // Then at most one ring's worth of idle intervals, newest last
+        let capacity = self.slots.len() as u64;
+        let first_idle = (self.current + 1).max(target.saturating_sub(capacity - 1));
+        for idle in first_idle..target {
+            self.prepare_slot(idle);
+            self.emit(idle);
+        }
+
+        self.current = target;
+        self.prepare_slot(target);
+    }
+
+    /// Merges the intervals overlapping the last `window` (including the
+    /// current, partial interval) into one histogram.
+    pub fn histogram_over(&self, window: Duration) -> LatencyHistogram {
+        self.histogram_over_at(Instant::now(), window)
+    }
+
+    /// Like [`histogram_over`](Self::histogram_over), evaluated at `now`.
+    ///
+    /// The window is rounded up to whole intervals and capped at the ring
+    /// span.
+    pub fn histogram_over_at(&self, now: Instant, window: Duration) -> LatencyHistogram {
+        let newest = self.interval_at(now).max(self.current);
+        let count = (window.as_nanos().div_ceil(self.interval.as_nanos()) as u64)
+            .clamp(1, self.slots.len() as u64);
+        let oldest = (newest + 1).saturating_sub(count);
+
+        let mut merged = LatencyHistogram::new();
+        for slot in self.slots.iter() {
+            if slot.interval >= oldest && slot.interval <= newest {
+                merged.merge(&slot.histogram);
+            }
+        }
+        merged
+    }
+
+    /// Returns the value at `quantile` over the last `window`.
+    pub fn percentile_over(&self, window: Duration, quantile: f64) -> Duration {
+        self.histogram_over(window).value_at_quantile(quantile)
+    }
+
+    /// Like [`percentile_over`](Self::percentile_over), evaluated at `now`.
+    pub fn percentile_over_at(&self, now: Instant, window: Duration, quantile: f64) -> Duration {
+        self.histogram_over_at(now, window)
+            .value_at_quantile(quantile)
+    }
+
+    /// Summarizes the last `window`.
+    pub fn metrics_over(&self, window: Duration) -> LatencyMetrics {
+        LatencyMetrics::from_histogram(&self.histogram_over(window))
+    }
+
+    fn interval_at(&self, now: Instant) -> u64 {
+        let offset = now.saturating_duration_since(self.epoch);
+        (offset.as_nanos() / self.interval.as_nanos()) as u64
+    }
+
+    fn slot_index(&self, interval: u64) -> usize {
+        (interval % self.slots.len() as u64) as usize
+    }
+
+    /// Makes the slot for `interval` hold that interval, clearing stale data.
+    fn prepare_slot(&mut self, interval: u64) {
+        let index = self.slot_index(interval);
+        let slot = &mut self.slots[index];
+        if slot.interval != interval {
+            slot.interval = interval;
+            slot.histogram.clear();
+        }
+    }
+
+    fn emit(&mut self, interval: u64) {
+        let Some(callback) = self.callback.as_mut() else {
+            return;
+        };
+        let slot = &self.slots[(interval % self.slots.len() as u64) as usize];
+        callback(&IntervalSnapshot {
+            index: interval,
+            start: Duration::from_nanos((self.interval.as_nanos() * interval as u128) as u64),
+            duration: self.interval,
+            metrics: LatencyMetrics::from_histogram(&slot.histogram),
+            histogram: &slot.histogram,
+        });
+    }
+}
+
+impl fmt::Debug for RollingLatency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RollingLatency")
+            .field("interval", &self.interval)
+            .field("intervals", &self.slots.len())
+            .field("current", &self.current)
+            .field("has_callback", &self.callback.is_some())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    const SECOND: Duration = Duration::from_secs(1);
+
+    #[test]
+    fn test_windowed_percentiles() {
+        let epoch = Instant::now();
+        let mut rolling = RollingLatency::starting_at(epoch, SECOND, 10);
+
+        // 5 quiet seconds, then one second with a hiccup
+        for second in 0..5 {
+            for _ in 0..100 {
+                rolling.record_at(epoch + SECOND * second, Duration::from_nanos(100));
+            }
+        }
+        for _ in 0..100 {
+            rolling.record_at(epoch + SECOND * 5, Duration::from_micros(30));
+        }
+
+        let now = epoch + SECOND * 5 + Duration::from_millis(500);
+        assert_eq!(
+            rolling.percentile_over_at(now, SECOND, 0.99),
+            Duration::from_micros(30)
+        );
+        let all = rolling.histogram_over_at(now, SECOND * 10);
+        assert_eq!(all.len(), 600);
+        assert_eq!(all.value_at_quantile(0.5), Duration::from_nanos(100));
+
+        // Two seconds later the hiccup has left a 1s window
+        let later = epoch + SECOND * 7;
+        assert!(rolling.histogram_over_at(later, SECOND).is_empty());
+    }
+
+    #[test]
+    fn test_ring_wraps_and_drops_old_intervals() {
+        let epoch = Instant::now();
+        let mut rolling = RollingLatency::starting_at(epoch, SECOND, 3);
+
+        rolling.record_at(epoch, Duration::from_nanos(1));
+        rolling.record_at(epoch + SECOND * 4, Duration::from_nanos(2));
+
+        let merged = rolling.histogram_over_at(epoch + SECOND * 4, SECOND * 60);
+        assert_eq!(merged.len(), 1);
+        assert_eq!(merged.min(), Duration::from_nanos(2));
+        assert_eq!(rolling.span(), SECOND * 3);
+    }
+
+    #[test]
+    fn test_interval_callback() {
+        let epoch = Instant::now();
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let mut rolling = RollingLatency::starting_at(epoch, SECOND, 4);
+        let sink = Arc::clone(&seen);
+        rolling.on_interval(move |snapshot| {
+            sink.lock()
+                .unwrap()
+                .push((snapshot.index, snapshot.metrics.samples, snapshot.start));
+        });
+
+        rolling.record_at(epoch, Duration::from_nanos(10));
+        rolling.record_at(epoch + Duration::from_millis(10), Duration::from_nanos(20));
+        rolling.record_at(epoch + SECOND * 2, Duration::from_nanos(30));
+        assert_eq!(
+            *seen.lock().unwrap(),
+            vec![(0, 2, Duration::ZERO), (1, 0, SECOND)]
+        );
+
+        // A long idle gap reports the interval in progress, then only the
+        // idle intervals that fit in the ring
+        rolling.advance_to(epoch + SECOND * 100);
+        let seen = seen.lock().unwrap();
+        assert_eq!(seen.len(), 2 + 4);
+        assert_eq!(seen[2], (2, 1, SECOND * 2));
+        let idle: Vec<(u64, usize)> = seen[3..].iter().map(|s| (s.0, s.1)).collect();
+        assert_eq!(idle, [(97, 0), (98, 0), (99, 0)]);
+    }
+}
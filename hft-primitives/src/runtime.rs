@@ -0,0 +1,634 @@
+//! Thread-per-core runtime for named, pinned workers.
+//!
+//! Every benchmark binary needs the same scaffolding: spawn N threads, pin
+//! each to a core, hold them at a start line so they begin together, tell
+//! them to stop, and collect their results. [`Runtime`] does this once;
+//! [`RunningRuntime::join`] reports each worker's result (or panic) by name,
+//! and [`stop_on_signals`] lets Ctrl+C stop the workers the same way.
+
+use crate::cpu_pinning::{try_pin_thread_to_core, PinError};
+use crate::realtime::{self, RealtimeError, SchedPolicy};
+use std::any::Any;
+use std::fmt;
+use std::io;
+use std::sync::atomic::{AtomicBool, AtomicU8, AtomicUsize, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::thread::{self, JoinHandle};
+
+/// Shared flag asking workers to finish.
+///
+/// Cloning is cheap; every clone observes the same flag, so one can be
+/// handed to a signal handler or a supervising thread.
+#[derive(Debug, Clone, Default)]
+pub struct StopFlag(Arc<AtomicBool>);
+
+impl StopFlag {
+    /// Creates a flag that is not set.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Asks every worker observing this flag to stop.
+    pub fn stop(&self) {
+        self.0.store(true, Ordering::Release);
+    }
+
+    /// Returns true once [`stop`](Self::stop) has been called.
+    #[inline]
+    pub fn is_stopped(&self) -> bool {
+        self.0.load(Ordering::Acquire)
+    }
+}
+
+/// What a worker knows about itself, passed to its body.
+#[derive(Debug, Clone)]
+pub struct WorkerContext {
+    name: String,
+    index: usize,
+    cpu: Option<usize>,
+    stop: StopFlag,
+}
+
+impl WorkerContext {
+    /// Returns the worker's name.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the worker's position in the order workers were added.
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// Returns the CPU the worker was asked to run on.
+    pub fn cpu(&self) -> Option<usize> {
+        self.cpu
+    }
+
+    /// Returns true once the runtime has been asked to stop.
+    #[inline]
+    pub fn should_stop(&self) -> bool {
+        self.stop.is_stopped()
+    }
+
+    /// Returns the runtime's stop flag, e.g. for a worker that decides the
+    /// whole run is over.
+    pub fn stop_flag(&self) -> &StopFlag {
+        &self.stop
+    }
+}
+
+type WorkerBody<T> = Box<dyn FnOnce(&WorkerContext) -> T + Send>;
+
+/// Description of one worker thread.
+pub struct Worker<T> {
+    name: String,
+    cpu: Option<usize>,
+    realtime_priority: Option<i32>,
+    prefault_stack: Option<usize>,
+    body: WorkerBody<T>,
+}
+
+impl<T> Worker<T> {
+    /// Creates a worker that runs `body` once started.
+    pub fn new<S, F>(name: S, body: F) -> Self
+    where
+        S: Into<String>,
+        F: FnOnce(&WorkerContext) -> T + Send + 'static,
+    {
+        Self {
+            name: name.into(),
+            cpu: None,
+            realtime_priority: None,
+            prefault_stack: None,
+            body: Box::new(body),
+        }
+    }
+
+    /// Pins the worker to `cpu` before it reaches the start line.
+    pub fn pin_to(mut self, cpu: usize) -> Self {
+        self.cpu = Some(cpu);
+        self
+    }
+
+    /// Runs the worker under `SCHED_FIFO` at `priority` (1-99).
+    ///
+    /// Requires `CAP_SYS_NICE`; without it the worker runs at normal
+    /// priority and the failure is reported in its [`WorkerReport`].
+    pub fn realtime_priority(mut self, priority: i32) -> Self {
+        self.realtime_priority = Some(priority);
+        self
+    }
+
+    /// Touches `bytes` of the worker's stack before it reaches the start
+    /// line, so its first deep calls do not page-fault.
+    pub fn prefault_stack(mut self, bytes: usize) -> Self {
+        self.prefault_stack = Some(bytes);
+        self
+    }
+}
+
+impl<T> fmt::Debug for Worker<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Worker")
+            .field("name", &self.name)
+            .field("cpu", &self.cpu)
+            .field("realtime_priority", &self.realtime_priority)
+            .field("prefault_stack", &self.prefault_stack)
+            .finish()
+    }
+}
+
+/// A worker that panicked, with the panic message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WorkerPanic(pub String);
+
+impl fmt::Display for WorkerPanic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "worker panicked: {}", self.0)
+    }
+}
+
+impl std::error::Error for WorkerPanic {}
+
+/// Outcome of one worker, returned by [`RunningRuntime::join`].
+#[derive(Debug)]
+pub struct WorkerReport<T> {
+    pub name: String,
+    pub cpu: Option<usize>,
+    /// The body's return value, or its panic.
+    pub result: Result<T, WorkerPanic>,
+    /// Set if pinning was requested and failed (the worker ran unpinned).
+    pub pin_error: Option<PinError>,
+    /// Set if a realtime priority was requested and could not be applied.
+    pub priority_error: Option<RealtimeError>,
+}
+
+impl<T> WorkerReport<T> {
+    /// Returns true if the worker completed and its setup succeeded.
+    pub fn is_clean(&self) -> bool {
+        self.result.is_ok() && self.pin_error.is_none() && self.priority_error.is_none()
+    }
+}
+
+/// Start-line states shared between the runtime and its workers.
+const GATE_WAITING: u8 = 0;
+const GATE_OPEN: u8 = 1;
+const GATE_ABORTED: u8 = 2;
+
+struct StartGate {
+    ready: AtomicUsize,
+    state: AtomicU8,
+}
+
+/// Counts a worker as arrived at the start line when dropped, so a worker
+/// that panics during setup cannot leave [`Runtime::start`] waiting forever.
+struct Arrival<'a>(&'a StartGate);
+
+impl Drop for Arrival<'_> {
+    fn drop(&mut self) {
+        self.0.ready.fetch_add(1, Ordering::AcqRel);
+    }
+}
+
+/// Builder for a set of workers started together.
+///
+/// # Examples
+/// ```
+/// use hft_primitives::runtime::{Runtime, Worker};
+///
+/// let mut runtime = Runtime::new();
+/// for i in 0..2 {
+///     runtime.add(Worker::new(format!("worker-{}", i), move |ctx| {
+///         let mut spins = 0u64;
+///         while !ctx.should_stop() && spins < 1_000 {
+///             spins += 1;
+///         }
+///         spins + i as u64
+///     }));
+/// }
+///
+/// let running = runtime.start().unwrap();
+/// running.stop();
+/// for report in running.join() {
+///     println!("{}: {:?}", report.name, report.result);
+///     assert!(report.result.is_ok());
+/// }
+/// ```
+pub struct Runtime<T> {
+    workers: Vec<Worker<T>>,
+    stop: StopFlag,
+}
+
+impl<T: Send + 'static> Runtime<T> {
+    /// Creates an empty runtime.
+    pub fn new() -> Self {
+        Self {
+            workers: Vec::new(),
+            stop: StopFlag::new(),
+        }
+    }
+
+    /// Adds a worker.
+    pub fn add(&mut self, worker: Worker<T>) -> &mut Self {
+        self.workers.push(worker);
+        self
+    }
+
+    /// Returns the stop flag shared with every worker.
+    pub fn stop_flag(&self) -> StopFlag {
+        self.stop.clone()
+    }
+
+    /// Spawns every worker, waits until all have applied their affinity and
+    /// priority, then releases them at once.
+    ///
+    /// A worker that panics during setup still counts as arrived; it is
+    /// reported as panicked by [`RunningRuntime::join`].
+    ///
+    /// # Errors
+    /// Returns the OS error if a thread cannot be spawned; workers already
+    /// spawned exit without running their body.
+    pub fn start(self) -> io::Result<RunningRuntime<T>> {
+        let gate = Arc::new(StartGate {
+            ready: AtomicUsize::new(0),
+            state: AtomicU8::new(GATE_WAITING),
+        });
+
+        let mut handles = Vec::with_capacity(self.workers.len());
+        for (index, worker) in self.workers.into_iter().enumerate() {
+            let context = WorkerContext {
+                name: worker.name.clone(),
+                index,
+                cpu: worker.cpu,
+                stop: self.stop.clone(),
+            };
+            let name = worker.name.clone();
+            let cpu = worker.cpu;
+            let gate_clone = Arc::clone(&gate);
+            let spawned = thread::Builder::new()
+                .name(name.clone())
+                .spawn(move || run_worker(worker, context, &gate_clone));
+
+            match spawned {
+                Ok(handle) => handles.push(SpawnedWorker { name, cpu, handle }),
+                Err(e) => {
+                    gate.state.store(GATE_ABORTED, Ordering::Release);
+                    for spawned in handles {
+                        let _ = spawned.handle.join();
+                    }
+                    return Err(e);
+                }
+            }
+        }
+
+        while gate.ready.load(Ordering::Acquire) < handles.len() {
+            thread::yield_now();
+        }
+        gate.state.store(GATE_OPEN, Ordering::Release);
+
+        Ok(RunningRuntime {
+            handles,
+            stop: self.stop,
+        })
+    }
+}
+
+impl<T: Send + 'static> Default for Runtime<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> fmt::Debug for Runtime<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Runtime")
+            .field("workers", &self.workers)
+            .finish()
+    }
+}
+
+struct SpawnedWorker<T> {
+    name: String,
+    cpu: Option<usize>,
+    handle: JoinHandle<WorkerExit<T>>,
+}
+
+/// Per-thread result before it is turned into a [`WorkerReport`].
+struct WorkerExit<T> {
+    result: Option<Result<T, WorkerPanic>>,
+    pin_error: Option<PinError>,
+    priority_error: Option<RealtimeError>,
+}
+
+fn run_worker<T>(worker: Worker<T>, context: WorkerContext, gate: &StartGate) -> WorkerExit<T> {
+    let arrival = Arrival(gate);
+    let pin_error = worker.cpu.and_then(|cpu| try_pin_thread_to_core(cpu).err());
+    let priority_error = worker
+        .realtime_priority
+        .and_then(|priority| realtime::set_thread_priority(SchedPolicy::Fifo, priority).err());
+    if let Some(bytes) = worker.prefault_stack {
+        realtime::prefault_stack(bytes);
+    }
+
+    drop(arrival);
+    let state = loop {
+        match gate.state.load(Ordering::Acquire) {
+            GATE_WAITING => thread::yield_now(),
+            state => break state,
+        }
+    };
+
+    let result = (state == GATE_OPEN).then(|| {
+        let body = worker.body;
+        std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| body(&context)))
+            .map_err(|payload| WorkerPanic(panic_message(payload.as_ref())))
+    });
+
+    WorkerExit {
+        result,
+        pin_error,
+        priority_error,
+    }
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "non-string panic payload".to_string()
+    }
+}
+
+/// Workers that have been started; see [`Runtime::start`].
+pub struct RunningRuntime<T> {
+    handles: Vec<SpawnedWorker<T>>,
+    stop: StopFlag,
+}
+
+impl<T> RunningRuntime<T> {
+    /// Asks every worker to stop (see [`WorkerContext::should_stop`]).
+    pub fn stop(&self) {
+        self.stop.stop();
+    }
+
+    /// Returns the stop flag shared with every worker.
+    pub fn stop_flag(&self) -> StopFlag {
+        self.stop.clone()
+    }
+
+    /// Waits for every worker and reports each one, in the order they were
+    /// added. Does not set the stop flag.
+    pub fn join(self) -> Vec<WorkerReport<T>> {
+        self.handles
+            .into_iter()
+            .map(|SpawnedWorker { name, cpu, handle }| {
+                match handle.join() {
+                    Ok(exit) => WorkerReport {
+                        name,
+                        cpu,
+                        result: exit.result.expect("gate was opened before join"),
+                        pin_error: exit.pin_error,
+                        priority_error: exit.priority_error,
+                    },
+                    // run_worker catches the body's panic, so this only
+                    // happens if setup itself panicked
+                    Err(payload) => WorkerReport {
+                        name,
+                        cpu,
+                        result: Err(WorkerPanic(panic_message(payload.as_ref()))),
+                        pin_error: None,
+                        priority_error: None,
+                    },
+                }
+            })
+            .collect()
+    }
+
+    /// Sets the stop flag and waits for every worker.
+    pub fn stop_and_join(self) -> Vec<WorkerReport<T>> {
+        self.stop();
+        self.join()
+    }
+}
+
+impl<T> fmt::Debug for RunningRuntime<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RunningRuntime")
+            .field("workers", &self.handles.len())
+            .field("stopped", &self.stop.is_stopped())
+            .finish()
+    }
+}
+
+/// Flag set by the `SIGINT`/`SIGTERM` handler.
+static SIGNAL_STOP: OnceLock<StopFlag> = OnceLock::new();
+
+/// Sets `stop` on the first `SIGINT` or `SIGTERM`, so Ctrl+C ends a run
+/// through the same path as a timer.
+///
+/// The handler only stores to the flag, which is async-signal-safe. It is
+/// installed with `SA_RESETHAND`, so a second signal gets the default
+/// action and terminates a process that is stuck shutting down. Only one
+/// flag can be registered per process.
+///
+/// # Errors
+/// Returns `AlreadyExists` if a flag is already registered, the OS error
+/// if `sigaction` fails, or `Unsupported` off Linux.
+///
+/// # Examples
+/// ```no_run
+/// use hft_primitives::runtime::{stop_on_signals, StopFlag};
+///
+/// let stop = StopFlag::new();
+/// stop_on_signals(&stop).unwrap();
+/// while !stop.is_stopped() {
+///     // ... work until Ctrl+C
+/// }
+/// ```
+pub fn stop_on_signals(stop: &StopFlag) -> io::Result<()> {
+    if SIGNAL_STOP.set(stop.clone()).is_err() {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            "a stop flag is already registered for signals",
+        ));
+    }
+    install_signal_handlers()
+}
+
+#[cfg(target_os = "linux")]
+extern "C" fn handle_stop_signal(_signal: libc::c_int) {
+    if let Some(stop) = SIGNAL_STOP.get() {
+        stop.stop();
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn install_signal_handlers() -> io::Result<()> {
+    for signal in [libc::SIGINT, libc::SIGTERM] {
+        // SAFETY: the action is zero-initialised and then fully set up, and
+        // the handler only performs an atomic store.
+        let result = unsafe {
+            let mut action: libc::sigaction = std::mem::zeroed();
+            action.sa_sigaction = handle_stop_signal as extern "C" fn(libc::c_int) as usize;
+            action.sa_flags = libc::SA_RESETHAND | libc::SA_RESTART;
+            libc::sigemptyset(&mut action.sa_mask);
+            libc::sigaction(signal, &action, std::ptr::null_mut())
+        };
+        if result != 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn install_signal_handlers() -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "signal handling is only supported on Linux",
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu_pinning::{current_affinity, MAX_CPUS};
+    use std::time::Duration;
+
+    #[test]
+    fn test_results_reported_per_worker() {
+        let mut runtime = Runtime::new();
+        runtime
+            .add(Worker::new("first", |ctx| ctx.index() * 10))
+            .add(Worker::new("second", |_| panic!("boom")))
+            .add(Worker::new("third", |ctx| ctx.name().len()));
+
+        let reports = runtime.start().unwrap().join();
+        let names: Vec<&str> = reports.iter().map(|r| r.name.as_str()).collect();
+        assert_eq!(names, vec!["first", "second", "third"]);
+        assert_eq!(reports[0].result, Ok(0));
+        assert_eq!(reports[1].result, Err(WorkerPanic("boom".to_string())));
+        assert_eq!(reports[2].result, Ok(5));
+        assert!(reports[0].is_clean());
+        assert!(!reports[1].is_clean());
+    }
+
+    #[test]
+    fn test_setup_panic_still_arrives() {
+        let gate = StartGate {
+            ready: AtomicUsize::new(0),
+            state: AtomicU8::new(GATE_WAITING),
+        };
+        let setup = thread::scope(|scope| {
+            scope
+                .spawn(|| {
+                    let _arrival = Arrival(&gate);
+                    panic!("setup failed");
+                })
+                .join()
+        });
+        assert!(setup.is_err());
+        assert_eq!(gate.ready.load(Ordering::Acquire), 1);
+    }
+
+    #[test]
+    fn test_stop_flag_ends_workers() {
+        let mut runtime = Runtime::new();
+        for i in 0..3 {
+            runtime.add(Worker::new(format!("spinner-{}", i), |ctx| {
+                let mut iterations = 0u64;
+                while !ctx.should_stop() {
+                    iterations += 1;
+                    thread::yield_now();
+                }
+                iterations
+            }));
+        }
+        let flag = runtime.stop_flag();
+
+        let running = runtime.start().unwrap();
+        thread::sleep(Duration::from_millis(10));
+        assert!(!flag.is_stopped());
+
+        let reports = running.stop_and_join();
+        assert!(flag.is_stopped());
+        assert_eq!(reports.len(), 3);
+        assert!(reports.iter().all(|r| r.result.is_ok()));
+    }
+
+    #[test]
+    fn test_pinning_and_setup_errors() {
+        let cpu = current_affinity()
+            .ok()
+            .and_then(|allowed| allowed.iter().next())
+            .unwrap_or(0);
+
+        let mut runtime = Runtime::new();
+        runtime
+            .add(Worker::new("pinned", |ctx| ctx.cpu()).pin_to(cpu))
+            .add(Worker::new("bad-core", |_| None).pin_to(MAX_CPUS));
+
+        let reports = runtime.start().unwrap().join();
+        assert_eq!(reports[0].cpu, Some(cpu));
+        assert_eq!(reports[0].result, Ok(Some(cpu)));
+        // Pinning failed, but the worker still ran
+        assert_eq!(
+            reports[1].pin_error,
+            Some(PinError::CoreOutOfRange(MAX_CPUS))
+        );
+        assert_eq!(reports[1].result, Ok(None));
+    }
+
+    #[test]
+    fn test_priority_error_reported() {
+        let mut runtime = Runtime::new();
+        runtime.add(
+            Worker::new("rt", |_| 1)
+                .realtime_priority(0)
+                .prefault_stack(16 * 1024),
+        );
+
+        let reports = runtime.start().unwrap().join();
+        assert_eq!(reports[0].result, Ok(1));
+        assert!(matches!(
+            reports[0].priority_error,
+            Some(RealtimeError::InvalidPriority { priority: 0, .. })
+        ));
+        assert!(!reports[0].is_clean());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_signal_sets_stop_flag() {
+        // Other tests share this process: put the old actions back afterwards
+        let signals = [libc::SIGINT, libc::SIGTERM];
+        let mut previous: [libc::sigaction; 2] = unsafe { std::mem::zeroed() };
+        for (signal, action) in signals.iter().zip(previous.iter_mut()) {
+            assert_eq!(
+                unsafe { libc::sigaction(*signal, std::ptr::null(), action) },
+                0
+            );
+        }
+
+        let stop = StopFlag::new();
+        stop_on_signals(&stop).unwrap();
+        assert_eq!(
+            stop_on_signals(&StopFlag::new()).unwrap_err().kind(),
+            io::ErrorKind::AlreadyExists
+        );
+
+        assert!(!stop.is_stopped());
+        assert_eq!(unsafe { libc::raise(libc::SIGTERM) }, 0);
+        assert!(stop.is_stopped());
+
+        for (signal, action) in signals.iter().zip(previous.iter()) {
+            assert_eq!(
+                unsafe { libc::sigaction(*signal, action, std::ptr::null_mut()) },
+                0
+            );
+        }
+    }
+}
@@ -0,0 +1,575 @@
+//! Host latency-readiness audit.
+//!
+//! Benchmark numbers only mean something on a quiet, tuned machine. A
+//! `powersave` governor, turbo boost, SMT siblings, transparent hugepage
+//! compaction, device interrupts on the measurement cores, swap, and deep
+//! C-states all add jitter that looks like a regression. [`HostAudit`] reads
+//! `/proc` and `/sys` and reports each of these as a [`AuditCheck`].
+//!
+//! The audit never fails: a file that is missing or unreadable (common in
+//! containers and VMs) yields [`AuditStatus::Unknown`] for that check.
+//! Reports embed the audit through
+//! [`RunReport::set_audit`](crate::report::RunReport::set_audit).
+
+use crate::cpu_pinning::CpuSet;
+use crate::metrics::csv_field;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// Outcome of one audit check.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum AuditStatus {
+    /// The setting is tuned for low latency.
+    Pass,
+    /// The setting adds jitter; see the check's advice.
+    Warn,
+    /// The setting could not be read on this host.
+    Unknown,
+}
+
+impl fmt::Display for AuditStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            AuditStatus::Pass => "pass",
+            AuditStatus::Warn => "warn",
+            AuditStatus::Unknown => "unknown",
+        })
+    }
+}
+
+/// One audited setting.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AuditCheck {
+    /// Stable identifier, e.g. `cpu_governor`.
+    pub name: String,
+    pub status: AuditStatus,
+    /// What was found, e.g. `powersave (0-3)`.
+    pub value: String,
+    /// What to change when the check warns; empty otherwise.
+    pub advice: String,
+}
+
+impl AuditCheck {
+    fn pass(name: &str, value: impl Into<String>) -> Self {
+        Self::new(name, AuditStatus::Pass, value.into(), "")
+    }
+
+    fn warn(name: &str, value: impl Into<String>, advice: &str) -> Self {
+        Self::new(name, AuditStatus::Warn, value.into(), advice)
+    }
+
+    fn unknown(name: &str, value: &str) -> Self {
+        Self::new(name, AuditStatus::Unknown, value.to_string(), "")
+    }
+
+    fn new(name: &str, status: AuditStatus, value: String, advice: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            status,
+            value,
+            advice: advice.to_string(),
+        }
+    }
+}
+
+/// Latency-readiness audit of the host.
+///
+/// # Examples
+/// ```
+/// use hft_primitives::sysaudit::HostAudit;
+///
+/// let audit = HostAudit::collect();
+/// audit.print_warnings();
+/// println!("{}", audit);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct HostAudit {
+    pub checks: Vec<AuditCheck>,
+}
+
+impl HostAudit {
+    /// Audits the running host.
+    pub fn collect() -> Self {
+        Self::from_roots("/proc", "/sys")
+    }
+
+    /// Audits the host whose procfs and sysfs are mounted at `proc_root`
+    /// and `sys_root` (used with fixture trees in tests).
+    pub fn from_roots<P: AsRef<Path>, S: AsRef<Path>>(proc_root: P, sys_root: S) -> Self {
+        let proc_root = proc_root.as_ref();
+        let sys_root = sys_root.as_ref();
+        let cpu_dir = sys_root.join("devices/system/cpu");
+        let isolated = read_cpu_list(&cpu_dir.join("isolated"));
+
+        Self {
+            checks: vec![
+                check_governor(&cpu_dir),
+                check_turbo(&cpu_dir),
+                check_smt(&cpu_dir),
+                check_isolcpus(isolated.as_ref()),
+                check_nohz_full(&cpu_dir),
+                check_transparent_hugepages(sys_root),
+                check_irq_affinity(proc_root, isolated.as_ref()),
+                check_swap(proc_root),
+                check_cstates(proc_root, sys_root),
+            ],
+        }
+    }
+
+    /// Returns the check with the given name.
+    pub fn check(&self, name: &str) -> Option<&AuditCheck> {
+        self.checks.iter().find(|check| check.name == name)
+    }
+
+    /// Iterates over the checks that warned.
+    pub fn warnings(&self) -> impl Iterator<Item = &AuditCheck> {
+        self.checks
+            .iter()
+            .filter(|check| check.status == AuditStatus::Warn)
+    }
+
+    /// Returns true if no check warned (unknown checks are not counted).
+    pub fn is_clean(&self) -> bool {
+        self.warnings().next().is_none()
+    }
+
+    /// Prints one line per warning to stderr.
+    pub fn print_warnings(&self) {
+        for check in self.warnings() {
+            eprintln!(
+                "Warning: {}: {} ({})",
+                check.name, check.value, check.advice
+            );
+        }
+    }
+
+    /// Writes the checks as CSV, one row per check.
+    pub fn write_csv<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writeln!(writer, "check,status,value,advice")?;
+        for check in &self.checks {
+            writeln!(
+                writer,
+                "{},{},{},{}",
+                csv_field(&check.name),
+                check.status,
+                csv_field(&check.value),
+                csv_field(&check.advice)
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Writes the audit as pretty-printed JSON.
+    #[cfg(feature = "serde")]
+    pub fn write_json<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        serde_json::to_writer_pretty(&mut *writer, self)?;
+        writeln!(writer)
+    }
+}
+
+impl fmt::Display for HostAudit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "=== Host Latency Audit ===")?;
+        for check in &self.checks {
+            writeln!(
+                f,
+                "[{:>7}] {:<22} {}",
+                check.status.to_string(),
+                check.name,
+                check.value
+            )?;
+            if !check.advice.is_empty() {
+                writeln!(f, "{:>10}{:<22} -> {}", "", "", check.advice)?;
+            }
+        }
+        let warnings = self.warnings().count();
+        if warnings == 0 {
+            writeln!(f, "No latency warnings")
+        } else {
+            writeln!(f, "{} latency warning(s)", warnings)
+        }
+    }
+}
+
+fn read_trimmed(path: &Path) -> Option<String> {
+    fs::read_to_string(path)
+        .ok()
+        .map(|contents| contents.trim().to_string())
+}
+
+/// Reads a cpulist file; "(null)" and empty files are the empty set.
+fn read_cpu_list(path: &Path) -> Option<CpuSet> {
+    let contents = read_trimmed(path)?;
+    if contents.is_empty() || contents == "(null)" {
+        return Some(CpuSet::new());
+    }
+    contents.parse().ok()
+}
+
+/// Returns numbered subdirectories (`cpu0`, `cpu1`, ... or `24`, `25`, ...)
+/// sorted by number.
+fn numbered_entries(dir: &Path, prefix: &str) -> Option<Vec<(usize, PathBuf)>> {
+    let mut entries: Vec<(usize, PathBuf)> = fs::read_dir(dir)
+        .ok()?
+        .filter_map(Result::ok)
+        .filter_map(|entry| {
+            let name = entry.file_name();
+            let number = name.to_str()?.strip_prefix(prefix)?.parse().ok()?;
+            Some((number, entry.path()))
+        })
+        .collect();
+    entries.sort();
+    Some(entries)
+}
+
+/// Returns the bracketed choice of a sysfs selector such as
+/// `always [madvise] never`.
+fn selected_option(contents: &str) -> Option<&str> {
+    let start = contents.find('[')?;
+    let end = contents[start..].find(']')?;
+    Some(&contents[start + 1..start + end])
+}
+
+fn check_governor(cpu_dir: &Path) -> AuditCheck {
+    const NAME: &str = "cpu_governor";
+    let mut governors: BTreeMap<String, CpuSet> = BTreeMap::new();
+    for (cpu, path) in numbered_entries(cpu_dir, "cpu").unwrap_or_default() {
+        if let Some(governor) = read_trimmed(&path.join("cpufreq/scaling_governor")) {
+            let _ = governors.entry(governor).or_default().insert(cpu);
+        }
+    }
+    if governors.is_empty() {
+        return AuditCheck::unknown(NAME, "cpufreq not exposed");
+    }
+
+    let value = governors
+        .iter()
+        .map(|(governor, cpus)| format!("{} ({})", governor, cpus))
+        .collect::<Vec<_>>()
+        .join(", ");
+    if governors.keys().all(|governor| governor == "performance") {
+        AuditCheck::pass(NAME, value)
+    } else {
+        AuditCheck::warn(
+            NAME,
+            value,
+            "set the performance governor (cpupower frequency-set -g performance)",
+        )
+    }
+}
+
+fn check_turbo(cpu_dir: &Path) -> AuditCheck {
+    const NAME: &str = "turbo";
+    const ADVICE: &str = "disable turbo so clock speed does not vary with load and temperature";
+    // intel_pstate exposes no_turbo; acpi-cpufreq and amd-pstate expose boost
+    if let Some(no_turbo) = read_trimmed(&cpu_dir.join("intel_pstate/no_turbo")) {
+        return match no_turbo.as_str() {
+            "1" => AuditCheck::pass(NAME, "disabled (intel_pstate)"),
+            _ => AuditCheck::warn(NAME, "enabled (intel_pstate)", ADVICE),
+        };
+    }
+    match read_trimmed(&cpu_dir.join("cpufreq/boost")).as_deref() {
+        Some("0") => AuditCheck::pass(NAME, "disabled"),
+        Some(_) => AuditCheck::warn(NAME, "enabled", ADVICE),
+        None => AuditCheck::unknown(NAME, "no turbo control exposed"),
+    }
+}
+
+fn check_smt(cpu_dir: &Path) -> AuditCheck {
+    const NAME: &str = "smt";
+    match read_trimmed(&cpu_dir.join("smt/control")).as_deref() {
+        Some(state @ ("off" | "forceoff" | "notsupported" | "notimplemented")) => {
+            AuditCheck::pass(NAME, state)
+        }
+        Some(state) => AuditCheck::warn(
+            NAME,
+            state,
+            "disable SMT or keep hot threads off sibling cores (PlacementPlanner::no_smt_sharing)",
+        ),
+        None => AuditCheck::unknown(NAME, "smt control not exposed"),
+    }
+}
+
+fn check_isolcpus(isolated: Option<&CpuSet>) -> AuditCheck {
+    const NAME: &str = "isolcpus";
+    match isolated {
+        Some(cpus) if !cpus.is_empty() => AuditCheck::pass(NAME, cpus.to_string()),
+        Some(_) => AuditCheck::warn(
+            NAME,
+            "none",
+            "isolate measurement cores from the scheduler (isolcpus= on the kernel command line)",
+        ),
+        None => AuditCheck::unknown(NAME, "isolated CPU list not exposed"),
+    }
+}
+
+fn check_nohz_full(cpu_dir: &Path) -> AuditCheck {
+    const NAME: &str = "nohz_full";
+    match read_cpu_list(&cpu_dir.join("nohz_full")) {
+        Some(cpus) if !cpus.is_empty() => AuditCheck::pass(NAME, cpus.to_string()),
+        Some(_) => AuditCheck::warn(
+            NAME,
+            "none",
+            "stop the scheduler tick on measurement cores (nohz_full= on the kernel command line)",
+        ),
+        None => AuditCheck::unknown(NAME, "kernel built without NO_HZ_FULL"),
+    }
+}
+
+fn check_transparent_hugepages(sys_root: &Path) -> AuditCheck {
+    const NAME: &str = "transparent_hugepages";
+    let path = sys_root.join("kernel/mm/transparent_hugepage/enabled");
+    let Some(contents) = read_trimmed(&path) else {
+        return AuditCheck::unknown(NAME, "transparent hugepage settings not exposed");
+    };
+    match selected_option(&contents) {
+        Some("always") => AuditCheck::warn(
+            NAME,
+            "always",
+            "set to madvise or never; khugepaged compaction stalls allocating threads",
+        ),
+        Some(mode) => AuditCheck::pass(NAME, mode),
+        None => AuditCheck::unknown(NAME, "unrecognized setting"),
+    }
+}
+
+fn check_irq_affinity(proc_root: &Path, isolated: Option<&CpuSet>) -> AuditCheck {
+    const NAME: &str = "irq_affinity";
+    let Some(irqs) = numbered_entries(&proc_root.join("irq"), "") else {
+        return AuditCheck::unknown(NAME, "/proc/irq not readable");
+    };
+    let Some(isolated) = isolated.filter(|cpus| !cpus.is_empty()) else {
+        return AuditCheck::unknown(NAME, "no isolated CPUs to check against");
+    };
+
+    let intruding: Vec<String> = irqs
+        .iter()
+        .filter(|(_, path)| {
+            read_cpu_list(&path.join("smp_affinity_list"))
+                .is_some_and(|affinity| affinity.iter().any(|cpu| isolated.contains(cpu)))
+        })
+        .map(|(irq, _)| irq.to_string())
+        .collect();
+    if intruding.is_empty() {
+        AuditCheck::pass(
+            NAME,
+            format!("{} IRQs kept off isolated CPUs {}", irqs.len(), isolated),
+        )
+    } else {
+        AuditCheck::warn(
+            NAME,
+            format!(
+                "{} IRQs may run on isolated CPUs {} ({})",
+                intruding.len(),
+                isolated,
+                intruding.join(", ")
+            ),
+            "steer device interrupts to housekeeping cores (smp_affinity_list, irqbalance --banirq)",
+        )
+    }
+}
+
+fn check_swap(proc_root: &Path) -> AuditCheck {
+    const NAME: &str = "swap";
+    let Some(swaps) = read_trimmed(&proc_root.join("swaps")) else {
+        return AuditCheck::unknown(NAME, "/proc/swaps not readable");
+    };
+    let devices = swaps
+        .lines()
+        .skip(1)
+        .filter(|line| !line.trim().is_empty())
+        .count();
+    let swappiness = read_trimmed(&proc_root.join("sys/vm/swappiness"))
+        .map(|value| format!(", swappiness {}", value))
+        .unwrap_or_default();
+    if devices == 0 {
+        AuditCheck::pass(NAME, format!("off{}", swappiness))
+    } else {
+        AuditCheck::warn(
+            NAME,
+            format!("{} device(s) active{}", devices, swappiness),
+            "disable swap (swapoff -a) or lock memory (realtime::lock_memory)",
+        )
+    }
+}
+
+fn check_cstates(proc_root: &Path, sys_root: &Path) -> AuditCheck {
+    const NAME: &str = "cstates";
+    const ADVICE: &str =
+        "limit C-states (intel_idle.max_cstate=1 processor.max_cstate=1, or idle=poll)";
+    let cmdline = read_trimmed(&proc_root.join("cmdline")).unwrap_or_default();
+    if cmdline.split_whitespace().any(|arg| arg == "idle=poll") {
+        return AuditCheck::pass(NAME, "idle=poll");
+    }
+    let cmdline_limit = |key: &str| {
+        cmdline
+            .split_whitespace()
+            .rev()
+            .filter_map(|arg| arg.split_once('='))
+            .find(|(k, _)| *k == key)
+            .and_then(|(_, value)| value.parse::<u32>().ok())
+    };
+    let module_limit = |module: &str| {
+        read_trimmed(&sys_root.join(format!("module/{}/parameters/max_cstate", module)))
+            .and_then(|value| value.parse::<u32>().ok())
+    };
+
+    // Only the limit of the driver actually in charge of idle matters;
+    // processor.max_cstate does nothing while intel_idle is active
+    let driver = read_trimmed(&sys_root.join("devices/system/cpu/cpuidle/current_driver"));
+    let limit = match driver.as_deref() {
+        None => return AuditCheck::unknown(NAME, "cpuidle not exposed"),
+        Some("none") => return AuditCheck::pass(NAME, "no cpuidle driver"),
+        Some("intel_idle") => {
+            module_limit("intel_idle").or_else(|| cmdline_limit("intel_idle.max_cstate"))
+        }
+        Some("acpi_idle") => {
+            module_limit("processor").or_else(|| cmdline_limit("processor.max_cstate"))
+        }
+        Some(_) => None,
+    };
+    let driver = driver.unwrap_or_default();
+    match limit {
+        // intel_idle.max_cstate=0 disables the driver instead of limiting it
+        Some(0) if driver == "intel_idle" => {
+            AuditCheck::warn(NAME, format!("max_cstate=0 ({}, disabled)", driver), ADVICE)
+        }
+        Some(limit) if limit <= 1 => {
+            AuditCheck::pass(NAME, format!("max_cstate={} ({})", limit, driver))
+        }
+        Some(limit) => AuditCheck::warn(NAME, format!("max_cstate={} ({})", limit, driver), ADVICE),
+        None => AuditCheck::warn(NAME, format!("unlimited ({})", driver), ADVICE),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture(name: &str) -> HostAudit {
+        let root = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures/host")
+            .join(name);
+        HostAudit::from_roots(root.join("proc"), root.join("sys"))
+    }
+
+    fn status(audit: &HostAudit, name: &str) -> AuditStatus {
+        audit.check(name).unwrap().status
+    }
+
+    #[test]
+    fn test_tuned_host_passes() {
+        let audit = fixture("tuned");
+        for check in &audit.checks {
+            assert_eq!(check.status, AuditStatus::Pass, "{:?}", check);
+        }
+        assert!(audit.is_clean());
+        assert_eq!(audit.check("isolcpus").unwrap().value, "2-3");
+        assert_eq!(
+            audit.check("cstates").unwrap().value,
+            "max_cstate=1 (intel_idle)"
+        );
+        assert!(audit.to_string().contains("No latency warnings"));
+    }
+
+    #[test]
+    fn test_untuned_host_warns() {
+        let audit = fixture("untuned");
+        let warned: Vec<&str> = audit.warnings().map(|check| check.name.as_str()).collect();
+        assert_eq!(
+            warned,
+            [
+                "cpu_governor",
+                "turbo",
+                "smt",
+                "isolcpus",
+                "nohz_full",
+                "transparent_hugepages",
+                "swap",
+                "cstates"
+            ]
+        );
+        // Nothing is isolated, so there is nothing for IRQs to intrude on
+        assert_eq!(status(&audit, "irq_affinity"), AuditStatus::Unknown);
+        assert_eq!(
+            audit.check("cpu_governor").unwrap().value,
+            "performance (1), powersave (0,2-3)"
+        );
+        assert_eq!(
+            audit.check("swap").unwrap().value,
+            "1 device(s) active, swappiness 60"
+        );
+
+        let mut csv = Vec::new();
+        audit.write_csv(&mut csv).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        assert_eq!(csv.lines().count(), audit.checks.len() + 1);
+        assert!(csv.contains("\nturbo,warn,enabled (intel_pstate),"));
+    }
+
+    #[test]
+    fn test_cstates_follow_active_driver() {
+        let root = std::env::temp_dir().join(format!("sysaudit-cstates-{}", std::process::id()));
+        let check = |cmdline: &str, driver: &str, intel_limit: Option<&str>| {
+            let (proc_root, sys_root) = (root.join("proc"), root.join("sys"));
+            let _ = std::fs::remove_dir_all(&root);
+            std::fs::create_dir_all(sys_root.join("devices/system/cpu/cpuidle")).unwrap();
+            std::fs::create_dir_all(sys_root.join("module/intel_idle/parameters")).unwrap();
+            std::fs::create_dir_all(&proc_root).unwrap();
+            std::fs::write(proc_root.join("cmdline"), cmdline).unwrap();
+            std::fs::write(
+                sys_root.join("devices/system/cpu/cpuidle/current_driver"),
+                driver,
+            )
+            .unwrap();
+            if let Some(limit) = intel_limit {
+                std::fs::write(
+                    sys_root.join("module/intel_idle/parameters/max_cstate"),
+                    limit,
+                )
+                .unwrap();
+            }
+            check_cstates(&proc_root, &sys_root)
+        };
+
+        // processor.max_cstate has no effect while intel_idle is in charge
+        let result = check("processor.max_cstate=1", "intel_idle", Some("9"));
+        assert_eq!(result.status, AuditStatus::Warn);
+        assert_eq!(result.value, "max_cstate=9 (intel_idle)");
+
+        let result = check("processor.max_cstate=1", "acpi_idle", None);
+        assert_eq!(result.status, AuditStatus::Pass);
+        assert_eq!(result.value, "max_cstate=1 (acpi_idle)");
+
+        let result = check("intel_idle.max_cstate=0", "intel_idle", None);
+        assert_eq!(result.status, AuditStatus::Warn);
+
+        let result = check("quiet", "acpi_idle", None);
+        assert_eq!(result.status, AuditStatus::Warn);
+        assert_eq!(result.value, "unlimited (acpi_idle)");
+
+        assert_eq!(
+            check("idle=poll", "intel_idle", Some("9")).status,
+            AuditStatus::Pass
+        );
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_missing_files_are_unknown() {
+        let audit = HostAudit::from_roots("/nonexistent/proc", "/nonexistent/sys");
+        assert_eq!(audit.checks.len(), 9);
+        assert!(audit
+            .checks
+            .iter()
+            .all(|check| check.status == AuditStatus::Unknown));
+        assert!(audit.is_clean());
+    }
+}
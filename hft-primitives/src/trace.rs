@@ -0,0 +1,363 @@
+//! Per-hop latency tracing for multi-stage pipelines.
+//!
+//! An end-to-end number does not say whether time went to the socket, the
+//! queue or the consumer. Each message carries a fixed-size [`TraceStamps`]
+//! array that every stage stamps with a [`Clock`] tick as it passes
+//! (recv, enqueue, dequeue, process). A [`PipelineTracer`] turns the deltas
+//! between consecutive stamps into one histogram per hop and produces a
+//! [`PipelineBreakdown`] of where the end-to-end latency went.
+
+use crate::clock::Clock;
+use crate::histogram::LatencyHistogram;
+use crate::metrics::LatencyMetrics;
+use std::fmt;
+use std::time::Duration;
+
+/// Points in the pipeline where a message is stamped, in order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Stage {
+    /// Message read from the socket.
+    Recv = 0,
+    /// Message pushed onto the hand-off queue.
+    Enqueue = 1,
+    /// Message popped by the consumer.
+    Dequeue = 2,
+    /// Consumer finished processing the message.
+    Process = 3,
+}
+
+impl Stage {
+    /// Number of stages.
+    pub const COUNT: usize = 4;
+
+    /// All stages in pipeline order.
+    pub const ALL: [Stage; Stage::COUNT] =
+        [Stage::Recv, Stage::Enqueue, Stage::Dequeue, Stage::Process];
+
+    /// Returns the stage name used in reports.
+    pub fn name(self) -> &'static str {
+        match self {
+            Stage::Recv => "recv",
+            Stage::Enqueue => "enqueue",
+            Stage::Dequeue => "dequeue",
+            Stage::Process => "process",
+        }
+    }
+}
+
+impl fmt::Display for Stage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Fixed-size timestamp array carried alongside a message.
+///
+/// A tick of zero means the stage has not been stamped. Copying the stamps
+/// is a 32-byte memcpy, so they can travel inside queue elements.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[repr(C)]
+pub struct TraceStamps {
+    ticks: [u64; Stage::COUNT],
+}
+
+impl TraceStamps {
+    /// Creates an empty set of stamps.
+    pub const fn new() -> Self {
+        Self {
+            ticks: [0; Stage::COUNT],
+        }
+    }
+
+    /// Stamps `stage` with the current tick of `clock`.
+    ///
+    /// Uses [`Clock::stop`], so work issued before the stamp has completed
+    /// when the tick is taken.
+    #[inline]
+    pub fn stamp<C: Clock>(&mut self, stage: Stage, clock: &C) {
+        self.ticks[stage as usize] = clock.stop();
+    }
+
+    /// Sets the tick for `stage` directly, e.g. one read earlier from the
+    /// same clock.
+    ///
+    /// `ticks` must come from the [`Clock`] later passed to
+    /// [`PipelineTracer::record`]. Kernel receive timestamps are
+    /// `CLOCK_REALTIME` nanoseconds, not clock ticks, and cannot be mixed in.
+    #[inline]
+    pub fn set(&mut self, stage: Stage, ticks: u64) {
+        self.ticks[stage as usize] = ticks;
+    }
+
+    /// Returns the tick recorded for `stage`, if it was stamped.
+    #[inline]
+    pub fn get(&self, stage: Stage) -> Option<u64> {
+        match self.ticks[stage as usize] {
+            0 => None,
+            ticks => Some(ticks),
+        }
+    }
+
+    /// Returns true if every stage has been stamped.
+    pub fn is_complete(&self) -> bool {
+        self.ticks.iter().all(|&ticks| ticks != 0)
+    }
+}
+
+/// Accumulates per-hop and end-to-end latency histograms from [`TraceStamps`].
+///
+/// # Examples
+/// ```
+/// use hft_primitives::clock::InstantClock;
+/// use hft_primitives::trace::{PipelineTracer, Stage, TraceStamps};
+///
+/// let clock = InstantClock::new();
+/// let mut tracer = PipelineTracer::new();
+///
+/// let mut stamps = TraceStamps::new();
+/// for stage in Stage::ALL {
+///     stamps.stamp(stage, &clock);
+/// }
+/// tracer.record(&stamps, &clock);
+///
+/// let breakdown = tracer.breakdown();
+/// assert_eq!(breakdown.end_to_end.samples, 1);
+/// breakdown.print_report("Pipeline");
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct PipelineTracer {
+    hops: [LatencyHistogram; Stage::COUNT - 1],
+    end_to_end: LatencyHistogram,
+    incomplete: u64,
+}
+
+impl PipelineTracer {
+    /// Creates an empty tracer.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records the hops of one message.
+    ///
+    /// Hops with both ends stamped are always recorded; the end-to-end
+    /// latency only when the message was stamped at every stage. Messages
+    /// missing any stamp are counted as incomplete.
+    pub fn record<C: Clock>(&mut self, stamps: &TraceStamps, clock: &C) {
+        for (hop, pair) in self.hops.iter_mut().zip(stamps.ticks.windows(2)) {
+            if pair[0] != 0 && pair[1] != 0 {
+                hop.record(clock.elapsed(pair[0], pair[1]));
+            }
+        }
+
+        if stamps.is_complete() {
+            let first = stamps.ticks[0];
+            let last = stamps.ticks[Stage::COUNT - 1];
+            self.end_to_end.record(clock.elapsed(first, last));
+        } else {
+            self.incomplete += 1;
+        }
+    }
+
+    /// Returns the histogram of the hop that starts at `from`.
+    ///
+    /// # Panics
+    /// Panics if `from` is the last stage.
+    pub fn hop(&self, from: Stage) -> &LatencyHistogram {
+        &self.hops[from as usize]
+    }
+
+    /// Returns the end-to-end (first to last stage) histogram.
+    pub fn end_to_end(&self) -> &LatencyHistogram {
+        &self.end_to_end
+    }
+
+    /// Returns the number of messages missing at least one stamp.
+    pub fn incomplete(&self) -> u64 {
+        self.incomplete
+    }
+
+    /// Adds another tracer's samples (e.g. from another consumer thread).
+    pub fn merge(&mut self, other: &PipelineTracer) {
+        for (hop, other_hop) in self.hops.iter_mut().zip(other.hops.iter()) {
+            hop.merge(other_hop);
+        }
+        self.end_to_end.merge(&other.end_to_end);
+        self.incomplete += other.incomplete;
+    }
+
+    /// Summarizes each hop and its share of the end-to-end latency.
+    pub fn breakdown(&self) -> PipelineBreakdown {
+        let total_mean: Duration = self.hops.iter().map(LatencyHistogram::mean).sum();
+
+        let hops = self
+            .hops
+            .iter()
+            .enumerate()
+            .map(|(i, histogram)| HopBreakdown {
+                from: Stage::ALL[i],
+                to: Stage::ALL[i + 1],
+                metrics: LatencyMetrics::from_histogram(histogram),
+                share: if total_mean.is_zero() {
+                    0.0
+                } else {
+                    histogram.mean().as_secs_f64() / total_mean.as_secs_f64()
+                },
+            })
+            .collect();
+
+        PipelineBreakdown {
+            hops,
+            end_to_end: LatencyMetrics::from_histogram(&self.end_to_end),
+            incomplete: self.incomplete,
+        }
+    }
+}
+
+/// Latency of one hop between consecutive stages.
+#[derive(Debug, Clone)]
+pub struct HopBreakdown {
+    pub from: Stage,
+    pub to: Stage,
+    pub metrics: LatencyMetrics,
+    /// Fraction of the summed mean hop latency spent in this hop (0.0-1.0).
+    ///
+    /// Means add up across hops; percentiles do not, so the share is
+    /// computed from means.
+    pub share: f64,
+}
+
+impl HopBreakdown {
+    /// Returns the hop label, e.g. `recv->enqueue`.
+    pub fn name(&self) -> String {
+        format!("{}->{}", self.from, self.to)
+    }
+}
+
+/// Per-hop breakdown of end-to-end pipeline latency.
+#[derive(Debug, Clone)]
+pub struct PipelineBreakdown {
+    pub hops: Vec<HopBreakdown>,
+    pub end_to_end: LatencyMetrics,
+    pub incomplete: u64,
+}
+
+impl PipelineBreakdown {
+    /// Returns the hop with the highest P99.
+    pub fn bottleneck(&self) -> Option<&HopBreakdown> {
+        self.hops
+            .iter()
+            .filter(|hop| hop.metrics.samples > 0)
+            .max_by_key(|hop| hop.metrics.p99)
+    }
+
+    /// Prints the breakdown as a table.
+    pub fn print_report(&self, name: &str) {
+        println!("=== {} ===", name);
+        print!("{}", self);
+    }
+}
+
+impl fmt::Display for PipelineBreakdown {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "  {:<18} {:>10} {:>12} {:>12} {:>12} {:>7}",
+            "Hop", "Samples", "P50", "P99", "Max", "Share"
+        )?;
+        for hop in &self.hops {
+            writeln!(
+                f,
+                "  {:<18} {:>10} {:>12} {:>12} {:>12} {:>6.1}%",
+                hop.name(),
+                hop.metrics.samples,
+                format!("{:?}", hop.metrics.p50),
+                format!("{:?}", hop.metrics.p99),
+                format!("{:?}", hop.metrics.max),
+                hop.share * 100.0
+            )?;
+        }
+        writeln!(
+            f,
+            "  {:<18} {:>10} {:>12} {:>12} {:>12}",
+            "end-to-end",
+            self.end_to_end.samples,
+            format!("{:?}", self.end_to_end.p50),
+            format!("{:?}", self.end_to_end.p99),
+            format!("{:?}", self.end_to_end.max)
+        )?;
+        if self.incomplete > 0 {
+            writeln!(f, "  Incomplete traces: {}", self.incomplete)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::InstantClock;
+
+    /// Builds stamps from nanosecond offsets (InstantClock ticks are ns).
+    fn stamps(ticks: [u64; Stage::COUNT]) -> TraceStamps {
+        let mut stamps = TraceStamps::new();
+        for (stage, ticks) in Stage::ALL.into_iter().zip(ticks) {
+            stamps.set(stage, ticks);
+        }
+        stamps
+    }
+
+    #[test]
+    fn test_per_hop_breakdown() {
+        let clock = InstantClock::new();
+        let mut tracer = PipelineTracer::new();
+        for i in 0..100 {
+            // recv->enqueue 100ns, enqueue->dequeue 700ns, dequeue->process 200ns
+            let base = 1_000 + i * 10_000;
+            tracer.record(
+                &stamps([base, base + 100, base + 800, base + 1_000]),
+                &clock,
+            );
+        }
+
+        assert_eq!(tracer.hop(Stage::Enqueue).len(), 100);
+        assert_eq!(tracer.end_to_end().max(), Duration::from_nanos(1_000));
+
+        let breakdown = tracer.breakdown();
+        assert_eq!(breakdown.hops.len(), 3);
+        assert_eq!(breakdown.hops[1].name(), "enqueue->dequeue");
+        assert!((breakdown.hops[1].share - 0.7).abs() < 0.02);
+        assert_eq!(breakdown.bottleneck().unwrap().from, Stage::Enqueue);
+
+        let table = breakdown.to_string();
+        assert!(table.contains("recv->enqueue"));
+        assert!(table.contains("end-to-end"));
+    }
+
+    #[test]
+    fn test_incomplete_traces() {
+        let clock = InstantClock::new();
+        let mut tracer = PipelineTracer::new();
+
+        let mut partial = stamps([10, 20, 0, 0]);
+        assert!(!partial.is_complete());
+        assert_eq!(partial.get(Stage::Dequeue), None);
+        tracer.record(&partial, &clock);
+
+        partial.stamp(Stage::Dequeue, &clock);
+        partial.stamp(Stage::Process, &clock);
+        assert!(partial.is_complete());
+
+        let mut other = PipelineTracer::new();
+        other.record(&partial, &clock);
+        tracer.merge(&other);
+
+        assert_eq!(tracer.incomplete(), 1);
+        assert_eq!(tracer.hop(Stage::Recv).len(), 2);
+        assert_eq!(tracer.hop(Stage::Dequeue).len(), 1);
+        assert_eq!(tracer.end_to_end().len(), 1);
+        assert!(tracer
+            .breakdown()
+            .to_string()
+            .contains("Incomplete traces: 1"));
+    }
+}
@@ -1,6 +1,10 @@
-use std::sync::{Arc, Barrier};
-use std::thread;
-use std::time::{Duration, Instant};
+use hft_primitives::clock::{AutoClock, Clock};
+use hft_primitives::plot::plot_requested_from_args;
+use hft_primitives::report::{report_path_from_args, ReportEntry, RunMetadata};
+use hft_primitives::runtime::{Runtime, Worker};
+use hft_primitives::sysaudit::HostAudit;
+use hft_primitives::{LatencyBudget, RunReport};
+use std::time::Duration;
 // Detect number of CPU cores
 fn get_cpu_count() -> usize {
     std::thread::available_parallelism()
@@ -61,160 +65,184 @@ fn do_work_with_branch(iteration: usize) {
 
     std::hint::black_box(result);
 }
-// Worker function for each thread
-fn worker_thread<F>(
-    worker_id: usize,
-    iterations: usize,
-    barrier: Arc<Barrier>,
-    latencies: Arc<Vec<Duration>>,
-    pin_to_core: bool,
-    work_fn: F,
-) where
-    F: Fn(usize) + Send + Sync + Clone + 'static,
+// Measure each call of the work function on the current thread
+fn measure_latencies<F>(iterations: usize, clock: AutoClock, work_fn: F) -> Vec<Duration>
+where
+    F: Fn(usize),
 {
-    // Pin thread to specific CPU core if requested
-    #[cfg(target_os = "linux")]
-    if pin_to_core {
-        pin_thread_to_core(worker_id);
-    }
-    // Wait for all workers to be ready
-    barrier.wait();
-    // Perform work and measure latencies
+    let mut latencies = Vec::with_capacity(iterations);
     for i in 0..iterations {
-        let start = Instant::now();
+        let start = clock.start();
         work_fn(i);
-        let latency = start.elapsed();
-        // Store latency (using unsafe for mutable shared access)
-        unsafe {
-            let latency_ptr = latencies.as_ptr().add(worker_id * iterations + i) as *mut Duration;
-            *latency_ptr = latency;
-        }
-    }
-}
-// CPU pinning for Linux
-#[cfg(target_os = "linux")]
-fn pin_thread_to_core(core_id: usize) {
-    use libc::{cpu_set_t, sched_setaffinity};
-    use std::mem;
-
-    let mut cpu_set: cpu_set_t = unsafe { mem::zeroed() };
-    unsafe {
-        libc::CPU_SET(core_id, &mut cpu_set);
-        sched_setaffinity(0, mem::size_of::<cpu_set_t>(), &cpu_set);
+        latencies.push(clock.elapsed(start, clock.stop()));
     }
+    latencies
 }
 fn run_experiment<F>(
     cpu_count: usize,
     iterations_per_thread: usize,
     pin_to_core: bool,
+    clock: AutoClock,
     work_fn: F,
     test_name: &str,
-) where
+) -> (ReportEntry, bool)
+where
     F: Fn(usize) + Send + Sync + Clone + 'static,
 {
-    // Shared data structures
-    let barrier = Arc::new(Barrier::new(cpu_count));
-    let latencies = Arc::new(vec![Duration::ZERO; cpu_count * iterations_per_thread]);
-    // Create worker threads
-    let mut handles = vec![];
+    // One worker per core, released together once all are pinned
+    let mut runtime = Runtime::new();
     for worker_id in 0..cpu_count {
-        let barrier_clone = Arc::clone(&barrier);
-        let latencies_clone = Arc::clone(&latencies);
         let work_fn_clone = work_fn.clone();
-        let handle = thread::spawn(move || {
-            worker_thread(
-                worker_id,
-                iterations_per_thread,
-                barrier_clone,
-                latencies_clone,
-                pin_to_core,
-                work_fn_clone,
-            );
+        let mut worker = Worker::new(format!("worker-{}", worker_id), move |_ctx| {
+            measure_latencies(iterations_per_thread, clock, work_fn_clone)
         });
-        handles.push(handle);
+        if pin_to_core {
+            worker = worker.pin_to(worker_id);
+        }
+        runtime.add(worker);
     }
-    // Wait for all threads to complete
-    for handle in handles {
-        handle.join().unwrap();
+    let mut latencies = Vec::with_capacity(cpu_count * iterations_per_thread);
+    let mut all_pinned = true;
+    for report in runtime.start().expect("failed to spawn workers").join() {
+        all_pinned &= report.cpu.is_some() && report.pin_error.is_none();
+        if let Some(e) = &report.pin_error {
+            eprintln!("Warning: {} ran unpinned: {}", report.name, e);
+        }
+        match report.result {
+            Ok(samples) => latencies.extend(samples),
+            Err(e) => panic!("{}: {}", report.name, e),
+        }
     }
     // Analyze latencies
     println!("{} - Latency Analysis:", test_name);
-    analyze_latencies(&latencies);
+    (
+        analyze_latencies(test_name, &latencies, clock.overhead()),
+        all_pinned,
+    )
 }
-fn analyze_latencies(latencies: &[Duration]) {
+fn analyze_latencies(test_name: &str, latencies: &[Duration], overhead: Duration) -> ReportEntry {
     let mut sorted_latencies = latencies.to_vec();
-    sorted_latencies.sort();
-    let len = sorted_latencies.len();
-    let p50 = sorted_latencies[len / 2];
-    let p99 = sorted_latencies[(len as f64 * 0.99) as usize];
-    let max = sorted_latencies[len - 1];
-    let avg = sorted_latencies.iter().sum::<Duration>() / len as u32;
-    println!("  Samples: {}", len);
-    println!("  Average: {:?}", avg);
-    println!("  P50: {:?}", p50);
-    println!("  P99: {:?}", p99);
-    println!("  Max: {:?}", max);
+    let entry = ReportEntry::from_samples(test_name, &mut sorted_latencies);
+    let metrics = &entry.metrics;
+    println!("  Samples: {}", metrics.samples);
+    println!("  Average: {:?}", metrics.avg);
+    println!("  P50: {:?}", metrics.p50);
+    println!("  P99: {:?}", metrics.p99);
+    println!("  Max: {:?}", metrics.max);
+    println!("  P99/P50 ratio: {:.2}x", metrics.consistency_ratio());
+    let corrected = metrics.subtract_overhead(overhead);
     println!(
-        "  P99/P50 ratio: {:.2}x",
-        p99.as_nanos() as f64 / p50.as_nanos() as f64
+        "  Minus clock overhead ({:?}): P50 {:?}, P99 {:?}",
+        overhead, corrected.p50, corrected.p99
     );
+    let verdict = metrics.evaluate(&LatencyBudget::hft_grade());
+    if verdict.passed() {
+        println!("  HFT grade: ✅");
+    } else {
+        println!("  HFT grade: ❌");
+        for violation in verdict.violations() {
+            println!("    {}", violation);
+        }
+    }
+    if plot_requested_from_args() {
+        entry.print_distribution();
+    }
+    entry
 }
 fn main() {
     let cpu_count = get_cpu_count();
     let iterations_per_thread = 100_000;
     let total_iterations = cpu_count * iterations_per_thread;
+    // Every experiment pins its workers; cleared if any pin fails
+    let mut report = RunReport::new(RunMetadata::collect(
+        "latency-lab",
+        true,
+        iterations_per_thread,
+    ));
+    let clock = AutoClock::new();
     println!("Thread-Per-Core Worker Model - Latency Impact Experiments");
     println!("CPU Cores: {}", cpu_count);
+    println!(
+        "Clock: {} (overhead {:?})",
+        clock.description(),
+        clock.overhead()
+    );
     println!("Iterations per thread: {}", iterations_per_thread);
     println!("Total iterations: {}", total_iterations);
     println!();
+    // Flag host settings that would make these numbers noisy
+    let audit = HostAudit::collect();
+    audit.print_warnings();
+    report.set_audit(audit);
     // Test 1: Baseline (no allocation)
     println!("=== Test 1: Baseline (No Allocation) ===");
-    run_experiment(
+    let (entry, pinned) = run_experiment(
         cpu_count,
         iterations_per_thread,
         true,
+        clock,
         do_work_baseline,
         "Baseline",
     );
+    report.metadata.pinned &= pinned;
+    report.add_entry(entry);
     println!();
     // Test 2: Add one allocation
     println!("=== Test 2: One Vec Allocation ===");
-    run_experiment(
+    let (entry, pinned) = run_experiment(
         cpu_count,
         iterations_per_thread,
         true,
+        clock,
         do_work_with_allocation,
         "Vec Allocation",
     );
+    report.metadata.pinned &= pinned;
+    report.add_entry(entry);
     println!();
     // Test 3: Add heap allocation
     println!("=== Test 3: One Box Allocation ===");
-    run_experiment(
+    let (entry, pinned) = run_experiment(
         cpu_count,
         iterations_per_thread,
         true,
+        clock,
         do_work_with_box,
         "Box Allocation",
     );
+    report.metadata.pinned &= pinned;
+    report.add_entry(entry);
     println!();
     // Test 4: Implement lock
     println!("=== Test 4: One lock ===");
-    run_experiment(
+    let (entry, pinned) = run_experiment(
         cpu_count,
         iterations_per_thread,
         true,
+        clock,
         do_work_with_lock,
         "One Lock",
     );
+    report.metadata.pinned &= pinned;
+    report.add_entry(entry);
     println!();
     // Test 5: Implement Branch
     println!("=== Test 4: One Branch ===");
-    run_experiment(
+    let (entry, pinned) = run_experiment(
         cpu_count,
         iterations_per_thread,
         true,
+        clock,
         do_work_with_branch,
         "One Branch",
     );
+    report.metadata.pinned &= pinned;
+    report.add_entry(entry);
+
+    if let Some(path) = report_path_from_args() {
+        match report.write_to_path(&path) {
+            Ok(()) => println!("\nReport written to {}", path.display()),
+            Err(e) => eprintln!("Failed to write report to {}: {}", path.display(), e),
+        }
+    }
 }
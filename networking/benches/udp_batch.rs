@@ -0,0 +1,119 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+use hft_system::batch::{RecvBatch, SendBatch};
+use hft_system::protocol::{self, AddOrder, Message, Side};
+use std::net::{SocketAddr, UdpSocket};
+use std::time::Duration;
+
+// Messages per round trip; small enough that the receive buffer never
+// overflows, so every datagram sent is received
+const ROUND: usize = 64;
+
+fn sockets() -> (UdpSocket, UdpSocket, SocketAddr) {
+    let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+    receiver
+        .set_read_timeout(Some(Duration::from_secs(1)))
+        .unwrap();
+    let sender = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let target = receiver.local_addr().unwrap();
+    (receiver, sender, target)
+}
+
+fn message() -> Vec<u8> {
+    let mut buf = [0u8; protocol::MAX_MESSAGE_SIZE];
+    let order = AddOrder::new(1, "AAPL", Side::Buy, 1_502_500, 100);
+    let len = protocol::encode(&Message::AddOrder(order), 1, 0, &mut buf).unwrap();
+    buf[..len].to_vec()
+}
+
+// One send_to and one recv_from per message; returns the syscalls made
+fn single_round(
+    receiver: &UdpSocket,
+    sender: &UdpSocket,
+    target: SocketAddr,
+    payload: &[u8],
+) -> u64 {
+    let mut buf = [0u8; 2048];
+    for _ in 0..ROUND {
+        sender.send_to(payload, target).unwrap();
+    }
+    for _ in 0..ROUND {
+        black_box(receiver.recv_from(&mut buf).unwrap());
+    }
+    2 * ROUND as u64
+}
+
+fn batched_round(
+    receiver: &UdpSocket,
+    sender: &UdpSocket,
+    target: SocketAddr,
+    payload: &[u8],
+    outgoing: &mut SendBatch,
+    incoming: &mut RecvBatch,
+) -> u64 {
+    let before = outgoing.syscalls() + incoming.syscalls();
+    for _ in 0..ROUND {
+        if !outgoing.push(target, payload) {
+            outgoing.flush(sender).unwrap();
+            outgoing.push(target, payload);
+        }
+    }
+    outgoing.flush(sender).unwrap();
+    let mut received = 0;
+    while received < ROUND {
+        received += incoming.recv(receiver).unwrap();
+        black_box(incoming.datagram(0));
+    }
+    outgoing.syscalls() + incoming.syscalls() - before
+}
+
+fn bench_udp_batching(c: &mut Criterion) {
+    let payload = message();
+    let mut group = c.benchmark_group("udp_batching");
+    group.throughput(Throughput::Elements(ROUND as u64));
+
+    let (receiver, sender, target) = sockets();
+    println!(
+        "single: {} syscalls per {} messages",
+        single_round(&receiver, &sender, target, &payload),
+        ROUND
+    );
+    group.bench_function("single", |b| {
+        b.iter(|| single_round(&receiver, &sender, target, &payload));
+    });
+
+    for batch in [8, 32, 64] {
+        let (receiver, sender, target) = sockets();
+        let mut outgoing = SendBatch::new(batch, payload.len());
+        let mut incoming = RecvBatch::new(batch, 2048);
+        println!(
+            "mmsg batch {}: {} syscalls per {} messages",
+            batch,
+            batched_round(
+                &receiver,
+                &sender,
+                target,
+                &payload,
+                &mut outgoing,
+                &mut incoming
+            ),
+            ROUND
+        );
+        group.bench_function(format!("mmsg_batch_{}", batch), |b| {
+            b.iter(|| {
+                batched_round(
+                    &receiver,
+                    &sender,
+                    target,
+                    &payload,
+                    &mut outgoing,
+                    &mut incoming,
+                )
+            });
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_udp_batching);
+criterion_main!(benches);
@@ -0,0 +1,558 @@
+//! Batched datagram I/O with `recvmmsg`/`sendmmsg`.
+//!
+//! One `recv_from` or `send_to` per 29-byte message spends most of its time
+//! crossing into the kernel. [`RecvBatch`] and [`SendBatch`] move up to
+//! `batch` datagrams per system call instead, and count the calls they make
+//! so the saving can be measured. On platforms without the `mmsg` calls
+//! they fall back to one datagram per call.
+
+use crate::timestamping::RxTimestamp;
+use std::io;
+use std::net::{SocketAddr, UdpSocket};
+
+/// Largest datagram a default-sized batch slot holds.
+pub const MAX_DATAGRAM_SIZE: usize = 2048;
+
+/// Receives up to `batch` datagrams per system call.
+///
+/// # Examples
+/// ```
+/// use hft_system::batch::{RecvBatch, SendBatch};
+/// use std::net::UdpSocket;
+///
+/// let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+/// let sender = UdpSocket::bind("127.0.0.1:0").unwrap();
+/// let target = receiver.local_addr().unwrap();
+///
+/// let mut outgoing = SendBatch::new(8, 64);
+/// for i in 0..3u8 {
+///     outgoing.push(target, &[i]);
+/// }
+/// assert_eq!(outgoing.flush(&sender).unwrap(), 3);
+///
+/// let mut incoming = RecvBatch::new(8, 64);
+/// let mut received = 0;
+/// while received < 3 {
+///     received += incoming.recv(&receiver).unwrap();
+/// }
+/// assert_eq!(incoming.datagram(0).1, [0]);
+/// ```
+pub struct RecvBatch {
+    slot_size: usize,
+    buffers: Box<[u8]>,
+    lens: Vec<usize>,
+    sources: Vec<Option<SocketAddr>>,
+    timestamps: Vec<Option<RxTimestamp>>,
+    received: usize,
+    syscalls: u64,
+    sys: sys::RecvState,
+}
+
+impl RecvBatch {
+    /// Creates a batch of `batch` slots of `slot_size` bytes each. Longer
+    /// datagrams are truncated.
+    ///
+    /// # Panics
+    /// Panics if `batch` or `slot_size` is zero.
+    pub fn new(batch: usize, slot_size: usize) -> Self {
+        assert!(batch > 0, "batch must hold at least one datagram");
+        assert!(slot_size > 0, "slots must hold at least one byte");
+        Self {
+            slot_size,
+            buffers: vec![0u8; batch * slot_size].into_boxed_slice(),
+            lens: vec![0; batch],
+            sources: vec![None; batch],
+            timestamps: vec![None; batch],
+            received: 0,
+            syscalls: 0,
+            sys: sys::RecvState::new(batch),
+        }
+    }
+
+    /// Returns the most datagrams received per call.
+    pub fn capacity(&self) -> usize {
+        self.lens.len()
+    }
+
+    /// Waits for at least one datagram (subject to the socket's read
+    /// timeout), then takes whatever else is already queued, up to the
+    /// batch size. Returns the number of datagrams received; earlier
+    /// datagrams are overwritten.
+    pub fn recv(&mut self, socket: &UdpSocket) -> io::Result<usize> {
+        self.received = 0;
+        self.syscalls += 1;
+        self.received = sys::recv(
+            socket,
+            &mut self.sys,
+            &mut self.buffers,
+            self.slot_size,
+            &mut self.lens,
+            &mut self.sources,
+            &mut self.timestamps,
+        )?;
+        Ok(self.received)
+    }
+
+    /// Returns the number of datagrams from the last [`recv`](Self::recv).
+    pub fn len(&self) -> usize {
+        self.received
+    }
+
+    /// Returns true if the last [`recv`](Self::recv) returned nothing.
+    pub fn is_empty(&self) -> bool {
+        self.received == 0
+    }
+
+    /// Returns the sender and payload of datagram `i`.
+    ///
+    /// # Panics
+    /// Panics if `i` is not below [`len`](Self::len).
+    pub fn datagram(&self, i: usize) -> (Option<SocketAddr>, &[u8]) {
+        assert!(i < self.received, "datagram {} of {}", i, self.received);
+        let start = i * self.slot_size;
+        (self.sources[i], &self.buffers[start..start + self.lens[i]])
+    }
+
+    /// Returns the kernel or NIC receive timestamp of datagram `i`, if
+    /// stamping was [enabled](crate::timestamping::enable) on the socket.
+    /// The kernel turns stamping on asynchronously, so datagrams received
+    /// right after enabling it may have none.
+    ///
+    /// # Panics
+    /// Panics if `i` is not below [`len`](Self::len).
+    pub fn timestamp(&self, i: usize) -> Option<RxTimestamp> {
+        assert!(i < self.received, "datagram {} of {}", i, self.received);
+        self.timestamps[i]
+    }
+
+    /// Returns the received datagrams in arrival order.
+    pub fn iter(&self) -> impl Iterator<Item = (Option<SocketAddr>, &[u8])> {
+        (0..self.received).map(move |i| self.datagram(i))
+    }
+
+    /// Returns the number of receive system calls made.
+    pub fn syscalls(&self) -> u64 {
+        self.syscalls
+    }
+}
+
+/// Queues datagrams and sends them with as few system calls as possible.
+pub struct SendBatch {
+    batch: usize,
+    slot_size: usize,
+    buffers: Box<[u8]>,
+    lens: Vec<usize>,
+    targets: Vec<SocketAddr>,
+    syscalls: u64,
+    sys: sys::SendState,
+}
+
+impl SendBatch {
+    /// Creates a batch of `batch` slots of `slot_size` bytes each.
+    ///
+    /// # Panics
+    /// Panics if `batch` or `slot_size` is zero.
+    pub fn new(batch: usize, slot_size: usize) -> Self {
+        assert!(batch > 0, "batch must hold at least one datagram");
+        assert!(slot_size > 0, "slots must hold at least one byte");
+        Self {
+            batch,
+            slot_size,
+            buffers: vec![0u8; batch * slot_size].into_boxed_slice(),
+            lens: Vec::with_capacity(batch),
+            targets: Vec::with_capacity(batch),
+            syscalls: 0,
+            sys: sys::SendState::new(batch),
+        }
+    }
+
+    /// Returns the most datagrams sent per call.
+    pub fn capacity(&self) -> usize {
+        self.batch
+    }
+
+    /// Returns the number of queued datagrams.
+    pub fn len(&self) -> usize {
+        self.lens.len()
+    }
+
+    /// Returns true if nothing is queued.
+    pub fn is_empty(&self) -> bool {
+        self.lens.is_empty()
+    }
+
+    /// Returns true if the next [`push`](Self::push) would be refused.
+    pub fn is_full(&self) -> bool {
+        self.lens.len() == self.capacity()
+    }
+
+    /// Queues `payload` for `target`. Returns false, queueing nothing, if
+    /// the batch is full.
+    ///
+    /// # Panics
+    /// Panics if `payload` is longer than the slot size.
+    pub fn push(&mut self, target: SocketAddr, payload: &[u8]) -> bool {
+        assert!(
+            payload.len() <= self.slot_size,
+            "{}-byte datagram exceeds the {}-byte slot",
+            payload.len(),
+            self.slot_size
+        );
+        if self.is_full() {
+            return false;
+        }
+        let start = self.lens.len() * self.slot_size;
+        self.buffers[start..start + payload.len()].copy_from_slice(payload);
+        self.lens.push(payload.len());
+        self.targets.push(target);
+        true
+    }
+
+    /// Sends every queued datagram and empties the batch. Returns the
+    /// number sent; on error the unsent datagrams are discarded.
+    pub fn flush(&mut self, socket: &UdpSocket) -> io::Result<usize> {
+        let mut sent = 0;
+        let result = loop {
+            if sent == self.lens.len() {
+                break Ok(sent);
+            }
+            self.syscalls += 1;
+            match sys::send(
+                socket,
+                &mut self.sys,
+                &self.buffers,
+                self.slot_size,
+                &self.lens,
+                &self.targets,
+                sent,
+            ) {
+                Ok(n) => sent += n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => break Err(e),
+            }
+        };
+        self.lens.clear();
+        self.targets.clear();
+        result
+    }
+
+    /// Returns the number of send system calls made.
+    pub fn syscalls(&self) -> u64 {
+        self.syscalls
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod sys {
+    use super::RxTimestamp;
+    use crate::timestamping::sys as timestamping;
+    use std::io;
+    use std::mem;
+    use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6, UdpSocket};
+    use std::os::fd::AsRawFd;
+    use std::ptr;
+
+    /// Kernel-facing arrays; pointers are refreshed before every call so
+    /// the owning batch may move between calls.
+    pub struct RecvState {
+        headers: Vec<libc::mmsghdr>,
+        iovecs: Vec<libc::iovec>,
+        addrs: Vec<libc::sockaddr_storage>,
+        control: Vec<[u64; timestamping::CONTROL_WORDS]>,
+    }
+
+    pub struct SendState {
+        headers: Vec<libc::mmsghdr>,
+        iovecs: Vec<libc::iovec>,
+        addrs: Vec<libc::sockaddr_storage>,
+    }
+
+    // SAFETY: the raw pointers only ever point into buffers owned by the
+    // same batch, and are rewritten before each use.
+    unsafe impl Send for RecvState {}
+    unsafe impl Send for SendState {}
+
+    fn zeroed<T>(n: usize) -> Vec<T> {
+        // SAFETY: only used for plain C structs, for which all-zero is a
+        // valid value.
+        (0..n).map(|_| unsafe { mem::zeroed() }).collect()
+    }
+
+    impl RecvState {
+        pub fn new(batch: usize) -> Self {
+            Self {
+                headers: zeroed(batch),
+                iovecs: zeroed(batch),
+                addrs: zeroed(batch),
+                control: vec![[0; timestamping::CONTROL_WORDS]; batch],
+            }
+        }
+    }
+
+    impl SendState {
+        pub fn new(batch: usize) -> Self {
+            Self {
+                headers: zeroed(batch),
+                iovecs: zeroed(batch),
+                addrs: zeroed(batch),
+            }
+        }
+    }
+
+    pub fn recv(
+        socket: &UdpSocket,
+        state: &mut RecvState,
+        buffers: &mut [u8],
+        slot_size: usize,
+        lens: &mut [usize],
+        sources: &mut [Option<SocketAddr>],
+        timestamps: &mut [Option<RxTimestamp>],
+    ) -> io::Result<usize> {
+        let batch = lens.len();
+        for i in 0..batch {
+            state.iovecs[i] = libc::iovec {
+                iov_base: buffers[i * slot_size..].as_mut_ptr() as *mut libc::c_void,
+                iov_len: slot_size,
+            };
+            let header = &mut state.headers[i].msg_hdr;
+            header.msg_name = &mut state.addrs[i] as *mut _ as *mut libc::c_void;
+            header.msg_namelen = mem::size_of::<libc::sockaddr_storage>() as libc::socklen_t;
+            header.msg_iov = &mut state.iovecs[i];
+            header.msg_iovlen = 1;
+            header.msg_control = state.control[i].as_mut_ptr() as *mut libc::c_void;
+            header.msg_controllen = mem::size_of_val(&state.control[i]) as _;
+            header.msg_flags = 0;
+        }
+        // SAFETY: every header points at a live iovec, buffer slot, address
+        // and control buffer of the advertised sizes. MSG_WAITFORONE blocks
+        // (up to SO_RCVTIMEO) for the first datagram only.
+        let n = unsafe {
+            libc::recvmmsg(
+                socket.as_raw_fd(),
+                state.headers.as_mut_ptr(),
+                batch as libc::c_uint,
+                libc::MSG_WAITFORONE,
+                ptr::null_mut(),
+            )
+        };
+        if n < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let n = n as usize;
+        for i in 0..n {
+            lens[i] = (state.headers[i].msg_len as usize).min(slot_size);
+            sources[i] = from_sockaddr(&state.addrs[i]);
+            // SAFETY: the kernel filled in this header's control buffer.
+            timestamps[i] = unsafe { timestamping::parse(&state.headers[i].msg_hdr) };
+        }
+        Ok(n)
+    }
+
+    pub fn send(
+        socket: &UdpSocket,
+        state: &mut SendState,
+        buffers: &[u8],
+        slot_size: usize,
+        lens: &[usize],
+        targets: &[SocketAddr],
+        offset: usize,
+    ) -> io::Result<usize> {
+        let count = lens.len() - offset;
+        for (k, i) in (offset..lens.len()).enumerate() {
+            state.iovecs[k] = libc::iovec {
+                iov_base: buffers[i * slot_size..].as_ptr() as *mut libc::c_void,
+                iov_len: lens[i],
+            };
+            let addr_len = to_sockaddr(targets[i], &mut state.addrs[k]);
+            let header = &mut state.headers[k].msg_hdr;
+            header.msg_name = &mut state.addrs[k] as *mut _ as *mut libc::c_void;
+            header.msg_namelen = addr_len;
+            header.msg_iov = &mut state.iovecs[k];
+            header.msg_iovlen = 1;
+            header.msg_control = ptr::null_mut();
+            header.msg_controllen = 0;
+            header.msg_flags = 0;
+        }
+        // SAFETY: the first `count` headers point at live iovecs, payloads
+        // and addresses; the kernel only reads the payloads.
+        let n = unsafe {
+            libc::sendmmsg(
+                socket.as_raw_fd(),
+                state.headers.as_mut_ptr(),
+                count as libc::c_uint,
+                0,
+            )
+        };
+        if n < 0 {
+            Err(io::Error::last_os_error())
+        } else {
+            Ok(n as usize)
+        }
+    }
+
+    fn from_sockaddr(storage: &libc::sockaddr_storage) -> Option<SocketAddr> {
+        match storage.ss_family as libc::c_int {
+            libc::AF_INET => {
+                // SAFETY: the family says this is a sockaddr_in.
+                let addr = unsafe { &*(storage as *const _ as *const libc::sockaddr_in) };
+                Some(SocketAddr::V4(SocketAddrV4::new(
+                    Ipv4Addr::from(addr.sin_addr.s_addr.to_ne_bytes()),
+                    u16::from_be(addr.sin_port),
+                )))
+            }
+            libc::AF_INET6 => {
+                // SAFETY: the family says this is a sockaddr_in6.
+                let addr = unsafe { &*(storage as *const _ as *const libc::sockaddr_in6) };
+                Some(SocketAddr::V6(SocketAddrV6::new(
+                    Ipv6Addr::from(addr.sin6_addr.s6_addr),
+                    u16::from_be(addr.sin6_port),
+                    addr.sin6_flowinfo,
+                    addr.sin6_scope_id,
+                )))
+            }
+            _ => None,
+        }
+    }
+
+    fn to_sockaddr(addr: SocketAddr, storage: &mut libc::sockaddr_storage) -> libc::socklen_t {
+        match addr {
+            SocketAddr::V4(addr) => {
+                // SAFETY: sockaddr_storage is large and aligned enough for
+                // any socket address.
+                let out = unsafe { &mut *(storage as *mut _ as *mut libc::sockaddr_in) };
+                out.sin_family = libc::AF_INET as libc::sa_family_t;
+                out.sin_port = addr.port().to_be();
+                out.sin_addr = libc::in_addr {
+                    s_addr: u32::from_ne_bytes(addr.ip().octets()),
+                };
+                mem::size_of::<libc::sockaddr_in>() as libc::socklen_t
+            }
+            SocketAddr::V6(addr) => {
+                // SAFETY: as above.
+                let out = unsafe { &mut *(storage as *mut _ as *mut libc::sockaddr_in6) };
+                out.sin6_family = libc::AF_INET6 as libc::sa_family_t;
+                out.sin6_port = addr.port().to_be();
+                out.sin6_flowinfo = addr.flowinfo();
+                out.sin6_addr = libc::in6_addr {
+                    s6_addr: addr.ip().octets(),
+                };
+                out.sin6_scope_id = addr.scope_id();
+                mem::size_of::<libc::sockaddr_in6>() as libc::socklen_t
+            }
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod sys {
+    use super::RxTimestamp;
+    use std::io;
+    use std::net::{SocketAddr, UdpSocket};
+
+    pub struct RecvState;
+    pub struct SendState;
+
+    impl RecvState {
+        pub fn new(_batch: usize) -> Self {
+            RecvState
+        }
+    }
+
+    impl SendState {
+        pub fn new(_batch: usize) -> Self {
+            SendState
+        }
+    }
+
+    pub fn recv(
+        socket: &UdpSocket,
+        _state: &mut RecvState,
+        buffers: &mut [u8],
+        slot_size: usize,
+        lens: &mut [usize],
+        sources: &mut [Option<SocketAddr>],
+        timestamps: &mut [Option<RxTimestamp>],
+    ) -> io::Result<usize> {
+        let (len, source) = socket.recv_from(&mut buffers[..slot_size])?;
+        lens[0] = len;
+        sources[0] = Some(source);
+        timestamps[0] = None;
+        Ok(1)
+    }
+
+    pub fn send(
+        socket: &UdpSocket,
+        _state: &mut SendState,
+        buffers: &[u8],
+        slot_size: usize,
+        lens: &[usize],
+        targets: &[SocketAddr],
+        offset: usize,
+    ) -> io::Result<usize> {
+        let start = offset * slot_size;
+        socket.send_to(&buffers[start..start + lens[offset]], targets[offset])?;
+        Ok(1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn pair() -> (UdpSocket, UdpSocket) {
+        let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+        receiver
+            .set_read_timeout(Some(Duration::from_millis(200)))
+            .unwrap();
+        (receiver, UdpSocket::bind("127.0.0.1:0").unwrap())
+    }
+
+    #[test]
+    fn test_batch_round_trip() {
+        let (receiver, sender) = pair();
+        let target = receiver.local_addr().unwrap();
+
+        let mut outgoing = SendBatch::new(16, 32);
+        for i in 0..20u8 {
+            if !outgoing.push(target, &[i; 4]) {
+                outgoing.flush(&sender).unwrap();
+                assert!(outgoing.push(target, &[i; 4]));
+            }
+        }
+        outgoing.flush(&sender).unwrap();
+        #[cfg(target_os = "linux")]
+        assert_eq!(outgoing.syscalls(), 2);
+
+        let mut incoming = RecvBatch::new(8, 32);
+        let mut payloads = Vec::new();
+        while payloads.len() < 20 {
+            incoming.recv(&receiver).unwrap();
+            for (source, payload) in incoming.iter() {
+                assert_eq!(source, Some(sender.local_addr().unwrap()));
+                payloads.push(payload[0]);
+            }
+        }
+        assert_eq!(payloads, (0..20).collect::<Vec<u8>>());
+        #[cfg(target_os = "linux")]
+        assert_eq!(incoming.syscalls(), 3);
+    }
+
+    #[test]
+    fn test_recv_times_out_and_truncates() {
+        let (receiver, sender) = pair();
+        let mut incoming = RecvBatch::new(4, 8);
+        let e = incoming.recv(&receiver).unwrap_err();
+        assert!(matches!(
+            e.kind(),
+            io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+        ));
+        assert!(incoming.is_empty());
+
+        sender
+            .send_to(&[7; 20], receiver.local_addr().unwrap())
+            .unwrap();
+        assert_eq!(incoming.recv(&receiver).unwrap(), 1);
+        assert_eq!(incoming.datagram(0).1, [7; 8]);
+    }
+}
@@ -0,0 +1,392 @@
+//! Wire codec for [`MarketMessage`].
+//!
+//! Every field is written at a fixed offset in little-endian byte order,
+//! independent of the host's endianness and struct layout:
+//!
+//! | Offset | Size | Field                          |
+//! |--------|------|--------------------------------|
+//! | 0      | 1    | message type (`1`)             |
+//! | 1      | 8    | symbol, ASCII, NUL-padded      |
+//! | 9      | 8    | price, fixed point (1e-4)      |
+//! | 17     | 4    | quantity                       |
+//! | 21     | 8    | timestamp, ns since epoch      |
+//!
+//! Decoding validates length, message type and symbol and returns a
+//! [`CodecError`] for anything malformed. [`MarketMessageView`] reads fields
+//! straight from a received buffer without copying it.
+
+use crate::timestamping::now_nanos;
+use std::fmt;
+
+/// Encoded size of a [`MarketMessage`] in bytes.
+pub const WIRE_SIZE: usize = 29;
+/// Message type byte of a market data update.
+pub const MARKET_MESSAGE_TYPE: u8 = 1;
+/// Prices are carried as integers in units of 1/`PRICE_SCALE`.
+pub const PRICE_SCALE: f64 = 10_000.0;
+
+const TYPE_OFFSET: usize = 0;
+const SYMBOL_OFFSET: usize = 1;
+const PRICE_OFFSET: usize = 9;
+const QUANTITY_OFFSET: usize = 17;
+const TIMESTAMP_OFFSET: usize = 21;
+
+/// Errors from encoding or decoding a message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CodecError {
+    /// The buffer is not exactly [`WIRE_SIZE`] bytes when decoding, or
+    /// smaller than that when encoding.
+    InvalidLength { expected: usize, actual: usize },
+    /// The type byte is not a known message type.
+    UnknownMessageType(u8),
+    /// The symbol has a non-printable or non-ASCII byte, text after the NUL
+    /// padding, or is empty.
+    InvalidSymbol,
+    /// A protocol header carries a version this build cannot decode.
+    UnsupportedVersion(u8),
+    /// A protocol header's length is shorter than its message type requires.
+    LengthMismatch { declared: usize, minimum: usize },
+    /// The side byte is neither `B` nor `S`.
+    InvalidSide(u8),
+}
+
+impl fmt::Display for CodecError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CodecError::InvalidLength { expected, actual } => {
+                write!(f, "expected {} bytes, got {}", expected, actual)
+            }
+            CodecError::UnknownMessageType(kind) => write!(f, "unknown message type {}", kind),
+            CodecError::InvalidSymbol => f.write_str("symbol is not NUL-padded printable ASCII"),
+            CodecError::UnsupportedVersion(version) => {
+                write!(f, "unsupported protocol version {}", version)
+            }
+            CodecError::LengthMismatch { declared, minimum } => write!(
+                f,
+                "declared length {} is below the minimum {}",
+                declared, minimum
+            ),
+            CodecError::InvalidSide(side) => write!(f, "invalid side byte {:#04x}", side),
+        }
+    }
+}
+
+impl std::error::Error for CodecError {}
+
+/// Fixed-size market data message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MarketMessage {
+    pub message_type: u8,
+    pub symbol: [u8; 8],
+    /// Price in units of 1/[`PRICE_SCALE`].
+    pub price: u64,
+    pub quantity: u32,
+    /// Send time in nanoseconds since the Unix epoch.
+    pub timestamp: u64,
+}
+
+impl MarketMessage {
+    /// Creates a message stamped with the current time.
+    ///
+    /// Symbols longer than 8 bytes are truncated.
+    pub fn new(symbol: &str, price: f64, quantity: u32) -> Self {
+        Self {
+            message_type: MARKET_MESSAGE_TYPE,
+            symbol: pack_symbol(symbol),
+            price: (price * PRICE_SCALE) as u64,
+            quantity,
+            timestamp: now_nanos(),
+        }
+    }
+
+    /// Returns the symbol without its NUL padding.
+    pub fn symbol_str(&self) -> &str {
+        symbol_str(&self.symbol)
+    }
+
+    /// Returns the price as a decimal.
+    pub fn price_f64(&self) -> f64 {
+        self.price as f64 / PRICE_SCALE
+    }
+
+    /// Encodes the message into a new array.
+    ///
+    /// # Examples
+    /// ```
+    /// use hft_system::MarketMessage;
+    ///
+    /// let message = MarketMessage::new("AAPL", 150.25, 100);
+    /// let bytes = message.to_bytes();
+    /// assert_eq!(MarketMessage::try_from(&bytes[..]), Ok(message));
+    /// ```
+    pub fn to_bytes(&self) -> [u8; WIRE_SIZE] {
+        let mut bytes = [0u8; WIRE_SIZE];
+        self.write(&mut bytes);
+        bytes
+    }
+
+    /// Encodes the message into the start of `buf`, returning the number of
+    /// bytes written.
+    pub fn encode_into(&self, buf: &mut [u8]) -> Result<usize, CodecError> {
+        let actual = buf.len();
+        let bytes: &mut [u8; WIRE_SIZE] = buf
+            .get_mut(..WIRE_SIZE)
+            .and_then(|prefix| prefix.try_into().ok())
+            .ok_or(CodecError::InvalidLength {
+                expected: WIRE_SIZE,
+                actual,
+            })?;
+        self.write(bytes);
+        Ok(WIRE_SIZE)
+    }
+
+    fn write(&self, bytes: &mut [u8; WIRE_SIZE]) {
+        bytes[TYPE_OFFSET] = self.message_type;
+        bytes[SYMBOL_OFFSET..PRICE_OFFSET].copy_from_slice(&self.symbol);
+        bytes[PRICE_OFFSET..QUANTITY_OFFSET].copy_from_slice(&self.price.to_le_bytes());
+        bytes[QUANTITY_OFFSET..TIMESTAMP_OFFSET].copy_from_slice(&self.quantity.to_le_bytes());
+        bytes[TIMESTAMP_OFFSET..WIRE_SIZE].copy_from_slice(&self.timestamp.to_le_bytes());
+    }
+}
+
+impl TryFrom<&[u8]> for MarketMessage {
+    type Error = CodecError;
+
+    fn try_from(bytes: &[u8]) -> Result<Self, CodecError> {
+        MarketMessageView::try_from(bytes).map(|view| view.to_message())
+    }
+}
+
+/// Zero-copy view of an encoded [`MarketMessage`].
+///
+/// Validated once on construction; accessors decode single fields from the
+/// borrowed bytes.
+///
+/// # Examples
+/// ```
+/// use hft_system::{MarketMessage, MarketMessageView};
+///
+/// let bytes = MarketMessage::new("MSFT", 410.5, 25).to_bytes();
+/// let view = MarketMessageView::try_from(&bytes[..]).unwrap();
+/// assert_eq!(view.symbol(), "MSFT");
+/// assert_eq!(view.quantity(), 25);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MarketMessageView<'a> {
+    bytes: &'a [u8; WIRE_SIZE],
+}
+
+impl<'a> MarketMessageView<'a> {
+    /// Returns the message type byte.
+    pub fn message_type(&self) -> u8 {
+        self.bytes[TYPE_OFFSET]
+    }
+
+    /// Returns the symbol without its NUL padding.
+    pub fn symbol(&self) -> &'a str {
+        symbol_str(self.symbol_bytes())
+    }
+
+    /// Returns the NUL-padded symbol bytes.
+    pub fn symbol_bytes(&self) -> &'a [u8; 8] {
+        self.bytes[SYMBOL_OFFSET..PRICE_OFFSET]
+            .try_into()
+            .expect("symbol field is 8 bytes")
+    }
+
+    /// Returns the price in units of 1/[`PRICE_SCALE`].
+    pub fn price(&self) -> u64 {
+        u64::from_le_bytes(self.field(PRICE_OFFSET))
+    }
+
+    pub fn quantity(&self) -> u32 {
+        u32::from_le_bytes(self.field(QUANTITY_OFFSET))
+    }
+
+    pub fn timestamp(&self) -> u64 {
+        u64::from_le_bytes(self.field(TIMESTAMP_OFFSET))
+    }
+
+    /// Returns the encoded bytes.
+    pub fn as_bytes(&self) -> &'a [u8; WIRE_SIZE] {
+        self.bytes
+    }
+
+    /// Decodes every field into an owned message.
+    pub fn to_message(&self) -> MarketMessage {
+        MarketMessage {
+            message_type: self.message_type(),
+            symbol: *self.symbol_bytes(),
+            price: self.price(),
+            quantity: self.quantity(),
+            timestamp: self.timestamp(),
+        }
+    }
+
+    fn field<const N: usize>(&self, offset: usize) -> [u8; N] {
+        self.bytes[offset..offset + N]
+            .try_into()
+            .expect("field lies within the message")
+    }
+}
+
+impl<'a> TryFrom<&'a [u8]> for MarketMessageView<'a> {
+    type Error = CodecError;
+
+    fn try_from(bytes: &'a [u8]) -> Result<Self, CodecError> {
+        let bytes: &'a [u8; WIRE_SIZE] =
+            bytes.try_into().map_err(|_| CodecError::InvalidLength {
+                expected: WIRE_SIZE,
+                actual: bytes.len(),
+            })?;
+        let view = Self { bytes };
+        if view.message_type() != MARKET_MESSAGE_TYPE {
+            return Err(CodecError::UnknownMessageType(view.message_type()));
+        }
+        if !is_valid_symbol(view.symbol_bytes()) {
+            return Err(CodecError::InvalidSymbol);
+        }
+        Ok(view)
+    }
+}
+
+/// NUL-pads `symbol` to 8 bytes, truncating longer symbols.
+pub(crate) fn pack_symbol(symbol: &str) -> [u8; 8] {
+    let mut bytes = [0u8; 8];
+    let len = symbol.len().min(8);
+    bytes[..len].copy_from_slice(&symbol.as_bytes()[..len]);
+    bytes
+}
+
+/// A symbol is 1-8 printable ASCII bytes followed only by NUL padding.
+pub(crate) fn is_valid_symbol(symbol: &[u8; 8]) -> bool {
+    let len = symbol.iter().position(|&b| b == 0).unwrap_or(symbol.len());
+    len > 0
+        && symbol[..len].iter().all(|b| b.is_ascii_graphic())
+        && symbol[len..].iter().all(|&b| b == 0)
+}
+
+pub(crate) fn symbol_str(symbol: &[u8; 8]) -> &str {
+    let len = symbol.iter().position(|&b| b == 0).unwrap_or(symbol.len());
+    // Only invalid for messages built by hand with non-UTF-8 symbols
+    std::str::from_utf8(&symbol[..len]).unwrap_or("")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::loadgen::Rng;
+
+    fn random_message(rng: &mut Rng) -> MarketMessage {
+        let mut symbol = [0u8; 8];
+        for byte in symbol.iter_mut().take(1 + rng.below(8)) {
+            *byte = b'!' + rng.below(94) as u8;
+        }
+        MarketMessage {
+            message_type: MARKET_MESSAGE_TYPE,
+            symbol,
+            price: rng.next_u64(),
+            quantity: rng.next_u64() as u32,
+            timestamp: rng.next_u64(),
+        }
+    }
+
+    #[test]
+    fn test_explicit_little_endian_layout() {
+        let message = MarketMessage {
+            message_type: MARKET_MESSAGE_TYPE,
+            symbol: *b"AAPL\0\0\0\0",
+            price: 0x0102_0304_0506_0708,
+            quantity: 0x0a0b_0c0d,
+            timestamp: 0x1112_1314_1516_1718,
+        };
+        let bytes = message.to_bytes();
+        assert_eq!(bytes[0], 1);
+        assert_eq!(&bytes[1..9], b"AAPL\0\0\0\0");
+        assert_eq!(bytes[9..17], [8, 7, 6, 5, 4, 3, 2, 1]);
+        assert_eq!(bytes[17..21], [0x0d, 0x0c, 0x0b, 0x0a]);
+        assert_eq!(
+            bytes[21..29],
+            [0x18, 0x17, 0x16, 0x15, 0x14, 0x13, 0x12, 0x11]
+        );
+
+        let view = MarketMessageView::try_from(&bytes[..]).unwrap();
+        assert_eq!(view.symbol(), "AAPL");
+        assert_eq!(view.price(), message.price);
+        assert_eq!(view.to_message(), message);
+    }
+
+    #[test]
+    fn test_malformed_buffers_are_rejected() {
+        let bytes = MarketMessage::new("AAPL", 150.25, 100).to_bytes();
+        assert_eq!(
+            MarketMessage::try_from(&bytes[..28]),
+            Err(CodecError::InvalidLength {
+                expected: WIRE_SIZE,
+                actual: 28
+            })
+        );
+        let mut longer = bytes.to_vec();
+        longer.push(0);
+        assert!(MarketMessage::try_from(&longer[..]).is_err());
+
+        let mut bad_type = bytes;
+        bad_type[0] = 7;
+        assert_eq!(
+            MarketMessage::try_from(&bad_type[..]),
+            Err(CodecError::UnknownMessageType(7))
+        );
+
+        for symbol in [b"\0\0\0\0\0\0\0\0", b"AA\0PL\0\0\0", b"AA\xffPL\0\0\0"] {
+            let mut bad_symbol = bytes;
+            bad_symbol[1..9].copy_from_slice(symbol);
+            assert_eq!(
+                MarketMessage::try_from(&bad_symbol[..]),
+                Err(CodecError::InvalidSymbol)
+            );
+        }
+
+        let mut small = [0u8; 16];
+        assert!(MarketMessage::new("AAPL", 1.0, 1)
+            .encode_into(&mut small)
+            .is_err());
+    }
+
+    #[test]
+    fn test_round_trip_random_messages() {
+        let mut rng = Rng::new(0x9e37_79b9_7f4a_7c15);
+        let mut buf = [0u8; 64];
+        for _ in 0..10_000 {
+            let message = random_message(&mut rng);
+            assert_eq!(message.encode_into(&mut buf), Ok(WIRE_SIZE));
+            assert_eq!(MarketMessage::try_from(&buf[..WIRE_SIZE]), Ok(message));
+        }
+    }
+
+    #[test]
+    fn test_random_bytes_never_panic() {
+        let mut rng = Rng::new(42);
+        let mut accepted = 0;
+        for i in 0..50_000u32 {
+            // Mostly exact-size buffers so some decode; the rest random sizes
+            let len = if i.is_multiple_of(4) {
+                rng.below(64)
+            } else {
+                WIRE_SIZE
+            };
+            let mut bytes: Vec<u8> = (0..len).map(|_| rng.next_u64() as u8).collect();
+            if len == WIRE_SIZE && i.is_multiple_of(2) {
+                bytes[0] = MARKET_MESSAGE_TYPE;
+                bytes[1] = b'A';
+                bytes[2..9].fill(0);
+            }
+
+            if let Ok(message) = MarketMessage::try_from(&bytes[..]) {
+                accepted += 1;
+                // Anything accepted re-encodes to the same bytes
+                assert_eq!(&message.to_bytes()[..], &bytes[..]);
+            }
+        }
+        assert!(accepted > 0);
+    }
+}
@@ -0,0 +1,338 @@
+//! Command-line configuration for the `hft-system` binary.
+//!
+//! Every option except `--plot` takes a value, written as `--option value`
+//! or `--option=value`. With `--mode receiver` and `--mode sender` the two
+//! halves of the system run as separate processes, e.g. on two hosts: the
+//! sender serves retransmissions on `--recovery`, and the receiver connects
+//! to the same address.
+
+use crate::loadgen::Arrival;
+use std::fmt;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// Help text printed for `--help` and after usage errors.
+pub const USAGE: &str = "\
+usage: hft-system [options]
+
+  --mode both|receiver|sender   stages to run in this process (default both)
+  --bind ADDR:PORT              receiver address (default 0.0.0.0:9001)
+  --target ADDR:PORT            sender destination (default 127.0.0.1:9001)
+  --multicast GROUP             use a multicast group on the bind/target port
+  --interface ADDR              multicast interface (default 127.0.0.1)
+  --recovery ADDR:PORT          retransmission server (default 127.0.0.1:9002)
+  --queue-capacity N            receiver-to-consumer queue slots (default 16384)
+  --receiver-core N             pin the receiver; any core option disables
+  --consumer-core N               automatic placement
+  --sender-core N
+  --duration SECS               run time, 0 to run until Ctrl+C (default 10)
+  --rate MSG/S                  send rate (default 20000)
+  --count N                     messages to send (default 10000)
+  --arrivals uniform|poisson|burst=N
+                                send pattern (default burst=16)
+  --report PATH                 write a run report (CSV if PATH ends in .csv,
+                                else JSON, which needs the `serde` feature)
+  --plot                        print receive latency distributions";
+
+/// Which stages a process runs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    /// Receiver, consumer and sender in one process.
+    Both,
+    /// Receiver and consumer.
+    Receiver,
+    /// Sender and its retransmission server.
+    Sender,
+}
+
+impl Mode {
+    /// Returns true if the receiver and consumer run.
+    pub fn runs_receiver(self) -> bool {
+        matches!(self, Mode::Both | Mode::Receiver)
+    }
+
+    /// Returns true if the sender and recovery server run.
+    pub fn runs_sender(self) -> bool {
+        matches!(self, Mode::Both | Mode::Sender)
+    }
+}
+
+impl fmt::Display for Mode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Mode::Both => f.write_str("both"),
+            Mode::Receiver => f.write_str("receiver"),
+            Mode::Sender => f.write_str("sender"),
+        }
+    }
+}
+
+/// Settings of one `hft-system` process.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SystemConfig {
+    pub mode: Mode,
+    pub bind: SocketAddr,
+    pub target: SocketAddr,
+    /// Multicast group replacing the unicast addresses' IPs.
+    pub multicast: Option<Ipv4Addr>,
+    pub interface: Ipv4Addr,
+    pub recovery: SocketAddr,
+    pub queue_capacity: usize,
+    pub receiver_core: Option<usize>,
+    pub consumer_core: Option<usize>,
+    pub sender_core: Option<usize>,
+    /// `None` runs until a signal.
+    pub run_time: Option<Duration>,
+    pub rate: f64,
+    pub count: u64,
+    pub arrivals: Arrival,
+    pub report: Option<PathBuf>,
+    /// Print latency distributions after the summary.
+    pub plot: bool,
+}
+
+impl Default for SystemConfig {
+    fn default() -> Self {
+        Self {
+            mode: Mode::Both,
+            bind: SocketAddr::from(([0, 0, 0, 0], 9001)),
+            target: SocketAddr::from(([127, 0, 0, 1], 9001)),
+            multicast: None,
+            interface: Ipv4Addr::LOCALHOST,
+            recovery: SocketAddr::from(([127, 0, 0, 1], 9002)),
+            queue_capacity: 16384,
+            receiver_core: None,
+            consumer_core: None,
+            sender_core: None,
+            run_time: Some(Duration::from_secs(10)),
+            rate: 20_000.0,
+            count: 10_000,
+            arrivals: Arrival::Burst { size: 16 },
+            report: None,
+            plot: false,
+        }
+    }
+}
+
+/// Error returned by [`SystemConfig::parse`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConfigError {
+    /// `-h` or `--help` was given; print [`USAGE`] and exit successfully.
+    Help,
+    /// The arguments are invalid; the message is meant for the user.
+    Invalid(String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Help => f.write_str(USAGE),
+            ConfigError::Invalid(message) => f.write_str(message),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl From<String> for ConfigError {
+    fn from(message: String) -> Self {
+        ConfigError::Invalid(message)
+    }
+}
+
+impl SystemConfig {
+    /// Parses the process's command line.
+    pub fn from_args() -> Result<Self, ConfigError> {
+        Self::parse(std::env::args().skip(1))
+    }
+
+    /// Parses `args`, which exclude the program name. `--help` returns
+    /// [`ConfigError::Help`] rather than a configuration.
+    ///
+    /// # Examples
+    /// ```
+    /// use hft_system::config::{Mode, SystemConfig};
+    ///
+    /// let args = ["--mode", "sender", "--target=10.0.0.2:9001", "--rate", "50000"];
+    /// let config = SystemConfig::parse(args.iter().map(|s| s.to_string())).unwrap();
+    /// assert_eq!(config.mode, Mode::Sender);
+    /// assert_eq!(config.target.port(), 9001);
+    /// assert_eq!(config.rate, 50_000.0);
+    /// ```
+    pub fn parse<I: IntoIterator<Item = String>>(args: I) -> Result<Self, ConfigError> {
+        let mut config = Self::default();
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            if arg == "-h" || arg == "--help" {
+                return Err(ConfigError::Help);
+            }
+            if arg == "--plot" {
+                config.plot = true;
+                continue;
+            }
+            let (name, inline) = match arg.split_once('=') {
+                Some((name, value)) if name.starts_with("--") => (name, Some(value.to_string())),
+                _ => (arg.as_str(), None),
+            };
+            let value = match inline.or_else(|| args.next()) {
+                Some(value) => value,
+                None if name.starts_with("--") => {
+                    return Err(format!("{} requires a value\n{}", name, USAGE).into())
+                }
+                None => return Err(format!("unexpected argument '{}'\n{}", name, USAGE).into()),
+            };
+            config.set(name, &value)?;
+        }
+        Ok(config)
+    }
+
+    fn set(&mut self, name: &str, value: &str) -> Result<(), String> {
+        match name {
+            "--mode" => {
+                self.mode = match value {
+                    "both" => Mode::Both,
+                    "receiver" => Mode::Receiver,
+                    "sender" => Mode::Sender,
+                    _ => return Err(format!("unknown mode '{}'", value)),
+                }
+            }
+            "--bind" => self.bind = parse_value(name, value)?,
+            "--target" => self.target = parse_value(name, value)?,
+            "--multicast" => {
+                let group: Ipv4Addr = parse_value(name, value)?;
+                if !group.is_multicast() {
+                    return Err(format!("{} is not a multicast group", group));
+                }
+                self.multicast = Some(group);
+            }
+            "--interface" => self.interface = parse_value(name, value)?,
+            "--recovery" => self.recovery = parse_value(name, value)?,
+            "--queue-capacity" => {
+                self.queue_capacity = parse_value(name, value)?;
+                if self.queue_capacity < 2 {
+                    return Err("--queue-capacity must be at least 2".to_string());
+                }
+            }
+            "--receiver-core" => self.receiver_core = Some(parse_value(name, value)?),
+            "--consumer-core" => self.consumer_core = Some(parse_value(name, value)?),
+            "--sender-core" => self.sender_core = Some(parse_value(name, value)?),
+            "--duration" => {
+                let secs: f64 = parse_value(name, value)?;
+                self.run_time = if secs == 0.0 {
+                    None
+                } else {
+                    Some(
+                        Duration::try_from_secs_f64(secs)
+                            .map_err(|_| format!("invalid duration '{}'", value))?,
+                    )
+                };
+            }
+            "--rate" => {
+                self.rate = parse_value(name, value)?;
+                if !(self.rate.is_finite() && self.rate > 0.0) {
+                    return Err("--rate must be positive".to_string());
+                }
+            }
+            "--count" => self.count = parse_value(name, value)?,
+            "--arrivals" => {
+                self.arrivals = match value {
+                    "uniform" => Arrival::Uniform,
+                    "poisson" => Arrival::Poisson,
+                    _ => match value.strip_prefix("burst=").map(str::parse) {
+                        Some(Ok(size)) if size > 0 => Arrival::Burst { size },
+                        _ => return Err(format!("unknown arrivals '{}'", value)),
+                    },
+                }
+            }
+            "--report" => self.report = Some(PathBuf::from(value)),
+            _ => return Err(format!("unexpected argument '{}'\n{}", name, USAGE)),
+        }
+        Ok(())
+    }
+
+    /// Returns true if any core was given, which turns off automatic
+    /// placement.
+    pub fn has_explicit_cores(&self) -> bool {
+        self.receiver_core.is_some() || self.consumer_core.is_some() || self.sender_core.is_some()
+    }
+}
+
+fn parse_value<T: std::str::FromStr>(name: &str, value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("invalid value '{}' for {}", value, name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<SystemConfig, ConfigError> {
+        SystemConfig::parse(args.iter().map(|s| s.to_string()))
+    }
+
+    #[test]
+    fn test_defaults_and_overrides() {
+        assert_eq!(parse(&[]).unwrap(), SystemConfig::default());
+
+        let config = parse(&[
+            "--mode=receiver",
+            "--bind",
+            "127.0.0.1:7001",
+            "--multicast",
+            "239.1.2.3",
+            "--queue-capacity",
+            "4096",
+            "--receiver-core=2",
+            "--consumer-core",
+            "3",
+            "--duration",
+            "0",
+            "--arrivals",
+            "burst=8",
+            "--report",
+            "run.json",
+            "--plot",
+        ])
+        .unwrap();
+        assert_eq!(config.mode, Mode::Receiver);
+        assert!(config.mode.runs_receiver() && !config.mode.runs_sender());
+        assert_eq!(config.bind, "127.0.0.1:7001".parse().unwrap());
+        assert_eq!(config.multicast, Some(Ipv4Addr::new(239, 1, 2, 3)));
+        assert_eq!(config.queue_capacity, 4096);
+        assert_eq!(
+            (config.receiver_core, config.consumer_core),
+            (Some(2), Some(3))
+        );
+        assert!(config.has_explicit_cores());
+        assert_eq!(config.run_time, None);
+        assert_eq!(config.arrivals, Arrival::Burst { size: 8 });
+        assert_eq!(config.report, Some(PathBuf::from("run.json")));
+        assert!(config.plot);
+    }
+
+    #[test]
+    fn test_rejects_bad_arguments() {
+        for (args, message) in [
+            (&["--mode", "relay"][..], "unknown mode"),
+            (&["--rate"], "requires a value"),
+            (&["--rate", "0"], "must be positive"),
+            (&["--count", "-5"], "invalid value"),
+            (&["--multicast", "10.0.0.1"], "not a multicast group"),
+            (&["--arrivals", "burst=0"], "unknown arrivals"),
+            (&["--duration", "-1"], "invalid duration"),
+            (&["--queue-capacity", "1"], "at least 2"),
+            (&["--verbose", "1"], "unexpected argument"),
+            (&["stray"], "unexpected argument"),
+        ] {
+            let error = parse(args).unwrap_err().to_string();
+            assert!(error.contains(message), "{:?}: {}", args, error);
+        }
+        assert_eq!(parse(&["--help"]).unwrap_err(), ConfigError::Help);
+        assert_eq!(
+            parse(&["--rate", "1", "-h"]).unwrap_err(),
+            ConfigError::Help
+        );
+    }
+}
@@ -0,0 +1,32 @@
+//! UDP market data system built on `hft-primitives`.
+//!
+//! # Modules
+//! - [`batch`]: batched datagram I/O with `recvmmsg`/`sendmmsg`
+//! - [`config`]: command-line configuration of the `hft-system` binary
+//! - [`codec`]: explicit little-endian wire format for [`MarketMessage`]
+//! - [`pipeline`]: receiver → queue → consumer counters and the consumer
+//!   stage
+//! - [`protocol`]: versioned multi-message protocol (add, modify, cancel,
+//!   execution, quote, heartbeat)
+//! - [`sequencer`]: per-source gap detection, duplicate suppression and
+//!   reordering
+//! - [`loadgen`]: paced synthetic order flow for load testing
+//! - [`multicast`]: multicast group publishing and subscription
+//! - [`receiver`]: UDP receiver that decodes and sequences datagrams
+//! - [`recovery`]: TCP retransmission of missed sequence ranges
+//! - [`timestamping`]: kernel and hardware receive timestamps, latency
+//!   histograms
+
+pub mod batch;
+pub mod codec;
+pub mod config;
+pub mod loadgen;
+pub mod multicast;
+pub mod pipeline;
+pub mod protocol;
+pub mod receiver;
+pub mod recovery;
+pub mod sequencer;
+pub mod timestamping;
+
+pub use codec::{CodecError, MarketMessage, MarketMessageView};
@@ -0,0 +1,658 @@
+//! Scriptable load generator for the UDP feed.
+//!
+//! [`LoadGenerator`] produces a synthetic order flow — adds, modifies,
+//! cancels, executions and quotes over a universe of symbols whose prices
+//! follow a random walk — and sends it at a target rate. Arrivals can be
+//! evenly spaced, Poisson or in bursts; runs end after a message count or
+//! a duration.
+//!
+//! Each message has an intended send time on a fixed schedule. The
+//! generator spin-waits against the monotonic clock until that time (like
+//! [`FixedRateDriver`](hft_primitives::load_driver::FixedRateDriver)), so a
+//! slow send delays the next one without stretching the schedule, and the
+//! [`LoadReport`] compares the achieved rate with the requested one.
+
+use crate::batch::SendBatch;
+use crate::codec::PRICE_SCALE;
+use crate::protocol::{self, AddOrder, CancelOrder, Execution, Message, ModifyOrder, Quote, Side};
+use crate::timestamping::now_nanos;
+use hft_primitives::runtime::StopFlag;
+use std::fmt;
+use std::io;
+use std::net::{SocketAddr, UdpSocket};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Price increment of the random walk, in units of 1/[`PRICE_SCALE`].
+pub const TICK: u64 = 100;
+
+/// Resting orders tracked for modifies, cancels and executions; beyond
+/// this the oldest is cancelled instead of adding another.
+const MAX_LIVE_ORDERS: usize = 1024;
+
+/// How intended send times are spaced.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Arrival {
+    /// One message every `1 / rate` seconds.
+    Uniform,
+    /// Exponentially distributed gaps averaging `1 / rate` seconds.
+    Poisson,
+    /// `size` messages back to back (one `sendmmsg`), then a pause that
+    /// keeps the average at `rate`.
+    Burst { size: usize },
+}
+
+impl fmt::Display for Arrival {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Arrival::Uniform => f.write_str("uniform"),
+            Arrival::Poisson => f.write_str("poisson"),
+            Arrival::Burst { size } => write!(f, "bursts of {}", size),
+        }
+    }
+}
+
+/// When a run ends.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RunLength {
+    Count(u64),
+    Duration(Duration),
+}
+
+impl fmt::Display for RunLength {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RunLength::Count(count) => write!(f, "{} messages", count),
+            RunLength::Duration(duration) => write!(f, "for {:?}", duration),
+        }
+    }
+}
+
+/// Outcome of [`LoadGenerator::run`].
+#[derive(Debug, Clone)]
+pub struct LoadReport {
+    pub requested_rate: f64,
+    /// Messages handed to the socket.
+    pub sent: u64,
+    /// Messages whose send failed.
+    pub errors: u64,
+    /// Send system calls made.
+    pub syscalls: u64,
+    /// Sends that started after their intended time.
+    pub late: u64,
+    /// Largest delay behind the schedule.
+    pub max_lag: Duration,
+    pub elapsed: Duration,
+}
+
+impl LoadReport {
+    /// Returns the achieved rate in messages per second, or 0 if the run
+    /// took no measurable time.
+    pub fn achieved_rate(&self) -> f64 {
+        let seconds = self.elapsed.as_secs_f64();
+        if seconds > 0.0 {
+            self.sent as f64 / seconds
+        } else {
+            0.0
+        }
+    }
+}
+
+impl fmt::Display for LoadReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Requested rate: {:.0} msg/s", self.requested_rate)?;
+        writeln!(
+            f,
+            "Achieved rate:  {:.0} msg/s ({:.1}%)",
+            self.achieved_rate(),
+            self.achieved_rate() / self.requested_rate * 100.0
+        )?;
+        writeln!(
+            f,
+            "Sent:           {} in {:.3}s ({} syscalls, {} errors)",
+            self.sent,
+            self.elapsed.as_secs_f64(),
+            self.syscalls,
+            self.errors
+        )?;
+        writeln!(
+            f,
+            "Late sends:     {} (max lag {:?})",
+            self.late, self.max_lag
+        )
+    }
+}
+
+/// xorshift64* generator; reproducible for a given seed. Also drives the
+/// codec's property tests.
+#[derive(Debug, Clone)]
+pub(crate) struct Rng(u64);
+
+impl Rng {
+    pub(crate) fn new(seed: u64) -> Self {
+        Self(seed.max(1))
+    }
+
+    pub(crate) fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    /// Uniform in `[0, 1)`.
+    fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    pub(crate) fn below(&mut self, n: usize) -> usize {
+        (self.next_u64() % n as u64) as usize
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Instrument {
+    symbol: [u8; 8],
+    name: String,
+    /// Mid price in units of 1/[`PRICE_SCALE`].
+    mid: u64,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct LiveOrder {
+    order_id: u64,
+    instrument: usize,
+    price: u64,
+    quantity: u32,
+}
+
+/// Synthetic order flow at a target rate.
+///
+/// # Examples
+/// ```
+/// use hft_primitives::runtime::StopFlag;
+/// use hft_system::loadgen::{Arrival, LoadGenerator};
+/// use std::net::UdpSocket;
+///
+/// let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+/// let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+///
+/// let mut generator = LoadGenerator::new(50_000.0)
+///     .arrivals(Arrival::Poisson)
+///     .symbol("AAPL", 150.25)
+///     .symbol("MSFT", 410.50)
+///     .count(100)
+///     .seed(7);
+/// let report = generator
+///     .run(&socket, receiver.local_addr().unwrap(), &StopFlag::new(), |_, _| {})
+///     .unwrap();
+///
+/// assert_eq!(report.sent, 100);
+/// println!("{}", report);
+/// ```
+#[derive(Debug, Clone)]
+pub struct LoadGenerator {
+    rate: f64,
+    arrival: Arrival,
+    length: RunLength,
+    spin_window: Option<Duration>,
+    instruments: Vec<Instrument>,
+    rng: Rng,
+    next_sequence: u64,
+    next_order_id: u64,
+    next_match_id: u64,
+    live: Vec<LiveOrder>,
+}
+
+impl LoadGenerator {
+    /// Creates a generator sending `rate` messages per second, uniformly
+    /// spaced, for 10,000 messages.
+    ///
+    /// # Panics
+    /// Panics unless `rate` is positive and finite.
+    pub fn new(rate: f64) -> Self {
+        assert!(
+            rate.is_finite() && rate > 0.0,
+            "rate must be positive, got {}",
+            rate
+        );
+        Self {
+            rate,
+            arrival: Arrival::Uniform,
+            length: RunLength::Count(10_000),
+            spin_window: None,
+            instruments: Vec::new(),
+            rng: Rng::new(0x9e37_79b9_7f4a_7c15),
+            next_sequence: 0,
+            next_order_id: 1,
+            next_match_id: 1,
+            live: Vec::new(),
+        }
+    }
+
+    /// Sets how intended send times are spaced.
+    ///
+    /// # Panics
+    /// Panics on a burst size of zero.
+    pub fn arrivals(mut self, arrival: Arrival) -> Self {
+        if let Arrival::Burst { size } = arrival {
+            assert!(size > 0, "bursts must hold at least one message");
+        }
+        self.arrival = arrival;
+        self
+    }
+
+    /// Adds `symbol` to the universe, starting at `price`. Without any,
+    /// a default universe of five large caps is used.
+    pub fn symbol(mut self, symbol: &str, price: f64) -> Self {
+        self.add_symbol(symbol, price);
+        self
+    }
+
+    fn add_symbol(&mut self, symbol: &str, price: f64) {
+        self.instruments.push(Instrument {
+            symbol: AddOrder::new(0, symbol, Side::Buy, 0, 0).symbol,
+            name: symbol.to_string(),
+            mid: ((price * PRICE_SCALE) as u64).max(TICK),
+        });
+    }
+
+    /// Ends the run after `count` messages.
+    pub fn count(mut self, count: u64) -> Self {
+        self.length = RunLength::Count(count);
+        self
+    }
+
+    /// Ends the run after `duration`.
+    pub fn run_for(mut self, duration: Duration) -> Self {
+        self.length = RunLength::Duration(duration);
+        self
+    }
+
+    /// Sleeps through waits longer than `window` and spins only for their
+    /// last `window`, leaving the core to other threads in between. By
+    /// default every wait is spun, which is the most accurate but needs a
+    /// core of its own.
+    pub fn spin_window(mut self, window: Duration) -> Self {
+        self.spin_window = Some(window);
+        self
+    }
+
+    /// Seeds the random choices, making runs reproducible.
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.rng = Rng::new(seed);
+        self
+    }
+
+    /// Sets the sequence number of the next message.
+    pub fn start_sequence(mut self, sequence: u64) -> Self {
+        self.next_sequence = sequence;
+        self
+    }
+
+    /// Returns the requested rate in messages per second.
+    pub fn rate(&self) -> f64 {
+        self.rate
+    }
+
+    /// Returns how the run ends.
+    pub fn length(&self) -> RunLength {
+        self.length
+    }
+
+    /// Returns the symbols in the universe.
+    pub fn symbols(&self) -> impl Iterator<Item = &str> {
+        self.instruments.iter().map(|i| i.name.as_str())
+    }
+
+    fn ensure_universe(&mut self) {
+        if self.instruments.is_empty() {
+            for (symbol, price) in [
+                ("AAPL", 150.25),
+                ("MSFT", 410.50),
+                ("GOOG", 140.10),
+                ("AMZN", 178.30),
+                ("NVDA", 880.00),
+            ] {
+                self.add_symbol(symbol, price);
+            }
+        }
+    }
+
+    /// Returns the gap before the next intended send time.
+    fn next_gap(&mut self, index: u64) -> Duration {
+        let mean = 1.0 / self.rate;
+        let secs = match self.arrival {
+            Arrival::Uniform => mean,
+            // Inverse transform sampling; 1 - u avoids ln(0)
+            Arrival::Poisson => -(1.0 - self.rng.next_f64()).ln() * mean,
+            Arrival::Burst { size } => {
+                if (index + 1).is_multiple_of(size as u64) {
+                    mean * size as f64
+                } else {
+                    0.0
+                }
+            }
+        };
+        Duration::from_secs_f64(secs)
+    }
+
+    /// Moves the chosen instrument's price one step and returns the next
+    /// message of the order flow.
+    pub fn next_message(&mut self) -> Message {
+        self.ensure_universe();
+        let instrument = self.rng.below(self.instruments.len());
+        let step = self.rng.below(3);
+        let mid = &mut self.instruments[instrument].mid;
+        *mid = match step {
+            0 => mid.saturating_sub(TICK).max(TICK),
+            1 => *mid,
+            _ => *mid + TICK,
+        };
+        let mid = *mid;
+        let symbol = self.instruments[instrument].symbol;
+
+        let roll = self.rng.below(100);
+        if self.live.is_empty() || (roll < 40 && self.live.len() < MAX_LIVE_ORDERS) {
+            return self.add_order(instrument, mid);
+        }
+        if roll < 40 {
+            // Book is full: retire the oldest order
+            let order = self.live.remove(0);
+            return Message::CancelOrder(CancelOrder {
+                order_id: order.order_id,
+            });
+        }
+        if roll < 60 {
+            return Message::Quote(Quote {
+                symbol,
+                bid_price: mid - TICK.min(mid),
+                bid_quantity: 100 * (1 + self.rng.below(10) as u32),
+                ask_price: mid + TICK,
+                ask_quantity: 100 * (1 + self.rng.below(10) as u32),
+            });
+        }
+
+        let index = self.rng.below(self.live.len());
+        if roll < 75 {
+            let order = &mut self.live[index];
+            order.price = self.instruments[order.instrument].mid;
+            order.quantity = 100 * (1 + self.rng.below(10) as u32);
+            Message::ModifyOrder(ModifyOrder {
+                order_id: order.order_id,
+                price: order.price,
+                quantity: order.quantity,
+            })
+        } else if roll < 90 {
+            let order = self.live.swap_remove(index);
+            Message::CancelOrder(CancelOrder {
+                order_id: order.order_id,
+            })
+        } else {
+            let order = self.live[index];
+            let quantity = (order.quantity / 2).max(1);
+            if quantity == order.quantity {
+                self.live.swap_remove(index);
+            } else {
+                self.live[index].quantity -= quantity;
+            }
+            self.next_match_id += 1;
+            Message::Execution(Execution {
+                order_id: order.order_id,
+                match_id: self.next_match_id - 1,
+                symbol: self.instruments[order.instrument].symbol,
+                price: order.price,
+                quantity,
+            })
+        }
+    }
+
+    fn add_order(&mut self, instrument: usize, mid: u64) -> Message {
+        let side = if self.rng.below(2) == 0 {
+            Side::Buy
+        } else {
+            Side::Sell
+        };
+        let offset = TICK * self.rng.below(5) as u64;
+        let price = match side {
+            Side::Buy => mid.saturating_sub(offset).max(TICK),
+            Side::Sell => mid + offset,
+        };
+        let order = LiveOrder {
+            order_id: self.next_order_id,
+            instrument,
+            price,
+            quantity: 100 * (1 + self.rng.below(10) as u32),
+        };
+        self.next_order_id += 1;
+        self.live.push(order);
+        Message::AddOrder(AddOrder {
+            order_id: order.order_id,
+            symbol: self.instruments[instrument].symbol,
+            side,
+            price,
+            quantity: order.quantity,
+        })
+    }
+
+    /// Sends the order flow to `target` until the run length is reached
+    /// or `stop` is set. `on_sent` sees every encoded message with its
+    /// sequence number before it is sent, e.g. to record it for recovery.
+    pub fn run<F>(
+        &mut self,
+        socket: &UdpSocket,
+        target: SocketAddr,
+        stop: &StopFlag,
+        mut on_sent: F,
+    ) -> io::Result<LoadReport>
+    where
+        F: FnMut(u64, &[u8]),
+    {
+        let burst = match self.arrival {
+            Arrival::Burst { size } => size,
+            _ => 1,
+        };
+        let mut batch = SendBatch::new(burst, protocol::MAX_MESSAGE_SIZE);
+        let mut buf = [0u8; protocol::MAX_MESSAGE_SIZE];
+        let mut report = LoadReport {
+            requested_rate: self.rate,
+            sent: 0,
+            errors: 0,
+            syscalls: 0,
+            late: 0,
+            max_lag: Duration::ZERO,
+            elapsed: Duration::ZERO,
+        };
+
+        let start = Instant::now();
+        let mut offset = Duration::ZERO;
+        let mut index = 0u64;
+        while !stop.is_stopped() {
+            match self.length {
+                RunLength::Count(count) if index >= count => break,
+                RunLength::Duration(duration) if offset >= duration => break,
+                _ => {}
+            }
+
+            // Wait for the first message of each batch; the rest follow it.
+            // The first deadline is `start` itself, which has always passed
+            if batch.is_empty() {
+                let lag = self.wait_until(start + offset);
+                if index > 0 && lag > Duration::ZERO {
+                    report.late += 1;
+                    report.max_lag = report.max_lag.max(lag);
+                }
+            }
+
+            let sequence = self.next_sequence;
+            self.next_sequence += 1;
+            let message = self.next_message();
+            let len = protocol::encode(&message, sequence, now_nanos(), &mut buf)
+                .expect("buffer holds the largest message");
+            on_sent(sequence, &buf[..len]);
+            batch.push(target, &buf[..len]);
+
+            offset += self.next_gap(index);
+            index += 1;
+            if batch.is_full() {
+                let queued = batch.len() as u64;
+                match batch.flush(socket) {
+                    Ok(sent) => report.sent += sent as u64,
+                    Err(_) => report.errors += queued,
+                }
+            }
+        }
+        if !batch.is_empty() {
+            let queued = batch.len() as u64;
+            match batch.flush(socket) {
+                Ok(sent) => report.sent += sent as u64,
+                Err(_) => report.errors += queued,
+            }
+        }
+
+        report.syscalls = batch.syscalls();
+        report.elapsed = start.elapsed();
+        Ok(report)
+    }
+
+    /// Waits until `deadline`, returning how late it already was.
+    fn wait_until(&self, deadline: Instant) -> Duration {
+        let now = Instant::now();
+        if now >= deadline {
+            return now - deadline;
+        }
+        if let Some(window) = self.spin_window {
+            let remaining = deadline - now;
+            if remaining > window {
+                thread::sleep(remaining - window);
+            }
+        }
+        while Instant::now() < deadline {
+            std::hint::spin_loop();
+        }
+        Duration::ZERO
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::decode;
+
+    #[test]
+    fn test_poisson_gaps_average_to_rate() {
+        let mut generator = LoadGenerator::new(10_000.0)
+            .arrivals(Arrival::Poisson)
+            .seed(42);
+        let total: Duration = (0..20_000).map(|i| generator.next_gap(i)).sum();
+        let mean = total.as_secs_f64() / 20_000.0;
+        assert!((mean - 1e-4).abs() < 5e-6, "mean gap {}", mean);
+
+        let mut burst = LoadGenerator::new(1_000.0).arrivals(Arrival::Burst { size: 4 });
+        let gaps: Vec<Duration> = (0..8).map(|i| burst.next_gap(i)).collect();
+        assert_eq!(gaps[..3], [Duration::ZERO; 3]);
+        assert_eq!(gaps[3], Duration::from_millis(4));
+    }
+
+    #[test]
+    fn test_order_flow_is_consistent() {
+        let mut generator = LoadGenerator::new(1.0).symbol("AAPL", 0.01).seed(9);
+        let mut live = std::collections::HashSet::new();
+        let mut last_mid = TICK;
+        for _ in 0..20_000 {
+            match generator.next_message() {
+                Message::AddOrder(order) => {
+                    assert!(order.price >= TICK);
+                    assert!(live.insert(order.order_id));
+                }
+                Message::ModifyOrder(modify) => assert!(live.contains(&modify.order_id)),
+                Message::CancelOrder(cancel) => assert!(live.remove(&cancel.order_id)),
+                Message::Execution(execution) => assert!(live.contains(&execution.order_id)),
+                Message::Quote(quote) => assert!(quote.bid_price < quote.ask_price),
+                Message::Heartbeat => {}
+            }
+            // The random walk moves at most one tick per message
+            let mid = generator.instruments[0].mid;
+            assert!(mid.abs_diff(last_mid) <= TICK && mid >= TICK);
+            last_mid = mid;
+        }
+        assert!(live.len() <= MAX_LIVE_ORDERS);
+    }
+
+    #[test]
+    fn test_paced_run_over_loopback() {
+        let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let mut generator = LoadGenerator::new(20_000.0)
+            .arrivals(Arrival::Burst { size: 8 })
+            .run_for(Duration::from_millis(50))
+            .start_sequence(100);
+
+        let mut sequences = Vec::new();
+        let report = generator
+            .run(
+                &socket,
+                receiver.local_addr().unwrap(),
+                &StopFlag::new(),
+                |sequence, bytes| {
+                    assert_eq!(decode(bytes).unwrap().header.sequence, sequence);
+                    sequences.push(sequence);
+                },
+            )
+            .unwrap();
+
+        assert_eq!(report.sent, 1_000);
+        assert_eq!(report.syscalls, 125);
+        assert_eq!(sequences.first(), Some(&100));
+        assert!(report.elapsed >= Duration::from_micros(49_600));
+        let accuracy = report.achieved_rate() / report.requested_rate;
+        assert!(accuracy > 0.8 && accuracy <= 1.01, "{}", report);
+    }
+
+    #[test]
+    fn test_empty_run_reports_zero_rate() {
+        let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let report = LoadGenerator::new(1_000.0)
+            .count(0)
+            .run(
+                &socket,
+                receiver.local_addr().unwrap(),
+                &StopFlag::new(),
+                |_, _| {},
+            )
+            .unwrap();
+        assert_eq!((report.sent, report.late), (0, 0));
+        assert_eq!(report.achieved_rate(), 0.0);
+
+        let instant = LoadReport {
+            elapsed: Duration::ZERO,
+            sent: 5,
+            ..report
+        };
+        assert_eq!(instant.achieved_rate(), 0.0);
+        assert!(instant
+            .to_string()
+            .contains("Achieved rate:  0 msg/s (0.0%)"));
+    }
+
+    #[test]
+    fn test_first_send_is_not_late() {
+        let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        // The first deadline is `start` itself, so it has always passed by
+        // the time it is checked; later sends depend on scheduling
+        let report = LoadGenerator::new(1_000.0)
+            .arrivals(Arrival::Uniform)
+            .count(1)
+            .run(
+                &socket,
+                receiver.local_addr().unwrap(),
+                &StopFlag::new(),
+                |_, _| {},
+            )
+            .unwrap();
+        assert_eq!(report.sent, 1);
+        assert_eq!(report.late, 0, "{}", report);
+    }
+}
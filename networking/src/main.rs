@@ -1,233 +1,553 @@
-use std::cell::UnsafeCell;
-use std::mem;
+use hft_primitives::clock::AutoClock;
+use hft_primitives::cpu_pinning::{current_affinity, PlacementPlanner, Topology};
+use hft_primitives::numa::MemoryPlacement;
+use hft_primitives::plot::print_distribution;
+use hft_primitives::report::{RunMetadata, RunReport};
+use hft_primitives::runtime::{stop_on_signals, Runtime, StopFlag, Worker, WorkerContext};
+use hft_primitives::sysaudit::HostAudit;
+use hft_primitives::trace::{PipelineTracer, Stage};
+use hft_primitives::LockFreeRingBuffer;
+use hft_system::config::{ConfigError, Mode, SystemConfig, USAGE};
+use hft_system::loadgen::{LoadGenerator, LoadReport};
+use hft_system::multicast::MulticastGroup;
+use hft_system::pipeline::{drain, FeedConsumer, PipelineCounters, TracedFrame};
+use hft_system::receiver::UdpReceiver;
+use hft_system::recovery::{RecoveryClient, RecoveryHistory, RecoveryServer};
+use hft_system::sequencer::{FeedEvent, FeedStats};
+use hft_system::timestamping::{RxLatency, TimestampMode};
+use std::io;
 use std::net::{SocketAddr, UdpSocket};
-use std::sync::atomic::{AtomicUsize, Ordering};
-use std::sync::Arc;
+use std::process::ExitCode;
+use std::sync::{Arc, Mutex};
 use std::thread;
-use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use std::time::{Duration, Instant};
+
+// Reorder window per source, in messages
+const REORDER_WINDOW: usize = 1024;
+// Sent messages kept for retransmission
+const RECOVERY_HISTORY: usize = 16384;
+// Datagrams per recvmmsg call
+const RECV_BATCH: usize = 32;
+// The sender sleeps through waits longer than this, so it can share a
+// core with the receiver
+const SPIN_WINDOW: Duration = Duration::from_micros(100);
+// How long the receiver keeps draining its socket after a stop, so a
+// sender that is still transmitting cannot keep it alive
+const DRAIN_TIMEOUT: Duration = Duration::from_millis(200);
+
+// What each worker hands back for the final report
+enum StageReport {
+    Receiver {
+        stats: FeedStats,
+        recovered: u64,
+        latency: Box<RxLatency>,
+    },
+    Sender(Option<LoadReport>),
+    Consumer {
+        consumer: FeedConsumer,
+        tracer: Box<PipelineTracer>,
+    },
+    Recovery,
+}
 
-// CPU pinning for Linux
-#[cfg(target_os = "linux")]
-fn pin_thread_to_core(core_id: usize) {
-    use libc::{cpu_set_t, sched_setaffinity};
+// Marks the receiver finished when dropped, even by a panic, so the
+// consumer never waits for it forever
+struct ReceiverDone(Arc<PipelineCounters>);
 
-    let mut cpu_set: cpu_set_t = unsafe { mem::zeroed() };
-    unsafe {
-        libc::CPU_SET(core_id, &mut cpu_set);
-        sched_setaffinity(0, mem::size_of::<cpu_set_t>(), &cpu_set);
+impl Drop for ReceiverDone {
+    fn drop(&mut self) {
+        self.0.finish_receiving();
     }
 }
 
-// Fixed-size market message
-#[repr(C, packed)]
-#[derive(Debug, Clone, Copy)]
-struct MarketMessage {
-    message_type: u8,
-    symbol: [u8; 8],
-    price: u64,
-    quantity: u32,
-    timestamp: u64,
-}
-
-impl MarketMessage {
-    fn new(symbol: &str, price: f64, quantity: u32) -> Self {
-        let mut symbol_bytes = [0u8; 8];
-        let symbol_len = symbol.len().min(8);
-        symbol_bytes[..symbol_len].copy_from_slice(symbol.as_bytes());
-
-        let price_fixed = (price * 10000.0) as u64;
-
-        Self {
-            message_type: 1,
-            symbol: symbol_bytes,
-            price: price_fixed,
-            quantity,
-            timestamp: SystemTime::now()
-                .duration_since(UNIX_EPOCH)
-                .unwrap()
-                .as_secs() as u64,
+// Binds the receiver socket, or joins the multicast group on the bind port
+fn open_receiver(config: &SystemConfig) -> io::Result<UdpReceiver> {
+    let receiver = match config.multicast {
+        Some(group) => {
+            let feed = MulticastGroup::new(group, config.bind.port()).interface(config.interface);
+            UdpReceiver::from_socket(feed.subscribe()?, REORDER_WINDOW)?
         }
+        None => UdpReceiver::bind(config.bind, REORDER_WINDOW)?,
+    };
+    let mut receiver = receiver.with_batch_size(RECV_BATCH);
+    // Kernel receive timestamps; hardware ones where the NIC provides them
+    if let Err(e) = receiver.enable_timestamps(TimestampMode::Hardware) {
+        eprintln!("Receive timestamps unavailable ({})", e);
     }
+    match RecoveryClient::connect(config.recovery) {
+        Ok(client) => receiver = receiver.with_recovery(client),
+        Err(e) => eprintln!("Recovery unavailable ({}); gaps will not be filled", e),
+    }
+    Ok(receiver)
 }
 
-// Send + Sync traits for threading
-unsafe impl<T: Send> Send for LockFreeRingBuffer<T> {}
-unsafe impl<T: Sync> Sync for LockFreeRingBuffer<T> {}
-
-// Lock-free ring buffer
-pub struct LockFreeRingBuffer<T> {
-    buffer: Box<[UnsafeCell<Option<T>>]>,
-    head: AtomicUsize,
-    tail: AtomicUsize,
-    mask: usize,
-}
-
-impl<T> LockFreeRingBuffer<T> {
-    pub fn new(size: usize) -> Self {
-        let capacity = size.next_power_of_two();
-        let mask = capacity - 1;
-        let buffer: Vec<UnsafeCell<Option<T>>> =
-            (0..capacity).map(|_| UnsafeCell::new(None)).collect();
-
-        Self {
-            buffer: buffer.into_boxed_slice(),
-            head: AtomicUsize::new(0),
-            tail: AtomicUsize::new(0),
-            mask,
+// Opens the sender socket and returns it with the destination: the
+// multicast group on the target port, or the unicast target
+fn open_sender(config: &SystemConfig) -> io::Result<(UdpSocket, SocketAddr)> {
+    match config.multicast {
+        Some(group) => {
+            let feed = MulticastGroup::new(group, config.target.port()).interface(config.interface);
+            Ok((feed.publisher()?, feed.addr()))
         }
+        None => Ok((UdpSocket::bind("0.0.0.0:0")?, config.target)),
     }
+}
 
-    pub fn send(&self, item: T) -> Result<(), T> {
-        let current_head = self.head.load(Ordering::Relaxed);
-        let next_head = (current_head + 1) & self.mask;
-        let current_tail = self.tail.load(Ordering::Acquire);
-
-        if next_head == current_tail {
-            return Err(item);
+// UDP receiver thread
+fn udp_receiver_thread(
+    mut receiver: UdpReceiver,
+    queue: Arc<LockFreeRingBuffer<TracedFrame>>,
+    counters: Arc<PipelineCounters>,
+    clock: AutoClock,
+    ctx: &WorkerContext,
+) -> StageReport {
+    let _done = ReceiverDone(Arc::clone(&counters));
+
+    // Frames arrive here in sequence order, without duplicates, and each
+    // received batch is pushed to the lock-free queue in one go. Polls
+    // time out, so the stop flag is seen even when the feed is idle; after
+    // it is set, reading continues until a poll receives nothing or
+    // DRAIN_TIMEOUT passes, so datagrams already in the socket buffer are
+    // not lost
+    let mut batch = Vec::with_capacity(RECV_BATCH);
+    let mut drain_deadline = None;
+    loop {
+        let datagrams =
+            match receiver.poll(|frame| batch.push(TracedFrame::received(frame, &clock))) {
+                Ok(outcome) => outcome.datagrams,
+                Err(e) => {
+                    eprintln!("UDP receive error: {}", e);
+                    0
+                }
+            };
+        if !batch.is_empty() {
+            let dropped = counters.enqueue(&queue, &mut batch, &clock);
+            if dropped > 0 {
+                eprintln!("Queue full - dropped {} messages", dropped);
+            }
         }
-
-        let cell = &self.buffer[current_head];
-        unsafe {
-            *cell.get() = Some(item);
+        if ctx.should_stop() {
+            let deadline = *drain_deadline.get_or_insert_with(|| Instant::now() + DRAIN_TIMEOUT);
+            if datagrams == 0 || Instant::now() >= deadline {
+                break;
+            }
         }
-        self.head.store(next_head, Ordering::Release);
-        Ok(())
     }
 
-    pub fn receive(&self) -> Option<T> {
-        let current_tail = self.tail.load(Ordering::Relaxed);
-        let current_head = self.head.load(Ordering::Acquire);
-
-        if current_head == current_tail {
-            return None;
+    println!(
+        "Receive syscalls: {} ({:.1} messages per call)",
+        receiver.syscalls(),
+        counters.snapshot().received as f64 / receiver.syscalls().max(1) as f64
+    );
+    if receiver.malformed() > 0 {
+        eprintln!(
+            "Receiver dropped {} malformed datagrams",
+            receiver.malformed()
+        );
+    }
+    if receiver.recovery_errors() > 0 {
+        eprintln!("{} recovery requests failed", receiver.recovery_errors());
+    }
+    for event in receiver.sequencer().events() {
+        match event {
+            FeedEvent::GapOpened(gap) => println!("Gap opened: {}", gap),
+            FeedEvent::GapLost(gap) => println!("Gap lost: {}", gap),
+            FeedEvent::Reset { source, from, to } => {
+                println!("Feed reset: {} seq {} -> {}", source, from, to)
+            }
         }
-
-        let cell = &self.buffer[current_tail];
-        let item = unsafe { (*cell.get()).take() };
-        let next_tail = (current_tail + 1) & self.mask;
-        self.tail.store(next_tail, Ordering::Release);
-        item
     }
-}
-
-// Convert MarketMessage to bytes safely
-fn message_to_bytes(message: &MarketMessage) -> [u8; 29] {
-    unsafe {
-        mem::transmute_copy::<[u8; 29], _>(&*(message as *const MarketMessage as *const [u8; 29]))
+    StageReport::Receiver {
+        stats: receiver.sequencer().stats(),
+        recovered: receiver.recovered(),
+        latency: Box::new(receiver.latency().clone()),
     }
 }
 
-// UDP receiver thread
-fn udp_receiver_thread(
-    queue: Arc<LockFreeRingBuffer<MarketMessage>>,
-    port: u16,
-    message_count: Arc<AtomicUsize>,
-) {
-    // Pin network thread to CPU core 0
-    #[cfg(target_os = "linux")]
-    pin_thread_to_core(0);
-
-    let socket = UdpSocket::bind(format!("0.0.0.0:{}", port)).unwrap();
-    let mut recv_buf = [0u8; 2048];
-
-    println!("UDP receiver listening on port {}", port);
+// Consumer thread: processes queued messages until the receiver has
+// finished and the queue is empty, tracing each one's way through
+fn consumer_thread(
+    queue: Arc<LockFreeRingBuffer<TracedFrame>>,
+    counters: Arc<PipelineCounters>,
+    clock: AutoClock,
+) -> StageReport {
+    let mut consumer = FeedConsumer::new();
+    let mut tracer = Box::new(PipelineTracer::new());
+    drain(&queue, &counters, &mut consumer, &clock, &mut tracer);
+    StageReport::Consumer { consumer, tracer }
+}
 
-    loop {
-        match socket.recv_from(&mut recv_buf) {
-            Ok((len, _addr)) => {
-                if len == mem::size_of::<MarketMessage>() {
-                    // Parse bytes to MarketMessage safely
-                    let recv_slice = &recv_buf[..mem::size_of::<MarketMessage>()];
-                    let message = unsafe {
-                        mem::transmute_copy::<[u8; 29], _>(
-                            &*(recv_slice.as_ptr() as *const [u8; 29]),
-                        )
-                    };
-
-                    // Push to lock-free queue
-                    if let Err(_) = queue.send(message) {
-                        eprintln!("Queue full - dropping message");
-                    } else {
-                        // Count Messages
-                        message_count.fetch_add(1, Ordering::Relaxed);
-                    }
-                }
-            }
-            Err(e) => eprintln!("UDP receive error: {}", e),
+// UDP sender thread for load testing: a paced synthetic order flow
+fn udp_sender_thread(
+    socket: UdpSocket,
+    target: SocketAddr,
+    mut generator: LoadGenerator,
+    history: Arc<Mutex<RecoveryHistory>>,
+    stop: &StopFlag,
+) -> StageReport {
+    println!(
+        "Sending {} to {} at {:.0} msg/s",
+        generator.length(),
+        target,
+        generator.rate()
+    );
+    // Keep a copy of every message for the recovery server
+    let result = generator.run(&socket, target, stop, |sequence, bytes| {
+        history.lock().unwrap().record(sequence, bytes);
+    });
+    match result {
+        Ok(report) => {
+            println!("UDP sender finished ({} messages)", report.sent);
+            StageReport::Sender(Some(report))
+        }
+        Err(e) => {
+            eprintln!("Load generator failed: {}", e);
+            StageReport::Sender(None)
         }
     }
 }
 
-// UDP sender thread for load testing
-fn udp_sender_thread(messages_to_send: usize) {
-    let socket = UdpSocket::bind("0.0.0.0:0").unwrap();
-    let target_addr: SocketAddr = "127.0.0.1:9001".parse().unwrap();
-
-    // Test message
-    let test_message = MarketMessage::new("AAPL", 150.25, 100);
-    let message_bytes = message_to_bytes(&test_message);
-
-    println!("Sending {} messages to port 9001", messages_to_send);
+// Assign a core to each role this process runs: the ones given on the
+// command line, or else separate physical cores, isolated ones first,
+// CPU 0 last
+fn assign_cores(config: &SystemConfig) -> Vec<(&'static str, usize)> {
+    let roles: &[&'static str] = match config.mode {
+        Mode::Both => &["receiver", "sender", "consumer"],
+        Mode::Receiver => &["receiver", "consumer"],
+        Mode::Sender => &["sender"],
+    };
+    if config.has_explicit_cores() {
+        return roles
+            .iter()
+            .filter_map(|&role| {
+                let cpu = match role {
+                    "receiver" => config.receiver_core,
+                    "sender" => config.sender_core,
+                    _ => config.consumer_core,
+                };
+                cpu.map(|cpu| (role, cpu))
+            })
+            .collect();
+    }
 
-    for i in 0..messages_to_send {
-        match socket.send_to(&message_bytes, target_addr) {
-            Ok(_) => {
-                if i % 1000 == 0 {
-                    println!("Sent {} messages", i + 1);
-                }
-            }
-            Err(e) => eprintln!("Send error: {}", e),
+    let topology = match Topology::detect() {
+        Ok(topology) => topology,
+        Err(e) => {
+            eprintln!("CPU topology unavailable ({}); running unpinned", e);
+            return Vec::new();
+        }
+    };
+    let mut planner = PlacementPlanner::new(&topology)
+        .no_smt_sharing()
+        .prefer_isolated();
+    for role in roles {
+        planner = planner.role(*role);
+    }
+    if let Ok(allowed) = current_affinity() {
+        planner = planner.within(allowed);
+    }
+    match planner.plan() {
+        Ok(placement) => roles
+            .iter()
+            .filter_map(|&role| placement.cpu(role).map(|cpu| (role, cpu)))
+            .collect(),
+        Err(e) => {
+            eprintln!("Thread placement failed ({}); running unpinned", e);
+            Vec::new()
         }
-
-        // Small delay between sends
-        thread::sleep(Duration::from_micros(50));
     }
-
-    println!("UDP sender finished");
 }
 
-fn main() {
+fn main() -> ExitCode {
+    let config = match SystemConfig::from_args() {
+        Ok(config) => config,
+        Err(ConfigError::Help) => {
+            println!("{}", USAGE);
+            return ExitCode::SUCCESS;
+        }
+        Err(message) => {
+            eprintln!("{}", message);
+            return ExitCode::from(2);
+        }
+    };
+
     println!("HFT System - First Real System");
     println!("================================");
 
-    // Create shared structures
-    let queue = Arc::new(LockFreeRingBuffer::<MarketMessage>::new(16384));
-    let message_count = Arc::new(AtomicUsize::new(0));
-
-    // Start UDP receiver thread (pinned to core 0)
-    let queue_clone = Arc::clone(&queue);
-    let count_clone = Arc::clone(&message_count);
-    let receiver_handle = thread::spawn(move || {
-        udp_receiver_thread(queue_clone, 9001, count_clone);
-    });
-
-    // Start UDP sender thread for load testing (pinned to core 1)
-    let sender_handle = thread::spawn(move || {
-        udp_sender_thread(10000); // Send 10k messages
-    });
+    let audit = HostAudit::collect();
+    audit.print_warnings();
+    let cores = assign_cores(&config);
+    let core_of = |role: &str| {
+        cores
+            .iter()
+            .find(|(name, _)| *name == role)
+            .map(|&(_, cpu)| cpu)
+    };
+
+    let counters = Arc::new(PipelineCounters::new());
+    let mut queue_info = None;
+
+    // Sockets are opened before any thread starts, so a port in use ends
+    // the run with a message rather than a panicking worker. The recovery
+    // server comes first so an in-process receiver can connect to it
+    let mut runtime = Runtime::new();
+    if config.mode.runs_sender() {
+        let history = RecoveryHistory::shared(RECOVERY_HISTORY);
+        let setup = RecoveryServer::bind(config.recovery, Arc::clone(&history))
+            .and_then(|server| Ok((server, open_sender(&config)?)));
+        let (recovery_server, (socket, target)) = match setup {
+            Ok(setup) => setup,
+            Err(e) => {
+                eprintln!("Sender setup failed: {}", e);
+                return ExitCode::from(2);
+            }
+        };
+        let generator = LoadGenerator::new(config.rate)
+            .arrivals(config.arrivals)
+            .count(config.count)
+            .spin_window(SPIN_WINDOW);
+        let mut sender = Worker::new("sender", move |ctx| {
+            udp_sender_thread(socket, target, generator, history, ctx.stop_flag())
+        });
+        if let Some(cpu) = core_of("sender") {
+            sender = sender.pin_to(cpu);
+        }
+        runtime.add(sender);
+        runtime.add(Worker::new("recovery", move |ctx| {
+            if let Err(e) = recovery_server.run(ctx.stop_flag()) {
+                eprintln!("Recovery server failed: {}", e);
+            }
+            StageReport::Recovery
+        }));
+    }
+    if config.mode.runs_receiver() {
+        // The queue's pages are first touched from the receiver's core so
+        // they live on its NUMA node
+        let placement = core_of("receiver").map_or(MemoryPlacement::Local, |cpu| {
+            MemoryPlacement::FirstTouch { cpu }
+        });
+        let capacity = config.queue_capacity;
+        let queue = Arc::new(
+            LockFreeRingBuffer::<TracedFrame>::with_placement(capacity, &placement).unwrap_or_else(
+                |e| {
+                    eprintln!("Queue placement failed ({}); using local memory", e);
+                    LockFreeRingBuffer::new(capacity)
+                },
+            ),
+        );
+        queue_info = Some((queue.capacity(), queue.placement().to_string()));
+        let udp_receiver = match open_receiver(&config) {
+            Ok(receiver) => receiver,
+            Err(e) => {
+                eprintln!("Receiver setup failed: {}", e);
+                return ExitCode::from(2);
+            }
+        };
+        // Both stages stamp frames with the same calibrated clock
+        let clock = AutoClock::new();
+        let queue_clone = Arc::clone(&queue);
+        let counters_clone = Arc::clone(&counters);
+        let mut receiver = Worker::new("receiver", move |ctx| {
+            udp_receiver_thread(udp_receiver, queue_clone, counters_clone, clock, ctx)
+        });
+        let queue_clone = Arc::clone(&queue);
+        let counters_clone = Arc::clone(&counters);
+        let mut consumer = Worker::new("consumer", move |_ctx| {
+            consumer_thread(queue_clone, counters_clone, clock)
+        });
+        if let Some(cpu) = core_of("receiver") {
+            receiver = receiver.pin_to(cpu);
+        }
+        if let Some(cpu) = core_of("consumer") {
+            consumer = consumer.pin_to(cpu);
+        }
+        runtime.add(receiver).add(consumer);
+    }
 
-    println!("System started:");
-    println!("  - UDP receiver on port 9001 (CPU core 0)");
-    println!("  - UDP sender to port 9001 (CPU core 1)");
-    println!("  - Lock-free queue (1024 capacity)");
+    // Ctrl+C and SIGTERM stop the run early, through the same shutdown
+    let stop = runtime.stop_flag();
+    if let Err(e) = stop_on_signals(&stop) {
+        eprintln!("Signal handling unavailable ({})", e);
+    }
+    let running = runtime.start().expect("failed to spawn network threads");
+
+    println!("System started ({} mode):", config.mode);
+    if config.mode.runs_receiver() {
+        match config.multicast {
+            Some(group) => println!(
+                "  - UDP receiver subscribed to {}:{}",
+                group,
+                config.bind.port()
+            ),
+            None => println!("  - UDP receiver on {}", config.bind),
+        }
+    }
+    if let Some((capacity, placement)) = &queue_info {
+        println!("  - Lock-free queue ({} capacity, {})", capacity, placement);
+    }
+    if config.mode.runs_sender() {
+        match config.multicast {
+            Some(group) => println!(
+                "  - UDP sender publishing to {}:{} (arrivals: {})",
+                group,
+                config.target.port(),
+                config.arrivals
+            ),
+            None => println!(
+                "  - UDP sender to {} (arrivals: {})",
+                config.target, config.arrivals
+            ),
+        }
+        println!("  - TCP recovery server on {}", config.recovery);
+    } else {
+        println!("  - TCP recovery from {}", config.recovery);
+    }
+    if cores.is_empty() {
+        println!("  - Threads unpinned");
+    }
+    for (role, cpu) in &cores {
+        println!("  - {} on CPU {}", role, cpu);
+    }
+    match config.run_time {
+        Some(run_time) => println!(
+            "Running for {:.1}s... Press Ctrl+C to stop",
+            run_time.as_secs_f64()
+        ),
+        None => println!("Running until Ctrl+C"),
+    }
     println!();
 
-    // Run for 10 seconds
-    thread::sleep(Duration::from_secs(10));
+    let started = Instant::now();
+    while !stop.is_stopped()
+        && config
+            .run_time
+            .is_none_or(|limit| started.elapsed() < limit)
+    {
+        thread::sleep(Duration::from_millis(10));
+    }
+    if stop.is_stopped() {
+        println!("Signal received; shutting down");
+    }
 
-    let final_count = message_count.load(Ordering::Relaxed);
-    println!("=== Performance Metrics ===");
-    println!("Messages received: {}", final_count);
-    println!("Messages/sec: {:.2}", final_count as f64 / 10.0);
-    println!(
-        "Queue efficiency: {:.2}%",
-        (final_count as f64 / 10000.0) * 100.0
-    );
+    // The receiver drains its socket and finishes, then the consumer
+    // drains the queue
+    let mut receiver_report = None;
+    let mut sender_report = None;
+    let mut consumer_report = None;
+    // Only counts as pinned if every requested pin took effect
+    let mut pinned = !cores.is_empty();
+    for report in running.stop_and_join() {
+        pinned &= report.pin_error.is_none();
+        if let Some(e) = &report.pin_error {
+            eprintln!("{} ran unpinned: {}", report.name, e);
+        }
+        match report.result {
+            Ok(StageReport::Receiver {
+                stats,
+                recovered,
+                latency,
+            }) => receiver_report = Some((stats, recovered, latency)),
+            Ok(StageReport::Sender(load)) => sender_report = load,
+            Ok(StageReport::Consumer { consumer, tracer }) => {
+                consumer_report = Some((consumer, tracer))
+            }
+            Ok(StageReport::Recovery) => {}
+            Err(e) => eprintln!("{}: {}", report.name, e),
+        }
+    }
+    let elapsed = started.elapsed();
+    let pipeline = counters.snapshot();
 
-    // Keep system running
-    println!("System running... Press Ctrl+C to stop");
+    if let Some(load) = &sender_report {
+        println!("=== Load Generator ===");
+        print!("{}", load);
+    }
+    if config.mode.runs_receiver() {
+        println!("=== Pipeline ===");
+        print!("{}", pipeline);
+        println!(
+            "Messages/sec: {:.2}",
+            pipeline.processed as f64 / elapsed.as_secs_f64()
+        );
+        if let Some(load) = &sender_report {
+            println!(
+                "Delivery:  {:.2}% of {} sent",
+                pipeline.processed as f64 / load.sent.max(1) as f64 * 100.0,
+                load.sent
+            );
+        }
+    }
+    if let Some((consumer, tracer)) = &consumer_report {
+        print!("{}", consumer);
+        tracer.breakdown().print_report("Pipeline Breakdown");
+    }
+    if let Some((stats, recovered, latency)) = &receiver_report {
+        println!("=== Feed Health ===");
+        print!("{}", stats);
+        println!("Recovered:     {}", recovered);
+        println!("=== Receive Latency ===");
+        print!("{}", latency);
+        if config.plot {
+            for (name, histogram) in [
+                ("wire_to_user", latency.wire_to_user()),
+                ("hardware_to_user", latency.hardware_to_user()),
+                ("end_to_end", latency.end_to_end()),
+            ] {
+                if !histogram.is_empty() {
+                    print_distribution(name, histogram);
+                }
+            }
+        }
+    }
 
-    // Join threads
-    receiver_handle.join().unwrap();
-    sender_handle.join().unwrap();
+    if let Some(path) = &config.report {
+        let mut report = RunReport::new(RunMetadata::collect(
+            "hft-system",
+            pinned,
+            config.count as usize,
+        ));
+        if config.mode.runs_receiver() {
+            report.add_value("messages_received", pipeline.received as f64, "messages");
+            report.add_value("messages_enqueued", pipeline.enqueued as f64, "messages");
+            report.add_value("messages_dropped", pipeline.dropped as f64, "messages");
+            report.add_value("messages_processed", pipeline.processed as f64, "messages");
+            report.add_value(
+                "throughput",
+                pipeline.processed as f64 / elapsed.as_secs_f64(),
+                "messages/sec",
+            );
+        }
+        if let Some(load) = &sender_report {
+            report.add_value("messages_sent", load.sent as f64, "messages");
+            report.add_value("send_rate", load.achieved_rate(), "messages/sec");
+        }
+        if let Some((stats, recovered, latency)) = receiver_report {
+            report.add_value("gaps_opened", stats.gaps_opened as f64, "gaps");
+            report.add_value("messages_lost", stats.messages_lost as f64, "messages");
+            report.add_value("messages_recovered", recovered as f64, "messages");
+            if !latency.wire_to_user().is_empty() {
+                report.add_histogram("wire_to_user", latency.wire_to_user().clone());
+            }
+            if !latency.hardware_to_user().is_empty() {
+                report.add_histogram("hardware_to_user", latency.hardware_to_user().clone());
+            }
+            if !latency.end_to_end().is_empty() {
+                report.add_histogram("end_to_end", latency.end_to_end().clone());
+            }
+        }
+        if let Some((_, tracer)) = &consumer_report {
+            for (from, to) in Stage::ALL.into_iter().zip(&Stage::ALL[1..]) {
+                let hop = tracer.hop(from);
+                if !hop.is_empty() {
+                    report.add_histogram(&format!("{}_to_{}", from, to), hop.clone());
+                }
+            }
+        }
+        report.set_audit(audit);
+        match report.write_to_path(path) {
+            Ok(()) => println!("Report written to {}", path.display()),
+            Err(e) => eprintln!("Failed to write report to {}: {}", path.display(), e),
+        }
+    }
+    ExitCode::SUCCESS
 }
@@ -0,0 +1,307 @@
+//! UDP multicast feeds.
+//!
+//! A [`MulticastGroup`] describes a feed: the group address and port, the
+//! local interface to use, and the TTL and loopback settings. Publishers
+//! get a socket that sends to the group from that interface; subscribers
+//! get a socket bound to the group with `SO_REUSEADDR`/`SO_REUSEPORT`, so
+//! several processes on one host can consume the same feed.
+
+use std::fmt;
+use std::io;
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4, UdpSocket};
+
+/// IPv4 multicast feed settings.
+///
+/// # Examples
+/// ```no_run
+/// use hft_system::multicast::MulticastGroup;
+/// use std::net::Ipv4Addr;
+///
+/// let feed = MulticastGroup::new(Ipv4Addr::new(239, 0, 0, 1), 9001)
+///     .interface(Ipv4Addr::LOCALHOST)
+///     .ttl(1);
+///
+/// let subscriber = feed.subscribe()?;
+/// let publisher = feed.publisher()?;
+/// publisher.send_to(b"hello", feed.addr())?;
+///
+/// let mut buf = [0u8; 64];
+/// let (len, _) = subscriber.recv_from(&mut buf)?;
+/// assert_eq!(&buf[..len], b"hello");
+/// feed.leave(&subscriber)?;
+/// # Ok::<(), std::io::Error>(())
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MulticastGroup {
+    group: Ipv4Addr,
+    port: u16,
+    interface: Ipv4Addr,
+    ttl: u32,
+    loopback: bool,
+}
+
+impl MulticastGroup {
+    /// Describes the feed on `group:port`, on the default interface with a
+    /// TTL of 1 (host and local network only) and loopback enabled.
+    pub fn new(group: Ipv4Addr, port: u16) -> Self {
+        Self {
+            group,
+            port,
+            interface: Ipv4Addr::UNSPECIFIED,
+            ttl: 1,
+            loopback: true,
+        }
+    }
+
+    /// Uses the interface with address `interface` for joining and
+    /// sending. [`Ipv4Addr::UNSPECIFIED`] lets the kernel choose.
+    pub fn interface(mut self, interface: Ipv4Addr) -> Self {
+        self.interface = interface;
+        self
+    }
+
+    /// Sets how many router hops published datagrams may cross.
+    pub fn ttl(mut self, ttl: u32) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// Sets whether datagrams are delivered to subscribers on the
+    /// publishing host.
+    pub fn loopback(mut self, loopback: bool) -> Self {
+        self.loopback = loopback;
+        self
+    }
+
+    /// Returns the group address and port publishers send to.
+    pub fn addr(&self) -> SocketAddr {
+        SocketAddr::V4(SocketAddrV4::new(self.group, self.port))
+    }
+
+    /// Returns the group address.
+    pub fn group(&self) -> Ipv4Addr {
+        self.group
+    }
+
+    /// Returns the interface address.
+    pub fn interface_addr(&self) -> Ipv4Addr {
+        self.interface
+    }
+
+    /// Opens a socket bound to the group's port and joins the group.
+    ///
+    /// The socket is bound to the group address itself, so it only sees
+    /// this group's traffic, and shares the port with other subscribers.
+    pub fn subscribe(&self) -> io::Result<UdpSocket> {
+        self.validate()?;
+        let socket = sys::bind_reusable(SocketAddrV4::new(self.group, self.port))?;
+        socket.join_multicast_v4(&self.group, &self.interface)?;
+        socket.set_multicast_loop_v4(self.loopback)?;
+        Ok(socket)
+    }
+
+    /// Leaves the group on a socket returned by
+    /// [`subscribe`](Self::subscribe). Closing the socket leaves it too.
+    pub fn leave(&self, socket: &UdpSocket) -> io::Result<()> {
+        socket.leave_multicast_v4(&self.group, &self.interface)
+    }
+
+    /// Opens a socket for sending to [`addr`](Self::addr).
+    pub fn publisher(&self) -> io::Result<UdpSocket> {
+        self.validate()?;
+        let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))?;
+        socket.set_multicast_ttl_v4(self.ttl)?;
+        socket.set_multicast_loop_v4(self.loopback)?;
+        if !self.interface.is_unspecified() {
+            sys::set_multicast_interface(&socket, self.interface)?;
+        }
+        Ok(socket)
+    }
+
+    fn validate(&self) -> io::Result<()> {
+        if self.group.is_multicast() {
+            Ok(())
+        } else {
+            Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} is not a multicast address", self.group),
+            ))
+        }
+    }
+}
+
+impl fmt::Display for MulticastGroup {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.group, self.port)?;
+        if !self.interface.is_unspecified() {
+            write!(f, " via {}", self.interface)?;
+        }
+        write!(f, " (ttl {})", self.ttl)
+    }
+}
+
+#[cfg(unix)]
+mod sys {
+    use std::io;
+    use std::mem;
+    use std::net::{Ipv4Addr, SocketAddrV4, UdpSocket};
+    use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+
+    fn set_option<T>(
+        fd: RawFd,
+        level: libc::c_int,
+        name: libc::c_int,
+        value: &T,
+    ) -> io::Result<()> {
+        // SAFETY: `value` points to a live T of the given size.
+        let rc = unsafe {
+            libc::setsockopt(
+                fd,
+                level,
+                name,
+                value as *const T as *const libc::c_void,
+                mem::size_of::<T>() as libc::socklen_t,
+            )
+        };
+        if rc == 0 {
+            Ok(())
+        } else {
+            Err(io::Error::last_os_error())
+        }
+    }
+
+    fn in_addr(addr: Ipv4Addr) -> libc::in_addr {
+        libc::in_addr {
+            s_addr: u32::from_ne_bytes(addr.octets()),
+        }
+    }
+
+    /// Binds a UDP socket that other sockets may bind to as well.
+    pub fn bind_reusable(addr: SocketAddrV4) -> io::Result<UdpSocket> {
+        // SAFETY: plain socket(2) call; the result is checked below.
+        let fd = unsafe { libc::socket(libc::AF_INET, libc::SOCK_DGRAM | libc::SOCK_CLOEXEC, 0) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        // SAFETY: `fd` is a freshly created socket owned by nobody else.
+        let owned = unsafe { OwnedFd::from_raw_fd(fd) };
+
+        let one: libc::c_int = 1;
+        set_option(fd, libc::SOL_SOCKET, libc::SO_REUSEADDR, &one)?;
+        set_option(fd, libc::SOL_SOCKET, libc::SO_REUSEPORT, &one)?;
+        // Only deliver groups this socket joined, not every group joined on
+        // the host by anyone bound to the same port
+        #[cfg(target_os = "linux")]
+        set_option(
+            fd,
+            libc::IPPROTO_IP,
+            libc::IP_MULTICAST_ALL,
+            &0 as &libc::c_int,
+        )?;
+
+        // SAFETY: sockaddr_in is plain old data; all-zero is a valid value.
+        let mut sockaddr: libc::sockaddr_in = unsafe { mem::zeroed() };
+        sockaddr.sin_family = libc::AF_INET as libc::sa_family_t;
+        sockaddr.sin_port = addr.port().to_be();
+        sockaddr.sin_addr = in_addr(*addr.ip());
+        // SAFETY: `sockaddr` is a valid sockaddr_in of the given length.
+        let rc = unsafe {
+            libc::bind(
+                fd,
+                &sockaddr as *const libc::sockaddr_in as *const libc::sockaddr,
+                mem::size_of::<libc::sockaddr_in>() as libc::socklen_t,
+            )
+        };
+        if rc != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(UdpSocket::from(owned))
+    }
+
+    /// Sets the interface outgoing multicast datagrams are sent from.
+    pub fn set_multicast_interface(socket: &UdpSocket, interface: Ipv4Addr) -> io::Result<()> {
+        set_option(
+            socket.as_raw_fd(),
+            libc::IPPROTO_IP,
+            libc::IP_MULTICAST_IF,
+            &in_addr(interface),
+        )
+    }
+}
+
+#[cfg(not(unix))]
+mod sys {
+    use std::io;
+    use std::net::{Ipv4Addr, SocketAddrV4, UdpSocket};
+
+    pub fn bind_reusable(addr: SocketAddrV4) -> io::Result<UdpSocket> {
+        UdpSocket::bind(addr)
+    }
+
+    pub fn set_multicast_interface(_socket: &UdpSocket, _interface: Ipv4Addr) -> io::Result<()> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "choosing the multicast interface is not supported on this platform",
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn loopback_feed(port: u16) -> MulticastGroup {
+        MulticastGroup::new(Ipv4Addr::new(239, 255, 0, 45), port).interface(Ipv4Addr::LOCALHOST)
+    }
+
+    fn subscriber(feed: &MulticastGroup) -> UdpSocket {
+        let socket = feed.subscribe().unwrap();
+        socket
+            .set_read_timeout(Some(Duration::from_millis(500)))
+            .unwrap();
+        socket
+    }
+
+    fn recv(socket: &UdpSocket) -> Option<Vec<u8>> {
+        let mut buf = [0u8; 64];
+        socket.recv(&mut buf).ok().map(|len| buf[..len].to_vec())
+    }
+
+    #[test]
+    fn test_rejects_unicast_group() {
+        let feed = MulticastGroup::new(Ipv4Addr::LOCALHOST, 9001);
+        assert_eq!(
+            feed.subscribe().unwrap_err().kind(),
+            io::ErrorKind::InvalidInput
+        );
+        assert!(feed.publisher().is_err());
+    }
+
+    #[test]
+    fn test_two_subscribers_share_feed() {
+        let feed = loopback_feed(19_045);
+        let first = subscriber(&feed);
+        let second = subscriber(&feed);
+        let publisher = feed.publisher().unwrap();
+
+        publisher.send_to(b"tick", feed.addr()).unwrap();
+        assert_eq!(recv(&first).as_deref(), Some(&b"tick"[..]));
+        assert_eq!(recv(&second).as_deref(), Some(&b"tick"[..]));
+
+        // After leaving, only the remaining member sees the feed
+        feed.leave(&first).unwrap();
+        publisher.send_to(b"tock", feed.addr()).unwrap();
+        assert_eq!(recv(&second).as_deref(), Some(&b"tock"[..]));
+        assert_eq!(recv(&first), None);
+    }
+
+    #[test]
+    fn test_publisher_options() {
+        let feed = loopback_feed(19_046).ttl(4).loopback(false);
+        let publisher = feed.publisher().unwrap();
+        assert_eq!(publisher.multicast_ttl_v4().unwrap(), 4);
+        assert!(!publisher.multicast_loop_v4().unwrap());
+        assert_eq!(feed.to_string(), "239.255.0.45:19046 via 127.0.0.1 (ttl 4)");
+    }
+}
@@ -0,0 +1,717 @@
+//! Versioned binary market data protocol.
+//!
+//! Every message starts with a common 20-byte header, followed by a body
+//! whose layout depends on the message type. All integers are
+//! little-endian.
+//!
+//! | Offset | Size | Header field                              |
+//! |--------|------|-------------------------------------------|
+//! | 0      | 1    | protocol version ([`PROTOCOL_VERSION`])   |
+//! | 1      | 1    | message type ([`MessageKind::code`])      |
+//! | 2      | 2    | message length, header included           |
+//! | 4      | 8    | sequence number                           |
+//! | 12     | 8    | send timestamp, ns since the Unix epoch   |
+//!
+//! A datagram may carry several messages back to back; the length field
+//! frames them. A length longer than the type's body is accepted and the
+//! extra bytes are skipped, so later versions can append fields.
+//!
+//! Decoded messages are returned as the [`Message`] enum ([`decode`],
+//! [`frames`]) or dispatched to a [`MessageVisitor`] ([`dispatch`]).
+
+use crate::codec::{is_valid_symbol, pack_symbol, symbol_str, CodecError};
+use std::fmt;
+
+/// Version written by [`encode`] and accepted by [`decode`].
+pub const PROTOCOL_VERSION: u8 = 1;
+/// Size of the common header in bytes.
+pub const HEADER_SIZE: usize = 20;
+/// Size of the largest message this version encodes.
+pub const MAX_MESSAGE_SIZE: usize = HEADER_SIZE + 36;
+
+/// Order side.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Side {
+    Buy,
+    Sell,
+}
+
+impl Side {
+    /// Returns the wire byte (`B` or `S`).
+    pub fn code(self) -> u8 {
+        match self {
+            Side::Buy => b'B',
+            Side::Sell => b'S',
+        }
+    }
+
+    fn from_code(code: u8) -> Result<Self, CodecError> {
+        match code {
+            b'B' => Ok(Side::Buy),
+            b'S' => Ok(Side::Sell),
+            other => Err(CodecError::InvalidSide(other)),
+        }
+    }
+}
+
+/// Message type carried in the header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MessageKind {
+    AddOrder,
+    ModifyOrder,
+    CancelOrder,
+    Execution,
+    Quote,
+    Heartbeat,
+}
+
+impl MessageKind {
+    /// All message kinds, in wire-code order of introduction.
+    pub const ALL: [MessageKind; 6] = [
+        MessageKind::AddOrder,
+        MessageKind::ModifyOrder,
+        MessageKind::CancelOrder,
+        MessageKind::Execution,
+        MessageKind::Quote,
+        MessageKind::Heartbeat,
+    ];
+
+    /// Returns the type byte written to the header.
+    pub fn code(self) -> u8 {
+        match self {
+            MessageKind::AddOrder => b'A',
+            MessageKind::ModifyOrder => b'U',
+            MessageKind::CancelOrder => b'X',
+            MessageKind::Execution => b'E',
+            MessageKind::Quote => b'Q',
+            MessageKind::Heartbeat => b'H',
+        }
+    }
+
+    /// Returns the kind for a type byte.
+    pub fn from_code(code: u8) -> Result<Self, CodecError> {
+        Self::ALL
+            .into_iter()
+            .find(|kind| kind.code() == code)
+            .ok_or(CodecError::UnknownMessageType(code))
+    }
+
+    /// Returns the body size in bytes for this version.
+    pub fn body_len(self) -> usize {
+        match self {
+            MessageKind::AddOrder => 29,
+            MessageKind::ModifyOrder => 20,
+            MessageKind::CancelOrder => 8,
+            MessageKind::Execution => 36,
+            MessageKind::Quote => 32,
+            MessageKind::Heartbeat => 0,
+        }
+    }
+
+    /// Returns a short name for reports.
+    pub fn name(self) -> &'static str {
+        match self {
+            MessageKind::AddOrder => "add",
+            MessageKind::ModifyOrder => "modify",
+            MessageKind::CancelOrder => "cancel",
+            MessageKind::Execution => "execution",
+            MessageKind::Quote => "quote",
+            MessageKind::Heartbeat => "heartbeat",
+        }
+    }
+}
+
+impl fmt::Display for MessageKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Decoded common header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MessageHeader {
+    pub version: u8,
+    pub kind: MessageKind,
+    /// Message length in bytes, header included.
+    pub length: u16,
+    pub sequence: u64,
+    /// Send time in nanoseconds since the Unix epoch.
+    pub timestamp: u64,
+}
+
+/// New resting order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AddOrder {
+    pub order_id: u64,
+    pub symbol: [u8; 8],
+    pub side: Side,
+    /// Price in units of 1/[`PRICE_SCALE`](crate::codec::PRICE_SCALE).
+    pub price: u64,
+    pub quantity: u32,
+}
+
+impl AddOrder {
+    /// Creates an order; symbols longer than 8 bytes are truncated.
+    pub fn new(order_id: u64, symbol: &str, side: Side, price: u64, quantity: u32) -> Self {
+        Self {
+            order_id,
+            symbol: pack_symbol(symbol),
+            side,
+            price,
+            quantity,
+        }
+    }
+
+    /// Returns the symbol without its NUL padding.
+    pub fn symbol_str(&self) -> &str {
+        symbol_str(&self.symbol)
+    }
+}
+
+/// New price and quantity for a resting order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ModifyOrder {
+    pub order_id: u64,
+    pub price: u64,
+    pub quantity: u32,
+}
+
+/// Removal of a resting order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CancelOrder {
+    pub order_id: u64,
+}
+
+/// Trade against a resting order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Execution {
+    pub order_id: u64,
+    pub match_id: u64,
+    pub symbol: [u8; 8],
+    pub price: u64,
+    pub quantity: u32,
+}
+
+/// Top-of-book quote.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Quote {
+    pub symbol: [u8; 8],
+    pub bid_price: u64,
+    pub bid_quantity: u32,
+    pub ask_price: u64,
+    pub ask_quantity: u32,
+}
+
+/// Any protocol message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Message {
+    AddOrder(AddOrder),
+    ModifyOrder(ModifyOrder),
+    CancelOrder(CancelOrder),
+    Execution(Execution),
+    Quote(Quote),
+    Heartbeat,
+}
+
+impl Message {
+    /// Returns the message's type.
+    pub fn kind(&self) -> MessageKind {
+        match self {
+            Message::AddOrder(_) => MessageKind::AddOrder,
+            Message::ModifyOrder(_) => MessageKind::ModifyOrder,
+            Message::CancelOrder(_) => MessageKind::CancelOrder,
+            Message::Execution(_) => MessageKind::Execution,
+            Message::Quote(_) => MessageKind::Quote,
+            Message::Heartbeat => MessageKind::Heartbeat,
+        }
+    }
+
+    /// Returns the encoded size, header included.
+    pub fn encoded_len(&self) -> usize {
+        HEADER_SIZE + self.kind().body_len()
+    }
+}
+
+/// A decoded message with its header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Frame {
+    pub header: MessageHeader,
+    pub message: Message,
+}
+
+impl Frame {
+    /// Calls the visitor method matching the message.
+    pub fn accept<V: MessageVisitor + ?Sized>(&self, visitor: &mut V) {
+        let header = &self.header;
+        match &self.message {
+            Message::AddOrder(order) => visitor.on_add_order(header, order),
+            Message::ModifyOrder(modify) => visitor.on_modify_order(header, modify),
+            Message::CancelOrder(cancel) => visitor.on_cancel_order(header, cancel),
+            Message::Execution(execution) => visitor.on_execution(header, execution),
+            Message::Quote(quote) => visitor.on_quote(header, quote),
+            Message::Heartbeat => visitor.on_heartbeat(header),
+        }
+    }
+}
+
+/// Callbacks for [`dispatch`]; every method defaults to doing nothing.
+pub trait MessageVisitor {
+    fn on_add_order(&mut self, _header: &MessageHeader, _order: &AddOrder) {}
+    fn on_modify_order(&mut self, _header: &MessageHeader, _modify: &ModifyOrder) {}
+    fn on_cancel_order(&mut self, _header: &MessageHeader, _cancel: &CancelOrder) {}
+    fn on_execution(&mut self, _header: &MessageHeader, _execution: &Execution) {}
+    fn on_quote(&mut self, _header: &MessageHeader, _quote: &Quote) {}
+    fn on_heartbeat(&mut self, _header: &MessageHeader) {}
+}
+
+/// Encodes `message` with the given sequence number and timestamp into the
+/// start of `buf`, returning the number of bytes written.
+///
+/// # Examples
+/// ```
+/// use hft_system::protocol::{decode, encode, AddOrder, Message, Side, MAX_MESSAGE_SIZE};
+///
+/// let order = Message::AddOrder(AddOrder::new(42, "AAPL", Side::Buy, 1_502_500, 100));
+/// let mut buf = [0u8; MAX_MESSAGE_SIZE];
+/// let len = encode(&order, 7, 0, &mut buf).unwrap();
+///
+/// let frame = decode(&buf[..len]).unwrap();
+/// assert_eq!(frame.header.sequence, 7);
+/// assert_eq!(frame.message, order);
+/// ```
+pub fn encode(
+    message: &Message,
+    sequence: u64,
+    timestamp: u64,
+    buf: &mut [u8],
+) -> Result<usize, CodecError> {
+    let len = message.encoded_len();
+    let actual = buf.len();
+    let buf = buf.get_mut(..len).ok_or(CodecError::InvalidLength {
+        expected: len,
+        actual,
+    })?;
+
+    let mut writer = Writer { buf, pos: 0 };
+    writer.u8(PROTOCOL_VERSION);
+    writer.u8(message.kind().code());
+    writer.u16(len as u16);
+    writer.u64(sequence);
+    writer.u64(timestamp);
+    match message {
+        Message::AddOrder(order) => {
+            writer.u64(order.order_id);
+            writer.bytes(&order.symbol);
+            writer.u8(order.side.code());
+            writer.u64(order.price);
+            writer.u32(order.quantity);
+        }
+        Message::ModifyOrder(modify) => {
+            writer.u64(modify.order_id);
+            writer.u64(modify.price);
+            writer.u32(modify.quantity);
+        }
+        Message::CancelOrder(cancel) => writer.u64(cancel.order_id),
+        Message::Execution(execution) => {
+            writer.u64(execution.order_id);
+            writer.u64(execution.match_id);
+            writer.bytes(&execution.symbol);
+            writer.u64(execution.price);
+            writer.u32(execution.quantity);
+        }
+        Message::Quote(quote) => {
+            writer.bytes(&quote.symbol);
+            writer.u64(quote.bid_price);
+            writer.u32(quote.bid_quantity);
+            writer.u64(quote.ask_price);
+            writer.u32(quote.ask_quantity);
+        }
+        Message::Heartbeat => {}
+    }
+    debug_assert_eq!(writer.pos, len);
+    Ok(len)
+}
+
+/// Decodes the first message in `bytes`.
+///
+/// The message occupies `header.length` bytes; use [`frames`] to walk a
+/// datagram holding several.
+pub fn decode(bytes: &[u8]) -> Result<Frame, CodecError> {
+    let mut reader = Reader { bytes, pos: 0 };
+    let version = reader.u8()?;
+    if version != PROTOCOL_VERSION {
+        return Err(CodecError::UnsupportedVersion(version));
+    }
+    let kind = MessageKind::from_code(reader.u8()?)?;
+    let length = reader.u16()?;
+    let minimum = HEADER_SIZE + kind.body_len();
+    if (length as usize) < minimum {
+        return Err(CodecError::LengthMismatch {
+            declared: length as usize,
+            minimum,
+        });
+    }
+    if bytes.len() < length as usize {
+        return Err(CodecError::InvalidLength {
+            expected: length as usize,
+            actual: bytes.len(),
+        });
+    }
+    let header = MessageHeader {
+        version,
+        kind,
+        length,
+        sequence: reader.u64()?,
+        timestamp: reader.u64()?,
+    };
+
+    let message = match kind {
+        MessageKind::AddOrder => Message::AddOrder(AddOrder {
+            order_id: reader.u64()?,
+            symbol: reader.symbol()?,
+            side: Side::from_code(reader.u8()?)?,
+            price: reader.u64()?,
+            quantity: reader.u32()?,
+        }),
+        MessageKind::ModifyOrder => Message::ModifyOrder(ModifyOrder {
+            order_id: reader.u64()?,
+            price: reader.u64()?,
+            quantity: reader.u32()?,
+        }),
+        MessageKind::CancelOrder => Message::CancelOrder(CancelOrder {
+            order_id: reader.u64()?,
+        }),
+        MessageKind::Execution => Message::Execution(Execution {
+            order_id: reader.u64()?,
+            match_id: reader.u64()?,
+            symbol: reader.symbol()?,
+            price: reader.u64()?,
+            quantity: reader.u32()?,
+        }),
+        MessageKind::Quote => Message::Quote(Quote {
+            symbol: reader.symbol()?,
+            bid_price: reader.u64()?,
+            bid_quantity: reader.u32()?,
+            ask_price: reader.u64()?,
+            ask_quantity: reader.u32()?,
+        }),
+        MessageKind::Heartbeat => Message::Heartbeat,
+    };
+    Ok(Frame { header, message })
+}
+
+/// Iterates over the messages packed into a datagram.
+///
+/// Stops after the first malformed message, since its length cannot be
+/// trusted to find the next one.
+pub fn frames(bytes: &[u8]) -> Frames<'_> {
+    Frames {
+        remaining: bytes,
+        failed: false,
+    }
+}
+
+/// Iterator returned by [`frames`].
+#[derive(Debug, Clone)]
+pub struct Frames<'a> {
+    remaining: &'a [u8],
+    failed: bool,
+}
+
+impl Iterator for Frames<'_> {
+    type Item = Result<Frame, CodecError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed || self.remaining.is_empty() {
+            return None;
+        }
+        match decode(self.remaining) {
+            Ok(frame) => {
+                self.remaining = &self.remaining[frame.header.length as usize..];
+                Some(Ok(frame))
+            }
+            Err(e) => {
+                self.failed = true;
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+/// Decodes every message in a datagram and hands each to `visitor`.
+///
+/// Returns the number of messages dispatched, or the error that stopped
+/// decoding (messages before it have already been dispatched).
+///
+/// # Examples
+/// ```
+/// use hft_system::protocol::{dispatch, encode, Message, MessageHeader, MessageVisitor};
+///
+/// #[derive(Default)]
+/// struct Heartbeats(u64);
+///
+/// impl MessageVisitor for Heartbeats {
+///     fn on_heartbeat(&mut self, header: &MessageHeader) {
+///         self.0 = header.sequence;
+///     }
+/// }
+///
+/// let mut buf = [0u8; 64];
+/// let len = encode(&Message::Heartbeat, 3, 0, &mut buf).unwrap();
+/// let mut visitor = Heartbeats::default();
+/// assert_eq!(dispatch(&buf[..len], &mut visitor), Ok(1));
+/// assert_eq!(visitor.0, 3);
+/// ```
+pub fn dispatch<V: MessageVisitor + ?Sized>(
+    bytes: &[u8],
+    visitor: &mut V,
+) -> Result<usize, CodecError> {
+    let mut count = 0;
+    for frame in frames(bytes) {
+        frame?.accept(visitor);
+        count += 1;
+    }
+    Ok(count)
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl Reader<'_> {
+    fn take<const N: usize>(&mut self) -> Result<[u8; N], CodecError> {
+        let field = self
+            .bytes
+            .get(self.pos..self.pos + N)
+            .ok_or(CodecError::InvalidLength {
+                expected: self.pos + N,
+                actual: self.bytes.len(),
+            })?;
+        self.pos += N;
+        Ok(field.try_into().expect("slice has N bytes"))
+    }
+
+    fn u8(&mut self) -> Result<u8, CodecError> {
+        self.take::<1>().map(|[byte]| byte)
+    }
+
+    fn u16(&mut self) -> Result<u16, CodecError> {
+        self.take().map(u16::from_le_bytes)
+    }
+
+    fn u32(&mut self) -> Result<u32, CodecError> {
+        self.take().map(u32::from_le_bytes)
+    }
+
+    fn u64(&mut self) -> Result<u64, CodecError> {
+        self.take().map(u64::from_le_bytes)
+    }
+
+    fn symbol(&mut self) -> Result<[u8; 8], CodecError> {
+        let symbol = self.take()?;
+        if !is_valid_symbol(&symbol) {
+            return Err(CodecError::InvalidSymbol);
+        }
+        Ok(symbol)
+    }
+}
+
+/// Writes into a buffer already checked to hold the whole message.
+struct Writer<'a> {
+    buf: &'a mut [u8],
+    pos: usize,
+}
+
+impl Writer<'_> {
+    fn bytes(&mut self, bytes: &[u8]) {
+        self.buf[self.pos..self.pos + bytes.len()].copy_from_slice(bytes);
+        self.pos += bytes.len();
+    }
+
+    fn u8(&mut self, value: u8) {
+        self.bytes(&[value]);
+    }
+
+    fn u16(&mut self, value: u16) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    fn u64(&mut self, value: u64) {
+        self.bytes(&value.to_le_bytes());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn samples() -> Vec<Message> {
+        vec![
+            Message::AddOrder(AddOrder::new(1, "AAPL", Side::Buy, 1_502_500, 100)),
+            Message::ModifyOrder(ModifyOrder {
+                order_id: 1,
+                price: 1_503_000,
+                quantity: 80,
+            }),
+            Message::CancelOrder(CancelOrder { order_id: 1 }),
+            Message::Execution(Execution {
+                order_id: 2,
+                match_id: 900,
+                symbol: pack_symbol("MSFT"),
+                price: 4_105_000,
+                quantity: 25,
+            }),
+            Message::Quote(Quote {
+                symbol: pack_symbol("ES"),
+                bid_price: 50_000_000,
+                bid_quantity: 10,
+                ask_price: 50_002_500,
+                ask_quantity: 12,
+            }),
+            Message::Heartbeat,
+        ]
+    }
+
+    #[test]
+    fn test_round_trip_every_kind() {
+        let mut buf = [0u8; MAX_MESSAGE_SIZE];
+        for (sequence, message) in samples().into_iter().enumerate() {
+            let len = encode(&message, sequence as u64, 1_000 + sequence as u64, &mut buf).unwrap();
+            assert_eq!(len, message.encoded_len());
+            assert_eq!(buf[0], PROTOCOL_VERSION);
+            assert_eq!(buf[1], message.kind().code());
+            assert_eq!(u16::from_le_bytes([buf[2], buf[3]]) as usize, len);
+
+            let frame = decode(&buf[..len]).unwrap();
+            assert_eq!(frame.message, message);
+            assert_eq!(frame.header.sequence, sequence as u64);
+            assert_eq!(frame.header.timestamp, 1_000 + sequence as u64);
+        }
+    }
+
+    #[test]
+    fn test_multiple_messages_per_datagram_and_visitor() {
+        #[derive(Default)]
+        struct Counts {
+            adds: usize,
+            quotes: usize,
+            other: usize,
+            sequences: Vec<u64>,
+        }
+        impl MessageVisitor for Counts {
+            fn on_add_order(&mut self, header: &MessageHeader, order: &AddOrder) {
+                assert_eq!(order.symbol_str(), "AAPL");
+                self.adds += 1;
+                self.sequences.push(header.sequence);
+            }
+            fn on_quote(&mut self, header: &MessageHeader, _quote: &Quote) {
+                self.quotes += 1;
+                self.sequences.push(header.sequence);
+            }
+            fn on_cancel_order(&mut self, header: &MessageHeader, _cancel: &CancelOrder) {
+                self.other += 1;
+                self.sequences.push(header.sequence);
+            }
+        }
+
+        let mut datagram = Vec::new();
+        let mut buf = [0u8; MAX_MESSAGE_SIZE];
+        for (sequence, message) in samples().iter().enumerate() {
+            let len = encode(message, sequence as u64, 0, &mut buf).unwrap();
+            datagram.extend_from_slice(&buf[..len]);
+        }
+
+        let mut counts = Counts::default();
+        assert_eq!(dispatch(&datagram, &mut counts), Ok(6));
+        assert_eq!((counts.adds, counts.quotes, counts.other), (1, 1, 1));
+        assert_eq!(counts.sequences, [0, 2, 4]);
+
+        // A truncated tail is reported after the complete messages
+        let results: Vec<_> = frames(&datagram[..datagram.len() - 1]).collect();
+        assert_eq!(results.len(), 6);
+        assert!(results[..5].iter().all(Result::is_ok));
+        assert!(results[5].is_err());
+    }
+
+    #[test]
+    fn test_malformed_headers() {
+        let mut buf = [0u8; MAX_MESSAGE_SIZE];
+        let len = encode(&samples()[0], 1, 0, &mut buf).unwrap();
+
+        let mut bad_version = buf;
+        bad_version[0] = 2;
+        assert_eq!(
+            decode(&bad_version[..len]),
+            Err(CodecError::UnsupportedVersion(2))
+        );
+
+        let mut bad_kind = buf;
+        bad_kind[1] = b'Z';
+        assert_eq!(
+            decode(&bad_kind[..len]),
+            Err(CodecError::UnknownMessageType(b'Z'))
+        );
+
+        let mut short = buf;
+        short[2..4].copy_from_slice(&(HEADER_SIZE as u16).to_le_bytes());
+        assert_eq!(
+            decode(&short[..len]),
+            Err(CodecError::LengthMismatch {
+                declared: HEADER_SIZE,
+                minimum: len
+            })
+        );
+
+        let mut bad_side = buf;
+        bad_side[HEADER_SIZE + 16] = b'?';
+        assert_eq!(decode(&bad_side[..len]), Err(CodecError::InvalidSide(b'?')));
+
+        assert!(matches!(
+            decode(&buf[..len - 1]),
+            Err(CodecError::InvalidLength { .. })
+        ));
+        assert!(decode(&[]).is_err());
+    }
+
+    #[test]
+    fn test_longer_messages_skip_extension_fields() {
+        let mut buf = [0u8; MAX_MESSAGE_SIZE + 8];
+        let len = encode(&Message::Heartbeat, 5, 0, &mut buf).unwrap();
+        // A future version appended 4 bytes to the heartbeat
+        buf[2..4].copy_from_slice(&(len as u16 + 4).to_le_bytes());
+        let next = encode(&Message::Heartbeat, 6, 0, &mut buf[len + 4..]).unwrap();
+
+        let sequences: Vec<u64> = frames(&buf[..len + 4 + next])
+            .map(|frame| frame.unwrap().header.sequence)
+            .collect();
+        assert_eq!(sequences, [5, 6]);
+    }
+
+    #[test]
+    fn test_random_bytes_never_panic() {
+        let mut state = 0x2545_f491_4f6c_dd1du64;
+        let mut next = move || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        };
+        for _ in 0..20_000 {
+            let len = (next() % 96) as usize;
+            let mut bytes: Vec<u8> = (0..len).map(|_| next() as u8).collect();
+            if len >= 4 {
+                // Make most headers plausible so bodies get exercised
+                bytes[0] = PROTOCOL_VERSION;
+                bytes[1] = MessageKind::ALL[(next() % 6) as usize].code();
+            }
+            for frame in frames(&bytes).flatten() {
+                assert!(frame.header.length as usize >= frame.message.encoded_len());
+            }
+        }
+    }
+}
@@ -0,0 +1,465 @@
+//! UDP feed receiver.
+//!
+//! [`UdpReceiver`] reads datagrams from a socket in batches (see
+//! [`RecvBatch`]), decodes every message in them and passes the frames
+//! through a [`FeedSequencer`], so callers only ever see each source's
+//! messages once and in sequence order. With a [`RecoveryClient`]
+//! attached, gaps are requested over TCP as soon as they open and the
+//! recovered messages are merged back in order. Every received message's
+//! latency is recorded in an [`RxLatency`], using kernel receive
+//! timestamps when they are [enabled](UdpReceiver::enable_timestamps).
+
+use crate::batch::{RecvBatch, MAX_DATAGRAM_SIZE};
+use crate::protocol::{self, Frame};
+use crate::recovery::{RecoveryClient, MAX_RESPONSE_MESSAGES};
+use crate::sequencer::FeedSequencer;
+use crate::timestamping::{self, RxLatency, TimestampMode};
+use hft_primitives::runtime::StopFlag;
+use std::collections::HashMap;
+use std::io::{self, ErrorKind};
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+use std::time::Duration;
+
+/// How long a blocking receive waits before the stop flag is checked again.
+pub const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Datagrams taken per receive system call unless configured otherwise.
+pub const DEFAULT_BATCH_SIZE: usize = 32;
+
+/// What one [`UdpReceiver::poll`] did.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PollOutcome {
+    /// Datagrams read from the socket (0 if the poll timed out).
+    pub datagrams: usize,
+    /// Messages delivered in order, including recovered ones. Can be 0
+    /// even when datagrams arrived, e.g. if they were all buffered or
+    /// duplicates.
+    pub delivered: usize,
+}
+
+/// Sequenced UDP receiver.
+pub struct UdpReceiver {
+    socket: UdpSocket,
+    sequencer: FeedSequencer,
+    malformed: u64,
+    batch: RecvBatch,
+    /// Sources heard from in the current batch.
+    touched: Vec<SocketAddr>,
+    recovery: Option<RecoveryClient>,
+    /// Per source, the end of the last range requested from recovery.
+    requested: HashMap<SocketAddr, u64>,
+    recovered: u64,
+    recovery_errors: u64,
+    latency: RxLatency,
+}
+
+impl UdpReceiver {
+    /// Binds to `addr` with a reorder window of `window` messages per
+    /// source.
+    pub fn bind<A: ToSocketAddrs>(addr: A, window: usize) -> io::Result<Self> {
+        Self::from_socket(UdpSocket::bind(addr)?, window)
+    }
+
+    /// Wraps an already configured socket.
+    pub fn from_socket(socket: UdpSocket, window: usize) -> io::Result<Self> {
+        socket.set_read_timeout(Some(POLL_INTERVAL))?;
+        Ok(Self {
+            socket,
+            sequencer: FeedSequencer::new(window),
+            malformed: 0,
+            batch: RecvBatch::new(DEFAULT_BATCH_SIZE, MAX_DATAGRAM_SIZE),
+            touched: Vec::new(),
+            recovery: None,
+            requested: HashMap::new(),
+            recovered: 0,
+            recovery_errors: 0,
+            latency: RxLatency::new(),
+        })
+    }
+
+    /// Receives up to `batch` datagrams per system call.
+    ///
+    /// # Panics
+    /// Panics if `batch` is zero.
+    pub fn with_batch_size(mut self, batch: usize) -> Self {
+        self.batch = RecvBatch::new(batch, MAX_DATAGRAM_SIZE);
+        self
+    }
+
+    /// Asks the kernel to stamp received datagrams, so
+    /// [`latency`](Self::latency) includes wire-to-userspace times.
+    pub fn enable_timestamps(&self, mode: TimestampMode) -> io::Result<()> {
+        timestamping::enable(&self.socket, mode)
+    }
+
+    /// Requests missing ranges from `client` whenever a gap opens.
+    ///
+    /// The recovery server is expected to hold the sequence space of every
+    /// source this receiver hears from.
+    ///
+    /// Requests are made synchronously inside [`poll`](Self::poll), so a
+    /// slow or unreachable server stalls the receive path for up to
+    /// [`RESPONSE_TIMEOUT`] per request (twice that when it must
+    /// reconnect). Datagrams arriving meanwhile queue in the socket buffer;
+    /// size `SO_RCVBUF` for that, or leave recovery off on a receiver that
+    /// cannot afford the stall.
+    ///
+    /// [`RESPONSE_TIMEOUT`]: crate::recovery::RESPONSE_TIMEOUT
+    pub fn with_recovery(mut self, client: RecoveryClient) -> Self {
+        self.recovery = Some(client);
+        self
+    }
+
+    /// Returns the bound address.
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+
+    /// Receives a batch of datagrams, waiting at most [`POLL_INTERVAL`]
+    /// for the first, and calls `deliver` for every message that is now in
+    /// order. Returns how many datagrams were read and messages delivered.
+    ///
+    /// With [recovery](Self::with_recovery) attached, new gaps are
+    /// requested before returning, which can block. A range whose request
+    /// failed is requested again on the next poll that reads from its
+    /// source.
+    pub fn poll<F: FnMut(Frame)>(&mut self, mut deliver: F) -> io::Result<PollOutcome> {
+        match self.batch.recv(&self.socket) {
+            Ok(_) => {}
+            Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                return Ok(PollOutcome::default())
+            }
+            Err(e) => return Err(e),
+        }
+        let now = timestamping::now_nanos();
+
+        let mut delivered = 0;
+        self.touched.clear();
+        for i in 0..self.batch.len() {
+            let (source, datagram) = self.batch.datagram(i);
+            let Some(source) = source else {
+                self.malformed += 1;
+                continue;
+            };
+            let stamp = self.batch.timestamp(i);
+            if !self.touched.contains(&source) {
+                self.touched.push(source);
+            }
+            // A datagram may hold several messages
+            for frame in protocol::frames(datagram) {
+                match frame {
+                    Ok(frame) => {
+                        self.latency.record(stamp, frame.header.timestamp, now);
+                        self.sequencer.process(source, frame, |frame| {
+                            delivered += 1;
+                            deliver(frame);
+                        })
+                    }
+                    Err(_) => self.malformed += 1,
+                }
+            }
+        }
+        if self.recovery.is_some() {
+            for i in 0..self.touched.len() {
+                delivered += self.recover(self.touched[i], &mut deliver);
+            }
+        }
+        Ok(PollOutcome {
+            datagrams: self.batch.len(),
+            delivered,
+        })
+    }
+
+    /// Returns the number of receive system calls made.
+    pub fn syscalls(&self) -> u64 {
+        self.batch.syscalls()
+    }
+
+    /// Requests the open gaps of `source` not asked for yet and processes
+    /// whatever comes back. Returns the number of messages delivered.
+    fn recover<F: FnMut(Frame)>(&mut self, source: SocketAddr, deliver: &mut F) -> usize {
+        let Some(client) = self.recovery.as_mut() else {
+            return 0;
+        };
+        let next = self.sequencer.next_expected(source).unwrap_or(0);
+        let requested = self.requested.entry(source).or_insert(0);
+        // A source re-based backwards leaves requests far beyond its window
+        if *requested > next.saturating_add(self.sequencer.window() as u64) {
+            *requested = next;
+        }
+        let mut delivered = 0;
+        for gap in self.sequencer.open_gaps(source) {
+            let mut start = gap.start.max(*requested);
+            while start < gap.end {
+                let count = (gap.end - start).min(MAX_RESPONSE_MESSAGES as u64) as u32;
+                match client.request(start, count) {
+                    Ok(frames) => {
+                        *requested = start + count as u64;
+                        for frame in frames {
+                            self.recovered += 1;
+                            self.sequencer.process(source, frame, |frame| {
+                                delivered += 1;
+                                deliver(frame);
+                            });
+                        }
+                    }
+                    // Leave `requested` at this range so the next poll
+                    // retries it; further requests would fail the same way
+                    Err(_) => {
+                        self.recovery_errors += 1;
+                        return delivered;
+                    }
+                }
+                start = *requested;
+            }
+        }
+        delivered
+    }
+
+    /// Polls until `stop` is set. Receive errors are reported to stderr
+    /// and do not end the loop.
+    pub fn run<F: FnMut(Frame)>(&mut self, stop: &StopFlag, mut deliver: F) {
+        while !stop.is_stopped() {
+            if let Err(e) = self.poll(&mut deliver) {
+                eprintln!("UDP receive error: {}", e);
+            }
+        }
+    }
+
+    /// Returns the sequencer holding per-source state, counters and events.
+    pub fn sequencer(&self) -> &FeedSequencer {
+        &self.sequencer
+    }
+
+    /// Returns the sequencer mutably, e.g. to drain its events.
+    pub fn sequencer_mut(&mut self) -> &mut FeedSequencer {
+        &mut self.sequencer
+    }
+
+    /// Returns the number of datagrams with an undecodable message.
+    pub fn malformed(&self) -> u64 {
+        self.malformed
+    }
+
+    /// Returns the number of messages received from the recovery server.
+    pub fn recovered(&self) -> u64 {
+        self.recovered
+    }
+
+    /// Returns the latency of every message received over UDP.
+    pub fn latency(&self) -> &RxLatency {
+        &self.latency
+    }
+
+    /// Returns the number of failed recovery requests.
+    pub fn recovery_errors(&self) -> u64 {
+        self.recovery_errors
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::{encode, Message};
+    use crate::recovery::{encode_response, RecoveryHistory, RecoveryServer, REQUEST_SIZE};
+    use crate::sequencer::{FeedEvent, Gap};
+    use crate::timestamping::now_nanos;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::sync::Arc;
+    use std::thread;
+
+    fn send(socket: &UdpSocket, target: SocketAddr, sequences: &[u64]) {
+        let mut buf = [0u8; protocol::MAX_MESSAGE_SIZE];
+        for &sequence in sequences {
+            let len = encode(&Message::Heartbeat, sequence, now_nanos(), &mut buf).unwrap();
+            socket.send_to(&buf[..len], target).unwrap();
+        }
+    }
+
+    // Polls until `expected` messages are delivered or the socket goes quiet
+    fn receive(receiver: &mut UdpReceiver, expected: usize) -> Vec<u64> {
+        let mut delivered = Vec::new();
+        let mut idle = 0;
+        while delivered.len() < expected && idle < 10 {
+            let before = delivered.len();
+            receiver
+                .poll(|frame| delivered.push(frame.header.sequence))
+                .unwrap();
+            idle = if delivered.len() == before {
+                idle + 1
+            } else {
+                0
+            };
+        }
+        delivered
+    }
+
+    #[test]
+    fn test_loopback_gap_and_duplicates() {
+        let mut receiver = UdpReceiver::bind("127.0.0.1:0", 16).unwrap();
+        let target = receiver.local_addr().unwrap();
+        let sender = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let source = sender.local_addr().unwrap();
+
+        sender.send_to(&[0xff; 8], target).unwrap();
+        send(&sender, target, &[1, 2, 5, 4, 2, 3]);
+        let delivered = receive(&mut receiver, 5);
+
+        assert_eq!(delivered, [1, 2, 3, 4, 5]);
+        assert_eq!(receiver.malformed(), 1);
+        let stats = receiver.sequencer().stats();
+        assert_eq!(stats.received, 6);
+        assert_eq!(stats.duplicates, 1);
+        assert_eq!(stats.out_of_order, 2);
+        assert_eq!(stats.gaps_opened, 1);
+        assert_eq!(
+            receiver.sequencer_mut().drain_events(),
+            [FeedEvent::GapOpened(Gap {
+                source,
+                start: 3,
+                end: 5
+            })]
+        );
+    }
+
+    #[test]
+    fn test_loopback_lost_gap() {
+        let mut receiver = UdpReceiver::bind("127.0.0.1:0", 2).unwrap();
+        let target = receiver.local_addr().unwrap();
+        let sender = UdpSocket::bind("127.0.0.1:0").unwrap();
+
+        send(&sender, target, &[10, 12, 13]);
+        let delivered = receive(&mut receiver, 3);
+
+        assert_eq!(delivered, [10, 12, 13]);
+        let stats = receiver.sequencer().stats();
+        assert_eq!(stats.messages_lost, 1);
+        assert!(receiver
+            .sequencer()
+            .events()
+            .any(|event| matches!(event, FeedEvent::GapLost(gap) if gap.start == 11)));
+    }
+
+    #[test]
+    fn test_loopback_recovery() {
+        let history = RecoveryHistory::shared(64);
+        let server = RecoveryServer::bind("127.0.0.1:0", Arc::clone(&history)).unwrap();
+        let client = RecoveryClient::connect(server.local_addr().unwrap()).unwrap();
+        let mut receiver = UdpReceiver::bind("127.0.0.1:0", 16)
+            .unwrap()
+            .with_recovery(client);
+        let target = receiver.local_addr().unwrap();
+        let sender = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let stop = StopFlag::new();
+
+        // Everything is published to the history; 3..6 never reaches the wire
+        let mut buf = [0u8; protocol::MAX_MESSAGE_SIZE];
+        for sequence in 0..8 {
+            let len = encode(&Message::Heartbeat, sequence, 0, &mut buf).unwrap();
+            history.lock().unwrap().record(sequence, &buf[..len]);
+        }
+
+        thread::scope(|scope| {
+            scope.spawn(|| server.run(&stop).unwrap());
+            send(&sender, target, &[0, 1, 2, 6, 7]);
+            let delivered = receive(&mut receiver, 8);
+            stop.stop();
+
+            assert_eq!(delivered, [0, 1, 2, 3, 4, 5, 6, 7]);
+        });
+        assert_eq!(receiver.recovered(), 3);
+        assert_eq!(receiver.recovery_errors(), 0);
+        let stats = receiver.sequencer().stats();
+        assert_eq!(stats.gaps_opened, 1);
+        assert_eq!(stats.messages_lost, 0);
+        assert!(receiver
+            .sequencer()
+            .open_gaps(sender.local_addr().unwrap())
+            .is_empty());
+    }
+
+    #[test]
+    fn test_failed_recovery_is_retried() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let client = RecoveryClient::connect(listener.local_addr().unwrap()).unwrap();
+        let mut receiver = UdpReceiver::bind("127.0.0.1:0", 16)
+            .unwrap()
+            .with_recovery(client);
+        let target = receiver.local_addr().unwrap();
+        let sender = UdpSocket::bind("127.0.0.1:0").unwrap();
+
+        let mut history = RecoveryHistory::new(64);
+        let mut buf = [0u8; protocol::MAX_MESSAGE_SIZE];
+        for sequence in 0..8 {
+            let len = encode(&Message::Heartbeat, sequence, 0, &mut buf).unwrap();
+            history.record(sequence, &buf[..len]);
+        }
+        let server = thread::spawn(move || {
+            let mut request = [0u8; REQUEST_SIZE];
+            // Hang up on the first request, answer the retry
+            let (mut stream, _) = listener.accept().unwrap();
+            stream.read_exact(&mut request).unwrap();
+            drop(stream);
+
+            let (mut stream, _) = listener.accept().unwrap();
+            stream.read_exact(&mut request).unwrap();
+            let mut response = Vec::new();
+            encode_response(&history, 3, 6, &mut response);
+            stream.write_all(&response).unwrap();
+        });
+
+        send(&sender, target, &[0, 1, 2, 6, 7]);
+        let mut delivered = receive(&mut receiver, 3);
+        assert_eq!(receiver.recovery_errors(), 1);
+        // The next datagram from the source retries the range
+        send(&sender, target, &[8]);
+        delivered.extend(receive(&mut receiver, 6));
+
+        assert_eq!(delivered, [0, 1, 2, 3, 4, 5, 6, 7, 8]);
+        assert_eq!(receiver.recovery_errors(), 1);
+        assert_eq!(receiver.recovered(), 3);
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_batched_poll() {
+        let mut receiver = UdpReceiver::bind("127.0.0.1:0", 16)
+            .unwrap()
+            .with_batch_size(64);
+        let target = receiver.local_addr().unwrap();
+        let sender = UdpSocket::bind("127.0.0.1:0").unwrap();
+
+        let sequences: Vec<u64> = (0..200).collect();
+        send(&sender, target, &sequences);
+        assert_eq!(receive(&mut receiver, 200), sequences);
+        assert!(receiver.syscalls() < 20);
+        assert_eq!(receiver.latency().end_to_end().len(), 200);
+    }
+
+    #[test]
+    fn test_poll_reports_undelivered_datagrams() {
+        let mut receiver = UdpReceiver::bind("127.0.0.1:0", 16).unwrap();
+        let target = receiver.local_addr().unwrap();
+        let sender = UdpSocket::bind("127.0.0.1:0").unwrap();
+
+        send(&sender, target, &[0]);
+        assert_eq!(receive(&mut receiver, 1), [0]);
+
+        // Both wait behind the missing 1: read, but not delivered
+        send(&sender, target, &[2, 3]);
+        let mut datagrams = 0;
+        while datagrams < 2 {
+            let outcome = receiver.poll(|_| panic!("nothing is in order")).unwrap();
+            assert!(outcome.datagrams > 0);
+            assert_eq!(outcome.delivered, 0);
+            datagrams += outcome.datagrams;
+        }
+    }
+
+    #[test]
+    fn test_poll_times_out() {
+        let mut receiver = UdpReceiver::bind("127.0.0.1:0", 4).unwrap();
+        assert_eq!(receiver.poll(|_| {}).unwrap(), PollOutcome::default());
+    }
+}
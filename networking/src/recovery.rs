@@ -0,0 +1,484 @@
+//! TCP recovery of missed sequence ranges.
+//!
+//! In the style of MoldUDP64 re-request servers and SoupBinTCP: the
+//! publisher keeps the last messages it sent in a [`RecoveryHistory`] and
+//! a [`RecoveryServer`] answers range requests for them over TCP. A
+//! [`RecoveryClient`] on the receiving side asks for the ranges its
+//! sequencer reports as missing and feeds the answers back through it.
+//!
+//! # Wire format
+//! All integers are little-endian.
+//! - Request: first sequence `u64`, message count `u32`.
+//! - Response: message count `u32`, then for each message its length `u16`
+//!   followed by the encoded message exactly as it was sent.
+//!
+//! The response holds the requested messages still in the history, in
+//! sequence order; ranges that have been evicted are silently missing.
+
+use crate::codec::CodecError;
+use crate::protocol::{self, Frame};
+use hft_primitives::runtime::StopFlag;
+use std::collections::VecDeque;
+use std::fmt;
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// Size of a range request.
+pub const REQUEST_SIZE: usize = 12;
+
+/// Most messages returned for a single request.
+pub const MAX_RESPONSE_MESSAGES: u32 = 4096;
+
+/// How long the server waits before checking its stop flag again.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// How long the client waits to reconnect or for a response.
+pub const RESPONSE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Error returned by [`RecoveryClient`].
+#[derive(Debug)]
+pub enum RecoveryError {
+    Io(io::Error),
+    /// A recovered message did not decode.
+    Codec(CodecError),
+    /// The server returned more messages than were asked for.
+    TooManyMessages {
+        requested: u32,
+        returned: u32,
+    },
+}
+
+impl fmt::Display for RecoveryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RecoveryError::Io(e) => write!(f, "recovery connection failed: {}", e),
+            RecoveryError::Codec(e) => write!(f, "recovered message is malformed: {}", e),
+            RecoveryError::TooManyMessages {
+                requested,
+                returned,
+            } => write!(
+                f,
+                "requested {} messages, server returned {}",
+                requested, returned
+            ),
+        }
+    }
+}
+
+impl std::error::Error for RecoveryError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            RecoveryError::Io(e) => Some(e),
+            RecoveryError::Codec(e) => Some(e),
+            RecoveryError::TooManyMessages { .. } => None,
+        }
+    }
+}
+
+impl From<io::Error> for RecoveryError {
+    fn from(e: io::Error) -> Self {
+        RecoveryError::Io(e)
+    }
+}
+
+impl From<CodecError> for RecoveryError {
+    fn from(e: CodecError) -> Self {
+        RecoveryError::Codec(e)
+    }
+}
+
+/// Bounded history of sent messages, oldest evicted first.
+///
+/// # Examples
+/// ```
+/// use hft_system::recovery::RecoveryHistory;
+///
+/// let mut history = RecoveryHistory::new(2);
+/// history.record(1, b"one");
+/// history.record(2, b"two");
+/// history.record(3, b"three");
+///
+/// let kept: Vec<u64> = history.range(0, 10).map(|(seq, _)| seq).collect();
+/// assert_eq!(kept, [2, 3]);
+/// ```
+#[derive(Debug)]
+pub struct RecoveryHistory {
+    capacity: usize,
+    messages: VecDeque<(u64, Box<[u8]>)>,
+}
+
+impl RecoveryHistory {
+    /// Creates a history keeping the last `capacity` messages.
+    ///
+    /// # Panics
+    /// Panics if `capacity` is zero.
+    pub fn new(capacity: usize) -> Self {
+        assert!(capacity > 0, "history capacity must be at least 1");
+        Self {
+            capacity,
+            messages: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    /// Creates an empty history shared between a publisher and a
+    /// [`RecoveryServer`].
+    pub fn shared(capacity: usize) -> Arc<Mutex<Self>> {
+        Arc::new(Mutex::new(Self::new(capacity)))
+    }
+
+    /// Stores the encoded message sent with `sequence`.
+    ///
+    /// Sequences are expected to increase; a message that is not newer than
+    /// the last one recorded is ignored.
+    pub fn record(&mut self, sequence: u64, message: &[u8]) {
+        if self
+            .messages
+            .back()
+            .is_some_and(|&(last, _)| sequence <= last)
+        {
+            return;
+        }
+        if self.messages.len() == self.capacity {
+            self.messages.pop_front();
+        }
+        self.messages.push_back((sequence, message.into()));
+    }
+
+    /// Returns the stored messages with sequences in `start..end`.
+    pub fn range(&self, start: u64, end: u64) -> impl Iterator<Item = (u64, &[u8])> {
+        let first = self.messages.partition_point(|&(seq, _)| seq < start);
+        self.messages
+            .range(first..)
+            .take_while(move |&&(seq, _)| seq < end)
+            .map(|(seq, message)| (*seq, &message[..]))
+    }
+
+    /// Returns the oldest sequence still stored.
+    pub fn first_sequence(&self) -> Option<u64> {
+        self.messages.front().map(|&(seq, _)| seq)
+    }
+
+    /// Returns the number of stored messages.
+    pub fn len(&self) -> usize {
+        self.messages.len()
+    }
+
+    /// Returns true if nothing has been recorded.
+    pub fn is_empty(&self) -> bool {
+        self.messages.is_empty()
+    }
+
+    /// Returns the maximum number of stored messages.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+}
+
+/// Answers range requests from a shared [`RecoveryHistory`].
+pub struct RecoveryServer {
+    listener: TcpListener,
+    history: Arc<Mutex<RecoveryHistory>>,
+}
+
+impl RecoveryServer {
+    /// Binds to `addr`. Clients may connect as soon as this returns.
+    pub fn bind<A: ToSocketAddrs>(
+        addr: A,
+        history: Arc<Mutex<RecoveryHistory>>,
+    ) -> io::Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        listener.set_nonblocking(true)?;
+        Ok(Self { listener, history })
+    }
+
+    /// Returns the bound address.
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Serves clients until `stop` is set. Each connection is handled on
+    /// its own thread; all of them are joined before returning.
+    pub fn run(&self, stop: &StopFlag) -> io::Result<()> {
+        let mut connections: Vec<JoinHandle<()>> = Vec::new();
+        while !stop.is_stopped() {
+            match self.listener.accept() {
+                Ok((stream, peer)) => {
+                    let history = Arc::clone(&self.history);
+                    let stop = stop.clone();
+                    connections.push(thread::spawn(move || {
+                        if let Err(e) = serve_connection(stream, &history, &stop) {
+                            eprintln!("Recovery connection from {} failed: {}", peer, e);
+                        }
+                    }));
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => thread::sleep(POLL_INTERVAL),
+                Err(e) => return Err(e),
+            }
+            connections.retain(|connection| !connection.is_finished());
+        }
+        for connection in connections {
+            let _ = connection.join();
+        }
+        Ok(())
+    }
+}
+
+fn serve_connection(
+    mut stream: TcpStream,
+    history: &Mutex<RecoveryHistory>,
+    stop: &StopFlag,
+) -> io::Result<()> {
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(POLL_INTERVAL))?;
+    stream.set_nodelay(true)?;
+
+    let mut request = [0u8; REQUEST_SIZE];
+    let mut filled = 0;
+    let mut response = Vec::new();
+    while !stop.is_stopped() {
+        match stream.read(&mut request[filled..]) {
+            Ok(0) => return Ok(()),
+            Ok(n) => filled += n,
+            Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => continue,
+            Err(e) => return Err(e),
+        }
+        if filled < REQUEST_SIZE {
+            continue;
+        }
+        filled = 0;
+
+        let start = u64::from_le_bytes(request[0..8].try_into().unwrap());
+        let count =
+            u32::from_le_bytes(request[8..12].try_into().unwrap()).min(MAX_RESPONSE_MESSAGES);
+        encode_response(
+            &history.lock().unwrap(),
+            start,
+            start.saturating_add(count as u64),
+            &mut response,
+        );
+        stream.write_all(&response)?;
+    }
+    Ok(())
+}
+
+pub(crate) fn encode_response(history: &RecoveryHistory, start: u64, end: u64, out: &mut Vec<u8>) {
+    out.clear();
+    out.extend_from_slice(&[0; 4]);
+    let mut count = 0u32;
+    for (_, message) in history.range(start, end) {
+        out.extend_from_slice(&(message.len() as u16).to_le_bytes());
+        out.extend_from_slice(message);
+        count += 1;
+    }
+    out[0..4].copy_from_slice(&count.to_le_bytes());
+}
+
+/// Connection to a [`RecoveryServer`].
+///
+/// A request that fails part-way leaves the stream at an unknown point in
+/// the response, so the connection is dropped and the next request
+/// reconnects.
+#[derive(Debug)]
+pub struct RecoveryClient {
+    addr: SocketAddr,
+    /// `None` after a failed request, until the next one reconnects.
+    stream: Option<TcpStream>,
+    /// The last response body: every message with its length prefix.
+    buf: Vec<u8>,
+}
+
+impl RecoveryClient {
+    /// Connects to the server at `addr`.
+    pub fn connect<A: ToSocketAddrs>(addr: A) -> io::Result<Self> {
+        let stream = TcpStream::connect(addr)?;
+        let addr = stream.peer_addr()?;
+        Ok(Self {
+            addr,
+            stream: Some(configure(stream)?),
+            buf: Vec::new(),
+        })
+    }
+
+    /// Returns false after a failed request dropped the connection.
+    pub fn is_connected(&self) -> bool {
+        self.stream.is_some()
+    }
+
+    /// Requests up to `count` messages starting at sequence `start` and
+    /// returns those the server still had, in sequence order.
+    ///
+    /// Reconnects first if the previous request failed. The whole response
+    /// is read before any message is decoded; if it cannot be read the
+    /// connection is dropped.
+    pub fn request(&mut self, start: u64, count: u32) -> Result<Vec<Frame>, RecoveryError> {
+        let mut stream = match self.stream.take() {
+            Some(stream) => stream,
+            None => configure(TcpStream::connect_timeout(&self.addr, RESPONSE_TIMEOUT)?)?,
+        };
+        let returned = read_response(&mut stream, start, count, &mut self.buf)?;
+        // The stream is at a message boundary again
+        self.stream = Some(stream);
+
+        let mut frames = Vec::with_capacity(returned as usize);
+        let mut body = &self.buf[..];
+        while let [lo, hi, rest @ ..] = body {
+            let len = u16::from_le_bytes([*lo, *hi]) as usize;
+            frames.push(protocol::decode(&rest[..len])?);
+            body = &rest[len..];
+        }
+        Ok(frames)
+    }
+}
+
+fn configure(stream: TcpStream) -> io::Result<TcpStream> {
+    stream.set_read_timeout(Some(RESPONSE_TIMEOUT))?;
+    stream.set_nodelay(true)?;
+    Ok(stream)
+}
+
+/// Sends a range request and reads the complete response body into `buf`.
+/// Returns the number of messages in it.
+fn read_response(
+    stream: &mut TcpStream,
+    start: u64,
+    count: u32,
+    buf: &mut Vec<u8>,
+) -> Result<u32, RecoveryError> {
+    let mut request = [0u8; REQUEST_SIZE];
+    request[0..8].copy_from_slice(&start.to_le_bytes());
+    request[8..12].copy_from_slice(&count.to_le_bytes());
+    stream.write_all(&request)?;
+
+    let mut header = [0u8; 4];
+    stream.read_exact(&mut header)?;
+    let returned = u32::from_le_bytes(header);
+    if returned > count {
+        return Err(RecoveryError::TooManyMessages {
+            requested: count,
+            returned,
+        });
+    }
+
+    buf.clear();
+    for _ in 0..returned {
+        let mut len = [0u8; 2];
+        stream.read_exact(&mut len)?;
+        let offset = buf.len() + len.len();
+        buf.extend_from_slice(&len);
+        buf.resize(offset + u16::from_le_bytes(len) as usize, 0);
+        stream.read_exact(&mut buf[offset..])?;
+    }
+    Ok(returned)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::{encode, Message};
+
+    fn history_with(
+        sequences: std::ops::Range<u64>,
+        capacity: usize,
+    ) -> Arc<Mutex<RecoveryHistory>> {
+        let history = RecoveryHistory::shared(capacity);
+        let mut buf = [0u8; protocol::MAX_MESSAGE_SIZE];
+        for sequence in sequences {
+            let len = encode(&Message::Heartbeat, sequence, sequence * 10, &mut buf).unwrap();
+            history.lock().unwrap().record(sequence, &buf[..len]);
+        }
+        history
+    }
+
+    #[test]
+    fn test_history_is_bounded() {
+        let history = history_with(0..100, 10);
+        let history = history.lock().unwrap();
+        assert_eq!(history.len(), 10);
+        assert_eq!(history.first_sequence(), Some(90));
+        let range: Vec<u64> = history.range(85, 93).map(|(seq, _)| seq).collect();
+        assert_eq!(range, [90, 91, 92]);
+        assert_eq!(history.range(100, 200).count(), 0);
+    }
+
+    #[test]
+    fn test_request_over_loopback() {
+        let history = history_with(0..50, 40);
+        let server = RecoveryServer::bind("127.0.0.1:0", history).unwrap();
+        let addr = server.local_addr().unwrap();
+        let stop = StopFlag::new();
+
+        thread::scope(|scope| {
+            scope.spawn(|| server.run(&stop).unwrap());
+
+            let mut client = RecoveryClient::connect(addr).unwrap();
+            let frames = client.request(20, 5).unwrap();
+            let sequences: Vec<u64> = frames.iter().map(|f| f.header.sequence).collect();
+            assert_eq!(sequences, [20, 21, 22, 23, 24]);
+            assert_eq!(frames[0].header.timestamp, 200);
+
+            // 0..10 has been evicted, so only 10..12 comes back
+            let frames = client.request(5, 7).unwrap();
+            assert_eq!(frames.len(), 2);
+            assert_eq!(frames[0].header.sequence, 10);
+
+            assert!(client.request(60, 3).unwrap().is_empty());
+            stop.stop();
+        });
+    }
+
+    #[test]
+    fn test_failed_request_reconnects() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let history = history_with(0..10, 10);
+
+        let server = thread::spawn(move || {
+            let mut request = [0u8; REQUEST_SIZE];
+            let mut response = Vec::new();
+            encode_response(&history.lock().unwrap(), 0, 2, &mut response);
+
+            // More messages than requested, with a body left unread
+            let (mut stream, _) = listener.accept().unwrap();
+            stream.read_exact(&mut request).unwrap();
+            stream.write_all(&5u32.to_le_bytes()).unwrap();
+            stream.write_all(&response[4..]).unwrap();
+
+            // Cut off halfway through the response
+            let (mut stream, _) = listener.accept().unwrap();
+            stream.read_exact(&mut request).unwrap();
+            stream.write_all(&response[..response.len() / 2]).unwrap();
+            drop(stream);
+
+            let (mut stream, _) = listener.accept().unwrap();
+            stream.read_exact(&mut request).unwrap();
+            stream.write_all(&response).unwrap();
+            stream.read_exact(&mut request).unwrap();
+            stream.write_all(&response).unwrap();
+        });
+
+        let mut client = RecoveryClient::connect(addr).unwrap();
+        assert!(matches!(
+            client.request(0, 2),
+            Err(RecoveryError::TooManyMessages { returned: 5, .. })
+        ));
+        assert!(!client.is_connected());
+        assert!(matches!(client.request(0, 2), Err(RecoveryError::Io(_))));
+        assert!(!client.is_connected());
+
+        // A fresh connection, and the stream stays in step afterwards
+        for _ in 0..2 {
+            let sequences: Vec<u64> = client
+                .request(0, 2)
+                .unwrap()
+                .iter()
+                .map(|f| f.header.sequence)
+                .collect();
+            assert_eq!(sequences, [0, 1]);
+            assert!(client.is_connected());
+        }
+        server.join().unwrap();
+    }
+}
@@ -0,0 +1,536 @@
+//! Per-source sequencing: gap detection, duplicate suppression and
+//! out-of-order buffering.
+//!
+//! UDP may drop, duplicate or reorder datagrams. [`FeedSequencer`] tracks
+//! the next expected sequence number of every source and delivers messages
+//! strictly in order. Messages that arrive early wait in a reorder window;
+//! when the window fills up the oldest hole is given up on. A jump of many
+//! windows in either direction (a restarted publisher, a receiver that fell
+//! far behind) re-bases the source at the new sequence instead. Opening and
+//! losing a hole and re-basing are recorded as [`FeedEvent`]s and counted
+//! in [`FeedStats`].
+
+use crate::protocol::Frame;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fmt;
+use std::net::SocketAddr;
+
+/// Number of recent events kept by [`FeedSequencer::events`].
+pub const EVENT_HISTORY: usize = 1024;
+
+/// Default jump, in reorder windows, that re-bases a source.
+pub const DEFAULT_RESET_WINDOWS: u64 = 64;
+
+/// A range of missing sequence numbers, `start..end`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Gap {
+    pub source: SocketAddr,
+    pub start: u64,
+    pub end: u64,
+}
+
+impl Gap {
+    /// Returns the number of missing messages.
+    pub fn len(&self) -> u64 {
+        self.end - self.start
+    }
+
+    /// Returns true for an empty range.
+    pub fn is_empty(&self) -> bool {
+        self.start >= self.end
+    }
+}
+
+impl fmt::Display for Gap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} seq {}..{} ({} messages)",
+            self.source,
+            self.start,
+            self.end,
+            self.len()
+        )
+    }
+}
+
+/// Structured sequencing event.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FeedEvent {
+    /// Messages after the gap arrived first; the gap may still fill.
+    GapOpened(Gap),
+    /// The reorder window moved past the gap; its messages are lost.
+    GapLost(Gap),
+    /// A sequence far outside the window re-based the source: delivery
+    /// continues at `to` instead of `from`.
+    Reset {
+        source: SocketAddr,
+        from: u64,
+        to: u64,
+    },
+}
+
+/// Feed health counters.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FeedStats {
+    /// Messages handed to [`FeedSequencer::process`].
+    pub received: u64,
+    /// Messages delivered in order.
+    pub delivered: u64,
+    /// Messages dropped because their sequence was already delivered,
+    /// buffered or given up on.
+    pub duplicates: u64,
+    /// Messages that arrived ahead of a gap and were buffered.
+    pub out_of_order: u64,
+    pub gaps_opened: u64,
+    /// Messages skipped when a gap was given up on.
+    pub messages_lost: u64,
+    /// Times a source was re-based by a far jump.
+    pub resets: u64,
+}
+
+impl FeedStats {
+    fn add(&mut self, other: &FeedStats) {
+        self.received += other.received;
+        self.delivered += other.delivered;
+        self.duplicates += other.duplicates;
+        self.out_of_order += other.out_of_order;
+        self.gaps_opened += other.gaps_opened;
+        self.messages_lost += other.messages_lost;
+        self.resets += other.resets;
+    }
+}
+
+impl fmt::Display for FeedStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Received:      {}", self.received)?;
+        writeln!(f, "Delivered:     {}", self.delivered)?;
+        writeln!(f, "Duplicates:    {}", self.duplicates)?;
+        writeln!(f, "Out of order:  {}", self.out_of_order)?;
+        writeln!(f, "Gaps opened:   {}", self.gaps_opened)?;
+        writeln!(f, "Messages lost: {}", self.messages_lost)?;
+        writeln!(f, "Resets:        {}", self.resets)
+    }
+}
+
+struct SourceState {
+    /// Next sequence number to deliver.
+    next: u64,
+    /// Messages received ahead of `next`, keyed by sequence.
+    pending: BTreeMap<u64, Frame>,
+    stats: FeedStats,
+}
+
+impl SourceState {
+    /// Delivers buffered messages that continue from `next`.
+    fn deliver_ready<F: FnMut(Frame)>(&mut self, deliver: &mut F) {
+        while let Some(buffered) = self.pending.remove(&self.next) {
+            self.next = self.next.saturating_add(1);
+            self.stats.delivered += 1;
+            deliver(buffered);
+        }
+    }
+
+    /// One past the highest buffered sequence, or `next` if none are.
+    fn frontier(&self) -> u64 {
+        self.pending
+            .keys()
+            .next_back()
+            .map_or(self.next, |&highest| highest.saturating_add(1))
+    }
+
+    /// Gives up on every hole below `limit`, delivering the messages
+    /// buffered between them.
+    fn skip_to<F: FnMut(Frame)>(
+        &mut self,
+        source: SocketAddr,
+        limit: u64,
+        events: &mut VecDeque<FeedEvent>,
+        deliver: &mut F,
+    ) {
+        while self.next < limit {
+            self.deliver_ready(deliver);
+            if self.next >= limit {
+                break;
+            }
+            let end = self
+                .pending
+                .range(self.next..)
+                .next()
+                .map_or(limit, |(&buffered, _)| buffered.min(limit));
+            let gap = Gap {
+                source,
+                start: self.next,
+                end,
+            };
+            self.stats.messages_lost += gap.len();
+            push_event(events, FeedEvent::GapLost(gap));
+            self.next = end;
+        }
+        self.deliver_ready(deliver);
+    }
+}
+
+/// Orders the messages of every source by sequence number.
+///
+/// The first message seen from a source sets its starting sequence.
+///
+/// # Examples
+/// ```
+/// use hft_system::protocol::{decode, encode, Message};
+/// use hft_system::sequencer::FeedSequencer;
+///
+/// let source = "127.0.0.1:9000".parse().unwrap();
+/// let frame = |sequence| {
+///     let mut buf = [0u8; 64];
+///     let len = encode(&Message::Heartbeat, sequence, 0, &mut buf).unwrap();
+///     decode(&buf[..len]).unwrap()
+/// };
+///
+/// let mut sequencer = FeedSequencer::new(16);
+/// let mut delivered = Vec::new();
+/// for sequence in [0, 2, 1, 1] {
+///     sequencer.process(source, frame(sequence), |f| delivered.push(f.header.sequence));
+/// }
+/// assert_eq!(delivered, [0, 1, 2]);
+/// assert_eq!(sequencer.stats().duplicates, 1);
+/// ```
+pub struct FeedSequencer {
+    window: u64,
+    reset_windows: u64,
+    sources: HashMap<SocketAddr, SourceState>,
+    events: VecDeque<FeedEvent>,
+}
+
+impl FeedSequencer {
+    /// Creates a sequencer that buffers messages up to `window` sequence
+    /// numbers ahead of the next expected one.
+    ///
+    /// # Panics
+    /// Panics if `window` is zero.
+    pub fn new(window: usize) -> Self {
+        assert!(window > 0, "reorder window must be at least 1");
+        Self {
+            window: window as u64,
+            reset_windows: DEFAULT_RESET_WINDOWS,
+            sources: HashMap::new(),
+            events: VecDeque::new(),
+        }
+    }
+
+    /// Re-bases a source when a sequence lands `windows` reorder windows
+    /// or more ahead of the next expected one, or more than that behind it
+    /// (default [`DEFAULT_RESET_WINDOWS`]).
+    ///
+    /// Buffered messages are delivered and the holes between them counted
+    /// as lost before delivery continues at the new sequence.
+    ///
+    /// # Panics
+    /// Panics if `windows` is zero.
+    pub fn with_reset_windows(mut self, windows: u64) -> Self {
+        assert!(windows > 0, "reset distance must be at least 1 window");
+        self.reset_windows = windows;
+        self
+    }
+
+    /// Returns the reorder window.
+    pub fn window(&self) -> usize {
+        self.window as usize
+    }
+
+    /// Accepts a message from `source`, calling `deliver` for every message
+    /// that is now in order (possibly none, possibly several).
+    pub fn process<F: FnMut(Frame)>(&mut self, source: SocketAddr, frame: Frame, mut deliver: F) {
+        let sequence = frame.header.sequence;
+        let window = self.window;
+        let reset_distance = window.saturating_mul(self.reset_windows);
+        let state = self.sources.entry(source).or_insert_with(|| SourceState {
+            next: sequence,
+            pending: BTreeMap::new(),
+            stats: FeedStats::default(),
+        });
+        state.stats.received += 1;
+
+        // Far outside the window: the source restarted or we lost track
+        if sequence.saturating_sub(state.next) >= reset_distance
+            || state.next.saturating_sub(sequence) > reset_distance
+        {
+            let frontier = state.frontier();
+            state.skip_to(source, frontier, &mut self.events, &mut deliver);
+            push_event(
+                &mut self.events,
+                FeedEvent::Reset {
+                    source,
+                    from: state.next,
+                    to: sequence,
+                },
+            );
+            state.stats.resets += 1;
+            state.next = sequence;
+        }
+
+        if sequence < state.next || state.pending.contains_key(&sequence) {
+            state.stats.duplicates += 1;
+            return;
+        }
+
+        // Keep `sequence` within the window by giving up on the oldest holes
+        if sequence - state.next >= window {
+            // `sequence >= window` here, so this cannot overflow
+            let limit = sequence - (window - 1);
+            state.skip_to(source, limit, &mut self.events, &mut deliver);
+        }
+
+        if sequence == state.next {
+            state.next = state.next.saturating_add(1);
+            state.stats.delivered += 1;
+            deliver(frame);
+            state.deliver_ready(&mut deliver);
+            return;
+        }
+
+        // Early arrival; a new hole opens only past the highest buffered one
+        let frontier = state.frontier();
+        if sequence > frontier {
+            state.stats.gaps_opened += 1;
+            push_event(
+                &mut self.events,
+                FeedEvent::GapOpened(Gap {
+                    source,
+                    start: frontier,
+                    end: sequence,
+                }),
+            );
+        }
+        state.stats.out_of_order += 1;
+        state.pending.insert(sequence, frame);
+    }
+
+    /// Returns the counters summed over every source.
+    pub fn stats(&self) -> FeedStats {
+        let mut total = FeedStats::default();
+        for state in self.sources.values() {
+            total.add(&state.stats);
+        }
+        total
+    }
+
+    /// Returns the counters of one source.
+    pub fn source_stats(&self, source: SocketAddr) -> Option<FeedStats> {
+        self.sources.get(&source).map(|state| state.stats)
+    }
+
+    /// Returns the next sequence number expected from `source`.
+    pub fn next_expected(&self, source: SocketAddr) -> Option<u64> {
+        self.sources.get(&source).map(|state| state.next)
+    }
+
+    /// Returns the sources seen so far.
+    pub fn sources(&self) -> impl Iterator<Item = SocketAddr> + '_ {
+        self.sources.keys().copied()
+    }
+
+    /// Returns the holes currently open for `source`, oldest first.
+    pub fn open_gaps(&self, source: SocketAddr) -> Vec<Gap> {
+        let Some(state) = self.sources.get(&source) else {
+            return Vec::new();
+        };
+        let mut gaps = Vec::new();
+        let mut expected = state.next;
+        for &buffered in state.pending.keys() {
+            if buffered > expected {
+                gaps.push(Gap {
+                    source,
+                    start: expected,
+                    end: buffered,
+                });
+            }
+            expected = buffered.saturating_add(1);
+        }
+        gaps
+    }
+
+    /// Returns the most recent events (up to [`EVENT_HISTORY`]), oldest
+    /// first.
+    pub fn events(&self) -> impl Iterator<Item = &FeedEvent> {
+        self.events.iter()
+    }
+
+    /// Removes and returns the recorded events.
+    pub fn drain_events(&mut self) -> Vec<FeedEvent> {
+        self.events.drain(..).collect()
+    }
+}
+
+impl fmt::Debug for FeedSequencer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FeedSequencer")
+            .field("window", &self.window)
+            .field("sources", &self.sources.len())
+            .field("stats", &self.stats())
+            .finish()
+    }
+}
+
+fn push_event(events: &mut VecDeque<FeedEvent>, event: FeedEvent) {
+    if events.len() == EVENT_HISTORY {
+        events.pop_front();
+    }
+    events.push_back(event);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::{decode, encode, Message};
+
+    fn frame(sequence: u64) -> Frame {
+        let mut buf = [0u8; 64];
+        let len = encode(&Message::Heartbeat, sequence, 0, &mut buf).unwrap();
+        decode(&buf[..len]).unwrap()
+    }
+
+    fn run(sequencer: &mut FeedSequencer, source: SocketAddr, sequences: &[u64]) -> Vec<u64> {
+        let mut delivered = Vec::new();
+        for &sequence in sequences {
+            sequencer.process(source, frame(sequence), |f| {
+                delivered.push(f.header.sequence)
+            });
+        }
+        delivered
+    }
+
+    #[test]
+    fn test_reorder_and_duplicates() {
+        let source = "10.0.0.1:5000".parse().unwrap();
+        let mut sequencer = FeedSequencer::new(8);
+
+        let delivered = run(
+            &mut sequencer,
+            source,
+            &[100, 101, 104, 103, 101, 102, 104, 105],
+        );
+        assert_eq!(delivered, [100, 101, 102, 103, 104, 105]);
+
+        let stats = sequencer.stats();
+        assert_eq!(stats.received, 8);
+        assert_eq!(stats.delivered, 6);
+        assert_eq!(stats.duplicates, 2);
+        assert_eq!(stats.out_of_order, 2);
+        assert_eq!(stats.gaps_opened, 1);
+        assert_eq!(stats.messages_lost, 0);
+        assert_eq!(
+            sequencer.drain_events(),
+            [FeedEvent::GapOpened(Gap {
+                source,
+                start: 102,
+                end: 104
+            })]
+        );
+        assert_eq!(sequencer.next_expected(source), Some(106));
+    }
+
+    #[test]
+    fn test_window_overflow_loses_gap() {
+        let source = "10.0.0.1:5000".parse().unwrap();
+        let mut sequencer = FeedSequencer::new(4);
+
+        assert_eq!(run(&mut sequencer, source, &[0, 2, 3]), [0]);
+        assert_eq!(
+            sequencer.open_gaps(source),
+            [Gap {
+                source,
+                start: 1,
+                end: 2
+            }]
+        );
+
+        // 5 is outside the window [1, 5), so 1 is given up on
+        assert_eq!(run(&mut sequencer, source, &[5]), [2, 3]);
+        // ...and the late copy of 1 is dropped
+        assert_eq!(run(&mut sequencer, source, &[1, 4]), [4, 5]);
+
+        let stats = sequencer.stats();
+        assert_eq!(stats.messages_lost, 1);
+        assert_eq!(stats.duplicates, 1);
+        let events = sequencer.drain_events();
+        assert_eq!(events.len(), 3);
+        assert_eq!(
+            events[1],
+            FeedEvent::GapLost(Gap {
+                source,
+                start: 1,
+                end: 2
+            })
+        );
+    }
+
+    #[test]
+    fn test_far_jumps_rebase_the_source() {
+        let source = "10.0.0.1:5000".parse().unwrap();
+        let mut sequencer = FeedSequencer::new(4).with_reset_windows(2);
+
+        // 0..8 is within two windows of 3: ordinary gap handling
+        assert_eq!(run(&mut sequencer, source, &[0, 1, 3]), [0, 1]);
+        assert_eq!(sequencer.stats().resets, 0);
+
+        // Eight or more ahead re-bases, flushing 3 and losing 2
+        assert_eq!(run(&mut sequencer, source, &[10, 11]), [3, 10, 11]);
+        assert_eq!(sequencer.next_expected(source), Some(12));
+        let events = sequencer.drain_events();
+        assert_eq!(
+            events[1..],
+            [
+                FeedEvent::GapLost(Gap {
+                    source,
+                    start: 2,
+                    end: 3
+                }),
+                FeedEvent::Reset {
+                    source,
+                    from: 4,
+                    to: 10
+                }
+            ]
+        );
+
+        // More than eight behind is a restarted publisher, not a duplicate
+        assert_eq!(run(&mut sequencer, source, &[4, 0, 1]), [0, 1]);
+        let stats = sequencer.stats();
+        assert_eq!((stats.duplicates, stats.resets), (1, 2));
+        assert_eq!(stats.messages_lost, 1);
+    }
+
+    #[test]
+    fn test_sequence_near_max_does_not_overflow() {
+        let source = "10.0.0.1:5000".parse().unwrap();
+        let mut sequencer = FeedSequencer::new(4);
+
+        let top = u64::MAX;
+        assert_eq!(run(&mut sequencer, source, &[top - 5, top - 3]), [top - 5]);
+        assert_eq!(run(&mut sequencer, source, &[top]), [top - 3]);
+        assert_eq!(
+            sequencer.open_gaps(source),
+            [Gap {
+                source,
+                start: top - 2,
+                end: top
+            }]
+        );
+        assert_eq!(sequencer.stats().messages_lost, 1);
+    }
+
+    #[test]
+    fn test_sources_are_independent() {
+        let a = "10.0.0.1:5000".parse().unwrap();
+        let b = "10.0.0.2:5000".parse().unwrap();
+        let mut sequencer = FeedSequencer::new(4);
+
+        run(&mut sequencer, a, &[0, 1]);
+        run(&mut sequencer, b, &[50, 52]);
+        assert_eq!(sequencer.source_stats(a).unwrap().gaps_opened, 0);
+        assert_eq!(sequencer.source_stats(b).unwrap().gaps_opened, 1);
+        assert_eq!(sequencer.open_gaps(a), []);
+        assert_eq!(sequencer.stats().delivered, 3);
+    }
+}
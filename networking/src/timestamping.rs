@@ -0,0 +1,366 @@
+//! Kernel and hardware receive timestamps.
+//!
+//! With `SO_TIMESTAMPNS` the kernel stamps every datagram as it comes off
+//! the driver; with `SO_TIMESTAMPING` a NIC that supports it stamps it on
+//! the wire. The stamps arrive as control messages next to the payload
+//! (see [`RecvBatch::timestamp`](crate::batch::RecvBatch::timestamp)) and,
+//! together with the sender's nanosecond timestamp in the message header,
+//! split latency into wire-to-userspace and end-to-end parts
+//! ([`RxLatency`]).
+//!
+//! Hardware stamps also need the device configured for them (`SIOCSHWTSTAMP`,
+//! e.g. `hwstamp_ctl -r 1`); without that only software stamps appear.
+//!
+//! Raw hardware stamps are read from the NIC's PTP hardware clock (PHC),
+//! not from `CLOCK_REALTIME`. They are kept apart from software stamps and
+//! only mean anything against [`now_nanos`] while the PHC is synchronized to
+//! the system clock, e.g. `phc2sys -s eth0 -c CLOCK_REALTIME -O 0` (or with
+//! `-w` to take the UTC offset from `ptp4l`).
+
+use hft_primitives::LatencyHistogram;
+use std::fmt;
+use std::io;
+use std::net::UdpSocket;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Which receive timestamps to ask the kernel for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimestampMode {
+    /// Kernel software stamps via `SO_TIMESTAMPNS`.
+    Software,
+    /// `SO_TIMESTAMPING`: software stamps, plus raw hardware (PHC) stamps
+    /// where the NIC provides them.
+    Hardware,
+}
+
+impl fmt::Display for TimestampMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TimestampMode::Software => f.write_str("software (SO_TIMESTAMPNS)"),
+            TimestampMode::Hardware => f.write_str("hardware (SO_TIMESTAMPING)"),
+        }
+    }
+}
+
+/// Receive timestamps of one datagram, in nanoseconds since the epoch.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RxTimestamp {
+    /// Kernel stamp from `CLOCK_REALTIME`, the clock of [`now_nanos`].
+    pub software: Option<u64>,
+    /// NIC stamp from its PTP hardware clock; see the module docs for when
+    /// it can be compared with [`now_nanos`].
+    pub hardware: Option<u64>,
+}
+
+/// Asks the kernel to stamp datagrams received on `socket`.
+pub fn enable(socket: &UdpSocket, mode: TimestampMode) -> io::Result<()> {
+    sys::enable(socket, mode)
+}
+
+/// Returns the wall-clock time in nanoseconds since the epoch, the clock
+/// receive timestamps and message headers use.
+pub fn now_nanos() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos() as u64)
+        .unwrap_or(0)
+}
+
+/// Wire-to-userspace and end-to-end latency of received messages.
+///
+/// Wire-to-userspace uses the software stamp. Hardware stamps, when
+/// present, go into a separate [`hardware_to_user`](Self::hardware_to_user)
+/// histogram since they come from the NIC's clock.
+///
+/// # Examples
+/// ```
+/// use hft_system::timestamping::{RxLatency, RxTimestamp};
+///
+/// let mut latency = RxLatency::new();
+/// let stamp = RxTimestamp { software: Some(1_000_500), hardware: None };
+/// // Sent at 1_000_000, stamped by the kernel at 1_000_500, read at 1_002_000
+/// latency.record(Some(stamp), 1_000_000, 1_002_000);
+///
+/// assert_eq!(latency.wire_to_user().max().as_nanos(), 1_500);
+/// assert_eq!(latency.end_to_end().max().as_nanos(), 2_000);
+/// ```
+#[derive(Clone)]
+pub struct RxLatency {
+    wire_to_user: LatencyHistogram,
+    hardware_to_user: LatencyHistogram,
+    end_to_end: LatencyHistogram,
+    clock_skew: u64,
+}
+
+impl RxLatency {
+    /// Creates empty histograms.
+    pub fn new() -> Self {
+        Self {
+            wire_to_user: LatencyHistogram::new(),
+            hardware_to_user: LatencyHistogram::new(),
+            end_to_end: LatencyHistogram::new(),
+            clock_skew: 0,
+        }
+    }
+
+    /// Records one message read at `now` (ns since the epoch) that the
+    /// sender stamped `sent` and the kernel or NIC stamped `rx`. A zero
+    /// `sent` means the message carries no send time.
+    pub fn record(&mut self, rx: Option<RxTimestamp>, sent: u64, now: u64) {
+        let rx = rx.unwrap_or_default();
+        for (stamp, histogram) in [
+            (rx.software, &mut self.wire_to_user),
+            (rx.hardware, &mut self.hardware_to_user),
+            ((sent != 0).then_some(sent), &mut self.end_to_end),
+        ] {
+            if let Some(stamp) = stamp {
+                match now.checked_sub(stamp) {
+                    Some(nanos) => histogram.record_nanos(nanos),
+                    None => self.clock_skew += 1,
+                }
+            }
+        }
+    }
+
+    /// Returns the time from the kernel's software receive stamp to the
+    /// application.
+    pub fn wire_to_user(&self) -> &LatencyHistogram {
+        &self.wire_to_user
+    }
+
+    /// Returns the time from the NIC's hardware receive stamp to the
+    /// application. Only meaningful with the PHC synchronized to the
+    /// system clock (`phc2sys`).
+    pub fn hardware_to_user(&self) -> &LatencyHistogram {
+        &self.hardware_to_user
+    }
+
+    /// Returns the time from the sender's stamp to the application.
+    pub fn end_to_end(&self) -> &LatencyHistogram {
+        &self.end_to_end
+    }
+
+    /// Returns the number of stamps that were later than the read time,
+    /// e.g. because the sender's clock or an unsynchronized PHC runs ahead.
+    pub fn clock_skew(&self) -> u64 {
+        self.clock_skew
+    }
+}
+
+impl Default for RxLatency {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Display for RxLatency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Most NICs (and loopback) never produce hardware stamps, so that
+        // line is left out rather than reported empty
+        for (name, histogram, optional) in [
+            ("Wire to user", &self.wire_to_user, false),
+            ("Hardware to user", &self.hardware_to_user, true),
+            ("End to end", &self.end_to_end, false),
+        ] {
+            if histogram.is_empty() {
+                if !optional {
+                    writeln!(f, "{}: no samples", name)?;
+                }
+                continue;
+            }
+            writeln!(
+                f,
+                "{}: p50 {:?}, p99 {:?}, max {:?} ({} samples)",
+                name,
+                histogram.value_at_quantile(0.5),
+                histogram.value_at_quantile(0.99),
+                histogram.max(),
+                histogram.len()
+            )?;
+        }
+        if self.clock_skew > 0 {
+            writeln!(f, "Clock skew:  {} stamps in the future", self.clock_skew)?;
+        }
+        Ok(())
+    }
+}
+
+fn timespec_nanos(secs: i64, nanos: i64) -> Option<u64> {
+    if secs == 0 && nanos == 0 {
+        return None;
+    }
+    let d = Duration::new(secs as u64, nanos as u32);
+    Some(d.as_nanos() as u64)
+}
+
+#[cfg(target_os = "linux")]
+pub(crate) mod sys {
+    use super::{timespec_nanos, RxTimestamp, TimestampMode};
+    use std::io;
+    use std::mem;
+    use std::net::UdpSocket;
+    use std::os::fd::AsRawFd;
+
+    /// Control buffer words per datagram; room for `SCM_TIMESTAMPING`'s
+    /// three timespecs plus slack, 8-byte aligned.
+    pub const CONTROL_WORDS: usize = 12;
+
+    pub fn enable(socket: &UdpSocket, mode: TimestampMode) -> io::Result<()> {
+        let (name, value) = match mode {
+            TimestampMode::Software => (libc::SO_TIMESTAMPNS, 1),
+            TimestampMode::Hardware => (
+                libc::SO_TIMESTAMPING,
+                (libc::SOF_TIMESTAMPING_RX_HARDWARE
+                    | libc::SOF_TIMESTAMPING_RAW_HARDWARE
+                    | libc::SOF_TIMESTAMPING_RX_SOFTWARE
+                    | libc::SOF_TIMESTAMPING_SOFTWARE) as libc::c_int,
+            ),
+        };
+        // SAFETY: `value` is a live c_int of the advertised size.
+        let rc = unsafe {
+            libc::setsockopt(
+                socket.as_raw_fd(),
+                libc::SOL_SOCKET,
+                name,
+                &value as *const libc::c_int as *const libc::c_void,
+                mem::size_of::<libc::c_int>() as libc::socklen_t,
+            )
+        };
+        if rc == 0 {
+            Ok(())
+        } else {
+            Err(io::Error::last_os_error())
+        }
+    }
+
+    /// Extracts receive timestamps from the control messages of `header`.
+    ///
+    /// # Safety
+    /// `header` must describe a control buffer filled in by the kernel.
+    pub unsafe fn parse(header: &libc::msghdr) -> Option<RxTimestamp> {
+        let mut stamp = RxTimestamp::default();
+        let mut cmsg = libc::CMSG_FIRSTHDR(header);
+        while !cmsg.is_null() {
+            let level = (*cmsg).cmsg_level;
+            let kind = (*cmsg).cmsg_type;
+            let data = libc::CMSG_DATA(cmsg) as *const libc::timespec;
+            if level == libc::SOL_SOCKET && kind == libc::SCM_TIMESTAMPNS {
+                let ts = data.read_unaligned();
+                stamp.software = timespec_nanos(ts.tv_sec, ts.tv_nsec);
+            } else if level == libc::SOL_SOCKET && kind == libc::SCM_TIMESTAMPING {
+                // [software, legacy, raw hardware]
+                let software = data.read_unaligned();
+                let hardware = data.add(2).read_unaligned();
+                stamp.software = timespec_nanos(software.tv_sec, software.tv_nsec);
+                stamp.hardware = timespec_nanos(hardware.tv_sec, hardware.tv_nsec);
+            }
+            cmsg = libc::CMSG_NXTHDR(header, cmsg);
+        }
+        (stamp != RxTimestamp::default()).then_some(stamp)
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub(crate) mod sys {
+    use super::TimestampMode;
+    use std::io;
+    use std::net::UdpSocket;
+
+    pub fn enable(_socket: &UdpSocket, _mode: TimestampMode) -> io::Result<()> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "receive timestamps are only supported on Linux",
+        ))
+    }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+    use crate::batch::RecvBatch;
+    use std::thread;
+    use std::time::Duration;
+
+    fn stamped_pair(mode: TimestampMode) -> (UdpSocket, UdpSocket) {
+        let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+        receiver
+            .set_read_timeout(Some(Duration::from_millis(500)))
+            .unwrap();
+        enable(&receiver, mode).unwrap();
+        let sender = UdpSocket::bind("127.0.0.1:0").unwrap();
+        sender.connect(receiver.local_addr().unwrap()).unwrap();
+        (receiver, sender)
+    }
+
+    #[test]
+    fn test_software_timestamps() {
+        for mode in [TimestampMode::Software, TimestampMode::Hardware] {
+            let (receiver, sender) = stamped_pair(mode);
+            let mut batch = RecvBatch::new(4, 64);
+
+            // The kernel switches stamping on asynchronously, so the first
+            // datagrams may go unstamped
+            let stamp = (0..100).find_map(|_| {
+                let before = now_nanos();
+                sender.send(b"tick").unwrap();
+                assert_eq!(batch.recv(&receiver).unwrap(), 1);
+                let after = now_nanos();
+                let Some(stamp) = batch.timestamp(0) else {
+                    thread::sleep(Duration::from_millis(1));
+                    return None;
+                };
+                let nanos = stamp.software.unwrap();
+                assert!(before <= nanos && nanos <= after, "{:?}", mode);
+                Some(stamp)
+            });
+            // Loopback has no NIC to stamp the packet
+            let stamp = stamp.unwrap_or_else(|| panic!("no {:?} stamp", mode));
+            assert_eq!(stamp.hardware, None);
+        }
+    }
+
+    #[test]
+    fn test_no_timestamps_unless_enabled() {
+        let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let sender = UdpSocket::bind("127.0.0.1:0").unwrap();
+        sender
+            .send_to(b"tick", receiver.local_addr().unwrap())
+            .unwrap();
+
+        let mut batch = RecvBatch::new(1, 64);
+        batch.recv(&receiver).unwrap();
+        assert_eq!(batch.timestamp(0), None);
+    }
+
+    #[test]
+    fn test_hardware_stamps_are_kept_apart() {
+        let mut latency = RxLatency::new();
+        // An unsynchronized PHC: far from the system clock
+        let stamp = RxTimestamp {
+            software: Some(1_000_500),
+            hardware: Some(37_000_000_000),
+        };
+        latency.record(Some(stamp), 1_000_000, 1_002_000);
+        assert_eq!(latency.wire_to_user().max().as_nanos(), 1_500);
+        assert!(latency.hardware_to_user().is_empty());
+        assert_eq!(latency.clock_skew(), 1);
+
+        let stamp = RxTimestamp {
+            software: None,
+            hardware: Some(1_000_200),
+        };
+        latency.record(Some(stamp), 0, 1_002_000);
+        assert_eq!(latency.wire_to_user().len(), 1);
+        assert_eq!(latency.hardware_to_user().max().as_nanos(), 1_800);
+    }
+
+    #[test]
+    fn test_latency_skips_missing_and_skewed() {
+        let mut latency = RxLatency::new();
+        latency.record(None, 0, 1_000);
+        latency.record(None, 2_000, 1_000);
+        assert!(latency.wire_to_user().is_empty());
+        assert!(latency.end_to_end().is_empty());
+        assert_eq!(latency.clock_skew(), 1);
+    }
+}
@@ -1,9 +1,10 @@
+use hft_primitives::report::{report_path_from_args, RunMetadata, RunReport};
 use std::cell::UnsafeCell;
 use std::collections::VecDeque;
 use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex};
 use std::thread;
-use std::time::Instant;
+use std::time::{Duration, Instant};
 
 pub struct MutexSPSCQueue<T> {
     buffer: Arc<Mutex<VecDeque<T>>>,
@@ -134,7 +135,11 @@ impl<T> LockFreeRingBuffer<T> {
     }
 }
 
-fn benchmark_counter(counter_type: &str, iterations: usize, thread_count: usize) {
+fn benchmark_counter(
+    counter_type: &str,
+    iterations: usize,
+    thread_count: usize,
+) -> Option<Duration> {
     let start = Instant::now();
 
     match counter_type {
@@ -158,6 +163,7 @@ fn benchmark_counter(counter_type: &str, iterations: usize, thread_count: usize)
 
             let duration = start.elapsed();
             println!("[{}] Time: {:?}", counter_type, duration);
+            Some(duration)
         }
         "mutex" => {
             let counter = Arc::new(MutexCounter::new());
@@ -179,12 +185,20 @@ fn benchmark_counter(counter_type: &str, iterations: usize, thread_count: usize)
 
             let duration = start.elapsed();
             println!("[{}] Time: {:?}", counter_type, duration);
+            Some(duration)
+        }
+        _ => {
+            println!("Unknown counter type: {}", counter_type);
+            None
         }
-        _ => println!("Unknown counter type: {}", counter_type),
     }
 }
 
-fn benchmark_ring_buffer(buffer_type: &str, iterations: usize, _thread_count: usize) {
+fn benchmark_ring_buffer(
+    buffer_type: &str,
+    iterations: usize,
+    _thread_count: usize,
+) -> Option<Duration> {
     let start = Instant::now();
 
     match buffer_type {
@@ -215,6 +229,7 @@ fn benchmark_ring_buffer(buffer_type: &str, iterations: usize, _thread_count: us
 
             let duration = start.elapsed();
             println!("[{}] Time: {:?}", buffer_type, duration);
+            Some(duration)
         }
         "mutex" => {
             let queue = Arc::new(MutexSPSCQueue::new());
@@ -241,22 +256,64 @@ fn benchmark_ring_buffer(buffer_type: &str, iterations: usize, _thread_count: us
 
             let duration = start.elapsed();
             println!("[{}] Time: {:?}", buffer_type, duration);
+            Some(duration)
+        }
+        _ => {
+            println!("Unknown buffer type: {}", buffer_type);
+            None
         }
-        _ => println!("Unknown buffer type: {}", buffer_type),
+    }
+}
+
+// Records elapsed time and throughput for one benchmark in the run report
+fn record_result(report: &mut RunReport, name: &str, operations: usize, elapsed: Option<Duration>) {
+    if let Some(elapsed) = elapsed {
+        report.add_value(
+            &format!("{} elapsed", name),
+            elapsed.as_nanos() as f64,
+            "ns",
+        );
+        report.add_value(
+            &format!("{} throughput", name),
+            operations as f64 / elapsed.as_secs_f64(),
+            "ops/sec",
+        );
     }
 }
 
 fn main() {
     let iterations = 100_000;
     let thread_count = 8;
+    let mut report = RunReport::new(RunMetadata::collect("atomics-bench", false, iterations));
 
     println!("=== Counter Benchmarks ===");
-    benchmark_counter("atomic", iterations, thread_count);
+    let elapsed = benchmark_counter("atomic", iterations, thread_count);
+    record_result(
+        &mut report,
+        "counter/atomic",
+        iterations * thread_count,
+        elapsed,
+    );
     println!();
-    benchmark_counter("mutex", iterations, thread_count);
+    let elapsed = benchmark_counter("mutex", iterations, thread_count);
+    record_result(
+        &mut report,
+        "counter/mutex",
+        iterations * thread_count,
+        elapsed,
+    );
 
     println!("\n=== Ring Buffer Benchmarks ===");
-    benchmark_ring_buffer("lockfree", iterations, 1);
+    let elapsed = benchmark_ring_buffer("lockfree", iterations, 1);
+    record_result(&mut report, "ring_buffer/lockfree", iterations, elapsed);
     println!();
-    benchmark_ring_buffer("mutex", iterations, 1);
+    let elapsed = benchmark_ring_buffer("mutex", iterations, 1);
+    record_result(&mut report, "ring_buffer/mutex", iterations, elapsed);
+
+    if let Some(path) = report_path_from_args() {
+        match report.write_to_path(&path) {
+            Ok(()) => println!("\nReport written to {}", path.display()),
+            Err(e) => eprintln!("Failed to write report to {}: {}", path.display(), e),
+        }
+    }
 }